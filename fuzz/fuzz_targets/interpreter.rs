@@ -0,0 +1,21 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate synacor;
+
+use synacor::input::Text;
+use synacor::output::Null;
+use synacor::{Config, Image, Synacor};
+
+const STEPS: u64 = 10_000;
+
+// Runs arbitrary images with no input and discarded output. Every way the
+// image can go wrong has to surface as a RunOutcome rather than a panic.
+fuzz_target!(|data: &[u8]| {
+    let config = Config { output: Box::new(Null), input: Box::new(Text::default()), ..Config::default() };
+    let mut synacor = Synacor::with_config(config);
+    if synacor.load_image(Image::Bytes(data.to_vec())).is_err() {
+        return;
+    }
+    synacor.run_for(STEPS);
+});
@@ -0,0 +1,16 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate synacor;
+
+use synacor::input::Text;
+use synacor::output::Null;
+use synacor::{Config, Image, Synacor};
+
+fuzz_target!(|data: &[u8]| {
+    let config = Config { output: Box::new(Null), input: Box::new(Text::default()), ..Config::default() };
+    let mut synacor = Synacor::with_config(config);
+    if let Ok(words) = synacor.load_image(Image::Bytes(data.to_vec())) {
+        assert_eq!(words * 2, data.len());
+    }
+});
@@ -0,0 +1,172 @@
+// Recordings of a session in asciinema's asciicast v2 format: a header line
+// with the terminal size, then one JSON array per event holding the seconds
+// since the start, "o" for output or "i" for input, and the text. Players
+// only show the output, so each line typed is also written out as if the
+// terminal had echoed it.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::rc::Rc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use input::InputSource;
+use json::Value;
+use output::OutputSink;
+
+// Used when the terminal can't say how big it is.
+pub const DEFAULT_SIZE: (usize, usize) = (80, 24);
+
+pub struct Cast {
+    file: Box<dyn Write>,
+    start: Instant,
+}
+
+impl Cast {
+    pub fn create(path: &str, size: (usize, usize)) -> io::Result<Rc<RefCell<Cast>>> {
+        Cast::new(Box::new(BufWriter::new(File::create(path)?)), size)
+    }
+    pub fn new(mut file: Box<dyn Write>, (width, height): (usize, usize)) -> io::Result<Rc<RefCell<Cast>>> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+        let header = Value::object(vec![
+            ("version", Value::Number(2.0)),
+            ("width", Value::Number(width as f64)),
+            ("height", Value::Number(height as f64)),
+            ("timestamp", Value::Number(timestamp as f64)),
+        ]);
+        writeln!(file, "{}", header)?;
+        Ok(Rc::new(RefCell::new(Cast { file, start: Instant::now() })))
+    }
+    fn event(&mut self, kind: &str, bytes: &[u8]) -> io::Result<()> {
+        // Players write output straight to a terminal, which needs CR LF,
+        // and a key press of Enter sends CR.
+        let text = String::from_utf8_lossy(bytes);
+        let text = if kind == "o" { text.replace('\n', "\r\n") } else { text.replace('\n', "\r") };
+        let seconds = self.start.elapsed().as_secs_f64();
+        writeln!(self.file, "[{:.6}, \"{}\", {}]", seconds, kind, Value::String(text))
+    }
+}
+
+// Records everything written to `sink` as output, an event for each line
+// or for whatever was written before a flush, which the VM does before it
+// reads input.
+pub struct Output {
+    cast: Rc<RefCell<Cast>>,
+    sink: Box<dyn OutputSink>,
+    pending: Vec<u8>,
+}
+
+impl Output {
+    pub fn new(cast: Rc<RefCell<Cast>>, sink: Box<dyn OutputSink>) -> Output {
+        Output { cast, sink, pending: Vec::new() }
+    }
+    fn record(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            self.cast.borrow_mut().event("o", &self.pending)?;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+}
+
+impl OutputSink for Output {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.pending.extend(bytes);
+        if bytes.contains(&b'\n') {
+            self.record()?;
+        }
+        self.sink.write(bytes)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.record()?;
+        self.cast.borrow_mut().file.flush()?;
+        self.sink.flush()
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+// Records each line read from `source` once its newline arrives, as input
+// and as its echo.
+pub struct Input {
+    cast: Rc<RefCell<Cast>>,
+    source: Box<dyn InputSource>,
+    line: Vec<u8>,
+}
+
+impl Input {
+    pub fn new(cast: Rc<RefCell<Cast>>, source: Box<dyn InputSource>) -> Input {
+        Input { cast, source, line: Vec::new() }
+    }
+}
+
+impl InputSource for Input {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.source.read_byte()?;
+        match byte {
+            Some(byte) => self.line.push(byte),
+            None if self.line.is_empty() => return Ok(None),
+            None => (),
+        }
+        if byte.is_none() || byte == Some(b'\n') {
+            let mut cast = self.cast.borrow_mut();
+            cast.event("i", &self.line)?;
+            cast.event("o", &self.line)?;
+            cast.file.flush()?;
+            self.line.clear();
+        }
+        Ok(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::Text;
+    use json;
+    use output::Buffer;
+
+    // Collects what the cast writes.
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend(bytes);
+            Ok(bytes.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn records_output_and_typed_lines() {
+        let written = Rc::new(RefCell::new(Vec::new()));
+        let cast = Cast::new(Box::new(Shared(written.clone())), (100, 30)).unwrap();
+        let mut output = Output::new(cast.clone(), Box::new(Buffer::default()));
+        let mut input = Input::new(cast, Box::new(Text::new("go\n")));
+        output.write(b"What do you do?").unwrap();
+        output.write(b"\n").unwrap();
+        while input.read_byte().unwrap().is_some() {}
+
+        let text = String::from_utf8(written.borrow().clone()).unwrap();
+        let lines: Vec<json::Value> = text.lines().map(|line| json::parse(line).unwrap()).collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0].get("version").and_then(|version| version.as_u64()), Some(2));
+        assert_eq!(lines[0].get("width").and_then(|width| width.as_u64()), Some(100));
+        let events: Vec<(&str, &str)> = lines[1..]
+            .iter()
+            .map(|line| {
+                let event = line.as_array().unwrap();
+                (event[1].as_str().unwrap(), event[2].as_str().unwrap())
+            })
+            .collect();
+        assert_eq!(events, [("o", "What do you do?\r\n"), ("i", "go\r"), ("o", "go\r\n")]);
+    }
+}
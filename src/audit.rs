@@ -0,0 +1,83 @@
+// Logs every change to some registers, a line each:
+//   701402 1800: r3 0 -> 1  eq r3 r4 10
+// giving how many instructions came before, the address of the instruction
+// that changed it, the old and new value and the instruction. Writes that
+// leave the value as it was aren't changes and aren't logged.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+use trace::{Step, Tracer};
+
+// The name the audit runs under (see Synacor::set_tracer).
+pub const NAME: &str = "audit";
+
+// Registers as a comma-separated list like "0,7" or "r0,r7".
+pub fn registers(list: &str) -> Result<Vec<usize>, String> {
+    list.split(',')
+        .map(|register| match register.trim().trim_start_matches('r').parse() {
+            Ok(index) if index < 8 => Ok(index),
+            _ => Err(format!("{:?} is not a register", register)),
+        })
+        .collect()
+}
+
+pub struct Audit<W: Write> {
+    registers: Vec<usize>,
+    file: W,
+    error: Option<io::Error>,
+}
+
+impl Audit<BufWriter<File>> {
+    pub fn create(registers: Vec<usize>, path: &str) -> io::Result<Audit<BufWriter<File>>> {
+        Ok(Audit::new(registers, BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Audit<W> {
+    pub fn new(registers: Vec<usize>, file: W) -> Audit<W> {
+        Audit { registers, file, error: None }
+    }
+    pub fn into_inner(self) -> W {
+        self.file
+    }
+}
+
+impl<W: Write> Tracer for Audit<W> {
+    fn step(&mut self, step: &Step) {
+        for &register in &self.registers {
+            let (old, new) = (step.before[register], step.after[register]);
+            if old != new && self.error.is_none() {
+                let instruction = step.disassembly();
+                self.error = writeln!(self.file, "{} {}: r{} {} -> {}  {}", step.count, step.pc, register, old, new, instruction).err();
+            }
+        }
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.file.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_changes() {
+        assert_eq!(registers("r0, 7"), Ok(vec![0, 7]));
+        assert!(registers("8").is_err());
+        let mut audit = Audit::new(vec![7], Vec::new());
+        let mut step = Step { count: 12, pc: 5451, opcode: 1, words: [32775, 25734, 0], before: [0; 8], after: [0; 8], next: 5454, stack_depth: 0 };
+        step.after[7] = 25734;
+        audit.step(&step);
+        step.count = 13;
+        step.before[7] = 25734;
+        audit.step(&step);
+        assert_eq!(String::from_utf8(audit.into_inner()).unwrap(), "12 5451: r7 0 -> 25734  set r7 25734\n");
+    }
+}
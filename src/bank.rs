@@ -0,0 +1,98 @@
+// Banked memory for programs bigger than the 15-bit address space: extra
+// pages of 32768 words, one at a time showing through a window over the
+// upper half of the 16-bit address space. `bank a` (opcode 22) picks the
+// page; rmem and wmem from 32768 up then reach it through the device bus
+// (see device.rs), so the window can't share that half with other devices.
+// Pages are allocated the first time they're chosen. Snapshots only hold
+// the 65536 words of memory, not the pages.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use device::{self, Device};
+use extension::OpcodeExtension;
+use synacor::{Synacor, SynacorErr};
+use types::Operand;
+
+pub const OPCODE: u16 = 22;
+pub const PAGE: usize = 32768;
+
+pub struct Banks {
+    pages: Vec<Option<Box<[u16]>>>,
+    current: usize,
+}
+
+impl Banks {
+    // Maps the window and adds the opcode for `count` pages.
+    pub fn enable(synacor: &mut Synacor, count: usize) -> Result<Rc<RefCell<Banks>>, String> {
+        if count == 0 || count > 32768 {
+            return Err(format!("{} isn't a number of banks between 1 and 32768", count));
+        }
+        let banks = Rc::new(RefCell::new(Banks { pages: (0..count).map(|_| None).collect(), current: 0 }));
+        synacor.attach(device::START, PAGE, Box::new(Window(banks.clone())))?;
+        synacor.add_extension(Box::new(Switch(banks.clone())));
+        Ok(banks)
+    }
+    pub fn current(&self) -> usize {
+        self.current
+    }
+    fn page(&mut self) -> &mut [u16] {
+        self.pages[self.current].get_or_insert_with(|| vec![0; PAGE].into_boxed_slice())
+    }
+}
+
+struct Window(Rc<RefCell<Banks>>);
+
+impl Device for Window {
+    fn read(&mut self, offset: u16) -> u16 {
+        self.0.borrow_mut().page()[offset as usize]
+    }
+    fn write(&mut self, offset: u16, word: u16) {
+        self.0.borrow_mut().page()[offset as usize] = word;
+    }
+}
+
+struct Switch(Rc<RefCell<Banks>>);
+
+impl OpcodeExtension for Switch {
+    fn operands(&self, opcode: u16) -> Option<usize> {
+        if opcode == OPCODE {
+            Some(1)
+        } else {
+            None
+        }
+    }
+    fn execute(&mut self, _: u16, operands: &[Operand], synacor: &mut Synacor) -> Result<(), SynacorErr> {
+        let bank = synacor.operand(operands[0]);
+        let mut banks = self.0.borrow_mut();
+        if bank as usize >= banks.pages.len() {
+            return Err(SynacorErr::BadBank(bank));
+        }
+        banks.current = bank as usize;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synacor::{Config, RunOutcome};
+    use Image;
+
+    #[test]
+    fn switches_pages_through_the_window() {
+        // r1 holds 40000, from address 30. Writes 5 to bank 0 and 6 to bank
+        // 1, then reads bank 0 back into r0 and asks for bank 2.
+        let mut words: Vec<u16> = vec![15, 32769, 30, 16, 32769, 5, 22, 1, 16, 32769, 6, 22, 0, 15, 32768, 32769, 22, 2];
+        words.resize(30, 0);
+        words.push(40000);
+        let mut synacor = Synacor::with_config(Config::default());
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let banks = Banks::enable(&mut synacor, 2).unwrap();
+        assert!(matches!(synacor.run(), RunOutcome::Faulted(SynacorErr::BadBank(2))));
+        assert_eq!(synacor.registers()[0], 5);
+        assert_eq!(banks.borrow().current(), 0);
+        banks.borrow_mut().current = 1;
+        assert_eq!(banks.borrow_mut().page()[40000 - PAGE], 6);
+    }
+}
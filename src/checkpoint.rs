@@ -0,0 +1,78 @@
+// Automatic checkpoints kept in a ring of save slots named auto-0, auto-1 and
+// so on, so that a crash or a bad move never costs more than a few turns.
+// They are ordinary slots and load with `/load auto-N`.
+
+use slots::Slots;
+use synacor::Synacor;
+
+pub const KEEP: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    // Every time the game waits for a line of input.
+    Prompt,
+    Every(u64),
+}
+
+pub struct Checkpoints {
+    slots: Slots,
+    keep: usize,
+    next: usize,
+    last: u64,
+}
+
+impl Checkpoints {
+    // Carries on the ring from an earlier session by overwriting its oldest
+    // checkpoint first.
+    pub fn new(slots: Slots, keep: usize) -> Checkpoints {
+        let keep = keep.max(1);
+        let mut saved = vec![None; keep];
+        for slot in slots.list().unwrap_or_default() {
+            if let Some(index) = slot.name.strip_prefix("auto-").and_then(|index| index.parse::<usize>().ok()) {
+                if index < keep {
+                    saved[index] = Some(slot.metadata.saved);
+                }
+            }
+        }
+        let next = (0..keep).min_by_key(|&index| saved[index]).unwrap_or(0);
+        Checkpoints { slots, keep, next, last: 0 }
+    }
+    pub fn name(index: usize) -> String {
+        format!("auto-{}", index)
+    }
+    // Saves into the next slot of the ring, only complaining on failure.
+    pub fn save(&mut self, synacor: &Synacor) {
+        let name = Checkpoints::name(self.next);
+        if let Err(err) = self.slots.save(synacor, &name, "checkpoint") {
+            notice!("Could not save the checkpoint {}: {}", name, err);
+        }
+        self.next = (self.next + 1) % self.keep;
+        self.last = synacor.instructions();
+    }
+    // How many instructions may run before the next checkpoint is due.
+    pub fn due_in(&self, synacor: &Synacor, every: u64) -> u64 {
+        (self.last + every).saturating_sub(synacor.instructions())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn resumes_after_the_newest_checkpoint() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("synacor-checkpoints-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (index, saved) in [(0, 30), (1, 40), (2, 10)].iter() {
+            fs::write(dir.join(format!("auto-{}.sav", index)), "").unwrap();
+            fs::write(dir.join(format!("auto-{}.meta", index)), format!("saved: {}\n", saved)).unwrap();
+        }
+        let fresh = Checkpoints::new(Slots::new(&dir), 4);
+        let full = Checkpoints::new(Slots::new(&dir), 3);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(fresh.next, 3);
+        assert_eq!(full.next, 2);
+    }
+}
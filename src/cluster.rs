@@ -0,0 +1,144 @@
+// Several VMs in one process, taking turns, with channels of words between
+// them. A channel has two ends, both devices (see device.rs): writing the
+// sending end's word queues it, and reading that word says how many are
+// queued. Reading the receiving end's first word takes the oldest word off
+// the queue, or 0 if there's none, and its second says how many are left,
+// so a program waiting on another polls the count.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use device::Device;
+use synacor::{RunOutcome, Synacor};
+
+// How many instructions each VM runs on its turn.
+pub const SLICE: u64 = 10_000;
+
+pub const SEND_LEN: usize = 1;
+pub const RECEIVE_LEN: usize = 2;
+// Where `pipeline` puts each VM's ends.
+pub const SEND: usize = 0xF000;
+pub const RECEIVE: usize = 0xF002;
+
+type Queue = Rc<RefCell<VecDeque<u16>>>;
+
+pub struct Sender(Queue);
+
+impl Device for Sender {
+    fn read(&mut self, _: u16) -> u16 {
+        self.0.borrow().len().min(32767) as u16
+    }
+    fn write(&mut self, _: u16, word: u16) {
+        self.0.borrow_mut().push_back(word);
+    }
+}
+
+pub struct Receiver(Queue);
+
+impl Device for Receiver {
+    fn read(&mut self, offset: u16) -> u16 {
+        let mut queue = self.0.borrow_mut();
+        match offset {
+            0 => queue.pop_front().unwrap_or(0),
+            _ => queue.len().min(32767) as u16,
+        }
+    }
+    fn write(&mut self, _: u16, _: u16) {}
+}
+
+pub fn channel() -> (Sender, Receiver) {
+    let queue = Queue::default();
+    (Sender(queue.clone()), Receiver(queue))
+}
+
+#[derive(Default)]
+pub struct Cluster {
+    pub vms: Vec<Synacor>,
+}
+
+impl Cluster {
+    // Connects `from`'s sending end at `send` to `to`'s receiving end at
+    // `receive`.
+    pub fn connect(&mut self, from: usize, send: usize, to: usize, receive: usize) -> Result<(), String> {
+        if from >= self.vms.len() || to >= self.vms.len() {
+            return Err(format!("there are only {} VMs", self.vms.len()));
+        }
+        let (sender, receiver) = channel();
+        self.vms[from].attach(send, SEND_LEN, Box::new(sender))?;
+        self.vms[to].attach(receive, RECEIVE_LEN, Box::new(receiver))
+    }
+    // Connects each VM to the next, through SEND and RECEIVE.
+    pub fn pipeline(vms: Vec<Synacor>) -> Result<Cluster, String> {
+        let mut cluster = Cluster { vms };
+        for from in 1..cluster.vms.len() {
+            cluster.connect(from - 1, SEND, from, RECEIVE)?;
+        }
+        Ok(cluster)
+    }
+    // Gives each VM a turn in order until every one has stopped, or those
+    // left are all waiting for input that nothing can give them. Hands back
+    // how each VM stopped, with None for those still waiting.
+    pub fn run(&mut self) -> Vec<Option<RunOutcome>> {
+        let mut outcomes: Vec<Option<RunOutcome>> = self.vms.iter().map(|_| None).collect();
+        loop {
+            let mut ran = false;
+            for (synacor, outcome) in self.vms.iter_mut().zip(&mut outcomes) {
+                if outcome.is_some() {
+                    continue;
+                }
+                let before = synacor.instructions();
+                match synacor.run_for(SLICE) {
+                    RunOutcome::BudgetExceeded => ran = true,
+                    RunOutcome::InputNeeded => ran |= synacor.instructions() != before,
+                    stopped => *outcome = Some(stopped),
+                }
+            }
+            if !ran {
+                return outcomes;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use output::Buffer;
+    use synacor::Config;
+    use Image;
+
+    const R0: u16 = 32768;
+    const R1: u16 = 32769;
+    const R2: u16 = 32770;
+    const R3: u16 = 32771;
+
+    fn vm(words: &[u16], output: Buffer) -> Synacor {
+        let mut synacor = Synacor::with_config(Config { output: Box::new(output), ..Config::default() });
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        synacor
+    }
+
+    #[test]
+    fn producer_feeds_consumer() {
+        // Sends 'a' to 'e' through the channel at 40000, kept at 21, then
+        // halts.
+        let producer = [
+            1, R0, 97, 15, R1, 21, 16, R1, R0, 9, R0, R0, 1, 4, R2, R0, 102, 8, R2, 6, 0, 40000,
+        ];
+        // Waits for a word at 50000 (kept at 25) while the count at 50001
+        // (kept at 26) is 0, prints it and stops after 'e'.
+        let consumer = [
+            15, R1, 25, 15, R3, 26, 15, R2, R3, 8, R2, 6, 15, R0, R1, 19, R0, 4, R2, R0, 101, 8, R2, 6, 0, 50000,
+            50001,
+        ];
+        let output = Buffer::default();
+        let mut cluster = Cluster { vms: vec![vm(&consumer, output.clone()), vm(&producer, Buffer::default())] };
+        cluster.connect(1, 40000, 0, 50000).unwrap();
+        assert!(cluster.connect(1, 40000, 0, 60000).is_err());
+        assert!(cluster.connect(2, 40000, 0, 50000).is_err());
+        let outcomes = cluster.run();
+        assert!(outcomes.iter().all(|outcome| matches!(outcome, Some(RunOutcome::Halted))));
+        assert_eq!(output.text(), "abcde");
+    }
+}
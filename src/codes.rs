@@ -0,0 +1,242 @@
+// Picks the challenge's codes out of the game's output. A code is a word of
+// twelve letters and digits with lower case letters and upper case past the
+// first in it, found where the game gives codes: after "code is:" or
+// "website:", in quotes (the tablet and the mirror), or on a line of its own
+// under one leading up to it (the passage wall, the stars and the sand).
+// Each new code is appended to a file with the instruction count it appeared
+// at and the room it was found in; codes already in the file are not counted
+// twice, so the tally carries over between sessions.
+//
+// Codes differ from one copy of the challenge to the next, so the expected
+// ones are kept as FNV-1a hashes (see hash.rs) in a file of their own, one
+// in hex per line with `#` starting a comment. `synacor codes hash` makes the
+// lines and `synacor codes check` compares the codes found against them.
+
+use std::cell::RefCell;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::rc::Rc;
+
+use hash;
+use regex::Regex;
+use trigger::Trigger;
+
+// The arch-spec holds one code and the game the other seven.
+pub const TOTAL: usize = 8;
+pub const HASHES: &str = "codes.hashes";
+
+pub fn is_code(word: &str) -> bool {
+    word.len() == 12
+        && word.bytes().all(|byte| byte.is_ascii_alphanumeric())
+        && word.bytes().skip(1).any(|byte| byte.is_ascii_uppercase())
+        && word.bytes().any(|byte| byte.is_ascii_lowercase())
+}
+
+// The last code is read in a mirror, so it shows up back to front and with
+// the letters that mirror into each other swapped.
+pub fn mirror(code: &str) -> String {
+    code.chars()
+        .rev()
+        .map(|c| match c {
+            'b' => 'd',
+            'd' => 'b',
+            'p' => 'q',
+            'q' => 'p',
+            c => c,
+        })
+        .collect()
+}
+
+// The code in a line like `Through the mirror, you see "bdqp" scrawled...`,
+// as shown and as it has to be entered.
+pub fn in_mirror(line: &str) -> Option<(&str, String)> {
+    let (_, after) = line.split_once("mirror, you see \"")?;
+    let (shown, _) = after.split_once('"')?;
+    if is_code(shown) {
+        Some((shown, mirror(shown)))
+    } else {
+        None
+    }
+}
+
+// Prints both readings of the code in the mirror as it appears, since
+// typing it as shown is the usual mistake.
+pub fn mirror_trigger() -> Trigger {
+    Trigger::new(Regex::new("mirror, you see").unwrap(), |fired| {
+        if let Some((shown, entered)) = in_mirror(fired.line) {
+            notice!("The mirror shows {}; read the right way round, the code is {}.", shown, entered);
+        }
+        None
+    })
+}
+
+// A line of the codes file.
+pub struct Found {
+    pub code: String,
+    pub at: u64,
+    pub context: String,
+}
+
+pub fn read(path: &str) -> io::Result<Vec<Found>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let found = text.lines().filter(|line| !line.is_empty()).map(|line| {
+        let mut fields = line.splitn(3, '\t');
+        let code = fields.next().unwrap_or("").to_string();
+        let at = fields.next().and_then(|at| at.parse().ok()).unwrap_or(0);
+        Found { code, at, context: fields.next().unwrap_or("").to_string() }
+    });
+    Ok(found.collect())
+}
+
+pub fn read_hashes(path: &str) -> Result<Vec<u64>, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let mut hashes = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if !line.is_empty() {
+            hashes.push(u64::from_str_radix(line, 16).map_err(|_| format!("line {}: {:?} is not a hex hash", number + 1, line))?);
+        }
+    }
+    Ok(hashes)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Valid,
+    // The code is valid read in a mirror, as this.
+    Mirrored(String),
+    Invalid,
+}
+
+// How a code measures up against the expected hashes.
+pub fn check(code: &str, hashes: &[u64]) -> Status {
+    let mirrored = mirror(code);
+    if hashes.contains(&hash::text(code)) {
+        Status::Valid
+    } else if hashes.contains(&hash::text(&mirrored)) {
+        Status::Mirrored(mirrored)
+    } else {
+        Status::Invalid
+    }
+}
+
+pub struct Codes {
+    path: String,
+    found: Vec<String>,
+    // The last room heading, e.g. `Foothills` for `== Foothills ==`.
+    room: String,
+    // Whether the last line that wasn't blank led up to a code on a line of
+    // its own, like `...a message in the sand here:`.
+    lead_in: bool,
+}
+
+impl Codes {
+    // Starts from the codes already recorded in `path`, if it exists.
+    pub fn open(path: &str) -> io::Result<Codes> {
+        let found = read(path)?.into_iter().map(|found| found.code).collect();
+        Ok(Codes { path: path.to_string(), found, room: String::new(), lead_in: false })
+    }
+    pub fn found(&self) -> &[String] {
+        &self.found
+    }
+    pub fn tally(&self) -> String {
+        format!("{}/{} codes found", self.found.len(), TOTAL)
+    }
+    // Looks for new codes in a line of output and records them.
+    pub fn scan(&mut self, line: &str, at: u64) -> io::Result<()> {
+        if let Some(room) = line.strip_prefix("== ").and_then(|line| line.strip_suffix(" ==")) {
+            self.room = room.to_string();
+        }
+        let mut candidates: Vec<&str> = ["code is: ", "website: "].iter().filter_map(|lead| line.split_once(lead)).map(|(_, after)| after.trim()).collect();
+        candidates.extend(line.split('"').skip(1).step_by(2));
+        if self.lead_in && line.starts_with("    ") {
+            candidates.push(line.trim());
+        }
+        if !line.trim().is_empty() {
+            self.lead_in = line.ends_with(':') || line.ends_with("...");
+        }
+        for word in candidates {
+            if !is_code(word) || self.found.iter().any(|code| code == word) {
+                continue;
+            }
+            let context = if self.room.is_empty() { line.trim() } else { &self.room };
+            let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            writeln!(file, "{}\t{}\t{}", word, at, context)?;
+            self.found.push(word.to_string());
+        }
+        Ok(())
+    }
+    // A trigger that feeds every line of output to `codes`.
+    pub fn trigger(codes: Rc<RefCell<Codes>>) -> Trigger {
+        let everything = Regex::new("").unwrap();
+        Trigger::new(everything, move |fired| {
+            let mut codes = codes.borrow_mut();
+            if let Err(err) = codes.scan(fired.line, fired.at) {
+                notice!("Could not record a code in {}: {}", codes.path, err);
+            }
+            None
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_codes() {
+        assert!(is_code("iMAHdtAGGtjT"));
+        assert!(is_code("cURRZySqnjPo"));
+        assert!(!is_code("instructions"));
+        assert!(!is_code("LDOb7UGhTi"));
+        assert!(!is_code("efFHYeYFHVGYx"));
+        assert!(!is_code("Headquarters"));
+    }
+
+    #[test]
+    fn finds_codes_only_where_the_game_gives_them() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("synacor-test-{}.codes", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let mut codes = Codes::open(&path).unwrap();
+        let output = [
+            "this one into the challenge website: iMAHdtAGGtjT",
+            "The self-test completion code is: cURRZySqnjPo",
+            "== Synacor Headquarters ==",
+            "You find yourself writing \"kcdbqFHsPXPa\" on the tablet.  Perhaps it's some kind of code?",
+            "Out of place, iMAHdtAGGtjX is not a code.",
+            "    NotaCodeHere",
+            "Someone seems to have drawn a message in the sand here:",
+            "",
+            "    wRQrgKDgXqBw",
+        ];
+        for (at, line) in output.iter().enumerate() {
+            codes.scan(line, at as u64).unwrap();
+        }
+        assert_eq!(codes.found(), ["iMAHdtAGGtjT", "cURRZySqnjPo", "kcdbqFHsPXPa", "wRQrgKDgXqBw"]);
+        let recorded = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((recorded[3].at, recorded[3].context.as_str()), (8, "Synacor Headquarters"));
+    }
+
+    #[test]
+    fn reads_the_mirror() {
+        let line = "Through the mirror, you see \"AWYpo88MXwxv\" scrawled in charcoal on your forehead.";
+        assert_eq!(in_mirror(line), Some(("AWYpo88MXwxv", "vxwXM88oqYWA".to_string())));
+        assert_eq!(in_mirror("You see \"AWYpo88MXwxv\" on the wall."), None);
+    }
+
+    #[test]
+    fn checks_against_hashes() {
+        assert_eq!(mirror("bdpqXY"), "YXpqbd");
+        let hashes = [hash::text("iMAHdtAGGtjT"), hash::text("Yxpqdb")];
+        assert_eq!(check("iMAHdtAGGtjT", &hashes), Status::Valid);
+        assert_eq!(check("dbpqxY", &hashes), Status::Mirrored("Yxpqdb".to_string()));
+        assert_eq!(check("cURRZySqnjPo", &hashes), Status::Invalid);
+    }
+}
@@ -0,0 +1,101 @@
+// Run-length compression for snapshots. Most of a saved machine is long runs
+// of zero words, which this squeezes down to a few bytes while leaving
+// everything else as it was.
+//
+// The compressed data is a sequence of blocks. A control byte below 0x80 is
+// followed by that many plus one literal bytes; 0x80 is followed by a byte
+// and a LEB128 count of how many times it repeats.
+
+const RUN: u8 = 0x80;
+const MAX_LITERAL: usize = 0x80;
+// Shorter runs cost more as a run block than as literals.
+const MIN_RUN: usize = 4;
+
+fn push_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn flush_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERAL) {
+        out.push(chunk.len() as u8 - 1);
+        out.extend_from_slice(chunk);
+    }
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literal_start = 0;
+    let mut at = 0;
+    while at < data.len() {
+        let run = data[at..].iter().take_while(|&&byte| byte == data[at]).count();
+        if run >= MIN_RUN {
+            flush_literals(&mut out, &data[literal_start..at]);
+            out.push(RUN);
+            out.push(data[at]);
+            push_varint(&mut out, run);
+            literal_start = at + run;
+        }
+        at += run;
+    }
+    flush_literals(&mut out, &data[literal_start..]);
+    out
+}
+
+// Fails on truncated data or if the result would be longer than `limit`.
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut bytes = data.iter().cloned();
+    while let Some(control) = bytes.next() {
+        if control == RUN {
+            let byte = bytes.next().ok_or("truncated run")?;
+            let mut count = 0usize;
+            for shift in (0..).step_by(7) {
+                let part = bytes.next().ok_or("truncated run length")?;
+                if shift > 56 {
+                    return Err("run length too long".to_string());
+                }
+                count |= ((part & 0x7f) as usize) << shift;
+                if part & 0x80 == 0 {
+                    break;
+                }
+            }
+            if out.len() + count > limit {
+                return Err("data too large".to_string());
+            }
+            out.resize(out.len() + count, byte);
+        } else if control < RUN {
+            let len = control as usize + 1;
+            if out.len() + len > limit {
+                return Err("data too large".to_string());
+            }
+            for _ in 0..len {
+                out.push(bytes.next().ok_or("truncated literal")?);
+            }
+        } else {
+            return Err(format!("bad control byte {:#x}", control));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut data = vec![1, 2, 3, 3, 3, 3, 3, 4];
+        data.resize(100_000, 0);
+        data.extend((0..=255).cycle().take(1000));
+        let compressed = compress(&data);
+        assert!(compressed.len() < 1100);
+        assert_eq!(decompress(&compressed, data.len()), Ok(data.clone()));
+        assert!(decompress(&compressed, data.len() - 1).is_err());
+        assert!(decompress(&compressed[..compressed.len() - 1], data.len()).is_err());
+        assert_eq!(decompress(&compress(&[]), 0), Ok(Vec::new()));
+    }
+}
@@ -0,0 +1,117 @@
+// Core files, written when the machine faults: the error, the addresses of
+// the last instructions executed and a full snapshot of the machine.
+//
+// The file is the magic bytes, the error as a u64 length and UTF-8 text, the
+// trace as a u64 count and u16 addresses, then the snapshot in its own
+// format.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+use disasm;
+use snapshot::Snapshot;
+use synacor::Synacor;
+
+const MAGIC: &[u8; 8] = b"SYNCORE1";
+pub const PATH: &str = "synacor.core";
+// How many instructions to keep for the trace.
+pub const TRACE: usize = 64;
+
+pub struct Core {
+    pub fault: String,
+    pub trace: Vec<u16>,
+    pub snapshot: Snapshot,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl Core {
+    pub fn new(synacor: &Synacor, fault: &str) -> Core {
+        Core { fault: fault.to_string(), trace: synacor.trace(), snapshot: synacor.snapshot() }
+    }
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&(self.fault.len() as u64).to_le_bytes())?;
+        file.write_all(self.fault.as_bytes())?;
+        file.write_all(&(self.trace.len() as u64).to_le_bytes())?;
+        for address in &self.trace {
+            file.write_all(&address.to_le_bytes())?;
+        }
+        self.snapshot.write_to(file)
+    }
+    pub fn load(path: &str) -> io::Result<Core> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a core file"));
+        }
+        let mut fault = vec![0; Core::len(&mut file)?];
+        file.read_exact(&mut fault)?;
+        let fault = String::from_utf8(fault).map_err(|_| invalid("the fault is not UTF-8"))?;
+        let mut trace = Vec::new();
+        for _ in 0..Core::len(&mut file)? {
+            let mut address = [0; 2];
+            file.read_exact(&mut address)?;
+            trace.push(u16::from_le_bytes(address));
+        }
+        let snapshot = Snapshot::read_from(file)?;
+        Ok(Core { fault, trace, snapshot })
+    }
+    fn len<R: Read>(reader: &mut R) -> io::Result<usize> {
+        let mut len = [0; 8];
+        reader.read_exact(&mut len)?;
+        match u64::from_le_bytes(len) {
+            len if len <= 1 << 20 => Ok(len as usize),
+            _ => Err(invalid("a length in the core file is too large")),
+        }
+    }
+    // A report of the machine at the time of the fault, for `inspect`.
+    pub fn report(&self) -> String {
+        let snapshot = &self.snapshot;
+        let read = |address: u16| snapshot.memory.word(address as usize);
+        let mut report = format!("fault: {}\n", self.fault);
+        report += &format!("pc: {}\n", snapshot.program_counter);
+        report += &format!("registers: {:?}\n", snapshot.registers);
+        report += &format!("stack ({} deep, top last): {:?}\n", snapshot.stack.len(), snapshot.stack);
+        report += &format!("instructions executed: {}\n", snapshot.instructions);
+        report += "last instructions:\n";
+        for &address in &self.trace {
+            report += &format!("  {:5}  {}\n", address, disasm::instruction(read, address).0);
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::Text;
+    use memory::Image;
+    use synacor::{Config, RunOutcome};
+
+    #[test]
+    fn faults_round_trip() {
+        let config = Config { input: Box::new(Text::default()), trace_depth: 2, ..Config::default() };
+        let mut synacor = Synacor::with_config(config);
+        let words: [u16; 5] = [21, 21, 19, 65, 99];
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let fault = match synacor.run() {
+            RunOutcome::Faulted(err) => err.to_string(),
+            _ => panic!("the program should fault"),
+        };
+        let mut path = std::env::temp_dir();
+        path.push(format!("synacor-test-{}.core", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        Core::new(&synacor, &fault).save(&path).unwrap();
+        let core = Core::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(core.trace, vec![2, 4]);
+        assert!(core.report().contains("      2  out 'A'\n      4  data 99\n"));
+    }
+}
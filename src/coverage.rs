@@ -0,0 +1,177 @@
+// Which words of memory have ever been executed, as an instruction or one of
+// its arguments, kept in a file across runs so that the parts of the game
+// never triggered stand out. The file has a range of covered words a line:
+//   0-1290
+//   1298-1301
+// and a lone address for a range of one.
+
+use std::fs;
+use std::io;
+
+use disasm;
+use trace::{Step, Tracer};
+
+// The name coverage runs under (see Synacor::set_tracer).
+pub const NAME: &str = "coverage";
+const WORDS: usize = 32768;
+// How many gaps a report lists.
+const GAPS: usize = 20;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Coverage {
+    covered: Vec<bool>,
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage { covered: vec![false; WORDS] }
+    }
+}
+
+impl Coverage {
+    pub fn parse(text: &str) -> Result<Coverage, String> {
+        let mut coverage = Coverage::default();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (start, end) = line.split_once('-').unwrap_or((line, line));
+            match (start.parse::<usize>(), end.parse::<usize>()) {
+                (Ok(start), Ok(end)) if start <= end && end < WORDS => coverage.covered[start..=end].iter_mut().for_each(|word| *word = true),
+                _ => return Err(format!("{:?} is not a range of addresses", line)),
+            }
+        }
+        Ok(coverage)
+    }
+    // The coverage in `path`, or none if there's no such file yet.
+    pub fn load(path: &str) -> Result<Coverage, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Coverage::parse(&text),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Coverage::default()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+    pub fn text(&self) -> String {
+        self.ranges()
+            .into_iter()
+            .map(|(start, end)| if start == end { format!("{}\n", start) } else { format!("{}-{}\n", start, end) })
+            .collect()
+    }
+    pub fn step(&mut self, step: &Step) {
+        let length = disasm::arguments(step.opcode).unwrap_or(0) + 1;
+        for offset in 0..length {
+            if let Some(word) = self.covered.get_mut(step.pc as usize + offset) {
+                *word = true;
+            }
+        }
+    }
+    pub fn merge(&mut self, other: &Coverage) {
+        for (word, &covered) in self.covered.iter_mut().zip(&other.covered) {
+            *word |= covered;
+        }
+    }
+    pub fn covered(&self, address: u16) -> bool {
+        self.covered.get(address as usize).cloned().unwrap_or(false)
+    }
+    // The runs of covered words, first and last.
+    pub fn ranges(&self) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+        for (address, _) in self.covered.iter().enumerate().filter(|&(_, &covered)| covered) {
+            match ranges.last_mut() {
+                Some(&mut (_, ref mut end)) if *end as usize + 1 == address => *end = address as u16,
+                _ => ranges.push((address as u16, address as u16)),
+            }
+        }
+        ranges
+    }
+    // The words between covered ones that never ran, biggest first. Some
+    // are data, but the rest is code no run has reached.
+    pub fn gaps(&self) -> Vec<(u16, u16)> {
+        let ranges = self.ranges();
+        let mut gaps: Vec<(u16, u16)> = ranges.windows(2).map(|pair| (pair[0].1 + 1, pair[1].0 - 1)).collect();
+        gaps.sort_by_key(|&(start, end)| (std::cmp::Reverse(end - start), start));
+        gaps
+    }
+    pub fn report<F: Fn(u16) -> u16>(&self, read: F) -> Vec<String> {
+        let ranges = self.ranges();
+        let (first, last) = match (ranges.first(), ranges.last()) {
+            (Some(&(first, _)), Some(&(_, last))) => (first, last),
+            _ => return vec!["Nothing covered.".to_string()],
+        };
+        let covered: usize = ranges.iter().map(|&(start, end)| (end - start) as usize + 1).sum();
+        let span = (last - first) as usize + 1;
+        let mut lines = vec![
+            format!("{} words covered in {} ranges from {} to {}.", covered, ranges.len(), first, last),
+            format!("{} words in between never ran ({:.2}% covered).", span - covered, covered as f64 * 100.0 / span as f64),
+            "Biggest gaps:".to_string(),
+        ];
+        for (start, end) in self.gaps().into_iter().take(GAPS) {
+            let (text, _) = disasm::instruction(&read, start);
+            lines.push(format!("  {:5}-{:<5} {:>5} words  {}", start, end, end - start + 1, text));
+        }
+        lines
+    }
+    // A disassembly of the code up to the last covered word, each line
+    // marked `*` if it ran. Uncovered words are disassembled as they come
+    // but never run into a covered instruction, so that stays in step.
+    pub fn listing<F: Fn(u16) -> u16>(&self, read: F) -> String {
+        let end = self.ranges().last().map_or(0, |&(_, end)| end as usize + 1);
+        let mut listing = String::new();
+        let mut address = 0;
+        while address < end {
+            let covered = self.covered[address];
+            let (mut text, mut length) = disasm::instruction(&read, address as u16);
+            if !covered && (1..length as usize).any(|offset| self.covered(address as u16 + offset as u16)) {
+                text = format!("data {}", read(address as u16));
+                length = 1;
+            }
+            listing += &format!("{} {:5}  {}\n", if covered { '*' } else { ' ' }, address, text);
+            address += length as usize;
+        }
+        listing
+    }
+}
+
+// Collects coverage for a run and adds it to the file when the run ends.
+pub struct Collector {
+    coverage: Coverage,
+    path: String,
+}
+
+impl Collector {
+    pub fn new(path: &str) -> Collector {
+        Collector { coverage: Coverage::default(), path: path.to_string() }
+    }
+}
+
+impl Tracer for Collector {
+    fn step(&mut self, step: &Step) {
+        self.coverage.step(step);
+    }
+    // Reads the file again, in case another run has added to it since.
+    fn finish(&mut self) -> io::Result<()> {
+        let mut coverage = Coverage::load(&self.path).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        coverage.merge(&self.coverage);
+        fs::write(&self.path, coverage.text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_and_lists() {
+        let mut coverage = Coverage::default();
+        let step = |pc: u16, opcode: u16| Step { count: 0, pc, opcode, words: [0; 3], before: [0; 8], after: [0; 8], next: 0, stack_depth: 0 };
+        coverage.step(&step(0, 9));
+        coverage.step(&step(7, 21));
+        coverage.step(&step(8, 0));
+        assert_eq!(coverage.text(), "0-3\n7-8\n");
+        assert_eq!(Coverage::parse(&coverage.text()), Ok(coverage.clone()));
+        assert!(Coverage::parse("5-2").is_err());
+        // 4 looks like an add that would run into 7.
+        let memory = [9, 32768, 32768, 1, 9, 32768, 5, 21, 0];
+        let read = |address: u16| memory.get(address as usize).cloned().unwrap_or(0);
+        assert_eq!(coverage.listing(read), "*     0  add r0 r0 1\n      4  data 9\n      5  data 32768\n      6  data 5\n*     7  noop\n*     8  halt\n");
+        assert_eq!(coverage.report(read)[..4], ["6 words covered in 2 ranges from 0 to 8.", "3 words in between never ran (66.67% covered).",
+                                                "Biggest gaps:", "      4-6         3 words  add r0 5 21"]);
+    }
+}
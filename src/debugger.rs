@@ -0,0 +1,394 @@
+// Breakpoints and watchpoints, for the full-screen frontend. A breakpoint
+// stops before the instruction at its address runs; a watchpoint stops
+// before an instruction reads (rmem) or writes (wmem) its word of memory.
+// Either can have a condition, and only stops when it holds:
+//   r0 == 3 && [2732] != 0
+// Conditions are expressions over numbers (decimal, or hex after `0x`), the
+// registers r0-r7, pc, the word at [ADDRESS] and, loosest last,
+//   ! -   * / %   + -   < <= > >=   == !=   &   |   &&   ||
+// with parentheses to group. Comparisons give 1 or 0, and anything but 0
+// holds. The same expressions can be watched, like a debugger's `display`:
+// worked out again at every stop, noting which changed.
+
+use synacor::{RunOutcome, Synacor};
+
+const WORDS: i64 = 32768;
+// The binary operators, loosest first.
+const LEVELS: [&[&str]; 8] = [&["||"], &["&&"], &["|"], &["&"], &["==", "!="], &["<=", ">=", "<", ">"], &["+", "-"], &["*", "/", "%"]];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Register(usize),
+    Pc,
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+fn tokens(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&character) = chars.peek() {
+        if character.is_whitespace() {
+            chars.next();
+        } else if character.is_ascii_alphanumeric() {
+            let mut word = String::new();
+            while let Some(&character) = chars.peek().filter(|character| character.is_ascii_alphanumeric()) {
+                word.push(character);
+                chars.next();
+            }
+            tokens.push(word);
+        } else {
+            chars.next();
+            let pair: String = [character].iter().chain(chars.peek()).collect();
+            if ["==", "!=", "<=", ">=", "&&", "||"].contains(&pair.as_str()) {
+                chars.next();
+                tokens.push(pair);
+            } else if "+-*/%&|<>!()[]".contains(character) {
+                tokens.push(character.to_string());
+            } else {
+                return Err(format!("{:?} is not allowed", character));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    at: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<&str> {
+        self.at += 1;
+        self.tokens.get(self.at - 1).map(String::as_str)
+    }
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.at).map(String::as_str)
+    }
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.next() {
+            Some(next) if next == token => Ok(()),
+            Some(next) => Err(format!("expected {} but found {}", token, next)),
+            None => Err(format!("expected {} at the end", token)),
+        }
+    }
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(&operator) = LEVELS[level].iter().find(|&&operator| self.peek() == Some(operator)) {
+            self.next();
+            left = Expr::Binary(operator, Box::new(left), Box::new(self.binary(level + 1)?));
+        }
+        Ok(left)
+    }
+    fn unary(&mut self) -> Result<Expr, String> {
+        let token = self.next().ok_or("the expression ends too soon")?.to_string();
+        Ok(match token.as_str() {
+            "!" => Expr::Not(Box::new(self.unary()?)),
+            "-" => Expr::Negate(Box::new(self.unary()?)),
+            "(" => {
+                let inner = self.binary(0)?;
+                self.expect(")")?;
+                inner
+            }
+            "[" => {
+                let address = self.binary(0)?;
+                self.expect("]")?;
+                Expr::Memory(Box::new(address))
+            }
+            "pc" => Expr::Pc,
+            _ => {
+                let register = token.strip_prefix('r').and_then(|index| index.parse().ok()).filter(|&index: &usize| index < 8);
+                let number = match token.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                    None => token.parse().ok(),
+                };
+                match (register, number) {
+                    (Some(index), _) => Expr::Register(index),
+                    (_, Some(number)) => Expr::Number(number),
+                    _ => return Err(format!("{:?} is not a number or register", token)),
+                }
+            }
+        })
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser = Parser { tokens: tokens(text)?, at: 0 };
+        let expr = parser.binary(0)?;
+        match parser.peek() {
+            Some(extra) => Err(format!("{} was not expected", extra)),
+            None => Ok(expr),
+        }
+    }
+    // The value on `synacor`, or None if it divides by 0 or reads past the
+    // end of memory.
+    pub fn eval(&self, synacor: &Synacor) -> Option<i64> {
+        Some(match *self {
+            Expr::Number(number) => number,
+            Expr::Register(index) => synacor.registers()[index] as i64,
+            Expr::Pc => synacor.program_counter() as i64,
+            Expr::Memory(ref address) => match address.eval(synacor)? {
+                address @ 0..=32767 => synacor.memory(address as u16) as i64,
+                _ => return None,
+            },
+            Expr::Not(ref inner) => (inner.eval(synacor)? == 0) as i64,
+            Expr::Negate(ref inner) => inner.eval(synacor)?.wrapping_neg(),
+            Expr::Binary(operator, ref left, ref right) => {
+                let left = left.eval(synacor)?;
+                // Only looks right if it matters.
+                match operator {
+                    "&&" if left == 0 => return Some(0),
+                    "||" if left != 0 => return Some(1),
+                    _ => (),
+                }
+                let right = right.eval(synacor)?;
+                match operator {
+                    "&&" | "||" => (right != 0) as i64,
+                    "|" => left | right,
+                    "&" => left & right,
+                    "==" => (left == right) as i64,
+                    "!=" => (left != right) as i64,
+                    "<=" => (left <= right) as i64,
+                    ">=" => (left >= right) as i64,
+                    "<" => (left < right) as i64,
+                    ">" => (left > right) as i64,
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    "/" => left.checked_div(right)?,
+                    _ => left.checked_rem(right)?,
+                }
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Break,
+    Read,
+    Write,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 3] = [Kind::Break, Kind::Read, Kind::Write];
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Break => "break",
+            Kind::Read => "read",
+            Kind::Write => "write",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Point {
+    pub kind: Kind,
+    pub address: u16,
+    // The condition as typed, and parsed.
+    pub condition: Option<(String, Expr)>,
+    pub enabled: bool,
+    pub hits: u64,
+}
+
+impl Point {
+    // A point at `address`, stopping when `condition` holds or, if it's
+    // blank, every time.
+    pub fn new(kind: Kind, address: u16, condition: &str) -> Result<Point, String> {
+        if address as i64 >= WORDS {
+            return Err(format!("{} is past the end of memory", address));
+        }
+        let condition = match condition.trim() {
+            "" => None,
+            text => Some((text.to_string(), Expr::parse(text)?)),
+        };
+        Ok(Point { kind, address, condition, enabled: true, hits: 0 })
+    }
+    pub fn describe(&self) -> String {
+        match self.condition {
+            Some((ref text, _)) => format!("{} {} if {}", self.kind.name(), self.address, text),
+            None => format!("{} {}", self.kind.name(), self.address),
+        }
+    }
+    // Whether the next instruction on `synacor` gets to this point.
+    fn reached(&self, synacor: &Synacor) -> bool {
+        let pc = synacor.program_counter();
+        let value = |offset: u16| match synacor.memory(pc.wrapping_add(offset)) {
+            word @ 32768..=32775 => synacor.registers()[word as usize - 32768],
+            word => word,
+        };
+        match (self.kind, synacor.memory(pc)) {
+            (Kind::Break, _) => pc == self.address,
+            (Kind::Read, 15) => value(2) == self.address,
+            (Kind::Write, 16) => value(1) == self.address,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watch {
+    pub text: String,
+    expr: Expr,
+    // None if it can't be worked out.
+    pub value: Option<i64>,
+    // Whether the value changed at the last refresh.
+    pub changed: bool,
+}
+
+pub enum Stop {
+    // Stopped at the point with this index.
+    Hit(usize),
+    Outcome(RunOutcome),
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    pub points: Vec<Point>,
+    pub watches: Vec<Watch>,
+    // Where the last stop was, so carrying on from there gets past it.
+    passing: Option<u16>,
+}
+
+impl Debugger {
+    // Adds a breakpoint at `address`, or takes away those already there.
+    pub fn toggle_break(&mut self, address: u16) {
+        let before = self.points.len();
+        self.points.retain(|point| point.kind != Kind::Break || point.address != address);
+        if self.points.len() == before {
+            self.points.push(Point { kind: Kind::Break, address, condition: None, enabled: true, hits: 0 });
+        }
+    }
+    // Whether there's a breakpoint at `address`: Some(true) if one's
+    // enabled, Some(false) if they're all disabled.
+    pub fn break_at(&self, address: u16) -> Option<bool> {
+        let mut at = self.points.iter().filter(|point| point.kind == Kind::Break && point.address == address).peekable();
+        at.peek()?;
+        Some(at.any(|point| point.enabled))
+    }
+    // The point the next instruction stops at, if any. One whose condition
+    // can't be worked out stops too, to show it's wrong.
+    pub fn check(&mut self, synacor: &Synacor) -> Option<usize> {
+        let pc = synacor.program_counter();
+        if self.passing.take() == Some(pc) {
+            return None;
+        }
+        let hit = self.points.iter().position(|point| {
+            point.enabled && point.reached(synacor) && point.condition.as_ref().is_none_or(|(_, expr)| expr.eval(synacor) != Some(0))
+        })?;
+        self.points[hit].hits += 1;
+        self.passing = Some(pc);
+        Some(hit)
+    }
+    // Runs at most `budget` instructions, stopping at points.
+    pub fn run_for(&mut self, synacor: &mut Synacor, budget: u64) -> Stop {
+        if !self.points.iter().any(|point| point.enabled) {
+            self.passing = None;
+            return Stop::Outcome(synacor.run_for(budget));
+        }
+        for _ in 0..budget {
+            if let Some(hit) = self.check(synacor) {
+                return Stop::Hit(hit);
+            }
+            if let Err(outcome) = synacor.run_optcode() {
+                return Stop::Outcome(outcome);
+            }
+        }
+        Stop::Outcome(RunOutcome::BudgetExceeded)
+    }
+    pub fn watch(&mut self, text: &str, synacor: &Synacor) -> Result<(), String> {
+        let expr = Expr::parse(text)?;
+        let value = expr.eval(synacor);
+        self.watches.push(Watch { text: text.trim().to_string(), expr, value, changed: false });
+        Ok(())
+    }
+    // Works the watches out again, after a stop.
+    pub fn refresh(&mut self, synacor: &Synacor) {
+        for watch in &mut self.watches {
+            let value = watch.expr.eval(synacor);
+            watch.changed = value != watch.value;
+            watch.value = value;
+        }
+    }
+    // Runs one instruction, whatever points are on it.
+    pub fn step(&mut self, synacor: &mut Synacor) -> Result<(), RunOutcome> {
+        self.passing = None;
+        synacor.run_optcode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::Image;
+    use output::Null;
+    use synacor::Config;
+
+    fn machine(words: &[u16]) -> Synacor {
+        let mut synacor = Synacor::with_config(Config { output: Box::new(Null), ..Config::default() });
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        synacor
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        // set r0 3; halt
+        let mut synacor = machine(&[1, 32768, 3, 0]);
+        synacor.run_optcode().ok().unwrap();
+        let eval = |text: &str| Expr::parse(text).unwrap().eval(&synacor);
+        assert_eq!(eval("r0 == 3 && [1] == 0x8000"), Some(1));
+        assert_eq!(eval("1 + 2 * (r0 - 1) % 3"), Some(2));
+        assert_eq!(eval("!pc || -r0 < 0"), Some(1));
+        assert_eq!(eval("r0 / 0"), None);
+        assert_eq!(eval("0 && r0 / 0"), Some(0));
+        assert_eq!(Expr::parse("r8"), Err("\"r8\" is not a number or register".to_string()));
+        assert_eq!(Expr::parse("(1"), Err("expected ) at the end".to_string()));
+        assert_eq!(Expr::parse("1 2"), Err("2 was not expected".to_string()));
+    }
+
+    #[test]
+    fn stops_at_points() {
+        // add r0 r0 1; wmem 100 r0; jmp 0
+        let mut synacor = machine(&[9, 32768, 32768, 1, 16, 100, 32768, 6, 0]);
+        let mut debugger = Debugger::default();
+        debugger.points.push(Point::new(Kind::Write, 100, "r0 >= 2").unwrap());
+        debugger.toggle_break(7);
+        debugger.points[1].enabled = false;
+        assert_eq!(debugger.break_at(7), Some(false));
+        assert!(matches!(debugger.run_for(&mut synacor, 100), Stop::Hit(0)));
+        assert_eq!((synacor.program_counter(), synacor.registers()[0]), (4, 2));
+        debugger.points[1].enabled = true;
+        assert!(matches!(debugger.run_for(&mut synacor, 100), Stop::Hit(1)));
+        assert!(matches!(debugger.run_for(&mut synacor, 100), Stop::Hit(0)));
+        assert_eq!((debugger.points[0].hits, debugger.points[1].hits, synacor.registers()[0]), (2, 1, 3));
+        debugger.toggle_break(7);
+        assert_eq!(debugger.points.len(), 1);
+        assert_eq!(debugger.points[0].describe(), "write 100 if r0 >= 2");
+    }
+
+    #[test]
+    fn watches_what_changes() {
+        // add r0 r0 1; jmp 0
+        let mut synacor = machine(&[9, 32768, 32768, 1, 6, 0]);
+        let mut debugger = Debugger::default();
+        debugger.watch("r0 * 2", &synacor).unwrap();
+        debugger.watch(" [r0 + 32767] ", &synacor).unwrap();
+        assert!(debugger.watch("r0 +", &synacor).is_err());
+        debugger.step(&mut synacor).ok().unwrap();
+        debugger.refresh(&synacor);
+        let shown = |debugger: &Debugger| debugger.watches.iter().map(|watch| (watch.value, watch.changed)).collect::<Vec<_>>();
+        assert_eq!(shown(&debugger), [(Some(2), true), (None, true)]);
+        debugger.step(&mut synacor).ok().unwrap();
+        debugger.refresh(&synacor);
+        assert_eq!(shown(&debugger), [(Some(2), false), (None, false)]);
+        assert_eq!(debugger.watches[1].text, "[r0 + 32767]");
+    }
+}
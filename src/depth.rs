@@ -0,0 +1,99 @@
+// How deep the value stack and the calls go over a run, as CSV for
+// plotting:
+//   instructions,stack,calls
+//   1000,3,1
+//   2000,5,2
+// A row for every `interval` instructions, with the deepest each got since
+// the last row, so short spikes still show. Calls are counted from when the
+// sampling started, and returns with no call to match are left out.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+use trace::{Step, Tracer};
+
+// The name the sampling runs under (see Synacor::set_tracer).
+pub const NAME: &str = "stack depth";
+// Instructions to a row unless asked for something else.
+pub const INTERVAL: u64 = 1000;
+
+pub struct Depth<W: Write> {
+    file: W,
+    interval: u64,
+    calls: usize,
+    // The deepest the stack and calls got since the last row, and when the
+    // next row is due.
+    peak: Option<(usize, usize)>,
+    due: u64,
+    last: u64,
+    error: Option<io::Error>,
+}
+
+impl Depth<BufWriter<File>> {
+    pub fn create(path: &str, interval: u64) -> io::Result<Depth<BufWriter<File>>> {
+        Ok(Depth::new(BufWriter::new(File::create(path)?), interval))
+    }
+}
+
+impl<W: Write> Depth<W> {
+    pub fn new(mut file: W, interval: u64) -> Depth<W> {
+        let error = writeln!(file, "instructions,stack,calls").err();
+        Depth { file, interval: interval.max(1), calls: 0, peak: None, due: 0, last: 0, error }
+    }
+    fn row(&mut self, at: u64) {
+        if let (Some((stack, calls)), None) = (self.peak.take(), self.error.as_ref()) {
+            self.error = writeln!(self.file, "{},{},{}", at, stack, calls).err();
+        }
+    }
+    pub fn into_inner(self) -> W {
+        self.file
+    }
+}
+
+impl<W: Write> Tracer for Depth<W> {
+    fn step(&mut self, step: &Step) {
+        let count = step.count + 1;
+        self.last = count;
+        if self.peak.is_none() {
+            self.due = (count / self.interval + 1) * self.interval;
+        }
+        match step.opcode {
+            17 => self.calls += 1,
+            18 => self.calls = self.calls.saturating_sub(1),
+            _ => (),
+        }
+        let (stack, calls) = self.peak.unwrap_or((0, 0));
+        self.peak = Some((stack.max(step.stack_depth), calls.max(self.calls)));
+        if count >= self.due {
+            self.row(count);
+        }
+    }
+    // The last row, for however far the run got into the interval.
+    fn finish(&mut self) -> io::Result<()> {
+        self.row(self.last);
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.file.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_the_peaks() {
+        let mut depth = Depth::new(Vec::new(), 2);
+        let step = |count: u64, opcode: u16, stack_depth: usize| Step { count, pc: 0, opcode, words: [0; 3], before: [0; 8], after: [0; 8], next: 0, stack_depth };
+        depth.step(&step(0, 17, 1));
+        depth.step(&step(1, 17, 2));
+        depth.step(&step(2, 18, 1));
+        depth.step(&step(3, 2, 2));
+        depth.step(&step(4, 18, 1));
+        depth.finish().unwrap();
+        assert_eq!(String::from_utf8(depth.into_inner()).unwrap(), "instructions,stack,calls\n2,2,2\n4,2,1\n5,1,0\n");
+    }
+}
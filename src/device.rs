@@ -0,0 +1,257 @@
+// Devices mapped into the addresses past the spec's 32768 words, for
+// programs written for this VM. rmem and wmem on an address a device has
+// claimed go to the device instead of memory; everything else, including
+// the host's own peeks and pokes, still sees plain memory. Strict mode has
+// no addresses past 32768, so it never reaches a device.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The first address a device can claim.
+pub const START: usize = 32768;
+// One past the last.
+pub const END: usize = 65536;
+
+pub trait Device {
+    // A read of the word `offset` words into the device's range.
+    fn read(&mut self, offset: u16) -> u16;
+    fn write(&mut self, offset: u16, word: u16);
+}
+
+#[derive(Default)]
+pub struct Bus {
+    // Start and length of each device's range, which don't overlap.
+    devices: Vec<(usize, usize, Box<dyn Device>)>,
+}
+
+impl Bus {
+    // Gives `device` the `len` words from `start`.
+    pub fn attach(&mut self, start: usize, len: usize, device: Box<dyn Device>) -> Result<(), String> {
+        if start < START || len == 0 || start + len > END {
+            return Err(format!("devices go between {} and {}", START, END - 1));
+        }
+        if let Some(&(other, other_len, _)) =
+            self.devices.iter().find(|&&(other, other_len, _)| start < other + other_len && other < start + len)
+        {
+            return Err(format!("{} to {} is already taken", other, other + other_len - 1));
+        }
+        self.devices.push((start, len, device));
+        Ok(())
+    }
+    // The start and length of each range that has been claimed.
+    pub fn ranges(&self) -> Vec<(usize, usize)> {
+        self.devices.iter().map(|&(start, len, _)| (start, len)).collect()
+    }
+    fn find(&mut self, address: usize) -> Option<(&mut Box<dyn Device>, u16)> {
+        if address < START {
+            return None;
+        }
+        self.devices
+            .iter_mut()
+            .find(|&&mut (start, len, _)| address >= start && address < start + len)
+            .map(|&mut (start, _, ref mut device)| (device, (address - start) as u16))
+    }
+    // What the device at `address` reads as, or None if no device is there.
+    pub fn read(&mut self, address: usize) -> Option<u16> {
+        self.find(address).map(|(device, offset)| device.read(offset))
+    }
+    // Whether there was a device at `address` to take the word.
+    pub fn write(&mut self, address: usize, word: u16) -> bool {
+        match self.find(address) {
+            Some((device, offset)) => {
+                device.write(offset, word);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Random numbers from a seeded PRNG (splitmix64), so a run can be played
+// again exactly by giving it the same seed. Reading the word gives the next
+// 15-bit number; writing it reseeds.
+pub struct Rng {
+    seed: u64,
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { seed, state: seed }
+    }
+    // A seed from the clock, for when nobody asked for one.
+    pub fn clock_seed() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_nanos() as u64).unwrap_or(0)
+    }
+    // The seed it started from or was last given.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn draw(&mut self) -> u16 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 49) as u16
+    }
+}
+
+impl Device for Rng {
+    fn read(&mut self, _: u16) -> u16 {
+        self.draw()
+    }
+    fn write(&mut self, _: u16, word: u16) {
+        *self = Rng::new(word as u64);
+    }
+}
+
+// Words in a sector of Storage.
+pub const SECTOR_WORDS: u64 = 256;
+
+// Block storage kept in a host file, a little-endian word at a time like a
+// ROM image. Its three words are the sector, the offset into the sector and
+// the data: reading or writing the data reads or writes the word at the
+// sector and offset and moves the offset on by one. Words past the end of
+// the file read as 0 and writing one grows the file.
+pub struct Storage {
+    file: File,
+    sector: u16,
+    offset: u16,
+}
+
+impl Storage {
+    pub const SECTOR: u16 = 0;
+    pub const OFFSET: u16 = 1;
+    pub const DATA: u16 = 2;
+    pub const LEN: usize = 3;
+
+    pub fn open(path: &str) -> io::Result<Storage> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        Ok(Storage { file, sector: 0, offset: 0 })
+    }
+    // Seeks to the current word and moves the offset on, wrapping within
+    // the sector.
+    fn seek(&mut self) -> io::Result<()> {
+        let word = self.sector as u64 * SECTOR_WORDS + self.offset as u64;
+        self.offset = ((self.offset as u64 + 1) % SECTOR_WORDS) as u16;
+        self.file.seek(SeekFrom::Start(word * 2)).map(|_| ())
+    }
+    fn read_word(&mut self) -> io::Result<u16> {
+        self.seek()?;
+        let mut bytes = [0; 2];
+        let mut read = 0;
+        while read < 2 {
+            match self.file.read(&mut bytes[read..])? {
+                0 => break,
+                count => read += count,
+            }
+        }
+        Ok(u16::from_le_bytes(bytes))
+    }
+    fn write_word(&mut self, word: u16) -> io::Result<()> {
+        self.seek()?;
+        self.file.write_all(&word.to_le_bytes())
+    }
+}
+
+impl Device for Storage {
+    fn read(&mut self, offset: u16) -> u16 {
+        match offset {
+            Storage::SECTOR => self.sector,
+            Storage::OFFSET => self.offset,
+            _ => self.read_word().unwrap_or_else(|err| {
+                notice!("Could not read from storage: {}", err);
+                0
+            }),
+        }
+    }
+    fn write(&mut self, offset: u16, word: u16) {
+        match offset {
+            Storage::SECTOR => self.sector = word,
+            Storage::OFFSET => self.offset = (word as u64 % SECTOR_WORDS) as u16,
+            _ => {
+                if let Err(err) = self.write_word(word) {
+                    notice!("Could not write to storage: {}", err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synacor::{Config, RunOutcome, Synacor};
+    use Image;
+
+    // Reads back the sum of everything written to it.
+    #[derive(Default)]
+    struct Adder(u16);
+
+    impl Device for Adder {
+        fn read(&mut self, offset: u16) -> u16 {
+            self.0 + offset
+        }
+        fn write(&mut self, _: u16, word: u16) {
+            self.0 += word;
+        }
+    }
+
+    #[test]
+    fn maps_devices_past_the_spec() {
+        let mut bus = Bus::default();
+        assert!(bus.attach(100, 4, Box::new(Adder::default())).is_err());
+        assert!(bus.attach(40000, 4, Box::new(Adder::default())).is_ok());
+        assert!(bus.attach(39998, 3, Box::new(Adder::default())).is_err());
+        assert_eq!(bus.ranges(), [(40000, 4)]);
+
+        // Addresses past 32767 can only come from registers, so they're kept
+        // at 20 to 22: wmem 40000 5; wmem 40003 2; rmem r0 40001; halt
+        let words: [u16; 23] = [
+            15, 32769, 20, 16, 32769, 5, 15, 32770, 21, 16, 32770, 2, 15, 32771, 22, 15, 32768, 32771, 0, 0, 40000,
+            40003, 40001,
+        ];
+        let mut synacor = Synacor::with_config(Config::default());
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        synacor.attach(40000, 4, Box::new(Adder::default())).unwrap();
+        assert!(matches!(synacor.run(), RunOutcome::Halted));
+        assert_eq!(synacor.registers()[0], 8);
+        assert_eq!(synacor.memory(40000), 0);
+    }
+
+    #[test]
+    fn rng_replays_from_its_seed() {
+        let mut rng = Rng::new(7);
+        let first: Vec<u16> = (0..8).map(|_| rng.draw()).collect();
+        assert!(first.iter().all(|&word| word < 32768));
+        assert!(first.windows(2).any(|pair| pair[0] != pair[1]));
+        rng.write(0, 7);
+        assert_eq!((0..8).map(|_| rng.read(0)).collect::<Vec<_>>(), first);
+        assert_eq!(rng.seed(), 7);
+    }
+
+    #[test]
+    fn storage_keeps_words_in_a_file() {
+        let path = ::std::env::temp_dir().join(format!("synacor-storage-{}", ::std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut storage = Storage::open(path).unwrap();
+        storage.write(Storage::SECTOR, 2);
+        storage.write(Storage::OFFSET, 255);
+        storage.write(Storage::DATA, 7);
+        storage.write(Storage::DATA, 8);
+        assert_eq!(storage.read(Storage::OFFSET), 1);
+
+        let mut storage = Storage::open(path).unwrap();
+        storage.write(Storage::SECTOR, 2);
+        storage.write(Storage::OFFSET, 255);
+        assert_eq!(storage.read(Storage::DATA), 7);
+        assert_eq!(storage.read(Storage::DATA), 8);
+        storage.write(Storage::SECTOR, 9);
+        assert_eq!(storage.read(Storage::DATA), 0);
+        assert_eq!(::std::fs::metadata(path).unwrap().len(), (2 * SECTOR_WORDS + 256) * 2);
+        ::std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+// Turns instructions back into text, e.g. `add r0 r1 4` or `out 'A'`, and
+// text into instructions.
+
+use std::collections::BTreeMap;
+
+use bank;
+use magic;
+
+const NAMES: [(&str, usize); 22] = [
+    ("halt", 0),
+    ("set", 2),
+    ("push", 1),
+    ("pop", 1),
+    ("eq", 3),
+    ("gt", 3),
+    ("jmp", 1),
+    ("jt", 2),
+    ("jf", 2),
+    ("add", 3),
+    ("mult", 3),
+    ("mod", 3),
+    ("and", 3),
+    ("or", 3),
+    ("not", 2),
+    ("rmem", 2),
+    ("wmem", 2),
+    ("call", 1),
+    ("ret", 0),
+    ("out", 1),
+    ("in", 1),
+    ("noop", 0),
+];
+
+// Opcodes past 21 that this VM can be given (see extension.rs).
+const EXTENDED: [(u16, &str, usize); 2] = [(bank::OPCODE, "bank", 1), (magic::OPCODE, "debug", 2)];
+
+fn entry(opcode: u16) -> Option<(&'static str, usize)> {
+    NAMES.get(opcode as usize).cloned().or_else(|| {
+        EXTENDED.iter().find(|&&(known, _, _)| known == opcode).map(|&(_, name, arguments)| (name, arguments))
+    })
+}
+
+fn operand(word: u16) -> String {
+    match word {
+        0..=32767 => word.to_string(),
+        32768..=32775 => format!("r{}", word - 32768),
+        _ => format!("<{}>", word),
+    }
+}
+
+// How many arguments an opcode takes, or None if it isn't one.
+pub fn arguments(opcode: u16) -> Option<usize> {
+    entry(opcode).map(|(_, arguments)| arguments)
+}
+
+pub fn name(opcode: u16) -> Option<&'static str> {
+    entry(opcode).map(|(name, _)| name)
+}
+
+// The opcode with the mnemonic `name`.
+pub fn opcode(name: &str) -> Option<u16> {
+    NAMES
+        .iter()
+        .position(|&(known, _)| known == name)
+        .map(|opcode| opcode as u16)
+        .or_else(|| EXTENDED.iter().find(|&&(_, known, _)| known == name).map(|&(opcode, _, _)| opcode))
+}
+
+// Disassembles the instruction at `address`, returning its text and length
+// in words. Words that aren't opcodes come out as `data N`.
+pub fn instruction<F: Fn(u16) -> u16>(read: F, address: u16) -> (String, u16) {
+    let opcode = read(address);
+    let (name, arguments) = match entry(opcode) {
+        Some(entry) => entry,
+        None => return (format!("data {}", opcode), 1),
+    };
+    let mut text = name.to_string();
+    for index in 1..=arguments as u16 {
+        let word = read(address.wrapping_add(index));
+        text.push(' ');
+        match word {
+            32..=126 if opcode == 19 => text += &format!("{:?}", word as u8 as char),
+            10 if opcode == 19 => text += "'\\n'",
+            _ => text += &operand(word),
+        }
+    }
+    (text, arguments as u16 + 1)
+}
+
+// Names the addresses that instructions call or jump to, found by reading
+// `words` words from the start as one instruction after another: `fn_N` for
+// calls, `L_N` for jumps. Data read as code can make a stray label or two.
+pub fn labels<F: Fn(u16) -> u16>(read: F, words: usize) -> BTreeMap<u16, String> {
+    let mut labels = BTreeMap::new();
+    let mut address = 0;
+    while address < words {
+        let opcode = read(address as u16);
+        let target = match opcode {
+            6 | 17 => read(address as u16 + 1),
+            7 | 8 => read(address as u16 + 2),
+            _ => 32768,
+        };
+        if (target as usize) < words {
+            if opcode == 17 {
+                labels.insert(target, format!("fn_{}", target));
+            } else {
+                labels.entry(target).or_insert_with(|| format!("L_{}", target));
+            }
+        }
+        address += arguments(opcode).unwrap_or(0) + 1;
+    }
+    labels
+}
+
+// The starts of up to `count` instructions leading up to `address`. Code
+// can't be read backwards, so this reads forwards from a little way back,
+// as far back as still lines up with `address`.
+pub fn preceding<F: Fn(u16) -> u16>(read: F, address: u16, count: usize) -> Vec<u16> {
+    for start in address.saturating_sub(count as u16 * 4)..address {
+        let mut starts = Vec::new();
+        let mut at = start;
+        while at < address {
+            starts.push(at);
+            at += arguments(read(at)).unwrap_or(0) as u16 + 1;
+        }
+        if at == address {
+            let skip = starts.len().saturating_sub(count);
+            return starts.split_off(skip);
+        }
+    }
+    Vec::new()
+}
+
+// Splits an instruction's text into words, keeping quoted characters like
+// ' ' whole.
+fn tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut token = c.to_string();
+        let quoted = c == '\'';
+        while let Some(&next) = chars.peek() {
+            if !quoted && next.is_whitespace() {
+                break;
+            }
+            token.push(next);
+            chars.next();
+            if quoted && next == '\'' && token.len() > 2 && !token.ends_with("\\'") {
+                break;
+            }
+        }
+        tokens.push(token);
+    }
+    tokens
+}
+
+fn parse_operand(token: &str) -> Result<u16, String> {
+    let register = token.strip_prefix('r').and_then(|index| index.parse::<u16>().ok()).filter(|&index| index < 8);
+    if let Some(index) = register {
+        return Ok(32768 + index);
+    }
+    if let Some(quoted) = token.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')) {
+        return match quoted {
+            "\\n" => Ok(10),
+            "\\'" => Ok(39),
+            "\\\\" => Ok(92),
+            _ if quoted.len() == 1 && quoted.is_ascii() => Ok(quoted.as_bytes()[0] as u16),
+            _ => Err(format!("{} isn't a character", token)),
+        };
+    }
+    match token.parse::<u16>() {
+        Ok(word) if word < 32768 => Ok(word),
+        _ => Err(format!("{} isn't a number, register or character", token)),
+    }
+}
+
+// Assembles one instruction written the way `instruction` writes them,
+// or `data` followed by any number of raw words.
+pub fn assemble(text: &str) -> Result<Vec<u16>, String> {
+    let tokens = tokens(text);
+    let (name, rest) = match tokens.split_first() {
+        Some((name, rest)) => (name, rest),
+        None => return Err("there's no instruction".to_string()),
+    };
+    if name == "data" {
+        return rest.iter().map(|word| word.parse().map_err(|_| format!("{} isn't a word", word))).collect();
+    }
+    let opcode = opcode(name).ok_or_else(|| format!("{} isn't an instruction", name))?;
+    let arguments = arguments(opcode).unwrap_or(0);
+    if rest.len() != arguments {
+        return Err(format!("{} takes {} operands", name, arguments));
+    }
+    let mut words = vec![opcode];
+    for token in rest {
+        words.push(parse_operand(token)?);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instructions() {
+        let memory = [9, 32768, 32769, 4, 19, 65, 19, 10, 30000];
+        let read = |address: u16| memory.get(address as usize).cloned().unwrap_or(0);
+        assert_eq!(instruction(read, 0), ("add r0 r1 4".to_string(), 4));
+        assert_eq!(instruction(read, 4), ("out 'A'".to_string(), 2));
+        assert_eq!(instruction(read, 6), ("out '\\n'".to_string(), 2));
+        assert_eq!(instruction(read, 8), ("data 30000".to_string(), 1));
+    }
+
+    #[test]
+    fn labels_and_reads_backwards() {
+        // call 7; jt r0 0; halt; out 'A'; ret
+        let memory = [17, 7, 7, 32768, 0, 0, 0, 19, 65, 18];
+        let read = |address: u16| memory.get(address as usize).cloned().unwrap_or(0);
+        let labels = labels(read, memory.len());
+        assert_eq!(labels.into_iter().collect::<Vec<_>>(), [(0, "L_0".to_string()), (7, "fn_7".to_string())]);
+        assert_eq!(preceding(read, 9, 3), [5, 6, 7]);
+        assert_eq!(preceding(read, 2, 5), [0]);
+    }
+
+    #[test]
+    fn assembles_what_it_disassembles() {
+        let memory = [9, 32768, 32769, 4, 19, 32, 19, 10, 22, 32770, 21];
+        let read = |address: u16| memory.get(address as usize).cloned().unwrap_or(0);
+        let mut address = 0;
+        while (address as usize) < memory.len() {
+            let (text, len) = instruction(read, address);
+            assert_eq!(assemble(&text).unwrap(), &memory[address as usize..(address + len) as usize], "{}", text);
+            address += len;
+        }
+        assert_eq!(instruction(read, 8).0, "bank r2");
+        assert_eq!(assemble("data 1 65535"), Ok(vec![1, 65535]));
+        assert!(assemble("add r0 1").is_err());
+        assert!(assemble("set r8 1").is_err());
+    }
+}
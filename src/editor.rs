@@ -0,0 +1,298 @@
+// A small readline-style editor for playing the game on a terminal. Lines are
+// edited with the terminal in raw mode and handed to opcode 20 one byte at a
+// time once Enter is pressed. Supported keys: the arrows, Home/End, Delete,
+// Backspace, Ctrl+A/E (start/end of line), Ctrl+U/K (kill to start/end),
+// Ctrl+D on an empty line for EOF and Ctrl+C to stop, which also ends the
+// input, so the session finishes as it would at EOF. Hotkeys (Ctrl+S and
+// Ctrl+L for quick saves) send a whole line of their own, leaving the line
+// being edited where it was.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+use input::InputSource;
+use terminal::{self, RawMode};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Char(u8),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    KillStart,
+    KillEnd,
+    Enter,
+    Eof,
+    Interrupt,
+    Hotkey(u8),
+    Ignored,
+}
+
+fn next_byte<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0; 1];
+    match reader.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn read_key<R: Read>(reader: &mut R) -> io::Result<Option<Key>> {
+    let byte = match next_byte(reader)? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        1 => Key::Home,
+        3 => Key::Interrupt,
+        4 => Key::Eof,
+        5 => Key::End,
+        8 | 0x7f => Key::Backspace,
+        0x0b => Key::KillEnd,
+        0x0c | 0x13 => Key::Hotkey(byte),
+        0x15 => Key::KillStart,
+        0x1b => {
+            if next_byte(reader)? != Some(b'[') {
+                return Ok(Some(Key::Ignored));
+            }
+            match next_byte(reader)? {
+                Some(b'A') => Key::Up,
+                Some(b'B') => Key::Down,
+                Some(b'C') => Key::Right,
+                Some(b'D') => Key::Left,
+                Some(b'H') => Key::Home,
+                Some(b'F') => Key::End,
+                Some(b'3') if next_byte(reader)? == Some(b'~') => Key::Delete,
+                _ => Key::Ignored,
+            }
+        }
+        0x20..=0x7e => Key::Char(byte),
+        _ => Key::Ignored,
+    };
+    Ok(Some(key))
+}
+
+// The line being edited and the history it can be swapped for.
+#[derive(Default)]
+struct Line {
+    text: Vec<u8>,
+    cursor: usize,
+    history: Vec<Vec<u8>>,
+    // Index into history while browsing it; history.len() means the draft.
+    recalled: usize,
+    draft: Vec<u8>,
+}
+
+impl Line {
+    fn recall(&mut self, index: usize) {
+        if self.recalled == self.history.len() {
+            self.draft = self.text.clone();
+        }
+        self.recalled = index;
+        self.text = self.history.get(index).unwrap_or(&self.draft).clone();
+        self.cursor = self.text.len();
+    }
+    // Applies a key and returns the finished line on Enter.
+    fn key(&mut self, key: Key) -> Option<Vec<u8>> {
+        match key {
+            Key::Char(byte) => {
+                self.text.insert(self.cursor, byte);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+            }
+            Key::Left if self.cursor > 0 => self.cursor -= 1,
+            Key::Right if self.cursor < self.text.len() => self.cursor += 1,
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.text.len(),
+            Key::Up if self.recalled > 0 => {
+                let index = self.recalled - 1;
+                self.recall(index);
+            }
+            Key::Down if self.recalled < self.history.len() => {
+                let index = self.recalled + 1;
+                self.recall(index);
+            }
+            Key::KillStart => {
+                self.text.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::KillEnd => self.text.truncate(self.cursor),
+            Key::Enter => {
+                let line = std::mem::take(&mut self.text);
+                self.cursor = 0;
+                if !line.is_empty() && self.history.last() != Some(&line) {
+                    self.history.push(line.clone());
+                }
+                self.recalled = self.history.len();
+                self.draft.clear();
+                return Some(line);
+            }
+            _ => (),
+        }
+        None
+    }
+}
+
+// Whether stdin is a terminal that the editor knows how to drive.
+pub fn available() -> bool {
+    terminal::supported()
+}
+
+pub struct LineEditor {
+    line: Line,
+    pending: VecDeque<u8>,
+    history_file: Option<File>,
+    hotkeys: Vec<(u8, String)>,
+}
+
+impl LineEditor {
+    // Loads earlier history from `history` and appends each new line to it.
+    pub fn new(history: Option<&str>) -> io::Result<LineEditor> {
+        let mut line = Line::default();
+        let mut history_file = None;
+        if let Some(path) = history {
+            if let Ok(file) = File::open(path) {
+                for entry in BufReader::new(file).split(b'\n') {
+                    line.history.push(entry?);
+                }
+                line.recalled = line.history.len();
+            }
+            history_file = Some(OpenOptions::new().create(true).append(true).open(path)?);
+        }
+        Ok(LineEditor { line, pending: VecDeque::new(), history_file, hotkeys: Vec::new() })
+    }
+    // Makes Ctrl+`letter` send `line` without touching the history.
+    pub fn hotkey(mut self, letter: char, line: &str) -> LineEditor {
+        self.hotkeys.push(((letter.to_ascii_uppercase() as u8) & 0x1f, line.to_string()));
+        self
+    }
+    // Redraws the line after the prompt, relative to where the cursor was.
+    fn redraw(&self, out: &mut impl Write, old_cursor: usize) -> io::Result<()> {
+        if old_cursor > 0 {
+            write!(out, "\x1b[{}D", old_cursor)?;
+        }
+        out.write_all(b"\x1b[K")?;
+        out.write_all(&self.line.text)?;
+        let back = self.line.text.len() - self.line.cursor;
+        if back > 0 {
+            write!(out, "\x1b[{}D", back)?;
+        }
+        out.flush()
+    }
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let _raw = RawMode::enable()?;
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        // Brings back a line that a hotkey interrupted.
+        self.redraw(&mut stdout, 0)?;
+        loop {
+            let key = match read_key(&mut stdin)? {
+                Some(Key::Interrupt) => {
+                    stdout.write_all(b"^C\r\n")?;
+                    return Ok(None);
+                }
+                Some(Key::Eof) if self.line.text.is_empty() => None,
+                Some(Key::Hotkey(byte)) => {
+                    if let Some((_, line)) = self.hotkeys.iter().find(|&&(key, _)| key == byte) {
+                        stdout.write_all(b"\r\n")?;
+                        stdout.flush()?;
+                        return Ok(Some(line.clone().into_bytes()));
+                    }
+                    Some(Key::Ignored)
+                }
+                Some(key) => Some(key),
+                None => None,
+            };
+            let key = match key {
+                Some(key) => key,
+                None => {
+                    stdout.write_all(b"\r\n")?;
+                    return Ok(None);
+                }
+            };
+            let old_cursor = self.line.cursor;
+            if let Some(line) = self.line.key(key) {
+                stdout.write_all(b"\r\n")?;
+                stdout.flush()?;
+                if let Some(ref mut file) = self.history_file {
+                    if !line.is_empty() {
+                        file.write_all(&line)?;
+                        file.write_all(b"\n")?;
+                    }
+                }
+                return Ok(Some(line));
+            }
+            self.redraw(&mut stdout, old_cursor)?;
+        }
+    }
+}
+
+impl InputSource for LineEditor {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pending.is_empty() {
+            match self.read_line()? {
+                Some(line) => {
+                    self.pending.extend(line);
+                    self.pending.push_back(b'\n');
+                }
+                None => return Ok(None),
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(line: &mut Line, keys: &[u8]) -> Option<Vec<u8>> {
+        let mut keys = keys;
+        let mut finished = None;
+        while let Some(key) = read_key(&mut keys).unwrap() {
+            finished = finished.or(line.key(key));
+        }
+        finished
+    }
+
+    #[test]
+    fn editing_keys() {
+        let mut line = Line::default();
+        assert_eq!(type_keys(&mut line, b"tak tablet\x1b[D\x1b[D\x1b[D\x1b[D\x1b[D\x1b[D\x1b[De\r"), Some(b"take tablet".to_vec()));
+        assert_eq!(type_keys(&mut line, b"go north\x15look\r"), Some(b"look".to_vec()));
+        assert_eq!(type_keys(&mut line, b"use\x01\x0bdrop\x7f\x7f\x7f\x7finv\r"), Some(b"inv".to_vec()));
+    }
+
+    #[test]
+    fn history_recall() {
+        let mut line = Line::default();
+        type_keys(&mut line, b"north\r");
+        type_keys(&mut line, b"south\r");
+        assert_eq!(type_keys(&mut line, b"x\x1b[A\x1b[A\r"), Some(b"north".to_vec()));
+        assert_eq!(type_keys(&mut line, b"x\x1b[A\x1b[B\r"), Some(b"x".to_vec()));
+    }
+
+    #[test]
+    fn hotkeys_leave_the_line_alone() {
+        let mut line = Line::default();
+        assert_eq!(read_key(&mut &b"\x13"[..]).unwrap(), Some(Key::Hotkey(0x13)));
+        assert_eq!(type_keys(&mut line, b"go\x13\x0c"), None);
+        assert_eq!(type_keys(&mut line, b" north\r"), Some(b"go north".to_vec()));
+    }
+}
@@ -0,0 +1,146 @@
+// Scripted play: waits for patterns in the game's output and answers them.
+//
+// A script has one command per line; blank lines and lines starting with `#`
+// are ignored.
+//   expect TEXT    run until TEXT appears in output printed since the last match
+//   send TEXT      queue TEXT and a newline as input; `\n` starts another line
+//   timeout SECS   how long later expects may take (10 seconds by default)
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use output::Buffer;
+use synacor::{RunOutcome, Synacor};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+// How many instructions to run between checks for the pattern.
+const CHUNK: u64 = 10_000;
+
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Expect(String),
+    Send(String),
+    Timeout(Duration),
+}
+
+pub enum ExpectErr {
+    Parse { line: usize, text: String },
+    TimedOut { pattern: String },
+    // The VM halted, faulted or wanted input before the pattern appeared.
+    Stopped { pattern: String, outcome: RunOutcome },
+}
+
+impl fmt::Display for ExpectErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExpectErr::Parse { line, ref text } => write!(f, "Line {} of the script is not a command: {}", line, text),
+            ExpectErr::TimedOut { ref pattern } => write!(f, "Timed out waiting for {:?}.", pattern),
+            ExpectErr::Stopped { ref pattern, ref outcome } => {
+                write!(f, "Stopped while waiting for {:?}: {}", pattern, outcome)
+            }
+        }
+    }
+}
+
+pub fn parse(script: &str) -> Result<Vec<Step>, ExpectErr> {
+    let mut steps = Vec::new();
+    for (number, line) in script.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let (command, argument) = trimmed.split_once(' ').unwrap_or((trimmed, ""));
+        let step = match command {
+            "expect" => Step::Expect(argument.to_string()),
+            "send" => Step::Send(argument.replace("\\n", "\n") + "\n"),
+            "timeout" => match argument.trim().parse() {
+                Ok(seconds) if seconds >= 0.0 => Step::Timeout(Duration::from_secs_f64(seconds)),
+                _ => return Err(ExpectErr::Parse { line: number + 1, text: line.to_string() }),
+            },
+            _ => return Err(ExpectErr::Parse { line: number + 1, text: line.to_string() }),
+        };
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+// Runs the steps against a VM whose output goes to `capture`. The VM should
+// yield when it runs out of input, so that waiting on a pattern that never
+// comes fails instead of reading past the script.
+pub fn run(synacor: &mut Synacor, capture: &Buffer, steps: &[Step]) -> Result<(), ExpectErr> {
+    let mut timeout = DEFAULT_TIMEOUT;
+    // Output before this offset has already been matched.
+    let mut matched = 0;
+    for step in steps {
+        match *step {
+            Step::Expect(ref pattern) => matched = expect(synacor, capture, pattern, matched, timeout)?,
+            Step::Send(ref text) => synacor.push_input(text),
+            Step::Timeout(duration) => timeout = duration,
+        }
+    }
+    Ok(())
+}
+
+fn expect(synacor: &mut Synacor, capture: &Buffer, pattern: &str, from: usize, timeout: Duration)
+    -> Result<usize, ExpectErr> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let outcome = synacor.run_for(CHUNK);
+        let text = capture.text();
+        if let Some(offset) = text.get(from..).and_then(|text| text.find(pattern)) {
+            return Ok(from + offset + pattern.len());
+        }
+        match outcome {
+            RunOutcome::BudgetExceeded if Instant::now() < deadline => (),
+            RunOutcome::BudgetExceeded => return Err(ExpectErr::TimedOut { pattern: pattern.to_string() }),
+            outcome => return Err(ExpectErr::Stopped { pattern: pattern.to_string(), outcome }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::Text;
+    use memory::Image;
+    use synacor::{Config, EofPolicy};
+
+    // Echoes its input back until it runs out.
+    fn echo(capture: &Buffer) -> Synacor {
+        let program: &[u16] = &[20, 32768, 19, 32768, 6, 0];
+        let bytes = program.iter().flat_map(|word| vec![*word as u8, (*word >> 8) as u8]).collect();
+        let config = Config {
+            output: Box::new(capture.clone()),
+            input: Box::new(Text::default()),
+            on_eof: EofPolicy::Yield,
+            ..Config::default()
+        };
+        let mut synacor = Synacor::with_config(config);
+        synacor.load_image(Image::Bytes(bytes)).ok().unwrap();
+        synacor
+    }
+
+    #[test]
+    fn parses_commands() {
+        let steps = parse("# coins\ntimeout 0.5\nexpect What do you do?\nsend take tablet\\nlook\n").ok().unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                Step::Timeout(Duration::from_millis(500)),
+                Step::Expect("What do you do?".to_string()),
+                Step::Send("take tablet\nlook\n".to_string()),
+            ]
+        );
+        assert!(parse("wait 5").is_err());
+    }
+
+    #[test]
+    fn sends_and_expects() {
+        let capture = Buffer::default();
+        let mut synacor = echo(&capture);
+        let steps = parse("send hello\nexpect hello\nsend world\nexpect world\n").ok().unwrap();
+        assert!(run(&mut synacor, &capture, &steps).is_ok());
+        let steps = parse("expect again\n").ok().unwrap();
+        assert!(matches!(run(&mut synacor, &capture, &steps), Err(ExpectErr::Stopped { .. })));
+    }
+}
@@ -0,0 +1,174 @@
+// Pulls out the text the game can print and works out what prints it.
+// Strings are a length followed by that many characters. Most sit as plain
+// text in the data after the code (once the self-test has decrypted it), and
+// the rest are XORed with a key that the code builds right before printing:
+//   set r0 STRING
+//   set r1 CALLBACK
+//   add r2 A B        the key is A + B
+//   call PRINT
+// A string belongs to the routine that mentions its address, taken to be the
+// last call target at or before the mention. Strings only pointed at from
+// data, like room descriptions, are grouped as data, and the rest as
+// unreferenced, which is where unused text would turn up.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use disasm;
+
+const REGISTER: u16 = 32768;
+// Plain strings shorter than this are only kept when something refers to
+// them, since short runs of letters turn up in data by chance.
+const SHORT: usize = 8;
+const MAX_LEN: usize = 4000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Owner {
+    Routine(u16),
+    Data,
+    Unreferenced,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Text {
+    pub address: u16,
+    pub text: String,
+    // The key the text is XORed with, if it's encrypted.
+    pub key: Option<u16>,
+}
+
+fn printable(c: u16) -> bool {
+    (32..127).contains(&c) || c == 10
+}
+
+fn decode(memory: &[u16], address: u16, key: u16) -> Option<String> {
+    let len = *memory.get(address as usize)? as usize;
+    let words = memory.get(address as usize + 1..address as usize + 1 + len)?;
+    if len == 0 || len > MAX_LEN || !words.iter().all(|&word| printable(word ^ key)) {
+        return None;
+    }
+    Some(words.iter().map(|&word| (word ^ key) as u8 as char).collect())
+}
+
+// The plain strings from `start` on, at least `min` characters long.
+fn plain(memory: &[u16], start: usize, min: usize) -> Vec<Text> {
+    let mut found = Vec::new();
+    let mut address = start;
+    while address < memory.len() {
+        match decode(memory, address as u16, 0) {
+            Some(text) if text.len() >= min => {
+                address += text.len() + 1;
+                found.push(Text { address: (address - text.len() - 1) as u16, text, key: None });
+            }
+            _ => address += 1,
+        }
+    }
+    found
+}
+
+struct Instruction {
+    address: u16,
+    opcode: u16,
+    arguments: Vec<u16>,
+}
+
+// A linear sweep of the code, skipping words that aren't opcodes.
+fn instructions(memory: &[u16], end: usize) -> Vec<Instruction> {
+    let mut found = Vec::new();
+    let mut address = 0;
+    while address < end {
+        let opcode = memory[address];
+        match disasm::arguments(opcode) {
+            Some(count) if address + count < memory.len() => {
+                let arguments = memory[address + 1..address + 1 + count].to_vec();
+                found.push(Instruction { address: address as u16, opcode, arguments });
+                address += count + 1;
+            }
+            _ => address += 1,
+        }
+    }
+    found
+}
+
+pub fn extract(memory: &[u16]) -> BTreeMap<Owner, Vec<Text>> {
+    // The code ends where the first long string starts.
+    let code_end = plain(memory, 0, SHORT).first().map_or(memory.len(), |text| text.address as usize);
+    let code = instructions(memory, code_end);
+    let calls: BTreeSet<u16> = code.iter().filter(|instruction| instruction.opcode == 17).map(|instruction| instruction.arguments[0]).collect();
+    let routine = |address: u16| Owner::Routine(calls.range(..=address).next_back().cloned().unwrap_or(0));
+    let mut owned: BTreeMap<u16, (Owner, Text)> = BTreeMap::new();
+    for window in code.windows(4) {
+        match (&window[0], &window[1], &window[2], &window[3]) {
+            (set_string, set_callback, add_key, call)
+                if set_string.opcode == 1
+                    && set_string.arguments[0] == REGISTER
+                    && set_string.arguments[1] < REGISTER
+                    && set_callback.opcode == 1
+                    && set_callback.arguments[0] == REGISTER + 1
+                    && add_key.opcode == 9
+                    && add_key.arguments[0] == REGISTER + 2
+                    && add_key.arguments[1..].iter().all(|&word| word < REGISTER)
+                    && call.opcode == 17 =>
+            {
+                let address = set_string.arguments[1];
+                let key = add_key.arguments[1].wrapping_add(add_key.arguments[2]) % REGISTER;
+                if let Some(text) = decode(memory, address, key) {
+                    owned.insert(address, (routine(call.address), Text { address, text, key: Some(key) }));
+                }
+            }
+            _ => (),
+        }
+    }
+    let mentions: BTreeMap<u16, u16> = code
+        .iter()
+        .rev()
+        .flat_map(|instruction| instruction.arguments.iter().map(move |&argument| (argument, instruction.address)))
+        .collect();
+    // Tables such as the rooms sit among the code, so any word can point.
+    let pointers: BTreeSet<u16> = memory.iter().cloned().collect();
+    for text in plain(memory, code_end, 1) {
+        let owner = match mentions.get(&text.address) {
+            Some(&at) => routine(at),
+            None if pointers.contains(&text.address) => Owner::Data,
+            None if text.text.len() >= SHORT => Owner::Unreferenced,
+            None => continue,
+        };
+        owned.entry(text.address).or_insert((owner, text));
+    }
+    let mut grouped: BTreeMap<Owner, Vec<Text>> = BTreeMap::new();
+    for (_, (owner, text)) in owned {
+        grouped.entry(owner).or_default().push(text);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(memory: &mut [u16], address: usize, text: &str, key: u16) {
+        memory[address] = text.len() as u16;
+        for (offset, c) in text.bytes().enumerate() {
+            memory[address + 1 + offset] = c as u16 ^ key;
+        }
+    }
+
+    #[test]
+    fn groups_text_by_routine() {
+        let mut memory = vec![0u16; 200];
+        // call 10; halt; then at 10: set r0 100; set r1 5; add r2 3 4; call 50;
+        // set r0 120; ret.
+        let code: &[u16] = &[17, 10, 0, 0, 0, 0, 0, 0, 0, 0, 1, 32768, 100, 1, 32769, 5, 9, 32770, 3, 4, 17, 50, 1, 32768, 120, 18];
+        memory[..code.len()].copy_from_slice(code);
+        put(&mut memory, 60, "You see nothing.", 0);
+        put(&mut memory, 100, "Secret", 7);
+        put(&mut memory, 120, "Plain", 0);
+        put(&mut memory, 140, "Pointed at", 0);
+        memory[190] = 140;
+        let grouped = extract(&memory);
+        let texts = |owner| grouped[&owner].iter().map(|text: &Text| text.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts(Owner::Routine(10)), ["Secret", "Plain"]);
+        assert_eq!(grouped[&Owner::Routine(10)][0].key, Some(7));
+        assert_eq!(texts(Owner::Data), ["Pointed at"]);
+        assert_eq!(texts(Owner::Unreferenced), ["You see nothing."]);
+    }
+}
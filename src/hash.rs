@@ -0,0 +1,55 @@
+// A stable hash of the machine's canonical state: the program counter, the
+// registers, the stack and the 32768 words of memory in the spec. Input,
+// output and anything past the spec's memory are left out, so two runs that
+// reach the same game state hash the same, whatever the emulator version.
+//
+// The hash is 64-bit FNV-1a over the little-endian bytes of the program
+// counter, the eight registers, the stack depth, the stack from the bottom
+// up and then memory.
+
+const OFFSET: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x100000001b3;
+pub const SPEC_WORDS: usize = 32768;
+
+struct Fnv(u64);
+
+impl Fnv {
+    fn byte(&mut self, byte: u8) {
+        self.0 = (self.0 ^ byte as u64).wrapping_mul(PRIME);
+    }
+    fn word(&mut self, word: u16) {
+        word.to_le_bytes().iter().for_each(|&byte| self.byte(byte));
+    }
+}
+
+pub fn state<F: Fn(usize) -> u16>(program_counter: u16, registers: &[u16; 8], stack: &[u16], memory: F) -> u64 {
+    let mut fnv = Fnv(OFFSET);
+    fnv.word(program_counter);
+    registers.iter().for_each(|&register| fnv.word(register));
+    fnv.word(stack.len() as u16);
+    stack.iter().for_each(|&value| fnv.word(value));
+    (0..SPEC_WORDS).for_each(|address| fnv.word(memory(address)));
+    fnv.0
+}
+
+// FNV-1a of some text, as used for the expected codes in codes.rs.
+pub fn text(text: &str) -> u64 {
+    let mut fnv = Fnv(OFFSET);
+    text.bytes().for_each(|byte| fnv.byte(byte));
+    fnv.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_values() {
+        // Changing these breaks every saved hash, including replay files.
+        assert_eq!(state(0, &[0; 8], &[], |_| 0), 0xab44360bff1f0935);
+        assert_ne!(state(0, &[0; 8], &[1], |_| 0), state(0, &[0; 8], &[], |_| 0));
+        assert_ne!(state(0, &[0; 8], &[], |address| (address == 32767) as u16), state(0, &[0; 8], &[], |_| 0));
+        assert_eq!(text(""), OFFSET);
+        assert_eq!(text("a"), 0xaf63dc4c8601ec8c);
+    }
+}
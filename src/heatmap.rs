@@ -0,0 +1,122 @@
+// Counts the reads (rmem) and writes (wmem) of each word of memory, to show
+// where the program keeps the state it works on and what it leaves alone.
+// The heatmap comes out as text, one character per block of words getting
+// denser with more accesses, or as a PPM image with a pixel per word: red
+// for writes, green for reads.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use trace::{Step, Tracer};
+
+// The name the heatmap runs under (see Synacor::set_tracer).
+pub const NAME: &str = "heatmap";
+const WORDS: usize = 32768;
+// Words per character and characters per line in the text map.
+const BLOCK: usize = 32;
+const LINE: usize = 64;
+const IMAGE_WIDTH: usize = 256;
+const SHADES: &[u8] = b" .:-=+*#%@";
+
+pub struct Heatmap {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl Default for Heatmap {
+    fn default() -> Heatmap {
+        Heatmap { reads: vec![0; WORDS], writes: vec![0; WORDS] }
+    }
+}
+
+// How bright `count` is next to `most`, from 0 to `levels - 1` on a log
+// scale, so that rarely touched words still show up.
+fn shade(count: u64, most: u64, levels: usize) -> usize {
+    if count == 0 {
+        return 0;
+    }
+    let scale = ((count as f64).ln_1p() / (most as f64).ln_1p() * (levels - 1) as f64).ceil() as usize;
+    scale.clamp(1, levels - 1)
+}
+
+impl Heatmap {
+    pub fn step(&mut self, step: &Step) {
+        let (counts, address) = match step.opcode {
+            15 => (&mut self.reads, step.value(step.words[1])),
+            16 => (&mut self.writes, step.value(step.words[0])),
+            _ => return,
+        };
+        if let Some(count) = counts.get_mut(address as usize) {
+            *count += 1;
+        }
+    }
+    pub fn reads(&self, address: u16) -> u64 {
+        self.reads.get(address as usize).cloned().unwrap_or(0)
+    }
+    pub fn writes(&self, address: u16) -> u64 {
+        self.writes.get(address as usize).cloned().unwrap_or(0)
+    }
+    // Both maps as text, each line headed by the address it starts at.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for (title, counts) in [("Reads", &self.reads), ("Writes", &self.writes)] {
+            let blocks: Vec<u64> = counts.chunks(BLOCK).map(|block| block.iter().sum()).collect();
+            let most = blocks.iter().cloned().max().unwrap_or(0);
+            text += &format!("{} ({} words to a character, {} at most):\n", title, BLOCK, most);
+            for (line, blocks) in blocks.chunks(LINE).enumerate() {
+                let shades: String = blocks.iter().map(|&count| SHADES[shade(count, most, SHADES.len())] as char).collect();
+                text += &format!("{:5} |{}|\n", line * LINE * BLOCK, shades);
+            }
+        }
+        text
+    }
+    // A binary PPM image, 256 words to a row.
+    pub fn ppm(&self) -> Vec<u8> {
+        let most_reads = self.reads.iter().cloned().max().unwrap_or(0);
+        let most_writes = self.writes.iter().cloned().max().unwrap_or(0);
+        let mut image = format!("P6\n{} {}\n255\n", IMAGE_WIDTH, WORDS / IMAGE_WIDTH).into_bytes();
+        for (&reads, &writes) in self.reads.iter().zip(&self.writes) {
+            image.extend_from_slice(&[shade(writes, most_writes, 256) as u8, shade(reads, most_reads, 256) as u8, 0]);
+        }
+        image
+    }
+    // The image if `path` ends in ".ppm" and the text otherwise.
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        if path.ends_with(".ppm") {
+            std::fs::write(path, self.ppm())
+        } else {
+            std::fs::write(path, self.text())
+        }
+    }
+}
+
+// Feeds a shared heatmap from the machine.
+pub struct Recorder(pub Rc<RefCell<Heatmap>>);
+
+impl Tracer for Recorder {
+    fn step(&mut self, step: &Step) {
+        self.0.borrow_mut().step(step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_reads_and_writes() {
+        let mut heatmap = Heatmap::default();
+        let mut step = Step { count: 0, pc: 0, opcode: 15, words: [32768, 32769, 0], before: [0; 8], after: [0; 8], next: 3, stack_depth: 0 };
+        step.before[1] = 100;
+        heatmap.step(&step);
+        heatmap.step(&step);
+        step.opcode = 16;
+        step.words = [2048, 5, 0];
+        heatmap.step(&step);
+        assert_eq!((heatmap.reads(100), heatmap.writes(2048), heatmap.writes(100)), (2, 1, 0));
+        let text = heatmap.text();
+        assert!(text.starts_with("Reads (32 words to a character, 2 at most):\n    0 |   @ "));
+        assert!(text.contains("\n 2048 |@ "));
+        assert_eq!(heatmap.ppm().len(), "P6\n256 128\n255\n".len() + WORDS * 3);
+    }
+}
@@ -0,0 +1,103 @@
+// Hints for whatever the player is working towards (see progress.rs), from the
+// database in hints.txt. Asking again about the same milestone goes a level
+// further: a nudge, then a spoiler, then the exact commands.
+
+use progress::Milestone;
+
+const DATABASE: &str = include_str!("hints.txt");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Nudge,
+    Spoiler,
+    Commands,
+}
+
+impl Level {
+    pub fn deeper(self) -> Level {
+        match self {
+            Level::Nudge => Level::Spoiler,
+            _ => Level::Commands,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Hint {
+    pub milestone: Milestone,
+    pub nudge: &'static str,
+    pub spoiler: &'static str,
+    pub commands: Vec<&'static str>,
+}
+
+impl Hint {
+    pub fn lines(&self, level: Level) -> Vec<String> {
+        match level {
+            Level::Nudge => vec![format!("Nudge: {}", self.nudge)],
+            Level::Spoiler => vec![format!("Spoiler: {}", self.spoiler)],
+            Level::Commands if self.commands.is_empty() => vec!["There is nothing to type for this one.".to_string()],
+            Level::Commands => {
+                let mut lines = vec![format!("Type these to get to \"{}\":", self.milestone)];
+                lines.extend(self.commands.iter().map(|command| format!("  {}", command)));
+                lines
+            }
+        }
+    }
+}
+
+pub fn parse(text: &'static str) -> Result<Vec<Hint>, String> {
+    let mut hints: Vec<Hint> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fail = |problem: &str| Err(format!("line {}: {}", number + 1, problem));
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            match Milestone::named(name) {
+                Some(milestone) => hints.push(Hint { milestone, nudge: "", spoiler: "", commands: Vec::new() }),
+                None => return fail(&format!("there is no milestone {:?}", name)),
+            }
+            continue;
+        }
+        let hint = match hints.last_mut() {
+            Some(hint) => hint,
+            None => return fail("a hint has to come after a [milestone] heading"),
+        };
+        if let Some(nudge) = line.strip_prefix("nudge: ") {
+            hint.nudge = nudge;
+        } else if let Some(spoiler) = line.strip_prefix("spoiler: ") {
+            hint.spoiler = spoiler;
+        } else if let Some(command) = line.strip_prefix("> ") {
+            hint.commands.push(command);
+        } else {
+            return fail("expected \"nudge: \", \"spoiler: \" or \"> \"");
+        }
+    }
+    Ok(hints)
+}
+
+// The hints that come with the emulator.
+pub fn bundled() -> Vec<Hint> {
+    parse(DATABASE).expect("hints.txt is well formed")
+}
+
+pub fn for_milestone(milestone: Milestone) -> Option<Hint> {
+    bundled().into_iter().find(|hint| hint.milestone == milestone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use progress::MILESTONES;
+
+    #[test]
+    fn covers_every_milestone() {
+        let hints = bundled();
+        assert_eq!(hints.iter().map(|hint| hint.milestone).collect::<Vec<_>>(), MILESTONES);
+        assert!(hints.iter().all(|hint| !hint.nudge.is_empty() && !hint.spoiler.is_empty()));
+        let mirror = for_milestone(Milestone::Mirror).unwrap();
+        assert_eq!(mirror.lines(Level::Commands), ["Type these to get to \"read the mirror\":", "  take mirror", "  use mirror"]);
+        assert_eq!(parse("[nowhere]\n").unwrap_err(), "line 1: there is no milestone \"nowhere\"");
+    }
+}
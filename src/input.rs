@@ -0,0 +1,189 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::sync::mpsc::Receiver;
+
+// Somewhere for opcode 20 to read bytes from.
+pub trait InputSource {
+    // Returns the next byte, or None once the source has run dry.
+    fn read_byte(&mut self) -> io::Result<Option<u8>>;
+}
+
+fn read_one<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0; 1];
+    match reader.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+pub struct Stdin;
+
+impl InputSource for Stdin {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        read_one(&mut io::stdin().lock())
+    }
+}
+
+pub struct FileSource(BufReader<File>);
+
+impl FileSource {
+    pub fn open(path: &str) -> io::Result<FileSource> {
+        File::open(path).map(|file| FileSource(BufReader::new(file)))
+    }
+}
+
+impl InputSource for FileSource {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        read_one(&mut self.0)
+    }
+}
+
+// A fixed piece of text, such as a script of game commands.
+#[derive(Default)]
+pub struct Text(VecDeque<u8>);
+
+impl Text {
+    pub fn new(text: &str) -> Text {
+        Text(text.bytes().collect())
+    }
+}
+
+impl InputSource for Text {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.0.pop_front())
+    }
+}
+
+// Bytes sent from another thread. Reading blocks until something arrives and
+// the source runs dry once every sender has been dropped.
+pub struct Channel {
+    receiver: Receiver<Vec<u8>>,
+    pending: VecDeque<u8>,
+}
+
+impl Channel {
+    pub fn new(receiver: Receiver<Vec<u8>>) -> Channel {
+        Channel { receiver, pending: VecDeque::new() }
+    }
+}
+
+impl InputSource for Channel {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        while self.pending.is_empty() {
+            match self.receiver.recv() {
+                Ok(bytes) => self.pending.extend(bytes),
+                Err(_) => return Ok(None),
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+// Reads each source in turn, moving on when one runs dry, e.g. a script
+// followed by stdin.
+pub struct Chain(VecDeque<Box<dyn InputSource>>);
+
+impl Chain {
+    pub fn new(sources: Vec<Box<dyn InputSource>>) -> Chain {
+        Chain(sources.into())
+    }
+}
+
+impl InputSource for Chain {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        while let Some(source) = self.0.front_mut() {
+            if let Some(byte) = source.read_byte()? {
+                return Ok(Some(byte));
+            }
+            self.0.pop_front();
+        }
+        Ok(None)
+    }
+}
+
+// Expands abbreviations typed as a whole line, e.g. `n` into `north`. The
+// table is read from lines like `tp = use teleporter`; `\n` in an expansion
+// separates several commands and `#` starts a comment.
+pub struct Macros {
+    source: Box<dyn InputSource>,
+    table: HashMap<String, String>,
+    pending: VecDeque<u8>,
+}
+
+impl Macros {
+    pub fn new(source: Box<dyn InputSource>, table: &str) -> Macros {
+        let table = table
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .filter_map(|line| {
+                let (name, expansion) = line.split_once('=')?;
+                Some((name.trim().to_string(), expansion.trim().replace("\\n", "\n")))
+            })
+            .collect();
+        Macros { source, table, pending: VecDeque::new() }
+    }
+    // Reads the next line from the source and queues its expansion. An
+    // unterminated last line is passed through without a newline added.
+    fn fill(&mut self) -> io::Result<()> {
+        let mut line = Vec::new();
+        let mut ended = false;
+        while let Some(byte) = self.source.read_byte()? {
+            if byte == b'\n' {
+                ended = true;
+                break;
+            }
+            line.push(byte);
+        }
+        let text = String::from_utf8_lossy(&line);
+        match self.table.get(text.trim()) {
+            Some(expansion) if ended => self.pending.extend(expansion.bytes()),
+            _ => self.pending.extend(line),
+        }
+        if ended {
+            self.pending.push_back(b'\n');
+        }
+        Ok(())
+    }
+}
+
+impl InputSource for Macros {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pending.is_empty() {
+            self.fill()?;
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn drain(source: &mut dyn InputSource) -> String {
+        let mut text = String::new();
+        while let Some(byte) = source.read_byte().unwrap() {
+            text.push(byte as char);
+        }
+        text
+    }
+
+    #[test]
+    fn chain_reads_sources_in_order() {
+        let (sender, receiver) = mpsc::channel();
+        sender.send(b"look\n".to_vec()).unwrap();
+        drop(sender);
+        let mut chain = Chain::new(vec![Box::new(Text::new("take tablet\n")), Box::new(Channel::new(receiver))]);
+        assert_eq!(drain(&mut chain), "take tablet\nlook\n");
+    }
+
+    #[test]
+    fn macros_expand_whole_lines() {
+        let table = "n = north  # go north\ntp = use teleporter\\nlook\n";
+        let mut macros = Macros::new(Box::new(Text::new("n\nnorth n\ntp\nn")), table);
+        assert_eq!(drain(&mut macros), "north\nnorth n\nuse teleporter\nlook\nn");
+    }
+}
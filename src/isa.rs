@@ -0,0 +1,138 @@
+// Instruction-set profiles, picked when the VM is built. The spec's 22
+// opcodes are always the VM's own; a profile decides what else there is:
+// whether strict mode holds the program to the spec, and which extensions
+// (see extension.rs) and devices (see device.rs) it gets.
+//
+// Where the spec and the challenge differ, the interpreter is built once for
+// each through Opcodes, so those checks are settled when it's compiled
+// rather than at every instruction.
+
+use bank::Banks;
+use magic::Magic;
+use synacor::{Config, Synacor, SynacorErr};
+
+pub trait Opcodes {
+    // Holds addresses, numbers and output to the spec.
+    const STRICT: bool;
+    // Runs an opcode past 21.
+    fn extended(synacor: &mut Synacor, opcode: u16) -> Result<(), SynacorErr>;
+}
+
+pub trait InstructionSet {
+    fn name(&self) -> &'static str;
+    // Changes the configuration before the VM is built.
+    fn configure(&self, _: &mut Config) {}
+    // Adds extensions and devices to the VM once it's built.
+    fn install(&self, _: &mut Synacor) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// Exactly the architecture spec, in strict mode.
+pub struct Spec;
+
+impl InstructionSet for Spec {
+    fn name(&self) -> &'static str {
+        "spec"
+    }
+    fn configure(&self, config: &mut Config) {
+        config.strict = true;
+    }
+}
+
+impl Opcodes for Spec {
+    const STRICT: bool = true;
+    fn extended(_: &mut Synacor, _: u16) -> Result<(), SynacorErr> {
+        Err(SynacorErr::BadOptcode)
+    }
+}
+
+// What the challenge needs and the VM does without asking: the spec's
+// opcodes on 65536 words of memory.
+pub struct Challenge;
+
+impl InstructionSet for Challenge {
+    fn name(&self) -> &'static str {
+        "challenge"
+    }
+}
+
+// Homebrew and Instrumented run on this one too; their extensions are what
+// differ.
+impl Opcodes for Challenge {
+    const STRICT: bool = false;
+    fn extended(synacor: &mut Synacor, opcode: u16) -> Result<(), SynacorErr> {
+        synacor.run_extension::<Challenge>(opcode)
+    }
+}
+
+// For bigger programs written for this VM: banked memory and the debug
+// opcode, reporting as notices.
+pub struct Homebrew {
+    pub banks: usize,
+}
+
+impl InstructionSet for Homebrew {
+    fn name(&self) -> &'static str {
+        "homebrew"
+    }
+    fn install(&self, synacor: &mut Synacor) -> Result<(), String> {
+        Banks::enable(synacor, self.banks)?;
+        Instrumented.install(synacor)
+    }
+}
+
+// The spec's opcodes and the debug opcode, so test ROMs can report.
+pub struct Instrumented;
+
+impl InstructionSet for Instrumented {
+    fn name(&self) -> &'static str {
+        "instrumented"
+    }
+    fn install(&self, synacor: &mut Synacor) -> Result<(), String> {
+        synacor.add_extension(Box::new(Magic::new(Box::new(|event| notice!("Debug {}.", event)))));
+        Ok(())
+    }
+}
+
+pub const DEFAULT_BANKS: usize = 8;
+
+pub fn named(name: &str) -> Option<Box<dyn InstructionSet>> {
+    match name {
+        "spec" => Some(Box::new(Spec)),
+        "challenge" => Some(Box::new(Challenge)),
+        "homebrew" => Some(Box::new(Homebrew { banks: DEFAULT_BANKS })),
+        "instrumented" => Some(Box::new(Instrumented)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::Image;
+    use output::Buffer;
+    use synacor::RunOutcome;
+    use {bank, magic};
+
+    #[test]
+    fn profiles_pick_what_the_vm_gets() {
+        let build = |name: &str| {
+            let isa = named(name).unwrap();
+            let mut config = Config { output: Box::new(Buffer::default()), ..Config::default() };
+            isa.configure(&mut config);
+            let mut synacor = Synacor::with_config(config);
+            isa.install(&mut synacor).unwrap();
+            let handles = (synacor.handles(bank::OPCODE), synacor.handles(magic::OPCODE));
+            // out 200, which only the spec's interpreter refuses.
+            synacor.load_image(Image::Bytes(vec![19, 0, 200, 0, 0, 0])).ok().unwrap();
+            let strict = matches!(synacor.run(), RunOutcome::Faulted(SynacorErr::BadChar(200)));
+            (strict, handles.0, handles.1)
+        };
+        assert_eq!(build("spec"), (true, false, false));
+        assert_eq!(build("challenge"), (false, false, false));
+        assert_eq!(build("homebrew"), (false, true, true));
+        assert_eq!(build("instrumented"), (false, false, true));
+        assert!(named("z80").is_none());
+    }
+}
@@ -0,0 +1,356 @@
+// A small JSON value type with a parser and a compact printer, for talking to
+// other programs.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // Keys keep the order they were given in.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object(fields: Vec<(&str, Value)>) -> Value {
+        Value::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref fields) => fields.iter().find(|field| field.0 == key).map(|field| &field.1),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref text) => Some(text),
+            _ => None,
+        }
+    }
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::Number(n) if n >= 0.0 && n.fract() == 0.0 && n <= u64::MAX as f64 => Some(n as u64),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+    fn is_container(&self) -> bool {
+        matches!(*self, Value::Array(_) | Value::Object(_))
+    }
+    // Like to_string, but arrays and objects that hold other arrays or
+    // objects get one element per line, for files people read and edit.
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+    fn write_pretty(&self, out: &mut String, indent: usize) {
+        let (open, close, elements): (&str, &str, Vec<(Option<&str>, &Value)>) = match *self {
+            Value::Array(ref values) if values.iter().any(Value::is_container) => {
+                ("[", "]", values.iter().map(|value| (None, value)).collect())
+            }
+            Value::Object(ref fields) if fields.iter().any(|field| field.1.is_container()) => {
+                ("{", "}", fields.iter().map(|field| (Some(field.0.as_str()), &field.1)).collect())
+            }
+            _ => return out.push_str(&self.to_string()),
+        };
+        out.push_str(open);
+        for (i, (key, value)) in elements.into_iter().enumerate() {
+            out.push_str(if i > 0 { ",\n" } else { "\n" });
+            out.push_str(&" ".repeat(indent + 2));
+            if let Some(key) = key {
+                out.push_str(&Value::from(key).to_string());
+                out.push_str(": ");
+            }
+            value.write_pretty(out, indent + 2);
+        }
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+        out.push_str(close);
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Value {
+        Value::String(text)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Value {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<u16> for Value {
+    fn from(n: u16) -> Value {
+        Value::Number(n as f64)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Bool(value)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", n as i64),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(ref text) => write_string(f, text),
+            Value::Array(ref values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(ref fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct JsonErr {
+    pub position: usize,
+}
+
+impl fmt::Display for JsonErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Malformed JSON at byte {}.", self.position)
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error<T>(&self) -> Result<T, JsonErr> {
+        Err(JsonErr { position: self.position })
+    }
+    fn skip_space(&mut self) {
+        while self.bytes.get(self.position).is_some_and(|byte| byte.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+    fn expect(&mut self, literal: &str) -> Result<(), JsonErr> {
+        if self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(())
+        } else {
+            self.error()
+        }
+    }
+    fn value(&mut self) -> Result<Value, JsonErr> {
+        self.skip_space();
+        let value = match self.bytes.get(self.position) {
+            Some(b'n') => self.expect("null").map(|_| Value::Null)?,
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true))?,
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false))?,
+            Some(b'"') => Value::String(self.string()?),
+            Some(b'[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                self.skip_space();
+                if self.bytes.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                } else {
+                    loop {
+                        values.push(self.value()?);
+                        self.skip_space();
+                        match self.bytes.get(self.position) {
+                            Some(b',') => self.position += 1,
+                            Some(b']') => {
+                                self.position += 1;
+                                break;
+                            }
+                            _ => return self.error(),
+                        }
+                    }
+                }
+                Value::Array(values)
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let mut fields = Vec::new();
+                self.skip_space();
+                if self.bytes.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                } else {
+                    loop {
+                        self.skip_space();
+                        let key = self.string()?;
+                        self.skip_space();
+                        self.expect(":")?;
+                        fields.push((key, self.value()?));
+                        self.skip_space();
+                        match self.bytes.get(self.position) {
+                            Some(b',') => self.position += 1,
+                            Some(b'}') => {
+                                self.position += 1;
+                                break;
+                            }
+                            _ => return self.error(),
+                        }
+                    }
+                }
+                Value::Object(fields)
+            }
+            Some(_) => self.number()?,
+            None => return self.error(),
+        };
+        Ok(value)
+    }
+    fn number(&mut self) -> Result<Value, JsonErr> {
+        let start = self.position;
+        while self.bytes.get(self.position).is_some_and(|&byte| b"+-.eE0123456789".contains(&byte)) {
+            self.position += 1;
+        }
+        let text = String::from_utf8_lossy(&self.bytes[start..self.position]);
+        match text.parse() {
+            Ok(n) => Ok(Value::Number(n)),
+            Err(_) => Err(JsonErr { position: start }),
+        }
+    }
+    fn hex4(&mut self) -> Result<u32, JsonErr> {
+        let digits = self.bytes.get(self.position..self.position + 4).and_then(|digits| std::str::from_utf8(digits).ok());
+        match digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()) {
+            Some(n) => {
+                self.position += 4;
+                Ok(n)
+            }
+            None => self.error(),
+        }
+    }
+    fn string(&mut self) -> Result<String, JsonErr> {
+        self.expect("\"")?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = match self.bytes.get(self.position) {
+                Some(&byte) => byte,
+                None => return self.error(),
+            };
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escaped = match self.bytes.get(self.position) {
+                        Some(&byte) => byte,
+                        None => return self.error(),
+                    };
+                    self.position += 1;
+                    let c = match escaped {
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => {
+                            let mut n = self.hex4()?;
+                            // A surrogate pair spells out one character.
+                            if (0xD800..0xDC00).contains(&n) && self.bytes[self.position..].starts_with(b"\\u") {
+                                self.position += 2;
+                                let low = self.hex4()?;
+                                n = 0x10000 + ((n - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            std::char::from_u32(n).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        other => other as char,
+                    };
+                    bytes.extend(c.to_string().bytes());
+                }
+                _ => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).or_else(|_| self.error())
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value, JsonErr> {
+    let mut parser = Parser { bytes: text.as_bytes(), position: 0 };
+    let value = parser.value()?;
+    parser.skip_space();
+    if parser.position != text.len() {
+        return parser.error();
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let text = r#"{"command":"input","text":"look\n\"here\"","ids":[1,2.5,true,null],"empty":{}}"#;
+        let value = parse(text).unwrap();
+        assert_eq!(value.get("text").and_then(Value::as_str), Some("look\n\"here\""));
+        assert_eq!(value.to_string(), text);
+        assert_eq!(parse(r#" "é😀" "#).unwrap(), Value::from("é😀"));
+        assert!(parse("{\"a\":}").is_err());
+        assert!(parse("[1] 2").is_err());
+    }
+
+    #[test]
+    fn pretty_printing() {
+        let value = parse(r#"{"pc":1,"memory":[[0,9],[5,1]],"flags":{"eof":false}}"#).unwrap();
+        let pretty = value.pretty();
+        assert_eq!(pretty, "{\n  \"pc\": 1,\n  \"memory\": [\n    [0,9],\n    [5,1]\n  ],\n  \"flags\": {\"eof\":false}\n}");
+        assert_eq!(parse(&pretty), Ok(value));
+    }
+}
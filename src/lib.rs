@@ -0,0 +1,68 @@
+#[macro_use]
+pub mod notice;
+
+pub mod asciicast;
+pub mod audit;
+pub mod bank;
+pub mod checkpoint;
+pub mod cluster;
+pub mod codes;
+pub mod compress;
+pub mod coredump;
+pub mod coverage;
+pub mod debugger;
+pub mod depth;
+pub mod device;
+pub mod disasm;
+pub mod editor;
+pub mod expect;
+pub mod extension;
+pub mod gametext;
+pub mod hash;
+pub mod heatmap;
+pub mod hints;
+pub mod input;
+pub mod isa;
+pub mod json;
+pub mod lockstep;
+pub mod magic;
+pub mod map;
+pub mod memory;
+pub mod metrics;
+pub mod monitor;
+pub mod output;
+pub mod profile;
+pub mod progress;
+pub mod protocol;
+pub mod regex;
+pub mod replay;
+pub mod rewind;
+pub mod screen;
+pub mod search;
+pub mod serve;
+pub mod slots;
+pub mod snapshot;
+pub mod speedrun;
+pub mod solve;
+pub mod statediff;
+pub mod statejson;
+pub mod status;
+pub mod summary;
+pub mod synacor;
+pub mod terminal;
+pub mod timeline;
+pub mod trace;
+pub mod tracediff;
+pub mod transcript;
+pub mod trigger;
+pub mod tui;
+pub mod types;
+pub mod verify;
+pub mod walkthrough;
+pub mod websocket;
+
+pub use input::InputSource;
+pub use memory::{Image, LoadError};
+pub use output::OutputSink;
+pub use synacor::{Config, EofPolicy, NonAscii, PcOverflow, Policy, Region, RunOutcome, Synacor, SynacorErr, Timer};
+pub use types::{Addr, Operand, Register, Word};
@@ -0,0 +1,86 @@
+// Differential testing against another Synacor implementation.
+//
+// The reference is started as `REFERENCE ROM` with the same input as this VM
+// on its stdin. Before executing each instruction it writes one line to its
+// stdout holding the program counter and the eight registers in decimal,
+// separated by spaces; game output belongs on stderr. The two machines are
+// stepped together and the first line that disagrees with this VM is
+// reported.
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufReader};
+use std::process::{Command, Stdio};
+
+use synacor::Synacor;
+
+type State = [u16; 9];
+
+fn state(synacor: &Synacor) -> State {
+    let mut state = [0; 9];
+    state[0] = synacor.program_counter();
+    state[1..].copy_from_slice(&synacor.registers());
+    state
+}
+
+fn parse(line: &str) -> Option<State> {
+    let mut state = [0; 9];
+    let mut words = line.split_whitespace();
+    for word in state.iter_mut() {
+        *word = words.next()?.parse().ok()?;
+    }
+    if words.next().is_some() {
+        return None;
+    }
+    Some(state)
+}
+
+fn show(state: &State) -> String {
+    format!("pc {} registers {:?}", state[0], &state[1..])
+}
+
+// Returns whether the two implementations agreed for the whole run.
+pub fn run(synacor: &mut Synacor, reference: &str, rom: &str, input: Option<&str>) -> io::Result<bool> {
+    let stdin = match input {
+        Some(path) => Stdio::from(File::open(path)?),
+        None => Stdio::null(),
+    };
+    let mut child = Command::new(reference).arg(rom).stdin(stdin).stdout(Stdio::piped()).spawn()?;
+    let mut trace = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut step = 0u64;
+    let agreed = loop {
+        let ours = state(synacor);
+        let theirs = match trace.next() {
+            Some(line) => line?,
+            None => {
+                notice!("The reference stopped at step {} while at {}.", step, show(&ours));
+                break false;
+            }
+        };
+        match parse(&theirs) {
+            Some(theirs) if theirs == ours => (),
+            Some(theirs) => {
+                notice!("Divergence at step {}:", step);
+                notice!("  this VM:   {}", show(&ours));
+                notice!("  reference: {}", show(&theirs));
+                break false;
+            }
+            None => {
+                notice!("The reference sent a malformed trace line at step {}: {:?}", step, theirs);
+                break false;
+            }
+        }
+        if let Err(err) = synacor.run_optcode() {
+            if let Some(line) = trace.next() {
+                notice!("This VM stopped at step {} ({}) but the reference continued: {}", step, err, line?);
+                break false;
+            }
+            notice!("Both implementations stopped after {} steps: {}", step + 1, err);
+            break true;
+        }
+        step += 1;
+    };
+    let _ = child.kill();
+    let _ = child.wait();
+    Ok(agreed)
+}
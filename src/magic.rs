@@ -0,0 +1,92 @@
+// A debug opcode for test ROMs to report to the host: `debug a b` (opcode
+// 23) dumps the registers when a is 0, marks checkpoint b when a is 1, and
+// otherwise reports the value b under the tag a. It does nothing to the
+// machine itself.
+
+use std::fmt;
+
+use extension::OpcodeExtension;
+use synacor::{Synacor, SynacorErr};
+use types::Operand;
+
+pub const OPCODE: u16 = 23;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Registers { pc: u16, registers: [u16; 8] },
+    Checkpoint(u16),
+    Value { tag: u16, value: u16 },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Event::Registers { pc, ref registers } => {
+                write!(f, "registers at {}:", pc)?;
+                for (index, value) in registers.iter().enumerate() {
+                    write!(f, " r{}={}", index, value)?;
+                }
+                Ok(())
+            }
+            Event::Checkpoint(number) => write!(f, "checkpoint {}", number),
+            Event::Value { tag, value } => write!(f, "value {}: {}", tag, value),
+        }
+    }
+}
+
+// Hands each event to `report`.
+pub struct Magic {
+    report: Box<dyn FnMut(Event)>,
+}
+
+impl Magic {
+    pub fn new(report: Box<dyn FnMut(Event)>) -> Magic {
+        Magic { report }
+    }
+}
+
+impl OpcodeExtension for Magic {
+    fn operands(&self, opcode: u16) -> Option<usize> {
+        if opcode == OPCODE {
+            Some(2)
+        } else {
+            None
+        }
+    }
+    fn execute(&mut self, _: u16, operands: &[Operand], synacor: &mut Synacor) -> Result<(), SynacorErr> {
+        let (kind, value) = (synacor.operand(operands[0]), synacor.operand(operands[1]));
+        let event = match kind {
+            // The program counter is already past the instruction.
+            0 => Event::Registers { pc: synacor.program_counter().wrapping_sub(3), registers: synacor.registers() },
+            1 => Event::Checkpoint(value),
+            tag => Event::Value { tag, value },
+        };
+        (self.report)(event);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use synacor::{Config, RunOutcome};
+    use Image;
+
+    #[test]
+    fn reports_events() {
+        // set r0 5; debug 0 0; debug 1 2; debug 7 r0; halt
+        let words: [u16; 13] = [1, 32768, 5, 23, 0, 0, 23, 1, 2, 23, 7, 32768, 0];
+        let mut synacor = Synacor::with_config(Config::default());
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let seen = events.clone();
+        synacor.add_extension(Box::new(Magic::new(Box::new(move |event| seen.borrow_mut().push(event.to_string())))));
+        assert!(matches!(synacor.run(), RunOutcome::Halted));
+        assert_eq!(
+            *events.borrow(),
+            ["registers at 3: r0=5 r1=0 r2=0 r3=0 r4=0 r5=0 r6=0 r7=0", "checkpoint 2", "value 7: 5"]
+        );
+    }
+}
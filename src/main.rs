@@ -1,279 +1,1522 @@
+#[macro_use]
+extern crate synacor;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
 use std::io;
-use std::fs::File;
-use std::io::prelude::*;
-use std::fmt;
+use std::process;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use synacor::input::{Chain, FileSource, InputSource, Macros, Stdin, Text};
+use synacor::output::{Buffer, FileSink, Null, Stdout, Tee};
+use synacor::checkpoint::{self, Checkpoints};
+use synacor::codes::{self, Codes};
+use synacor::coredump::{self, Core};
+use synacor::snapshot::Snapshot;
+use synacor::editor::{self, LineEditor};
+use synacor::transcript::{self, Transcript};
+use synacor::asciicast::{self, Cast};
+use synacor::walkthrough::{self, Segment};
+use synacor::trigger::Trigger;
+use synacor::map::{self, Map};
+use synacor::monitor::Monitor;
+use synacor::profile::{self, Profile, Profiler};
+use synacor::progress::Progress;
+use synacor::regex::Regex;
+use synacor::replay::Replay;
+use synacor::search::Search;
+use synacor::solve::Game;
+use synacor::slots::{self, Slots};
+use synacor::status::{self, Status};
+use synacor::gametext::{self, Owner};
+use synacor::timeline::{self, Timeline};
+use synacor::heatmap::{self, Heatmap, Recorder};
+use synacor::summary::{self, Counter, Summary};
+use synacor::audit::{self, Audit};
+use synacor::coverage::{self, Collector, Coverage};
+use synacor::depth::{self, Depth};
+use synacor::bank::Banks;
+use synacor::cluster::Cluster;
+use synacor::magic::Magic;
+use synacor::isa::{self, InstructionSet};
+use synacor::device::{Rng, Storage};
+use synacor::tui::Tui;
+use synacor::{expect, lockstep, monitor, protocol, rewind, serve, solve, speedrun, statediff, terminal, trace, tracediff, verify};
+use synacor::{Config, EofPolicy, Image, NonAscii, PcOverflow, Policy, Region, RunOutcome, Synacor, Timer};
 
-struct Synacor {
-    registers: [u16; 8],
-    memory: [u16; 0x1FFFFF],
-    stack: Vec<u16>,
-    program_counter: u16,
-    stdin: std::io::Stdin,
+fn usage() -> ! {
+    eprintln!("usage: synacor [--strict] [--literal-writes ignore|warn|error]");
+    eprintln!("               [--uninitialized-exec ignore|warn|error] [--pc-overflow wrap|error]");
+    eprintln!("               [--write-exec ignore|warn|error] [--exec-allow START-END|challenge]");
+    eprintln!("               [--eof halt|value:N|file:PATH] [--non-ascii truncate|escape|latin1|error]");
+    eprintln!("               [--stack-capacity WORDS] [--max-stack WORDS] [--mmap] [--budget INSTRUCTIONS]");
+    eprintln!("               [--timer INSTRUCTIONS:HANDLER] [--rng ADDRESS[:SEED]]");
+    eprintln!("               [--storage ADDRESS:FILE] [--banks COUNT] [--debug-opcode]");
+    eprintln!("               [--isa spec|challenge|homebrew|instrumented] [--read-only START-END]");
+    eprintln!("               [--tee FILE] [--history FILE] [--no-line-editing]");
+    eprintln!("               [--macros FILE] [--transcript FILE] [--asciicast FILE] [--triggers FILE]");
+    eprintln!("               [--codes FILE] [--protocol jsonl]");
+    eprintln!("               [--saves DIR] [--load NAME] [--save NAME] [--no-commands]");
+    eprintln!("               [--checkpoint prompt|INSTRUCTIONS] [--checkpoints COUNT]");
+    eprintln!("               [--core FILE] [--no-core] [--rewind TURNS] [--record FILE]");
+    eprintln!("               [--bypass-teleporter] [--map FILE] [--play-to MILESTONE]");
+    eprintln!("               [--progress] [--trace FILE] [--trace-format text|binary]");
+    eprintln!("               [--trace-filter FILTER] [--profile] [--flamegraph FILE]");
+    eprintln!("               [--timeline FILE] [--heatmap FILE] [--stats [json]]");
+    eprintln!("               [--audit REGISTERS FILE] [--coverage FILE]");
+    eprintln!("               [--stack-depth FILE] [--stack-interval INSTRUCTIONS]");
+    eprintln!("       synacor serve --telnet|--websocket ADDRESS [ROM] [--metrics ADDRESS]");
+    eprintln!("       synacor tui [ROM] [--map FILE]");
+    eprintln!("       synacor saves list [DIR]");
+    eprintln!("       synacor status SAVE");
+    eprintln!("       synacor codes check FILE [--hashes FILE]");
+    eprintln!("       synacor codes hash CODE...");
+    eprintln!("       synacor statediff A B");
+    eprintln!("       synacor tracediff A B [--context N]");
+    eprintln!("       synacor export SNAPSHOT FILE [--spec]");
+    eprintln!("       synacor inspect CORE [--report]");
+    eprintln!("       synacor strings [ROM|SNAPSHOT]");
+    eprintln!("       synacor coverage report|listing FILE [ROM|SNAPSHOT]");
+    eprintln!("       synacor convert FROM TO");
+    eprintln!("       synacor map where SAVE [MAP]");
+    eprintln!("       synacor map dot MAP");
+    eprintln!("       synacor search SAVE PATTERN [--depth N] [--states N] [--moves-only]");
+    eprintln!("       synacor solve list");
+    eprintln!("       synacor solve teleporter|coins|vault|maze [SAVE|ROM] [ARGUMENT]");
+    eprintln!("       synacor solve teleporter [SAVE|ROM] --save SLOT");
+    eprintln!("       synacor verify [ROM]");
+    eprintln!("       synacor expect SCRIPT [ROM]");
+    eprintln!("       synacor replay TRANSCRIPT [ROM]");
+    eprintln!("       synacor replay RECORDING");
+    eprintln!("       synacor speedrun RECORDING [SCRIPT]");
+    eprintln!("       synacor lockstep REFERENCE [--input FILE] [ROM]");
+    eprintln!("       synacor pipeline ROM...");
+    process::exit(2);
 }
 
-enum SynacorErr {
-    Halted,
-    BadRegister,
-    StackUnderflow,
-    BadOptcode,
-    InputErr(io::Error),
+// The value after `flag`, which should be `kind`, e.g. "a number of words".
+fn flag_value<T: FromStr>(args: &mut std::slice::Iter<String>, flag: &str, kind: &str) -> T {
+    match args.next().map(|value| value.parse()) {
+        Some(Ok(value)) => value,
+        _ => {
+            notice!("{} expects {}.", flag, kind);
+            usage();
+        }
+    }
+}
+
+// ADDRESS[:SEED] for --rng. Without a seed it takes one from the clock and
+// says what it was, so the run can be played again.
+fn rng_option(arg: &str) -> Result<(usize, u64), String> {
+    let (address, seed) = match arg.split_once(':') {
+        Some((address, seed)) => (address, Some(seed.parse().map_err(|_| format!("{:?} isn't a seed", seed))?)),
+        None => (arg, None),
+    };
+    let address = trace::address(address)? as usize;
+    let seed = seed.unwrap_or_else(|| {
+        let seed = Rng::clock_seed();
+        notice!("The RNG's seed is {}; --rng {}:{} plays this run again.", seed, address, seed);
+        seed
+    });
+    Ok((address, seed))
+}
+
+fn policy(arg: Option<&String>) -> Policy {
+    match arg.map(|arg| arg.as_str()) {
+        Some("ignore") => Policy::Ignore,
+        Some("warn") => Policy::Warn,
+        Some("error") => Policy::Error,
+        _ => usage(),
+    }
 }
 
-impl fmt::Display for SynacorErr {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            SynacorErr::Halted => write!(f, "The synacor halted."),
-            SynacorErr::BadRegister => write!(f, "The synacor accessed a bad register."),
-            SynacorErr::StackUnderflow => write!(f, "The synacor's stack underflowed."),
-            SynacorErr::BadOptcode => write!(f, "The synacor's optcode is not implemented."),
-            SynacorErr::InputErr(ref err) => write!(f, "{}", err),
+fn open_input(path: &str) -> FileSource {
+    match FileSource::open(path) {
+        Ok(file) => file,
+        Err(err) => {
+            notice!("Could not open {}: {}", path, err);
+            process::exit(1);
         }
     }
 }
 
-impl Synacor {
-    fn new() -> Synacor {
-        Synacor {
-            registers: [0; 8],
-            memory: [0; 0x1FFFFF],
-            stack: Vec::new(),
-            program_counter: 0,
-            stdin: io::stdin(),
+// Sets the EOF policy; file:PATH instead returns a file to keep reading from
+// once stdin runs out.
+fn eof_policy(config: &mut Config, arg: Option<&String>) -> Option<FileSource> {
+    let arg = arg.map(|arg| arg.as_str()).unwrap_or("");
+    if arg == "halt" {
+        config.on_eof = EofPolicy::Halt;
+    } else if let Some(value) = arg.strip_prefix("value:") {
+        match value.parse() {
+            Ok(value) => config.on_eof = EofPolicy::Value(value),
+            Err(_) => usage(),
         }
+    } else if let Some(path) = arg.strip_prefix("file:") {
+        return Some(open_input(path));
+    } else {
+        usage()
     }
-    fn read_word_code(&mut self) -> u16 {
-        self.program_counter += 1;
-        self.memory[self.program_counter as usize - 1]
+    None
+}
+
+// The line editor when playing on a terminal, otherwise plain stdin.
+// With emulator commands on, Ctrl+S and Ctrl+L save and load the quick slot.
+fn interactive_input(line_editing: bool, history: Option<String>, hotkeys: bool) -> Box<dyn InputSource> {
+    if !line_editing || !editor::available() {
+        return Box::new(Stdin);
     }
-    fn read_word_data(&mut self, location: u16) -> Result<u16, SynacorErr> {
-        if location < 32768 {
-            Ok(location)
-        } else {
-            let register = location % 32768;
-            if register > 8 {
-                Err(SynacorErr::BadRegister)
-            } else {
-                Ok(self.registers[register as usize])
-            }
+    let history = history.or_else(|| env::var("HOME").ok().map(|home| format!("{}/.synacor_history", home)));
+    match LineEditor::new(history.as_deref()) {
+        Ok(editor) if hotkeys => Box::new(
+            editor
+                .hotkey('s', &format!("{}save {}", monitor::PREFIX as char, monitor::QUICK))
+                .hotkey('l', &format!("{}load {}", monitor::PREFIX as char, monitor::QUICK)),
+        ),
+        Ok(editor) => Box::new(editor),
+        Err(err) => {
+            notice!("Could not open the history file: {}", err);
+            Box::new(Stdin)
         }
     }
-    fn read_bytes_into_ram(&mut self, bytes: &[u8]) {
-        for i in bytes.iter().enumerate().zip(bytes.iter().skip(1)) {
-            let ((mut index, byte1), byte2) = i;
-            if index % 2 == 1 {
-                continue;
+}
+
+// Macros come from --macros, or ~/.synacor_macros if it exists.
+fn macro_table(path: Option<String>) -> Option<String> {
+    match path {
+        Some(path) => match fs::read_to_string(&path) {
+            Ok(table) => Some(table),
+            Err(err) => {
+                notice!("Could not read {}: {}", path, err);
+                process::exit(1);
             }
-            index /= 2;
-            let mut word = *byte2 as u16;
-            word <<= 8;
-            word |= *byte1 as u16;
-            self.memory[index] = word;
-        }
-    }
-    fn write_word_data(&mut self, location: u16, word: u16) -> Result<(), SynacorErr> {
-        if location < 32768 {
-            Ok(())
-        } else {
-            let register = location % 32768;
-            if register > 8 {
-                Err(SynacorErr::BadRegister)
-            } else {
-                self.registers[register as usize] = word;
-                Ok(())
-            }
-        }
-    }
-    fn run_optcode(&mut self) -> Result<(), SynacorErr> {
-        match self.read_word_code() {
-            0 => Err(SynacorErr::Halted),
-            1 => {
-                let write_reg = self.read_word_code();
-                let word_loc = self.read_word_code();
-                let word = try!(self.read_word_data(word_loc));
-                self.write_word_data(write_reg, word)
-            }
-            2 => {
-                let location = self.read_word_code();
-                let word = try!(self.read_word_data(location));
-                self.stack.push(word);
-                Ok(())
-            }
-            3 => {
-                if let Some(word) = self.stack.pop() {
-                    let location = self.read_word_code();
-                    try!(self.write_word_data(location, word));
-                    Ok(())
-                } else {
-                    Err(SynacorErr::StackUnderflow)
+        },
+        None => env::var("HOME").ok().and_then(|home| fs::read_to_string(format!("{}/.synacor_macros", home)).ok()),
+    }
+}
+
+struct Options {
+    config: Config,
+    mmap: bool,
+    budget: Option<u64>,
+    codes: Option<Rc<RefCell<Codes>>>,
+    protocol: bool,
+    saves: String,
+    checkpoint: Option<checkpoint::Policy>,
+    checkpoints: usize,
+    load: Option<String>,
+    save: Option<String>,
+    core: Option<String>,
+    rewind: usize,
+    record: Option<String>,
+    trace: Option<(String, trace::Format, Option<trace::Filter>)>,
+    profile: bool,
+    flamegraph: Option<String>,
+    timeline: Option<String>,
+    heatmap: Option<String>,
+    // Some(true) for JSON.
+    stats: Option<bool>,
+    audit: Option<(Vec<usize>, String)>,
+    coverage: Option<String>,
+    stack_depth: Option<(String, u64)>,
+    bypass_teleporter: bool,
+    map: Option<(String, Rc<RefCell<Map>>)>,
+    progress: Option<Rc<RefCell<Progress>>>,
+    play_to: Option<&'static [Segment]>,
+    // Where to map the RNG and its seed.
+    rng: Option<(usize, u64)>,
+    // Where to map storage and the file it keeps.
+    storage: Option<(usize, String)>,
+    banks: Option<usize>,
+    debug_opcode: bool,
+    isa: Option<Box<dyn InstructionSet>>,
+}
+
+fn parse_options(args: &[String]) -> Options {
+    let mut config = Config::default();
+    let mut mmap = false;
+    let mut budget = None;
+    let mut fallback = None;
+    let mut line_editing = true;
+    let mut history = None;
+    let mut macros = None;
+    let mut cast = None;
+    let mut codes = None;
+    let mut protocol = false;
+    let mut saves = slots::DIR.to_string();
+    let mut checkpoint = None;
+    let mut checkpoints = checkpoint::KEEP;
+    let mut load = None;
+    let mut save = None;
+    let mut core = Some(coredump::PATH.to_string());
+    let mut rewind = rewind::KEEP;
+    let mut record = None;
+    let mut trace = None;
+    let mut trace_format = trace::Format::Text;
+    let mut trace_filter = None;
+    let mut profile = false;
+    let mut flamegraph = None;
+    let mut timeline = None;
+    let mut heatmap = None;
+    let mut stats = None;
+    let mut audit = None;
+    let mut coverage = None;
+    let mut stack_depth = None;
+    let mut stack_interval = depth::INTERVAL;
+    let mut bypass_teleporter = false;
+    let mut map = None;
+    let mut progress = None;
+    let mut play_to = None;
+    let mut rng = None;
+    let mut storage = None;
+    let mut banks = None;
+    let mut debug_opcode = false;
+    let mut isa = None;
+    config.command_prefix = Some(monitor::PREFIX);
+    config.triggers.push(codes::mirror_trigger());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mmap" => mmap = true,
+            "--budget" => budget = Some(flag_value(&mut args, arg, "a number of instructions")),
+            "--no-line-editing" => line_editing = false,
+            "--no-commands" => config.command_prefix = None,
+            "--saves" => saves = args.next().unwrap_or_else(|| usage()).clone(),
+            "--checkpoint" => match args.next().map(|value| value.as_str()) {
+                Some("prompt") => checkpoint = Some(checkpoint::Policy::Prompt),
+                Some(value) => match value.parse() {
+                    Ok(every) if every > 0 => checkpoint = Some(checkpoint::Policy::Every(every)),
+                    _ => usage(),
+                },
+                None => usage(),
+            },
+            "--checkpoints" => checkpoints = flag_value(&mut args, arg, "a number of checkpoints"),
+            "--core" => core = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--no-core" => core = None,
+            "--rewind" => rewind = flag_value(&mut args, arg, "a number of turns"),
+            "--bypass-teleporter" => bypass_teleporter = true,
+            "--play-to" => match walkthrough::to(args.next().unwrap_or_else(|| usage())) {
+                Ok(segments) => play_to = Some(segments),
+                Err(err) => {
+                    notice!("Can't play to that: {}.", err);
+                    process::exit(2);
+                }
+            },
+            "--trace" => trace = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--trace-format" => {
+                trace_format = match args.next().map(|value| value.as_str()) {
+                    Some("text") => trace::Format::Text,
+                    Some("binary") => trace::Format::Binary,
+                    _ => usage(),
                 }
             }
-            4 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let location_c = self.read_word_code();
-                if try!(self.read_word_data(location_b)) == try!(self.read_word_data(location_c)) {
-                    self.write_word_data(location_a, 1)
-                } else {
-                    self.write_word_data(location_a, 0)
+            "--trace-filter" => match trace::Filter::parse(args.next().unwrap_or_else(|| usage())) {
+                Ok(filter) => trace_filter = Some(filter),
+                Err(err) => {
+                    notice!("Could not read the trace filter: {}.", err);
+                    process::exit(2);
                 }
+            },
+            "--profile" => profile = true,
+            "--flamegraph" => flamegraph = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--timeline" => timeline = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--heatmap" => heatmap = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--stats" => {
+                let json = args.clone().next().map(|value| value.as_str()) == Some("json");
+                if json {
+                    args.next();
+                }
+                stats = Some(json);
             }
-            5 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let location_c = self.read_word_code();
-                if try!(self.read_word_data(location_b)) > try!(self.read_word_data(location_c)) {
-                    self.write_word_data(location_a, 1)
-                } else {
-                    self.write_word_data(location_a, 0)
+            "--audit" => {
+                let registers = audit::registers(args.next().unwrap_or_else(|| usage())).unwrap_or_else(|err| {
+                    notice!("Could not read the registers to audit: {}.", err);
+                    process::exit(2);
+                });
+                audit = Some((registers, args.next().unwrap_or_else(|| usage()).clone()));
+            }
+            "--coverage" => coverage = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--stack-depth" => stack_depth = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--stack-interval" => stack_interval = flag_value(&mut args, arg, "a number of instructions"),
+            "--record" => record = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--load" => load = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--save" => save = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--protocol" => match args.next().map(|value| value.as_str()) {
+                Some("jsonl") => protocol = true,
+                _ => usage(),
+            },
+            "--history" => history = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--transcript" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                match Transcript::create(path) {
+                    Ok(transcript) => config.transcript = Some(transcript),
+                    Err(err) => {
+                        notice!("Could not create {}: {}", path, err);
+                        process::exit(1);
+                    }
                 }
             }
-            6 => {
-                let location = self.read_word_code();
-                let jump = try!(self.read_word_data(location));
-                self.program_counter = jump;
-                Ok(())
-            }
-            7 => {
-                let test_loc = self.read_word_code();
-                let test = try!(self.read_word_data(test_loc));
-                let jump_loc = self.read_word_code();
-                if test != 0 {
-                    let jump = try!(self.read_word_data(jump_loc));
-                    self.program_counter = jump;
-                    Ok(())
-                } else {
-                    Ok(())
+            "--asciicast" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                let size = terminal::size().unwrap_or(asciicast::DEFAULT_SIZE);
+                match Cast::create(path, size) {
+                    Ok(created) => cast = Some(created),
+                    Err(err) => {
+                        notice!("Could not create {}: {}", path, err);
+                        process::exit(1);
+                    }
                 }
             }
-            8 => {
-                let test_loc = self.read_word_code();
-                let test = try!(self.read_word_data(test_loc));
-                let jump_loc = self.read_word_code();
-                if test == 0 {
-                    let jump = try!(self.read_word_data(jump_loc));
-                    self.program_counter = jump;
-                    Ok(())
-                } else {
-                    Ok(())
+            "--triggers" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                match fs::read_to_string(path).map(|text| Trigger::parse(&text)) {
+                    Ok(Ok(triggers)) => config.triggers.extend(triggers),
+                    Ok(Err(err)) => {
+                        notice!("{}", err);
+                        process::exit(1);
+                    }
+                    Err(err) => {
+                        notice!("Could not read {}: {}", path, err);
+                        process::exit(1);
+                    }
                 }
             }
-            9 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let location_c = self.read_word_code();
-                let b = try!(self.read_word_data(location_b));
-                let c = try!(self.read_word_data(location_c));
-                let mut sum = b.wrapping_add(c);
-                sum %= 32768;
-                self.write_word_data(location_a, sum)
-            }
-            10 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let location_c = self.read_word_code();
-                let b = try!(self.read_word_data(location_b));
-                let c = try!(self.read_word_data(location_c));
-                let mut prod = b.wrapping_mul(c);
-                prod %= 32768;
-                self.write_word_data(location_a, prod)
-            }
-            11 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let location_c = self.read_word_code();
-                let b = try!(self.read_word_data(location_b));
-                let c = try!(self.read_word_data(location_c));
-                let mut rem = b % c;
-                rem %= 32768;
-                self.write_word_data(location_a, rem)
-            }
-            12 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let location_c = self.read_word_code();
-                let b = try!(self.read_word_data(location_b));
-                let c = try!(self.read_word_data(location_c));
-                let and = b & c;
-                self.write_word_data(location_a, and)
-            }
-            13 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let location_c = self.read_word_code();
-                let b = try!(self.read_word_data(location_b));
-                let c = try!(self.read_word_data(location_c));
-                let or = b | c;
-                self.write_word_data(location_a, or)
-            }
-            14 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let b = try!(self.read_word_data(location_b));
-                let b_inv = b ^ 0x7FFF;
-                self.write_word_data(location_a, b_inv)
-            }
-            15 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let b = try!(self.read_word_data(location_b));
-                let a = self.memory[b as usize];
-                self.write_word_data(location_a, a)
-            }
-            16 => {
-                let location_a = self.read_word_code();
-                let location_b = self.read_word_code();
-                let a = try!(self.read_word_data(location_a));
-                let b = try!(self.read_word_data(location_b));
-                self.memory[a as usize] = b;
-                Ok(())
-            }
-            17 => {
-                let location_a = self.read_word_code();
-                let a = try!(self.read_word_data(location_a));
-                self.stack.push(self.program_counter);
-                self.program_counter = a;
-                Ok(())
-            }
-            18 => {
-                if let Some(jump) = self.stack.pop() {
-                    self.program_counter = jump;
-                    Ok(())
-                } else {
-                    Err(SynacorErr::StackUnderflow)
+            "--codes" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                match Codes::open(path) {
+                    Ok(found) => {
+                        let found = Rc::new(RefCell::new(found));
+                        config.triggers.push(Codes::trigger(found.clone()));
+                        codes = Some(found);
+                    }
+                    Err(err) => {
+                        notice!("Could not read {}: {}", path, err);
+                        process::exit(1);
+                    }
                 }
             }
-            19 => {
-                let location = self.read_word_code();
-                let char = try!(self.read_word_data(location)) as u8 as char;
-                print!("{}", char);
-                Ok(())
-            }
-            20 => {
-                ;
-                let location_a = self.read_word_code();
-                let mut char_buf = [0; 1];
-                if let Err(err) = self.stdin.lock().read(&mut char_buf) {
-                    return Err(SynacorErr::InputErr(err))
+            "--map" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                match Map::open(path) {
+                    Ok(opened) => {
+                        let opened = Rc::new(RefCell::new(opened));
+                        config.triggers.push(Map::trigger(opened.clone()));
+                        map = Some((path.clone(), opened));
+                    }
+                    Err(err) => {
+                        notice!("Could not read {}: {}", path, err);
+                        process::exit(1);
+                    }
+                }
+            }
+            "--progress" => {
+                let seen = Rc::new(RefCell::new(Progress::default()));
+                config.triggers.push(Progress::trigger(seen.clone()));
+                progress = Some(seen);
+            }
+            "--macros" => macros = Some(args.next().unwrap_or_else(|| usage()).clone()),
+            "--tee" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                match FileSink::create(path) {
+                    Ok(file) => config.output = Box::new(Tee::new(vec![Box::new(Stdout), Box::new(file)])),
+                    Err(err) => {
+                        notice!("Could not create {}: {}", path, err);
+                        process::exit(1);
+                    }
+                }
+            }
+            "--strict" => config.strict = true,
+            "--literal-writes" => config.literal_writes = policy(args.next()),
+            "--uninitialized-exec" => config.uninitialized_exec = policy(args.next()),
+            "--write-exec" => config.write_exec = policy(args.next()),
+            "--exec-allow" => match args.next().map(|arg| arg.as_str()).unwrap_or_else(|| usage()) {
+                "challenge" => config.exec_allowed.push(solve::SELF_MODIFIED),
+                arg => match Region::parse(arg) {
+                    Ok(region) => config.exec_allowed.push(region),
+                    Err(err) => {
+                        notice!("Could not read the region: {}.", err);
+                        usage();
+                    }
+                },
+            },
+            "--pc-overflow" => {
+                config.pc_overflow = match args.next().map(|value| value.as_str()) {
+                    Some("wrap") => PcOverflow::Wrap,
+                    Some("error") => PcOverflow::Error,
+                    _ => usage(),
+                }
+            }
+            "--eof" => fallback = eof_policy(&mut config, args.next()),
+            "--non-ascii" => {
+                config.non_ascii = match args.next().map(|value| value.as_str()) {
+                    Some("truncate") => NonAscii::Truncate,
+                    Some("escape") => NonAscii::Escape,
+                    Some("latin1") => NonAscii::Latin1,
+                    Some("error") => NonAscii::Error,
+                    _ => usage(),
+                }
+            }
+            "--stack-capacity" => config.stack_capacity = flag_value(&mut args, arg, "a number of words"),
+            "--max-stack" => config.max_stack_depth = Some(flag_value(&mut args, arg, "a number of words")),
+            "--rng" => match rng_option(args.next().unwrap_or_else(|| usage())) {
+                Ok(found) => rng = Some(found),
+                Err(err) => {
+                    notice!("Could not read the RNG: {}.", err);
+                    usage();
+                }
+            },
+            "--storage" => {
+                let arg = args.next().unwrap_or_else(|| usage());
+                let (address, path) = arg.split_once(':').unwrap_or_else(|| usage());
+                match trace::address(address) {
+                    Ok(address) => storage = Some((address as usize, path.to_string())),
+                    Err(err) => {
+                        notice!("Could not read the storage address: {}.", err);
+                        usage();
+                    }
+                }
+            }
+            "--debug-opcode" => debug_opcode = true,
+            "--isa" => isa = Some(isa::named(args.next().unwrap_or_else(|| usage())).unwrap_or_else(|| usage())),
+            "--banks" => banks = Some(flag_value(&mut args, arg, "a number of banks")),
+            "--timer" => match Timer::parse(args.next().unwrap_or_else(|| usage())) {
+                Ok(timer) => config.timer = Some(timer),
+                Err(err) => {
+                    notice!("Could not read the timer: {}.", err);
+                    usage();
+                }
+            },
+            "--read-only" => match Region::parse(args.next().unwrap_or_else(|| usage())) {
+                Ok(region) => config.read_only.push(region),
+                Err(err) => {
+                    notice!("Could not read the region: {}.", err);
+                    usage();
+                }
+            },
+            _ => usage(),
+        }
+    }
+    if let Some(ref isa) = isa {
+        isa.configure(&mut config);
+    }
+    let interactive = interactive_input(line_editing && !protocol, history, config.command_prefix.is_some());
+    config.input = match fallback {
+        Some(file) => Box::new(Chain::new(vec![interactive, Box::new(file)])),
+        None => interactive,
+    };
+    if let Some(table) = macro_table(macros) {
+        config.input = Box::new(Macros::new(config.input, &table));
+    }
+    if let Some((_, ref map)) = map {
+        config.input = Box::new(map::Tap::new(map.clone(), config.input));
+    }
+    if let Some(cast) = cast {
+        config.input = Box::new(asciicast::Input::new(cast.clone(), config.input));
+        config.output = Box::new(asciicast::Output::new(cast, config.output));
+    }
+    if play_to.is_some() && load.is_some() {
+        notice!("--play-to starts from the beginning, so it can't be used with --load.");
+        process::exit(2);
+    }
+    // Turns for /rewind are taken at prompts too, the teleporter bypass sets
+    // r7 at the first one and --play-to types each segment at one.
+    config.pause_at_prompt = checkpoint == Some(checkpoint::Policy::Prompt)
+        || (rewind > 0 && config.command_prefix.is_some())
+        || bypass_teleporter
+        || play_to.is_some();
+    if core.is_some() {
+        config.trace_depth = coredump::TRACE;
+    }
+    Options { config, mmap, budget, codes, protocol, saves, checkpoint, checkpoints, load, save, core, rewind, record,
+        trace: trace.map(|path| (path, trace_format, trace_filter)), profile, flamegraph, timeline, heatmap, stats, audit, coverage,
+        stack_depth: stack_depth.map(|path| (path, stack_interval)), bypass_teleporter, map, progress, play_to, rng, storage, banks, debug_opcode, isa }
+}
+
+fn load(path: &str, mmap: bool, config: Config) -> Synacor {
+    let image = match Image::open(path, mmap) {
+        Ok(image) => image,
+        Err(err) => {
+            notice!("Could not open {}: {}", path, err);
+            process::exit(1);
+        }
+    };
+    let mut synacor = Synacor::with_config(config);
+    if let Err(err) = synacor.load_image(image) {
+        notice!("Could not load {}: {}", path, err);
+        process::exit(1);
+    }
+    synacor
+}
+
+fn serve_command(args: &[String]) -> ! {
+    let websocket = match args.first().map(|arg| arg.as_str()) {
+        Some("--telnet") => false,
+        Some("--websocket") => true,
+        _ => usage(),
+    };
+    let address = args.get(1).unwrap_or_else(|| usage());
+    let mut rom = "challenge.bin";
+    let mut metrics = None;
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--metrics" => metrics = Some(rest.next().unwrap_or_else(|| usage()).as_str()),
+            _ => rom = arg,
+        }
+    }
+    let bytes = match fs::read(rom) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            notice!("Could not open {}: {}", rom, err);
+            process::exit(1);
+        }
+    };
+    let served = if websocket { serve::websocket(address, bytes, metrics) } else { serve::telnet(address, bytes, metrics) };
+    if let Err(err) = served {
+        notice!("Could not serve on {}: {}", address, err);
+    }
+    process::exit(1);
+}
+
+fn tui_command(args: &[String]) -> ! {
+    let (rom, path) = match args {
+        [] => ("challenge.bin", None),
+        [rom] => (rom.as_str(), None),
+        [flag, path] if flag == "--map" => ("challenge.bin", Some(path)),
+        [rom, flag, path] if flag == "--map" => (rom.as_str(), Some(path)),
+        _ => usage(),
+    };
+    let map = match path.map(|path| Map::open(path)) {
+        Some(Ok(map)) => map,
+        Some(Err(err)) => {
+            notice!("Could not read {}: {}", path.unwrap(), err);
+            process::exit(1);
+        }
+        None => Map::default(),
+    };
+    let map = Rc::new(RefCell::new(map));
+    if !editor::available() {
+        notice!("The TUI needs a terminal.");
+        process::exit(1);
+    }
+    let output = Buffer::default();
+    let config = Config { output: Box::new(output.clone()), input: Box::new(Text::default()), on_eof: EofPolicy::Yield, ..Config::default() };
+    let synacor = load(rom, false, config);
+    let run = Tui::new(synacor, output).with_map(map.clone()).run();
+    if let Some(path) = path {
+        if let Err(err) = map.borrow().save(path) {
+            notice!("Could not write the map to {}: {}", path, err);
+        }
+    }
+    if let Err(err) = run {
+        notice!("The TUI failed: {}", err);
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
+// Restores a save into a machine that prints to a buffer and yields for
+// input, ready to be fed commands.
+fn restore_save(save: &str) -> Game {
+    let (path, snapshot) = Slots::new(slots::DIR).load(save).unwrap_or_else(|err| {
+        notice!("Could not load {}: {}", save, err);
+        process::exit(1);
+    });
+    Game::new(&snapshot).unwrap_or_else(|err| {
+        notice!("Could not restore {}: {}", path.display(), err);
+        process::exit(1);
+    })
+}
+
+fn search_command(args: &[String]) -> ! {
+    let (save, pattern) = match args {
+        [save, pattern, ..] => (save, pattern),
+        _ => usage(),
+    };
+    let goal = Regex::new(pattern).unwrap_or_else(|err| {
+        notice!("Bad pattern {:?}: {}", pattern, err);
+        process::exit(2);
+    });
+    let mut search = Search::default();
+    let mut flags = args[2..].iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--depth" => search.depth = flag_value(&mut flags, flag, "a number of commands"),
+            "--states" => search.states = flag_value(&mut flags, flag, "a number of states"),
+            "--moves-only" => search.items = false,
+            _ => usage(),
+        }
+    }
+    let mut game = restore_save(save);
+    match search.run(&mut game.synacor, &game.capture, |text| text.lines().any(|line| goal.is_match(line))) {
+        Some(path) => {
+            println!("Found it after {} commands:", path.len());
+            for command in path {
+                println!("{}", command);
+            }
+            process::exit(0);
+        }
+        None => {
+            println!("Nothing within {} commands matched.", search.depth);
+            process::exit(1);
+        }
+    }
+}
+
+fn solve_command(args: &[String]) -> ! {
+    // --save SLOT keeps the game with the solution in place.
+    let mut slot = None;
+    let mut positional = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--save" => slot = Some(rest.next().unwrap_or_else(|| usage())),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    let args = &positional[..];
+    let name = args.first().unwrap_or_else(|| usage());
+    if name == "list" {
+        for solver in solve::solvers() {
+            println!("{:<11} {}", solver.name(), solver.about());
+        }
+        process::exit(0);
+    }
+    let solver = solve::solver(name).unwrap_or_else(|| usage());
+    if slot.is_some() && solver.name() != "teleporter" {
+        notice!("Only the teleporter's solution can be saved.");
+        process::exit(2);
+    }
+    let mut game = match args.get(1) {
+        Some(save) if Slots::new(slots::DIR).load(save).is_ok() => restore_save(save),
+        source => {
+            // A fresh game, once it's through the self-test.
+            let rom = source.map_or("challenge.bin", |rom| rom.as_str());
+            let config = Config { output: Box::new(Null), input: Box::new(Text::default()), on_eof: EofPolicy::Yield, ..Config::default() };
+            let mut synacor = load(rom, false, config);
+            synacor.run_for(10_000_000);
+            Game::fork(&synacor).unwrap_or_else(|err| {
+                notice!("Could not copy the machine: {}", err);
+                process::exit(1);
+            })
+        }
+    };
+    match solver.solve(&mut game, args.get(2).map(|argument| argument.as_str())) {
+        Ok(solution) => {
+            println!("{}", solution.summary);
+            for command in solution.commands {
+                println!("{}", command);
+            }
+            if let Some(slot) = slot {
+                match Slots::new(slots::DIR).save(&game.synacor, slot, "teleporter solved") {
+                    Ok(path) => println!("Saved to {}.", path.display()),
+                    Err(err) => {
+                        notice!("Could not save {}: {}", slot, err);
+                        process::exit(1);
+                    }
                 }
-                let char16 = char_buf[0] as u16;
-                self.write_word_data(location_a, char16)
             }
-            21 => Ok(()),
-            _ => Err(SynacorErr::BadOptcode),
+            process::exit(0);
+        }
+        Err(err) => {
+            println!("Could not solve the {}: {}.", solver.name(), err);
+            process::exit(1);
+        }
+    }
+}
+
+fn expect_command(args: &[String]) -> ! {
+    let path = args.first().unwrap_or_else(|| usage());
+    let steps = match fs::read_to_string(path).map(|script| expect::parse(&script)) {
+        Ok(Ok(steps)) => steps,
+        Ok(Err(err)) => {
+            notice!("{}", err);
+            process::exit(1);
+        }
+        Err(err) => {
+            notice!("Could not read {}: {}", path, err);
+            process::exit(1);
+        }
+    };
+    let capture = Buffer::default();
+    let config = Config {
+        output: Box::new(Tee::new(vec![Box::new(Stdout), Box::new(capture.clone())])),
+        input: Box::new(Text::default()),
+        on_eof: EofPolicy::Yield,
+        ..Config::default()
+    };
+    let rom = args.get(1).map_or("challenge.bin", |rom| rom.as_str());
+    let mut synacor = load(rom, false, config);
+    match expect::run(&mut synacor, &capture, &steps) {
+        Ok(()) => process::exit(0),
+        Err(err) => {
+            notice!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn replay_command(args: &[String]) -> ! {
+    let path = args.first().unwrap_or_else(|| usage());
+    if Replay::is_replay(path) {
+        let replay = Replay::load(path).unwrap_or_else(|err| {
+            notice!("Could not read {}: {}", path, err);
+            process::exit(1);
+        });
+        match replay.verify() {
+            Ok(()) => {
+                println!("The replay of {} inputs matched, ending after {} instructions.", replay.recording.input.len(), replay.end);
+                process::exit(0);
+            }
+            Err(mismatch) => {
+                println!("{}", mismatch);
+                process::exit(1);
+            }
+        }
+    }
+    let recorded = match Transcript::open(path) {
+        Ok(transcript) => transcript,
+        Err(err) => {
+            notice!("Could not read {}: {}", path, err);
+            process::exit(1);
+        }
+    };
+    let config = Config {
+        output: Box::new(Null),
+        input: Box::new(Text::new(&String::from_utf8_lossy(&recorded.input()))),
+        transcript: Some(Transcript::default()),
+        ..Config::default()
+    };
+    let rom = args.get(1).map_or("challenge.bin", |rom| rom.as_str());
+    let mut synacor = load(rom, false, config);
+    let outcome = synacor.run();
+    let replayed = synacor.take_transcript().unwrap_or_default();
+    match transcript::compare(&recorded, &replayed) {
+        None => {
+            println!("The replay matched all {} entries ({}).", recorded.entries().len(), outcome);
+            process::exit(0);
+        }
+        Some(divergence) => {
+            println!("{}", divergence);
+            process::exit(1);
+        }
+    }
+}
+
+fn protocol_command(options: Options) -> ! {
+    let capture = Buffer::default();
+    let config = Config {
+        output: Box::new(capture.clone()),
+        input: Box::new(Text::default()),
+        on_eof: EofPolicy::Yield,
+        ..options.config
+    };
+    let mut synacor = load("challenge.bin", options.mmap, config);
+    let stdin = io::stdin();
+    if let Err(err) = protocol::jsonl(&mut synacor, &capture, stdin.lock(), io::stdout()) {
+        notice!("The protocol stopped: {}", err);
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
+fn lockstep_command(args: &[String]) -> ! {
+    let reference = args.first().unwrap_or_else(|| usage());
+    let mut input = None;
+    let mut rom = "challenge.bin";
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input = Some(args.next().unwrap_or_else(|| usage()).as_str()),
+            _ => rom = arg,
+        }
+    }
+    let vm_input: Box<dyn InputSource> = match input {
+        Some(path) => Box::new(open_input(path)),
+        None => Box::new(Text::default()),
+    };
+    let config = Config { output: Box::new(Null), input: vm_input, ..Config::default() };
+    let mut synacor = load(rom, false, config);
+    match lockstep::run(&mut synacor, reference, rom, input) {
+        Ok(true) => process::exit(0),
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            notice!("Could not run {}: {}", reference, err);
+            process::exit(1);
+        }
+    }
+}
+
+// Runs the ROMs side by side, each sending words on to the next through the
+// channel ends at cluster::SEND and cluster::RECEIVE. The first reads stdin;
+// they all print to stdout.
+fn pipeline_command(args: &[String]) -> ! {
+    if args.is_empty() {
+        usage();
+    }
+    let vms = args
+        .iter()
+        .enumerate()
+        .map(|(index, rom)| {
+            let config = match index {
+                0 => Config::default(),
+                _ => Config { input: Box::new(Text::default()), on_eof: EofPolicy::Yield, ..Config::default() },
+            };
+            load(rom, false, config)
+        })
+        .collect();
+    let mut cluster = Cluster::pipeline(vms).unwrap_or_else(|err| {
+        notice!("Could not connect the ROMs: {}.", err);
+        process::exit(1);
+    });
+    let mut failed = false;
+    for (rom, outcome) in args.iter().zip(cluster.run()) {
+        match outcome {
+            Some(RunOutcome::Halted) => (),
+            Some(outcome) => {
+                notice!("{}: {}", rom, outcome);
+                failed = true;
+            }
+            None => notice!("{} was left waiting for input.", rom),
+        }
+    }
+    process::exit(if failed { 1 } else { 0 });
+}
+
+fn statediff_command(args: &[String]) -> ! {
+    if args.len() != 2 {
+        usage();
+    }
+    let slots = Slots::new(slots::DIR);
+    let snapshots: Vec<Snapshot> = args
+        .iter()
+        .map(|name| {
+            let path = slots.path(name);
+            Snapshot::load(&path.to_string_lossy()).unwrap_or_else(|err| {
+                notice!("Could not load {}: {}", path.display(), err);
+                process::exit(1);
+            })
+        })
+        .collect();
+    print!("{}", statediff::diff(&snapshots[0], &snapshots[1]));
+    process::exit(0);
+}
+
+fn tracediff_command(args: &[String]) -> ! {
+    let (a, b, flags) = match args {
+        [a, b, flags @ ..] => (a, b, flags),
+        _ => usage(),
+    };
+    let mut context = tracediff::CONTEXT;
+    let mut flags = flags.iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--context" => context = flag_value(&mut flags, flag, "a number of lines"),
+            _ => usage(),
+        }
+    }
+    match tracediff::diff_files(a, b, context) {
+        Ok(None) => {
+            println!("The runs are the same.");
+            process::exit(0);
+        }
+        Ok(Some(divergence)) => {
+            print!("{}", divergence);
+            process::exit(1);
+        }
+        Err(err) => {
+            notice!("{}", err);
+            process::exit(2);
+        }
+    }
+}
+
+fn export_command(args: &[String]) -> ! {
+    let (name, path, spec_only) = match args {
+        [name, path] => (name, path, false),
+        [name, path, spec] if spec == "--spec" => (name, path, true),
+        _ => usage(),
+    };
+    let path_of = Slots::new(slots::DIR).path(name);
+    let snapshot = Snapshot::load(&path_of.to_string_lossy()).unwrap_or_else(|err| {
+        notice!("Could not load {}: {}", path_of.display(), err);
+        process::exit(1);
+    });
+    let mut synacor = Synacor::with_config(Config::default());
+    if let Err(err) = synacor.restore(&snapshot) {
+        notice!("Could not restore {}: {}", path_of.display(), err);
+        process::exit(1);
+    }
+    monitor::export(&synacor, path, spec_only);
+    process::exit(0);
+}
+
+// Converts between snapshot formats, e.g. `synacor convert quick state.json`.
+fn convert_command(args: &[String]) -> ! {
+    let (from, to) = match args {
+        [from, to] => (from, to),
+        _ => usage(),
+    };
+    let slots = Slots::new(slots::DIR);
+    let (from, to) = (slots.path(from), slots.path(to));
+    let snapshot = Snapshot::load(&from.to_string_lossy()).unwrap_or_else(|err| {
+        notice!("Could not load {}: {}", from.display(), err);
+        process::exit(1);
+    });
+    if let Err(err) = snapshot.save(&to.to_string_lossy()) {
+        notice!("Could not write {}: {}", to.display(), err);
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
+// The memory of a snapshot, or of a ROM once it's reached the first prompt.
+fn decrypted_memory(path: &str) -> Vec<u16> {
+    match Snapshot::load(path) {
+        Ok(snapshot) => (0..32768).map(|address| snapshot.memory.word(address)).collect(),
+        Err(_) => {
+            // Most of the text is only readable once the self-test has
+            // decrypted it, which is done by the first prompt.
+            let config = Config { output: Box::new(Null), input: Box::new(Text::default()), on_eof: EofPolicy::Yield, ..Config::default() };
+            let mut synacor = load(path, false, config);
+            synacor.run_for(10_000_000);
+            (0..32768).map(|address| synacor.memory(address)).collect()
+        }
+    }
+}
+
+fn coverage_command(args: &[String]) -> ! {
+    let (listing, path, rom) = match args {
+        [kind, path, rest @ ..] if rest.len() <= 1 && (kind == "report" || kind == "listing") => {
+            (kind == "listing", path, rest.first().map_or("challenge.bin", |rom| rom.as_str()))
+        }
+        _ => usage(),
+    };
+    let coverage = Coverage::load(path).unwrap_or_else(|err| {
+        notice!("Could not read {}: {}", path, err);
+        process::exit(1);
+    });
+    let memory = decrypted_memory(rom);
+    let read = |address: u16| memory.get(address as usize).cloned().unwrap_or(0);
+    if listing {
+        print!("{}", coverage.listing(read));
+    } else {
+        coverage.report(read).iter().for_each(|line| println!("{}", line));
+    }
+    process::exit(0);
+}
+
+fn strings_command(args: &[String]) -> ! {
+    let path = match args {
+        [] => "challenge.bin",
+        [path] => path,
+        _ => usage(),
+    };
+    let memory = decrypted_memory(path);
+    for (owner, texts) in gametext::extract(&memory) {
+        match owner {
+            Owner::Routine(address) => println!("routine {}:", address),
+            Owner::Data => println!("data:"),
+            Owner::Unreferenced => println!("unreferenced:"),
+        }
+        for text in texts {
+            match text.key {
+                Some(key) => println!("  {:5} {:?} (key {})", text.address, text.text, key),
+                None => println!("  {:5} {:?}", text.address, text.text),
+            }
+        }
+    }
+    process::exit(0);
+}
+
+// Opens a core file in the TUI, read-only, or prints what's in it with
+// --report or without a terminal.
+fn inspect_command(args: &[String]) -> ! {
+    let (path, report) = match args {
+        [path] => (path, !editor::available()),
+        [path, flag] if flag == "--report" => (path, true),
+        _ => usage(),
+    };
+    let core = Core::load(path).unwrap_or_else(|err| {
+        notice!("Could not read {}: {}", path, err);
+        process::exit(1);
+    });
+    if report {
+        print!("{}", core.report());
+        process::exit(0);
+    }
+    let output = Buffer::default();
+    let mut synacor = Synacor::with_config(Config { output: Box::new(output.clone()), ..Config::default() });
+    if let Err(err) = synacor.restore(&core.snapshot) {
+        notice!("Could not restore {}: {}", path, err);
+        process::exit(1);
+    }
+    if let Err(err) = Tui::new(synacor, output).with_core(&core).run() {
+        notice!("The TUI failed: {}", err);
+        process::exit(1);
+    }
+    process::exit(0);
+}
+
+fn map_command(args: &[String]) -> ! {
+    let open = |path: &str| {
+        Map::open(path).unwrap_or_else(|err| {
+            notice!("Could not read {}: {}", path, err);
+            process::exit(1);
+        })
+    };
+    match (args.first().map(|arg| arg.as_str()), args.len()) {
+        (Some("dot"), 2) => {
+            print!("{}", open(&args[1]).to_dot());
+            process::exit(0);
+        }
+        (Some("where"), 2) | (Some("where"), 3) => (),
+        _ => usage(),
+    }
+    let mut map = args.get(2).map_or_else(Map::default, |path| open(path));
+    let (path, snapshot) = Slots::new(slots::DIR).load(&args[1]).unwrap_or_else(|err| {
+        notice!("Could not load {}: {}", args[1], err);
+        process::exit(1);
+    });
+    // Looks around to find out which room the save is in.
+    let capture = Buffer::default();
+    let config = Config {
+        output: Box::new(capture.clone()),
+        input: Box::new(Text::new("look\n")),
+        on_eof: EofPolicy::Yield,
+        ..Config::default()
+    };
+    let mut synacor = load("challenge.bin", false, config);
+    if let Err(err) = synacor.restore(&snapshot) {
+        notice!("Could not restore {}: {}", path.display(), err);
+        process::exit(1);
+    }
+    synacor.run_for(10_000_000);
+    for line in capture.text().lines() {
+        map.output_line(line);
+    }
+    for line in map.describe() {
+        println!("{}", line);
+    }
+    process::exit(0);
+}
+
+fn speedrun_command(args: &[String]) -> ! {
+    let (path, script) = match args {
+        [path] => (path, None),
+        [path, script] => (path, Some(script)),
+        _ => usage(),
+    };
+    let replay = Replay::load(path).unwrap_or_else(|err| {
+        notice!("Could not read {}: {}", path, err);
+        process::exit(1);
+    });
+    let commands = speedrun::commands(&replay.recording);
+    notice!("Shortening {} commands...", commands.len());
+    let shortest = speedrun::shorten(&replay.recording.start, &commands).unwrap_or_else(|err| {
+        notice!("Could not replay {}: {}.", path, err);
+        process::exit(1);
+    });
+    let text: String = shortest.iter().map(|command| format!("{}\n", command)).collect();
+    match script {
+        Some(script) => {
+            if let Err(err) = fs::write(script, &text) {
+                notice!("Could not write {}: {}", script, err);
+                process::exit(1);
+            }
+            notice!("Wrote {} of the {} commands to {}.", shortest.len(), commands.len(), script);
+        }
+        None => print!("{}", text),
+    }
+    process::exit(0);
+}
+
+fn codes_command(args: &[String]) -> ! {
+    match (args.first().map(|arg| arg.as_str()), args.get(1)) {
+        (Some("hash"), Some(_)) => {
+            for code in &args[1..] {
+                println!("{:016x}", synacor::hash::text(code));
+            }
+            process::exit(0);
+        }
+        (Some("check"), Some(_)) => (),
+        _ => usage(),
+    }
+    let hashes_path = match &args[2..] {
+        [] => codes::HASHES,
+        [flag, path] if flag == "--hashes" => path,
+        _ => usage(),
+    };
+    let found = codes::read(&args[1]).unwrap_or_else(|err| {
+        notice!("Could not read {}: {}", args[1], err);
+        process::exit(1);
+    });
+    let hashes = match codes::read_hashes(hashes_path) {
+        Ok(hashes) => Some(hashes),
+        Err(err) => {
+            notice!("Can't check the codes against {} ({}); make it with `synacor codes hash`.", hashes_path, err);
+            None
+        }
+    };
+    let mut valid = 0;
+    for found in &found {
+        let status = match hashes {
+            Some(ref hashes) => codes::check(&found.code, hashes),
+            None => {
+                if found.context == "Vault" || found.context.contains("mirror") {
+                    println!("{}  read in a mirror, so probably {}", found.code, codes::mirror(&found.code));
+                } else {
+                    println!("{}  found in {}", found.code, found.context);
+                }
+                continue;
+            }
+        };
+        match status {
+            codes::Status::Valid => println!("{}  valid", found.code),
+            codes::Status::Mirrored(ref code) => println!("{}  mirrored; enter {}", found.code, code),
+            codes::Status::Invalid => println!("{}  not an expected code", found.code),
+        }
+        if status != codes::Status::Invalid {
+            valid += 1;
+        }
+    }
+    if hashes.is_none() {
+        process::exit(1);
+    }
+    println!("{}/{} codes valid; {} remain.", valid, codes::TOTAL, codes::TOTAL.saturating_sub(valid));
+    process::exit(0);
+}
+
+fn status_command(args: &[String]) -> ! {
+    let save = match args {
+        [save] => save,
+        _ => usage(),
+    };
+    let (path, snapshot) = Slots::new(slots::DIR).load(save).unwrap_or_else(|err| {
+        notice!("Could not load {}: {}", save, err);
+        process::exit(1);
+    });
+    match Status::read(&status::CHALLENGE, |address| snapshot.memory.word(address as usize), snapshot.registers[7]) {
+        Ok(status) => {
+            println!("{}", status);
+            println!("Progress: {}", Progress::from_status(&status));
+            process::exit(0);
+        }
+        Err(err) => {
+            notice!("Could not read the game's status from {}: {}.", path.display(), err);
+            process::exit(1);
+        }
+    }
+}
+
+fn saves_command(args: &[String]) -> ! {
+    if args.first().map(|arg| arg.as_str()) != Some("list") || args.len() > 2 {
+        usage();
+    }
+    let slots = Slots::new(args.get(1).map_or(slots::DIR, |dir| dir.as_str()));
+    match slots.describe() {
+        Ok(lines) => {
+            for line in lines {
+                println!("{}", line);
+            }
+            process::exit(0);
+        }
+        Err(err) => {
+            notice!("Could not list the saves: {}", err);
+            process::exit(1);
         }
     }
 }
 
 fn main() {
-    let mut input_file = File::open("challenge.bin").unwrap();
-    let mut input_bytes = Vec::new();
-    input_file.read_to_end(&mut input_bytes).unwrap();
-    let mut synacor = Synacor::new();
-    synacor.read_bytes_into_ram(&input_bytes);
-    loop {
-        if let Err(error) = synacor.run_optcode() {
-            println!("{}", error);
-            break;
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("solve") => solve_command(&args[1..]),
+        Some("tui") => tui_command(&args[1..]),
+        Some("tracediff") => tracediff_command(&args[1..]),
+        Some("statediff") => statediff_command(&args[1..]),
+        Some("export") => export_command(&args[1..]),
+        Some("convert") => convert_command(&args[1..]),
+        Some("coverage") => coverage_command(&args[1..]),
+        Some("strings") => strings_command(&args[1..]),
+        Some("inspect") => inspect_command(&args[1..]),
+        Some("search") => search_command(&args[1..]),
+        Some("map") => map_command(&args[1..]),
+        Some("codes") => codes_command(&args[1..]),
+        Some("status") => status_command(&args[1..]),
+        Some("saves") => saves_command(&args[1..]),
+        Some("serve") => serve_command(&args[1..]),
+        Some("verify") => {
+            let capture = Buffer::default();
+            let config = Config { output: Box::new(capture.clone()), ..Config::default() };
+            let path = args.get(1).map_or("challenge.bin", |path| path.as_str());
+            let mut synacor = load(path, false, config);
+            if verify::self_test(&mut synacor, &capture) {
+                println!("The self-test passed.");
+                return;
+            }
+            process::exit(1);
         }
+        Some("expect") => expect_command(&args[1..]),
+        Some("replay") => replay_command(&args[1..]),
+        Some("speedrun") => speedrun_command(&args[1..]),
+        Some("lockstep") => lockstep_command(&args[1..]),
+        Some("pipeline") => pipeline_command(&args[1..]),
+        _ => (),
+    }
+    let options = parse_options(&args);
+    if options.protocol {
+        protocol_command(options);
+    }
+    let mut synacor = load("challenge.bin", options.mmap, options.config);
+    if let Some((address, seed)) = options.rng {
+        if let Err(err) = synacor.attach(address, 1, Box::new(Rng::new(seed))) {
+            notice!("Could not map the RNG at {}: {}.", address, err);
+            process::exit(2);
+        }
+    }
+    if let Some(ref isa) = options.isa {
+        if let Err(err) = isa.install(&mut synacor) {
+            notice!("Could not set up the {} instruction set: {}.", isa.name(), err);
+            process::exit(2);
+        }
+    }
+    if options.debug_opcode {
+        synacor.add_extension(Box::new(Magic::new(Box::new(|event| notice!("Debug {}.", event)))));
+    }
+    if let Some(count) = options.banks {
+        if let Err(err) = Banks::enable(&mut synacor, count) {
+            notice!("Could not turn on banking: {}.", err);
+            process::exit(2);
+        }
+    }
+    if let Some((address, ref path)) = options.storage {
+        let attached = Storage::open(path)
+            .map_err(|err| format!("could not open {}: {}", path, err))
+            .and_then(|storage| synacor.attach(address, Storage::LEN, Box::new(storage)));
+        if let Err(err) = attached {
+            notice!("Could not map storage at {}: {}.", address, err);
+            process::exit(2);
+        }
+    }
+    let mut monitor = Monitor::new(&options.saves);
+    if options.rewind > 0 {
+        monitor = monitor.with_rewind(options.rewind);
+    }
+    if let Some((_, ref map)) = options.map {
+        monitor = monitor.with_map(map.clone());
+    }
+    if let Some(ref progress) = options.progress {
+        monitor = monitor.with_progress(progress.clone());
+    }
+    if let Some(ref path) = options.timeline {
+        match Timeline::create(path) {
+            Ok(timeline) => {
+                synacor.set_tracer(timeline::NAME, Some(Box::new(timeline)));
+            }
+            Err(err) => {
+                notice!("Could not create {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some((ref registers, ref path)) = options.audit {
+        match Audit::create(registers.clone(), path) {
+            Ok(audit) => {
+                synacor.set_tracer(audit::NAME, Some(Box::new(audit)));
+            }
+            Err(err) => {
+                notice!("Could not create {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some((ref path, interval)) = options.stack_depth {
+        match Depth::create(path, interval) {
+            Ok(depth) => {
+                synacor.set_tracer(depth::NAME, Some(Box::new(depth)));
+            }
+            Err(err) => {
+                notice!("Could not create {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+    }
+    if let Some(ref path) = options.coverage {
+        synacor.set_tracer(coverage::NAME, Some(Box::new(Collector::new(path))));
+    }
+    let summary = options.stats.map(|_| Rc::new(RefCell::new(Summary::default())));
+    if let Some(ref summary) = summary {
+        synacor.set_tracer(summary::NAME, Some(Box::new(Counter(summary.clone()))));
+    }
+    let heated = options.heatmap.as_ref().map(|_| Rc::new(RefCell::new(Heatmap::default())));
+    if let Some(ref heated) = heated {
+        synacor.set_tracer(heatmap::NAME, Some(Box::new(Recorder(heated.clone()))));
+        monitor = monitor.with_heatmap(heated.clone());
+    }
+    let profiled = if options.profile || options.flamegraph.is_some() { Some(Rc::new(RefCell::new(Profile::default()))) } else { None };
+    if let Some(ref profiled) = profiled {
+        synacor.set_tracer(profile::NAME, Some(Box::new(Profiler(profiled.clone()))));
+        monitor = monitor.with_profile(profiled.clone());
+    }
+    if let Some(ref name) = options.load {
+        if !monitor.load(&mut synacor, name) {
+            process::exit(1);
+        }
+    }
+    let mut solved_r7 = None;
+    let teleporting = options.play_to.is_some_and(|segments| segments.iter().any(|segment| segment.teleporter));
+    if options.bypass_teleporter || teleporting {
+        notice!("Solving the teleporter's confirmation...");
+        let bypassed = match solve::teleporter() {
+            Some(r7) => solve::bypass_teleporter(&mut synacor).map(|check| (check, r7)),
+            None => Err("no value of r7 confirms the teleporter".to_string()),
+        };
+        match bypassed {
+            Ok((check, r7)) => {
+                notice!("Patched the teleporter check at {}; r7 will be {} once the game is underway.", check, r7);
+                solved_r7 = Some(r7);
+            }
+            Err(err) => {
+                notice!("Could not bypass the teleporter: {}.", err);
+                process::exit(1);
+            }
+        }
+    }
+    if options.record.is_some() {
+        synacor.start_recording();
+    }
+    if let Some((ref path, format, ref filter)) = options.trace {
+        match trace::open(path, format, filter.clone()) {
+            Ok(tracer) => {
+                synacor.set_tracer(trace::NAME, Some(tracer));
+            }
+            Err(err) => {
+                notice!("Could not create {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+    }
+    let mut checkpoints = match options.checkpoint {
+        Some(_) => Some(Checkpoints::new(Slots::new(&options.saves), options.checkpoints)),
+        None => None,
+    };
+    let mut playing = options.play_to.map(|segments| segments.iter());
+    let start = synacor.instructions();
+    let outcome = loop {
+        let left = options.budget.map(|budget| budget.saturating_sub(synacor.instructions() - start));
+        let due = match (options.checkpoint, checkpoints.as_ref()) {
+            (Some(checkpoint::Policy::Every(every)), Some(checkpoints)) => Some(checkpoints.due_in(&synacor, every)),
+            _ => None,
+        };
+        let outcome = match (left, due) {
+            (Some(left), Some(due)) => synacor.run_for(left.min(due)),
+            (Some(limit), None) | (None, Some(limit)) => synacor.run_for(limit),
+            (None, None) => synacor.run(),
+        };
+        match outcome {
+            RunOutcome::Command(line) => monitor.command(&mut synacor, &line),
+            RunOutcome::Prompt => {
+                // r7 waits for the segment that teleports to the beach, since
+                // the earlier teleport has to land at headquarters.
+                let segment = playing.as_mut().map(|segments| segments.next());
+                match segment {
+                    Some(Some(segment)) => {
+                        if segment.teleporter {
+                            solved_r7.take().into_iter().for_each(|r7| synacor.set_register(7, r7));
+                        }
+                        notice!("Playing to the {}...", segment.milestone);
+                        synacor.push_input(segment.script);
+                    }
+                    Some(None) => {
+                        let milestone = options.play_to.and_then(|segments| segments.last()).unwrap().milestone;
+                        notice!("Reached the {}; it's your turn.", milestone);
+                        monitor.save(&synacor, milestone, "play-to");
+                        playing = None;
+                    }
+                    None => (),
+                }
+                if playing.is_none() {
+                    if let Some(r7) = solved_r7.take() {
+                        synacor.set_register(7, r7);
+                    }
+                }
+                monitor.prompt(&synacor);
+                if options.checkpoint == Some(checkpoint::Policy::Prompt) {
+                    checkpoints.iter_mut().for_each(|checkpoints| checkpoints.save(&synacor));
+                }
+            }
+            RunOutcome::BudgetExceeded if due.is_some_and(|due| left.is_none_or(|left| left >= due)) => {
+                checkpoints.iter_mut().for_each(|checkpoints| checkpoints.save(&synacor));
+                // The budget ran out on the checkpoint, which is written all
+                // the same.
+                if left == due {
+                    break RunOutcome::BudgetExceeded;
+                }
+            }
+            outcome => break outcome,
+        }
+    };
+    if let Some(ref name) = options.save {
+        monitor.save(&synacor, name, "");
+    }
+    if let (Some(recording), Some(path)) = (synacor.take_recording(), &options.record) {
+        match Replay::new(&synacor, recording).save(path) {
+            Ok(()) => notice!("Recorded the session to {}; check it with `synacor replay {}`.", path, path),
+            Err(err) => notice!("Could not write {}: {}", path, err),
+        }
+    }
+    if let (RunOutcome::Faulted(err), Some(path)) = (&outcome, &options.core) {
+        match Core::new(&synacor, &err.to_string()).save(path) {
+            Ok(()) => notice!("Wrote a core file to {}; see it with `synacor inspect {}`.", path, path),
+            Err(err) => notice!("Could not write a core file to {}: {}", path, err),
+        }
+    }
+    if let Some((ref path, ref map)) = options.map {
+        if let Err(err) = map.borrow().save(path) {
+            notice!("Could not write {}: {}", path, err);
+        }
+    }
+    if let (Some(profiled), true) = (profiled.as_ref(), options.profile) {
+        let profiled = profiled.borrow();
+        profiled.report(|address| synacor.memory(address), profile::TOP).iter().for_each(|line| notice!("{}", line));
+        profiled.branch_report(|address| synacor.memory(address), profile::TOP).iter().for_each(|line| notice!("{}", line));
+    }
+    if let (Some(profiled), Some(path)) = (profiled, &options.flamegraph) {
+        match fs::write(path, profiled.borrow().folded()) {
+            Ok(()) => notice!("Wrote the folded stacks to {}; see them with flamegraph.pl or inferno.", path),
+            Err(err) => notice!("Could not write {}: {}", path, err),
+        }
+    }
+    if let (Some(heated), Some(path)) = (heated, &options.heatmap) {
+        match heated.borrow().save(path) {
+            Ok(()) => notice!("Wrote the heatmap to {}.", path),
+            Err(err) => notice!("Could not write {}: {}", path, err),
+        }
+    }
+    match (summary, options.stats) {
+        // Plain on stdout, for tools to read.
+        (Some(summary), Some(true)) => println!("{}", summary.borrow().json()),
+        (Some(summary), _) => summary.borrow().text().iter().for_each(|line| notice!("{}", line)),
+        _ => (),
+    }
+    for (name, mut tracer) in synacor.take_tracers() {
+        if let Err(err) = tracer.finish() {
+            notice!("Could not write the {}: {}", name, err);
+        }
+    }
+    synacor.take_transcript();
+    if let Some(codes) = options.codes {
+        notice!("{}.", codes.borrow().tally());
+    }
+    #[cfg(feature = "counters")]
+    if options.stats.is_some() {
+        notice!("{} instructions executed.", synacor.stats().total);
+    }
+    if let RunOutcome::Halted = outcome {
+        return;
     }
+    notice!("{}", outcome);
+    process::exit(1);
 }
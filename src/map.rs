@@ -0,0 +1,386 @@
+// A map of the game built from what it prints. Each room shows up as
+//   == Title ==
+//   First paragraph of the description.
+//
+//   There are 2 exits:
+//   - north
+//   - south
+//
+//   What do you do?
+// and a move is a command naming one of the exits of the room the player was
+// in. Rooms are told apart by their title and first paragraph, so rooms that
+// read the same (most of the twisty passages) end up as one. The map is kept
+// as JSON:
+//   {"rooms": [{"title": "...", "description": "...", "exits": [...]}],
+//    "passages": [[from, "exit", to], ...]}
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+use input::InputSource;
+use json::{self, Value};
+use regex::Regex;
+use trigger::Trigger;
+
+const PROMPT: &str = "What do you do?";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Room {
+    pub title: String,
+    pub description: String,
+    pub exits: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Description,
+    Exits,
+    Other,
+}
+
+// A room whose text is still coming in.
+struct Reading {
+    room: Room,
+    section: Section,
+}
+
+#[derive(Default)]
+pub struct Map {
+    rooms: Vec<Room>,
+    passages: BTreeMap<(usize, String), usize>,
+    here: Option<usize>,
+    // The exit just taken, until the room it leads to shows up.
+    leaving: Option<(usize, String)>,
+    reading: Option<Reading>,
+}
+
+impl Map {
+    pub fn rooms(&self) -> &[Room] {
+        &self.rooms
+    }
+    // Where each known exit leads, as (from, exit, to).
+    pub fn passages(&self) -> Vec<(usize, &str, usize)> {
+        self.passages.iter().map(|(&(from, ref exit), &to)| (from, exit.as_str(), to)).collect()
+    }
+    pub fn here(&self) -> Option<usize> {
+        self.here
+    }
+    // Forgets where the player is, as after loading a save.
+    pub fn lost(&mut self) {
+        self.here = None;
+        self.leaving = None;
+        self.reading = None;
+    }
+    // Takes in a line of the game's output.
+    pub fn output_line(&mut self, line: &str) {
+        let line = line.trim();
+        if let Some(title) = line.strip_prefix("== ").and_then(|line| line.strip_suffix(" ==")) {
+            let room = Room { title: title.to_string(), description: String::new(), exits: Vec::new() };
+            self.reading = Some(Reading { room, section: Section::Description });
+            return;
+        }
+        let finished = match self.reading {
+            Some(ref mut reading) => {
+                if line == PROMPT {
+                    true
+                } else {
+                    if line.starts_with("There are ") && line.ends_with(" exits:") || line == "There is 1 exit:" {
+                        reading.section = Section::Exits;
+                    } else if line.ends_with(':') {
+                        reading.section = Section::Other;
+                    } else if let (Section::Exits, Some(exit)) = (reading.section, line.strip_prefix("- ")) {
+                        reading.room.exits.push(exit.to_string());
+                    } else if reading.section == Section::Description {
+                        if line.is_empty() {
+                            if !reading.room.description.is_empty() {
+                                reading.section = Section::Other;
+                            }
+                        } else {
+                            if !reading.room.description.is_empty() {
+                                reading.room.description.push(' ');
+                            }
+                            reading.room.description.push_str(line);
+                        }
+                    }
+                    false
+                }
+            }
+            None => false,
+        };
+        if finished {
+            let room = self.reading.take().unwrap().room;
+            self.arrive(room);
+        }
+    }
+    fn arrive(&mut self, room: Room) {
+        let found = self.rooms.iter().position(|known| known.title == room.title && known.description == room.description);
+        let index = match found {
+            Some(index) => {
+                self.rooms[index].exits = room.exits;
+                index
+            }
+            None => {
+                self.rooms.push(room);
+                self.rooms.len() - 1
+            }
+        };
+        if let Some(key) = self.leaving.take() {
+            self.passages.insert(key, index);
+        }
+        self.here = Some(index);
+    }
+    // Takes in a line the player typed.
+    pub fn command(&mut self, line: &str) {
+        let line = line.trim();
+        let exit = line.strip_prefix("go ").unwrap_or(line).trim();
+        self.leaving = match self.here {
+            Some(here) if self.rooms[here].exits.iter().any(|known| known == exit) => Some((here, exit.to_string())),
+            _ => None,
+        };
+    }
+    // Where the player is and where the exits from there lead.
+    pub fn describe(&self) -> Vec<String> {
+        let here = match self.here {
+            Some(here) => here,
+            None => return vec!["Where you are isn't known yet; try looking around.".to_string()],
+        };
+        let room = &self.rooms[here];
+        let mut lines = vec![format!("You are in {} (room {} of {} mapped).", room.title, here + 1, self.rooms.len())];
+        for exit in &room.exits {
+            let to = match self.passages.get(&(here, exit.clone())) {
+                Some(&to) => self.rooms[to].title.as_str(),
+                None => "?",
+            };
+            lines.push(format!("  {} -> {}", exit, to));
+        }
+        lines
+    }
+    pub fn to_json(&self) -> Value {
+        let rooms = self
+            .rooms
+            .iter()
+            .map(|room| {
+                Value::object(vec![
+                    ("title", Value::from(room.title.as_str())),
+                    ("description", Value::from(room.description.as_str())),
+                    ("exits", Value::Array(room.exits.iter().map(|exit| Value::from(exit.as_str())).collect())),
+                ])
+            })
+            .collect();
+        let passages = self
+            .passages()
+            .into_iter()
+            .map(|(from, exit, to)| Value::Array(vec![Value::from(from as u64), Value::from(exit), Value::from(to as u64)]))
+            .collect();
+        Value::object(vec![("rooms", Value::Array(rooms)), ("passages", Value::Array(passages))])
+    }
+    pub fn from_json(value: &Value) -> Result<Map, String> {
+        let text = |value: &Value, key: &str| -> Result<String, String> {
+            value.get(key).and_then(Value::as_str).map(str::to_string).ok_or_else(|| format!("\"{}\" is not a string", key))
+        };
+        let mut map = Map::default();
+        for room in value.get("rooms").and_then(Value::as_array).ok_or("\"rooms\" is not an array")? {
+            let exits = room.get("exits").and_then(Value::as_array).ok_or("\"exits\" is not an array")?;
+            map.rooms.push(Room {
+                title: text(room, "title")?,
+                description: text(room, "description")?,
+                exits: exits.iter().map(|exit| exit.as_str().map(str::to_string).ok_or("exits are strings")).collect::<Result<_, _>>()?,
+            });
+        }
+        let count = map.rooms.len() as u64;
+        for passage in value.get("passages").and_then(Value::as_array).ok_or("\"passages\" is not an array")? {
+            match passage.as_array() {
+                Some([from, exit, to]) => match (from.as_u64(), exit.as_str(), to.as_u64()) {
+                    (Some(from), Some(exit), Some(to)) if from < count && to < count => {
+                        map.passages.insert((from as usize, exit.to_string()), to as usize);
+                    }
+                    _ => return Err("a passage is not between known rooms".to_string()),
+                },
+                _ => return Err("passages are [from, exit, to] triples".to_string()),
+            }
+        }
+        Ok(map)
+    }
+    // Where each room goes on a grid, from `centre` at (0, 0) outwards. An
+    // exit named for a direction goes that way if the square is free; other
+    // exits, and those whose square is taken, go to the nearest free square
+    // in a straight line, looking north first going out and south first
+    // coming back, so a room ends up the same way round from either end.
+    // Rooms not joined to `centre` are left out.
+    pub fn grid(&self, centre: usize) -> BTreeMap<usize, (i32, i32)> {
+        const SIDES: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+        let direction = |exit: &str| match exit {
+            "north" => Some((0, -1)),
+            "east" => Some((1, 0)),
+            "south" => Some((0, 1)),
+            "west" => Some((-1, 0)),
+            _ => None,
+        };
+        let mut placed = BTreeMap::new();
+        let mut taken = BTreeSet::new();
+        let mut queue = VecDeque::new();
+        if centre < self.rooms.len() {
+            placed.insert(centre, (0, 0));
+            taken.insert((0, 0));
+            queue.push_back(centre);
+        }
+        while let Some(room) = queue.pop_front() {
+            let (x, y) = placed[&room];
+            // Passages out of the room, then those into it the other way.
+            let out = self.passages.iter().filter(|&(&(from, _), _)| from == room).map(|((_, exit), &to)| (to, direction(exit), 0));
+            let back = self.passages.iter().filter(|&(_, &to)| to == room).map(|(&(from, ref exit), _)| (from, direction(exit).map(|(dx, dy)| (-dx, -dy)), 2));
+            for (next, way, turn) in out.chain(back).collect::<Vec<_>>() {
+                if placed.contains_key(&next) {
+                    continue;
+                }
+                let free = |(dx, dy): (i32, i32), distance: i32| Some((x + dx * distance, y + dy * distance)).filter(|square| !taken.contains(square));
+                let square = way
+                    .and_then(|way| free(way, 1))
+                    .unwrap_or_else(|| (1..).find_map(|distance| (0..4).find_map(|side| free(SIDES[(side + turn) % 4], distance))).unwrap());
+                placed.insert(next, square);
+                taken.insert(square);
+                queue.push_back(next);
+            }
+        }
+        placed
+    }
+    // Graphviz source for the map, one node per room.
+    pub fn to_dot(&self) -> String {
+        let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut dot = String::from("digraph map {\n");
+        for (index, room) in self.rooms.iter().enumerate() {
+            dot.push_str(&format!("    r{} [label={}];\n", index, quote(&room.title)));
+        }
+        for (from, exit, to) in self.passages() {
+            dot.push_str(&format!("    r{} -> r{} [label={}];\n", from, to, quote(exit)));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+    // Starts from the map in `path`, if there is one.
+    pub fn open(path: &str) -> Result<Map, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Map::from_json(&json::parse(&text).map_err(|err| err.to_string())?),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Map::default()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+    // Writes DOT if `path` ends in ".dot" and JSON otherwise.
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        if path.ends_with(".dot") {
+            fs::write(path, self.to_dot())
+        } else {
+            fs::write(path, self.to_json().pretty() + "\n")
+        }
+    }
+    // A trigger that feeds every line of output to `map`.
+    pub fn trigger(map: Rc<RefCell<Map>>) -> Trigger {
+        let everything = Regex::new("").unwrap();
+        Trigger::new(everything, move |fired| {
+            map.borrow_mut().output_line(fired.line);
+            None
+        })
+    }
+}
+
+// Passes input through, telling the map about each line typed.
+pub struct Tap {
+    map: Rc<RefCell<Map>>,
+    source: Box<dyn InputSource>,
+    line: Vec<u8>,
+}
+
+impl Tap {
+    pub fn new(map: Rc<RefCell<Map>>, source: Box<dyn InputSource>) -> Tap {
+        Tap { map, source, line: Vec::new() }
+    }
+}
+
+impl InputSource for Tap {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.source.read_byte()?;
+        match byte {
+            Some(b'\n') | Some(b'\r') => {
+                self.map.borrow_mut().command(&String::from_utf8_lossy(&self.line));
+                self.line.clear();
+            }
+            Some(byte) => self.line.push(byte),
+            None => (),
+        }
+        Ok(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::Text;
+
+    const FOOTHILLS: &str = "== Foothills ==\nYou are at the foot of a mountain.\n\nThings of interest here:\n- tablet\n\n\
+                             There are 2 exits:\n- doorway\n- south\n\nWhat do you do?\n";
+    const CAVE: &str = "\n== Dark cave ==\nThis seems to be the mouth of a deep cave.\n\nThere is 1 exit:\n- south\n\nWhat do you do?\n";
+
+    fn show(map: &mut Map, text: &str) {
+        for line in text.lines() {
+            map.output_line(line);
+        }
+    }
+
+    #[test]
+    fn follows_exits() {
+        let mut map = Map::default();
+        show(&mut map, FOOTHILLS);
+        assert_eq!(map.rooms()[0].exits, ["doorway", "south"]);
+        assert_eq!(map.rooms()[0].description, "You are at the foot of a mountain.");
+        map.command("take tablet");
+        map.command("go doorway");
+        show(&mut map, CAVE);
+        map.command("south");
+        show(&mut map, FOOTHILLS);
+        assert_eq!(map.rooms().len(), 2);
+        assert_eq!(map.passages(), [(0, "doorway", 1), (1, "south", 0)]);
+        assert_eq!(map.describe(), ["You are in Foothills (room 1 of 2 mapped).", "  doorway -> Dark cave", "  south -> ?"]);
+        assert!(map.to_dot().contains("r0 -> r1 [label=\"doorway\"];"));
+    }
+
+    #[test]
+    fn lays_rooms_out_on_a_grid() {
+        let mut map = Map::default();
+        show(&mut map, FOOTHILLS);
+        map.command("south");
+        show(&mut map, "== Path ==\nA path.\n\nThere are 2 exits:\n- north\n- west\n\nWhat do you do?\n");
+        map.command("north");
+        show(&mut map, FOOTHILLS);
+        map.command("doorway");
+        show(&mut map, CAVE);
+        let grid = |centre| map.grid(centre).into_iter().collect::<Vec<_>>();
+        assert_eq!(grid(0), [(0, (0, 0)), (1, (0, 1)), (2, (0, -1))]);
+        assert_eq!(grid(2), [(0, (0, 0)), (1, (0, 1)), (2, (0, -1))].map(|(room, (x, y))| (room, (x, y + 1))));
+        assert_eq!(grid(3), []);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut map = Map::default();
+        show(&mut map, FOOTHILLS);
+        map.command("doorway");
+        show(&mut map, CAVE);
+        let copy = Map::from_json(&json::parse(&map.to_json().to_string()).unwrap()).unwrap();
+        assert_eq!(copy.rooms(), map.rooms());
+        assert_eq!(copy.passages(), map.passages());
+        assert!(Map::from_json(&json::parse(r#"{"rooms":[],"passages":[[0,"north",1]]}"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn taps_typed_lines() {
+        let map = Rc::new(RefCell::new(Map::default()));
+        show(&mut map.borrow_mut(), FOOTHILLS);
+        let mut tap = Tap::new(map.clone(), Box::new(Text::new("doorway\n")));
+        while tap.read_byte().unwrap().is_some() {}
+        show(&mut map.borrow_mut(), CAVE);
+        assert_eq!(map.borrow().passages(), [(0, "doorway", 1)]);
+    }
+}
@@ -0,0 +1,252 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+const PAGE_BITS: usize = 12;
+const PAGE_WORDS: usize = 1 << PAGE_BITS;
+
+// The bytes of a ROM image, either read into a buffer or mapped straight from
+// the file.
+pub enum Image {
+    Bytes(Vec<u8>),
+    #[cfg(unix)]
+    Mapped(mmap::Mapping),
+}
+
+impl Image {
+    pub fn open(path: &str, map: bool) -> io::Result<Image> {
+        let mut file = File::open(path)?;
+        #[cfg(unix)]
+        {
+            if map && file.metadata()?.len() > 0 {
+                return mmap::Mapping::new(&file).map(Image::Mapped);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = map;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        Ok(Image::Bytes(bytes))
+    }
+    pub fn bytes(&self) -> &[u8] {
+        match *self {
+            Image::Bytes(ref bytes) => bytes,
+            #[cfg(unix)]
+            Image::Mapped(ref mapping) => mapping.bytes(),
+        }
+    }
+    fn word(&self, address: usize) -> u16 {
+        let bytes = self.bytes();
+        match (bytes.get(address * 2), bytes.get(address * 2 + 1)) {
+            (Some(&low), Some(&high)) => (high as u16) << 8 | low as u16,
+            _ => 0,
+        }
+    }
+}
+
+pub enum LoadError {
+    OddLength(usize),
+    TooLarge { words: usize, capacity: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::OddLength(len) => {
+                write!(f, "The image is {} bytes long, which is not a whole number of words.", len)
+            }
+            LoadError::TooLarge { words, capacity } => {
+                write!(f, "The image is {} words long but only {} words are addressable.", words, capacity)
+            }
+        }
+    }
+}
+
+// Word-addressed memory that is built lazily from a ROM image. Reads of a page
+// that has never been written go straight to the image; a page is only copied
+// into RAM the first time something writes to it.
+pub struct Memory {
+    pages: Vec<Option<Page>>,
+    image: Image,
+    len: usize,
+    // Words below `loaded` came from the image.
+    loaded: usize,
+}
+
+struct Page {
+    words: Box<[u16]>,
+    // One bit for every word of the page that has been written.
+    written: [u64; PAGE_WORDS / 64],
+}
+
+impl Memory {
+    pub fn new(len: usize) -> Memory {
+        Memory {
+            pages: (0..len.div_ceil(PAGE_WORDS)).map(|_| None).collect(),
+            image: Image::Bytes(Vec::new()),
+            len,
+            loaded: 0,
+        }
+    }
+    // Replaces the contents of memory with the image, as long as the image
+    // fits in the first `capacity` words. Returns the number of words loaded.
+    pub fn load(&mut self, image: Image, capacity: usize) -> Result<usize, LoadError> {
+        let len = image.bytes().len();
+        if len % 2 == 1 {
+            return Err(LoadError::OddLength(len));
+        }
+        let words = len / 2;
+        if words > capacity.min(self.len) {
+            return Err(LoadError::TooLarge { words, capacity: capacity.min(self.len) });
+        }
+        for page in &mut self.pages {
+            *page = None;
+        }
+        self.image = image;
+        self.loaded = words;
+        Ok(words)
+    }
+    pub fn read(&self, address: usize) -> u16 {
+        match self.pages[address >> PAGE_BITS] {
+            Some(ref page) => page.words[address & (PAGE_WORDS - 1)],
+            None if address < self.len => self.image.word(address),
+            None => panic!("memory read out of bounds: {}", address),
+        }
+    }
+    pub fn write(&mut self, address: usize, word: u16) {
+        assert!(address < self.len, "memory write out of bounds: {}", address);
+        let image = &self.image;
+        let page = self.pages[address >> PAGE_BITS].get_or_insert_with(|| {
+            let start = address & !(PAGE_WORDS - 1);
+            Page {
+                words: (start..start + PAGE_WORDS).map(|address| image.word(address)).collect(),
+                written: [0; PAGE_WORDS / 64],
+            }
+        });
+        let offset = address & (PAGE_WORDS - 1);
+        page.words[offset] = word;
+        page.written[offset / 64] |= 1 << (offset % 64);
+    }
+    pub fn initialized(&self, address: usize) -> bool {
+        address < self.loaded || self.written(address)
+    }
+    // Whether the word has been written since the image was loaded.
+    pub fn written(&self, address: usize) -> bool {
+        let offset = address & (PAGE_WORDS - 1);
+        match self.pages[address >> PAGE_BITS] {
+            Some(ref page) => page.written[offset / 64] & 1 << (offset % 64) != 0,
+            None => false,
+        }
+    }
+}
+
+// Everything needed to put a Memory back exactly as it was: the image and
+// every page that has been written to.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SavedMemory {
+    pub image: Vec<u8>,
+    pub loaded: usize,
+    pub pages: Vec<SavedPage>,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SavedPage {
+    pub index: usize,
+    pub words: Vec<u16>,
+    pub written: Vec<u64>,
+}
+
+impl SavedMemory {
+    // The number of words the saved memory covers: the image and every saved
+    // page.
+    pub fn extent(&self) -> usize {
+        let pages = self.pages.iter().map(|page| (page.index + 1) * PAGE_WORDS).max().unwrap_or(0);
+        pages.max(self.image.len() / 2)
+    }
+    pub fn word(&self, address: usize) -> u16 {
+        match self.pages.iter().find(|page| page.index == address >> PAGE_BITS) {
+            Some(page) => page.words[address & (PAGE_WORDS - 1)],
+            None => match (self.image.get(address * 2), self.image.get(address * 2 + 1)) {
+                (Some(&low), Some(&high)) => (high as u16) << 8 | low as u16,
+                _ => 0,
+            },
+        }
+    }
+}
+
+impl Memory {
+    pub fn save(&self) -> SavedMemory {
+        let pages = self
+            .pages
+            .iter()
+            .enumerate()
+            .filter_map(|(index, page)| {
+                page.as_ref().map(|page| SavedPage { index, words: page.words.to_vec(), written: page.written.to_vec() })
+            })
+            .collect();
+        SavedMemory { image: self.image.bytes().to_vec(), loaded: self.loaded, pages }
+    }
+    // Fails if a saved page is malformed or out of range.
+    pub fn restore(&mut self, saved: &SavedMemory) -> Result<(), String> {
+        let mut pages: Vec<Option<Page>> = (0..self.pages.len()).map(|_| None).collect();
+        for page in &saved.pages {
+            if page.index >= pages.len() || page.words.len() != PAGE_WORDS || page.written.len() != PAGE_WORDS / 64 {
+                return Err(format!("page {} does not fit in memory", page.index));
+            }
+            let mut written = [0; PAGE_WORDS / 64];
+            written.copy_from_slice(&page.written);
+            pages[page.index] = Some(Page { words: page.words.clone().into_boxed_slice(), written });
+        }
+        self.pages = pages;
+        self.image = Image::Bytes(saved.image.clone());
+        self.loaded = saved.loaded;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod mmap {
+    use std::fs::File;
+    use std::io;
+    use std::os::raw::{c_int, c_long, c_void};
+    use std::os::unix::io::AsRawFd;
+    use std::slice;
+
+    const PROT_READ: c_int = 1;
+    const MAP_PRIVATE: c_int = 2;
+
+    extern "C" {
+        fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: c_long)
+            -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    // A read-only private mapping of a whole file.
+    pub struct Mapping {
+        ptr: *mut c_void,
+        len: usize,
+    }
+
+    impl Mapping {
+        pub fn new(file: &File) -> io::Result<Mapping> {
+            let len = file.metadata()?.len() as usize;
+            let ptr = unsafe { mmap(std::ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0) };
+            if ptr as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Mapping { ptr, len })
+        }
+        pub fn bytes(&self) -> &[u8] {
+            unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
+
+    impl Drop for Mapping {
+        fn drop(&mut self) {
+            unsafe {
+                munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
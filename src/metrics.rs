@@ -0,0 +1,116 @@
+// Counters for a server's sessions, shared by their threads and served over
+// HTTP in the Prometheus text format, so a hosted instance can be scraped:
+//   # HELP synacor_sessions_active Sessions connected now.
+//   # TYPE synacor_sessions_active gauge
+//   synacor_sessions_active 2
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use websocket;
+
+pub const PATH: &str = "/metrics";
+
+#[derive(Default)]
+pub struct Metrics {
+    pub instructions: AtomicU64,
+    pub sessions: AtomicU64,
+    pub active: AtomicU64,
+    pub faults: AtomicU64,
+    pub input_bytes: AtomicU64,
+    pub output_bytes: AtomicU64,
+}
+
+impl Metrics {
+    pub fn add(counter: &AtomicU64, amount: u64) {
+        counter.fetch_add(amount, Ordering::Relaxed);
+    }
+    pub fn text(&self) -> String {
+        let metrics = [
+            ("synacor_instructions_total", "counter", "Instructions executed by all sessions.", &self.instructions),
+            ("synacor_sessions_total", "counter", "Sessions started.", &self.sessions),
+            ("synacor_sessions_active", "gauge", "Sessions connected now.", &self.active),
+            ("synacor_faults_total", "counter", "Sessions that ended in a fault.", &self.faults),
+            ("synacor_input_bytes_total", "counter", "Bytes of input the programs read.", &self.input_bytes),
+            ("synacor_output_bytes_total", "counter", "Bytes the programs printed.", &self.output_bytes),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics.iter() {
+            text += &format!("# HELP {} {}\n# TYPE {} {}\n{} {}\n", name, help, name, kind, name, value.load(Ordering::Relaxed));
+        }
+        text
+    }
+    // Answers an HTTP request with the metrics, or a 404 for anything but
+    // /metrics.
+    pub fn respond<W: Write>(&self, path: &str, writer: &mut W) -> io::Result<()> {
+        let (status, body) = if path == PATH { ("200 OK", self.text()) } else { ("404 Not Found", "Not found.\n".to_string()) };
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+}
+
+// Counts a session as active while it's alive.
+pub struct Session(Arc<Metrics>);
+
+impl Session {
+    pub fn start(metrics: Arc<Metrics>) -> Session {
+        Metrics::add(&metrics.sessions, 1);
+        Metrics::add(&metrics.active, 1);
+        Session(metrics)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn answer(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let request = websocket::read_request(&mut BufReader::new(stream))?;
+    metrics.respond(&request.path, &mut writer)
+}
+
+// Serves the metrics on their own address, on a thread of their own.
+pub fn serve(address: &str, metrics: Arc<Metrics>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    notice!("Serving metrics on http://{}{}.", listener.local_addr()?, PATH);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(err) = answer(stream, &metrics) {
+                notice!("Could not answer a metrics request: {}", err);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_for_prometheus() {
+        let metrics = Arc::new(Metrics::default());
+        let session = Session::start(metrics.clone());
+        Metrics::add(&metrics.instructions, 1200);
+        assert!(metrics.text().contains("# TYPE synacor_instructions_total counter\nsynacor_instructions_total 1200\n"));
+        assert!(metrics.text().contains("\nsynacor_sessions_active 1\n"));
+        drop(session);
+        assert!(metrics.text().contains("\nsynacor_sessions_active 0\n"));
+        let mut response = Vec::new();
+        metrics.respond("/", &mut response).unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,508 @@
+// Emulator commands typed into the game, on lines starting with the command
+// prefix (`/` on the command line):
+//   /save NAME [NOTE]   save the machine to the slot NAME
+//   /load NAME          restore the slot NAME
+//   /saves              list the save slots
+//   /export FILE [spec] write memory out as a ROM image, optionally only the
+//                       spec's 32768 words
+//   /rewind [N|Ns]      go back N turns (default 1) or to N seconds ago
+//   /reg N VALUE        set register N
+//   /poke ADDRESS WORD...  write words into memory from ADDRESS on
+//   /asm ADDRESS INSTRUCTION  assemble an instruction like `add r0 r1 4`
+//                       into memory at ADDRESS (see disasm.rs)
+//   /protect [START-END|off]  make wmem into START to END fault, list the
+//                       read-only regions, or clear them
+//   /map [FILE]         show where you are on the map, or write it to FILE
+//   /status             show the room, inventory and puzzles from memory
+//   /progress           show which milestones have been reached
+//   /trace FILE [binary] [FILTER]  log the instructions executed, or those
+//                       FILTER picks out, to FILE (see trace.rs); /trace off
+//                       stops
+//   /profile on|off|reset  count how often each address runs (see
+//                       profile.rs)
+//   /profile report [N]  show the N hottest addresses and functions
+//   /profile branches [N]  show the N most one-sided and most even branches
+//   /profile flame FILE  write the call stacks for a flame graph to FILE
+//   /timeline FILE|off  write calls and returns to FILE for about:tracing or
+//                       Perfetto (see timeline.rs)
+//   /stackdepth FILE [N]|off  write the stack and call depth to FILE as CSV,
+//                       a row every N instructions (see depth.rs)
+//   /heatmap on|off     count reads and writes of each word (see heatmap.rs)
+//   /heatmap [FILE]     show the counts, or write them to FILE
+//   /audit REGISTERS FILE  log every change to REGISTERS (like "0,7") to FILE
+//                       (see audit.rs); /audit off stops
+//   /hint               a hint for the next milestone; asking again gives
+//                       a bigger one (see hints.rs)
+//   /solve NAME [ARG]   run a solver (see solve.rs) on a copy of the game and
+//                       type in what it comes up with
+//   /help               list the commands
+// A NAME with a slash or a dot in it is a file path rather than a slot. The
+// line editor binds Ctrl+S and Ctrl+L to saving and loading the quick slot.
+
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+use depth::{self, Depth};
+use disasm;
+use heatmap::{self, Heatmap, Recorder};
+use audit::{self, Audit};
+use hints::{self, Level};
+use map::Map;
+use profile::{self, Profile, Profiler};
+use progress::{Milestone, Progress};
+use rewind::{Back, Rewind};
+use slots::{self, Slots};
+use solve::{self, Game};
+use status::{self, Status};
+use synacor::{Region, Synacor};
+use timeline::{self, Timeline};
+use trace::{self, Filter, Format, Tracer};
+
+pub const PREFIX: u8 = b'/';
+pub const QUICK: &str = "quick";
+
+pub struct Monitor {
+    slots: Slots,
+    rewind: Option<Rewind>,
+    map: Option<Rc<RefCell<Map>>>,
+    progress: Option<Rc<RefCell<Progress>>>,
+    profile: Option<Rc<RefCell<Profile>>>,
+    heatmap: Option<Rc<RefCell<Heatmap>>>,
+    // The last hint given.
+    hint: Option<(Milestone, Level)>,
+}
+
+impl Default for Monitor {
+    fn default() -> Monitor {
+        Monitor::new(slots::DIR)
+    }
+}
+
+impl Monitor {
+    // Keeps save slots in `saves`.
+    pub fn new(saves: &str) -> Monitor {
+        Monitor { slots: Slots::new(saves), rewind: None, map: None, progress: None, profile: None, heatmap: None, hint: None }
+    }
+    // Keeps the last `keep` turns for /rewind. The machine has to pause at
+    // prompts for turns to be recorded.
+    pub fn with_rewind(mut self, keep: usize) -> Monitor {
+        self.rewind = Some(Rewind::new(keep));
+        self
+    }
+    // Answers /map from `map`, which something else keeps up to date.
+    pub fn with_map(mut self, map: Rc<RefCell<Map>>) -> Monitor {
+        self.map = Some(map);
+        self
+    }
+    // Adds the milestones seen in `progress`, which something else keeps up
+    // to date, to the ones /progress reads from memory.
+    pub fn with_progress(mut self, progress: Rc<RefCell<Progress>>) -> Monitor {
+        self.progress = Some(progress);
+        self
+    }
+    // Reports on `profile`, which is already running.
+    pub fn with_profile(mut self, profile: Rc<RefCell<Profile>>) -> Monitor {
+        self.profile = Some(profile);
+        self
+    }
+    // Shows `heatmap`, which is already running.
+    pub fn with_heatmap(mut self, heatmap: Rc<RefCell<Heatmap>>) -> Monitor {
+        self.heatmap = Some(heatmap);
+        self
+    }
+    // Called when the machine pauses at a prompt.
+    pub fn prompt(&mut self, synacor: &Synacor) {
+        if let Some(ref mut rewind) = self.rewind {
+            rewind.record(synacor);
+        }
+    }
+    // Carries out one command, reporting what happened on stderr.
+    pub fn command(&mut self, synacor: &mut Synacor, line: &str) {
+        let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+        match name {
+            "save" if !argument.is_empty() => {
+                let (slot, note) = argument.split_once(' ').unwrap_or((argument, ""));
+                self.save(synacor, slot, note.trim());
+            }
+            "load" if !argument.is_empty() => {
+                self.load(synacor, argument);
+            }
+            "saves" => self.list(),
+            "reg" => match argument.split_once(' ').map(|(index, value)| (index.parse::<usize>(), value.trim().parse())) {
+                Some((Ok(index), Ok(value))) if index < 8 => {
+                    synacor.set_register(index, value);
+                    notice!("Set r{} to {}.", index, value);
+                }
+                _ => notice!("Usage: /reg N VALUE with N from 0 to 7."),
+            },
+            "poke" => {
+                let words: Result<Vec<u16>, _> = argument.split_whitespace().map(|word| word.parse()).collect();
+                match words {
+                    Ok(ref words) if words.len() >= 2 && words[0] as usize + words.len() - 1 <= 65536 => {
+                        for (offset, &word) in words[1..].iter().enumerate() {
+                            synacor.poke(words[0] + offset as u16, word);
+                        }
+                        notice!("Wrote {} words at {}.", words.len() - 1, words[0]);
+                    }
+                    _ => notice!("Usage: /poke ADDRESS WORD..."),
+                }
+            }
+            "asm" => {
+                let (address, text) = argument.split_once(' ').unwrap_or((argument, ""));
+                let assembled = trace::address(address).and_then(|address| Ok((address, disasm::assemble(text)?)));
+                match assembled {
+                    Ok((address, ref words)) if address as usize + words.len() <= 65536 => {
+                        for (offset, &word) in words.iter().enumerate() {
+                            synacor.poke(address + offset as u16, word);
+                        }
+                        notice!("Wrote {} words at {}.", words.len(), address);
+                    }
+                    Ok(_) => notice!("That runs past the end of memory."),
+                    Err(err) => notice!("Could not assemble that: {}. Usage: /asm ADDRESS INSTRUCTION", err),
+                }
+            }
+            "protect" => match argument {
+                "" if synacor.protected().is_empty() => notice!("Nothing is read-only."),
+                "" => {
+                    for region in synacor.protected() {
+                        notice!("{} is read-only.", region);
+                    }
+                }
+                "off" => {
+                    synacor.unprotect_all();
+                    notice!("Nothing is read-only now.");
+                }
+                _ => match Region::parse(argument) {
+                    Ok(region) => {
+                        synacor.protect(region);
+                        notice!("{} is read-only now.", region);
+                    }
+                    Err(err) => notice!("Could not read the region: {}. Usage: /protect [START-END|off]", err),
+                },
+            },
+            "rewind" => {
+                let back = if argument.is_empty() { Ok(Back::Steps(1)) } else { argument.parse() };
+                let rewound = match (back, self.rewind.as_mut()) {
+                    (Ok(back), Some(rewind)) => rewind.rewind(synacor, back),
+                    (Err(err), _) => Err(err),
+                    (_, None) => Err("rewinding is turned off".to_string()),
+                };
+                if rewound.is_ok() {
+                    self.moved(synacor);
+                }
+                match rewound {
+                    Ok(1) => notice!("Went back 1 turn."),
+                    Ok(steps) => notice!("Went back {} turns.", steps),
+                    Err(err) => notice!("Could not rewind: {}.", err),
+                }
+            }
+            "export" if !argument.is_empty() => {
+                let (path, spec) = match argument.split_once(' ') {
+                    Some((path, "spec")) => (path, true),
+                    Some(_) => return notice!("Usage: /export FILE [spec]"),
+                    None => (argument, false),
+                };
+                export(synacor, path, spec);
+            }
+            "map" => match (self.map.as_ref(), argument) {
+                (Some(map), "") => {
+                    for line in map.borrow().describe() {
+                        notice!("{}", line);
+                    }
+                }
+                (Some(map), path) => match map.borrow().save(path) {
+                    Ok(()) => notice!("Wrote the map to {}.", path),
+                    Err(err) => notice!("Could not write {}: {}", path, err),
+                },
+                (None, _) => notice!("Mapping is turned off; start with --map FILE."),
+            },
+            "status" => match Status::read(&status::CHALLENGE, |address| synacor.memory(address), synacor.registers()[7]) {
+                Ok(status) => status.to_string().lines().for_each(|line| notice!("{}", line)),
+                Err(err) => notice!("Could not read the game's status: {}.", err),
+            },
+            "progress" => match self.progress(synacor) {
+                Ok(progress) => notice!("Progress: {}", progress),
+                Err(err) => notice!("Could not read the game's progress: {}.", err),
+            },
+            "trace" if argument == "off" => match synacor.set_tracer(trace::NAME, None) {
+                Some(mut tracer) => match tracer.finish() {
+                    Ok(()) => notice!("Stopped tracing."),
+                    Err(err) => notice!("Could not write the trace: {}", err),
+                },
+                None => notice!("Tracing is already off."),
+            },
+            "trace" if !argument.is_empty() => {
+                let mut words = argument.split_whitespace();
+                let path = words.next().unwrap();
+                let (mut format, mut filter) = (Format::Text, None);
+                for word in words {
+                    match (word, Filter::parse(word)) {
+                        ("binary", _) => format = Format::Binary,
+                        (_, Ok(parsed)) => filter = Some(parsed),
+                        (_, Err(err)) => return notice!("Usage: /trace FILE [binary] [FILTER] or /trace off ({})", err),
+                    }
+                }
+                match trace::open(path, format, filter) {
+                    Ok(tracer) => {
+                        if let Some(mut tracer) = synacor.set_tracer(trace::NAME, Some(tracer)) {
+                            if let Err(err) = tracer.finish() {
+                                notice!("Could not write the last trace: {}", err);
+                            }
+                        }
+                        notice!("Tracing to {}.", path);
+                    }
+                    Err(err) => notice!("Could not create {}: {}", path, err),
+                }
+            }
+            "profile" => {
+                let (action, top) = argument.split_once(' ').unwrap_or((argument, ""));
+                match (action, self.profile.as_ref()) {
+                    ("on", _) => {
+                        let profile = Rc::new(RefCell::new(Profile::default()));
+                        synacor.set_tracer(profile::NAME, Some(Box::new(Profiler(profile.clone()))));
+                        self.profile = Some(profile);
+                        notice!("Profiling.");
+                    }
+                    ("off", _) => match synacor.set_tracer(profile::NAME, None) {
+                        Some(_) => notice!("Stopped profiling; /profile report still shows what was counted."),
+                        None => notice!("Profiling is already off."),
+                    },
+                    ("reset", Some(profile)) => *profile.borrow_mut() = Profile::default(),
+                    ("report", Some(profile)) => match if top.is_empty() { Ok(profile::TOP) } else { top.trim().parse() } {
+                        Ok(top) => profile.borrow().report(|address| synacor.memory(address), top).iter().for_each(|line| notice!("{}", line)),
+                        Err(_) => notice!("Usage: /profile report [N]"),
+                    },
+                    ("branches", Some(profile)) => match if top.is_empty() { Ok(profile::TOP) } else { top.trim().parse() } {
+                        Ok(top) => profile.borrow().branch_report(|address| synacor.memory(address), top).iter().for_each(|line| notice!("{}", line)),
+                        Err(_) => notice!("Usage: /profile branches [N]"),
+                    },
+                    ("flame", Some(profile)) if !top.is_empty() => match fs::write(top.trim(), profile.borrow().folded()) {
+                        Ok(()) => notice!("Wrote the folded stacks to {}; see them with flamegraph.pl or inferno.", top.trim()),
+                        Err(err) => notice!("Could not write {}: {}", top.trim(), err),
+                    },
+                    ("reset", None) | ("report", None) | ("branches", None) | ("flame", None) => notice!("Nothing has been profiled; start with /profile on."),
+                    _ => notice!("Usage: /profile on|off|reset|report [N]|branches [N]|flame FILE"),
+                }
+            }
+            "timeline" if !argument.is_empty() => {
+                let started = match argument {
+                    "off" => None,
+                    path => match Timeline::create(path) {
+                        Ok(timeline) => Some(Box::new(timeline) as Box<dyn Tracer>),
+                        Err(err) => return notice!("Could not create {}: {}", path, err),
+                    },
+                };
+                let on = started.is_some();
+                if let Some(mut stopped) = synacor.set_tracer(timeline::NAME, started) {
+                    if let Err(err) = stopped.finish() {
+                        notice!("Could not write the last timeline: {}", err);
+                    }
+                }
+                if on {
+                    notice!("Writing a timeline to {}.", argument);
+                } else {
+                    notice!("Stopped the timeline.");
+                }
+            }
+            "stackdepth" if !argument.is_empty() => {
+                let (path, interval) = argument.split_once(' ').unwrap_or((argument, ""));
+                let interval = match interval.trim() {
+                    "" => depth::INTERVAL,
+                    interval => match interval.parse() {
+                        Ok(interval) => interval,
+                        Err(_) => return notice!("Usage: /stackdepth FILE [N] or /stackdepth off"),
+                    },
+                };
+                let started = match path {
+                    "off" => None,
+                    path => match Depth::create(path, interval) {
+                        Ok(depth) => Some(Box::new(depth) as Box<dyn Tracer>),
+                        Err(err) => return notice!("Could not create {}: {}", path, err),
+                    },
+                };
+                let on = started.is_some();
+                if let Some(mut stopped) = synacor.set_tracer(depth::NAME, started) {
+                    if let Err(err) = stopped.finish() {
+                        notice!("Could not write the last stack depths: {}", err);
+                    }
+                }
+                if on {
+                    notice!("Writing the stack depth every {} instructions to {}.", interval, path);
+                } else {
+                    notice!("Stopped writing the stack depth.");
+                }
+            }
+            "heatmap" => match (argument, self.heatmap.as_ref()) {
+                ("on", _) => {
+                    let heatmap = Rc::new(RefCell::new(Heatmap::default()));
+                    synacor.set_tracer(heatmap::NAME, Some(Box::new(Recorder(heatmap.clone()))));
+                    self.heatmap = Some(heatmap);
+                    notice!("Counting memory accesses.");
+                }
+                ("off", _) => match synacor.set_tracer(heatmap::NAME, None) {
+                    Some(_) => notice!("Stopped counting memory accesses; /heatmap still shows what was counted."),
+                    None => notice!("The heatmap is already off."),
+                },
+                ("", Some(heatmap)) => heatmap.borrow().text().lines().for_each(|line| notice!("{}", line)),
+                (path, Some(heatmap)) => match heatmap.borrow().save(path) {
+                    Ok(()) => notice!("Wrote the heatmap to {}.", path),
+                    Err(err) => notice!("Could not write {}: {}", path, err),
+                },
+                (_, None) => notice!("Nothing has been counted; start with /heatmap on."),
+            },
+            "audit" if !argument.is_empty() => {
+                let started = match argument.split_once(' ') {
+                    _ if argument == "off" => None,
+                    Some((list, path)) => match audit::registers(list).map(|registers| Audit::create(registers, path.trim())) {
+                        Ok(Ok(audit)) => Some(Box::new(audit) as Box<dyn Tracer>),
+                        Ok(Err(err)) => return notice!("Could not create {}: {}", path.trim(), err),
+                        Err(err) => return notice!("Usage: /audit REGISTERS FILE or /audit off ({})", err),
+                    },
+                    None => return notice!("Usage: /audit REGISTERS FILE or /audit off"),
+                };
+                let on = started.is_some();
+                if let Some(mut stopped) = synacor.set_tracer(audit::NAME, started) {
+                    if let Err(err) = stopped.finish() {
+                        notice!("Could not write the last audit: {}", err);
+                    }
+                }
+                if on {
+                    notice!("Logging register changes.");
+                } else {
+                    notice!("Stopped logging register changes.");
+                }
+            }
+            "hint" => {
+                let milestone = match self.progress(synacor).map(|progress| progress.working_on()) {
+                    Ok(Some(milestone)) => milestone,
+                    Ok(None) => return notice!("You've reached every milestone; there's nothing left to hint at."),
+                    Err(err) => return notice!("Could not read the game's progress: {}.", err),
+                };
+                let level = match self.hint {
+                    Some((last, level)) if last == milestone => level.deeper(),
+                    _ => Level::Nudge,
+                };
+                self.hint = Some((milestone, level));
+                match hints::for_milestone(milestone) {
+                    Some(hint) => hint.lines(level).iter().for_each(|line| notice!("{}", line)),
+                    None => notice!("There is no hint for \"{}\".", milestone),
+                }
+            }
+            "solve" if !argument.is_empty() => {
+                let (name, extra) = argument.split_once(' ').map_or((argument, None), |(name, extra)| (name, Some(extra.trim())));
+                let solver = match solve::solver(name) {
+                    Some(solver) => solver,
+                    None => return notice!("There is no {} solver; try one of {}.", name, solver_names()),
+                };
+                match Game::fork(synacor).and_then(|mut game| solver.solve(&mut game, extra)) {
+                    Ok(solution) => {
+                        notice!("{}", solution.summary);
+                        for command in solution.commands {
+                            notice!("  {}", command);
+                            match command.strip_prefix(PREFIX as char) {
+                                Some(command) => self.command(synacor, command),
+                                None => synacor.push_input(&format!("{}\n", command)),
+                            }
+                        }
+                    }
+                    Err(err) => notice!("Could not solve the {}: {}.", name, err),
+                }
+            }
+            "help" => {
+                notice!("/save NAME [NOTE]   save the machine to the slot NAME");
+                notice!("/load NAME          restore the slot NAME");
+                notice!("/saves              list the save slots");
+                notice!("/export FILE [spec] write memory out as a ROM image");
+                notice!("/rewind [N|Ns]      go back N turns (default 1) or to N seconds ago");
+                notice!("/reg N VALUE        set register N");
+                notice!("/poke ADDRESS WORD...  write words into memory from ADDRESS on");
+                notice!("/asm ADDRESS INSTRUCTION  assemble an instruction into memory at ADDRESS");
+                notice!("/protect [START-END|off]  make writes into START to END fault, or list or clear regions");
+                notice!("/map [FILE]         show where you are on the map, or write it to FILE");
+                notice!("/status             show the room, inventory and puzzles from memory");
+                notice!("/progress           show which milestones have been reached");
+                notice!("/trace FILE [binary] [FILTER]  log instructions to FILE; /trace off stops");
+                notice!("/profile on|off|reset  count how often each address runs");
+                notice!("/profile report [N]  show the N hottest addresses and functions");
+                notice!("/profile branches [N]  show the N most one-sided and most even branches");
+                notice!("/profile flame FILE  write the call stacks for a flame graph to FILE");
+                notice!("/timeline FILE|off  write calls and returns to FILE for about:tracing");
+                notice!("/stackdepth FILE [N]|off  write the stack and call depth every N instructions to FILE");
+                notice!("/heatmap on|off     count reads and writes of each word");
+                notice!("/heatmap [FILE]     show the counts, or write them to FILE (.ppm for an image)");
+                notice!("/audit REGISTERS FILE  log every change to REGISTERS to FILE; /audit off stops");
+                notice!("/hint               a hint for the next milestone; ask again for more");
+                notice!("/solve NAME [ARG]   type in the answer from one of: {}", solver_names());
+            }
+            _ => notice!("Unknown command {:?}; try /help.", line),
+        }
+    }
+    // The milestones memory shows along with the ones seen happen.
+    fn progress(&self, synacor: &Synacor) -> Result<Progress, String> {
+        let mut progress = Progress::read(synacor)?;
+        if let Some(ref seen) = self.progress {
+            progress.merge(&seen.borrow());
+        }
+        Ok(progress)
+    }
+    // After jumping to another state, what was seen before no longer
+    // applies: the map can't tell where the player is, and progress starts
+    // over from what memory shows.
+    fn moved(&self, synacor: &Synacor) {
+        if let Some(ref map) = self.map {
+            map.borrow_mut().lost();
+        }
+        if let Some(ref progress) = self.progress {
+            *progress.borrow_mut() = Progress::read(synacor).unwrap_or_default();
+        }
+    }
+    pub fn save(&self, synacor: &Synacor, name: &str, note: &str) {
+        match self.slots.save(synacor, name, note) {
+            Ok(path) => notice!("Saved to {}.", path.display()),
+            Err(err) => notice!("Could not save to {}: {}", self.slots.path(name).display(), err),
+        }
+    }
+    // Returns whether the machine was restored.
+    pub fn load(&self, synacor: &mut Synacor, name: &str) -> bool {
+        match self.slots.load(name).map(|(path, snapshot)| (synacor.restore(&snapshot), path)) {
+            Ok((Ok(()), path)) => {
+                self.moved(synacor);
+                notice!("Loaded {}.", path.display());
+                true
+            }
+            Ok((Err(err), path)) => {
+                notice!("Could not restore {}: {}", path.display(), err);
+                false
+            }
+            Err(err) => {
+                notice!("Could not load {}: {}", self.slots.path(name).display(), err);
+                false
+            }
+        }
+    }
+    pub fn list(&self) {
+        match self.slots.describe() {
+            Ok(ref lines) if lines.is_empty() => notice!("There are no saves."),
+            Ok(lines) => {
+                for line in lines {
+                    notice!("{}", line);
+                }
+            }
+            Err(err) => notice!("Could not list the saves: {}", err),
+        }
+    }
+}
+
+fn solver_names() -> String {
+    solve::solvers().iter().map(|solver| solver.name()).collect::<Vec<_>>().join(", ")
+}
+
+pub fn export(synacor: &Synacor, path: &str, spec_only: bool) {
+    let image = synacor.export_memory(spec_only);
+    match fs::write(path, &image) {
+        Ok(()) => notice!("Wrote {} words to {}.", image.len() / 2, path),
+        Err(err) => notice!("Could not write {}: {}", path, err),
+    }
+}
@@ -0,0 +1,26 @@
+// Messages from the emulator itself, as opposed to the game. They always go to
+// stderr so stdout only ever carries the VM's output, and are shown in yellow
+// when stderr is a terminal (unless NO_COLOR is set).
+
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+
+#[macro_export]
+macro_rules! notice {
+    ($($arg:tt)*) => {
+        $crate::notice::write(format_args!($($arg)*))
+    };
+}
+
+fn colored() -> bool {
+    env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal()
+}
+
+pub fn write(message: fmt::Arguments) {
+    if colored() {
+        eprintln!("\x1b[33m{}\x1b[0m", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
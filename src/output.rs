@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::rc::Rc;
+
+// Somewhere for the text printed by opcode 19 to go.
+pub trait OutputSink {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Stdout;
+
+impl OutputSink for Stdout {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        io::stdout().write_all(bytes)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+// Discards everything.
+pub struct Null;
+
+impl OutputSink for Null {
+    fn write(&mut self, _: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct FileSink(BufWriter<File>);
+
+impl FileSink {
+    pub fn create(path: &str) -> io::Result<FileSink> {
+        File::create(path).map(|file| FileSink(BufWriter::new(file)))
+    }
+}
+
+impl OutputSink for FileSink {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.0.write_all(bytes)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Collects output in memory. Clones share the same buffer, so one clone can
+// be handed to the VM while another reads what it printed.
+#[derive(Clone, Default)]
+pub struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Buffer {
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+impl OutputSink for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+// Writes to several sinks at once, stopping at the first error.
+pub struct Tee(Vec<Box<dyn OutputSink>>);
+
+impl Tee {
+    pub fn new(sinks: Vec<Box<dyn OutputSink>>) -> Tee {
+        Tee(sinks)
+    }
+}
+
+impl OutputSink for Tee {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for sink in &mut self.0 {
+            sink.write(bytes)?;
+        }
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        for sink in &mut self.0 {
+            sink.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tee_writes_to_every_sink() {
+        let first = Buffer::default();
+        let second = Buffer::default();
+        let mut tee = Tee::new(vec![Box::new(first.clone()), Box::new(Null), Box::new(second.clone())]);
+        tee.write(b"hello").unwrap();
+        assert_eq!(first.text(), "hello");
+        assert_eq!(second.text(), "hello");
+    }
+}
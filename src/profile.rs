@@ -0,0 +1,257 @@
+// Counts how often each address is executed, to find where the time goes,
+// and follows calls and returns on a shadow call stack to total up each
+// function: the instructions run inside it (exclusive), those run until it
+// returns (inclusive) and how often it was called. Each distinct stack of
+// calls gets a count too, for flame graphs, and each conditional jump counts
+// how often it was taken.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use disasm;
+use trace::{Step, Tracer};
+
+// The name the profiler runs under (see Synacor::set_tracer).
+pub const NAME: &str = "profile";
+// How many rows a report shows unless asked for more.
+pub const TOP: usize = 20;
+// Branches run fewer times than this say too little to rank.
+const MIN_BRANCHES: u64 = 10;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Calls {
+    pub calls: u64,
+    // Instructions run between a call and its return, including other calls.
+    pub inclusive: u64,
+    // Instructions run in the function itself.
+    pub exclusive: u64,
+}
+
+pub struct Profile {
+    hits: Vec<u64>,
+    total: u64,
+    // Keyed by where the function starts; None is the code that isn't in
+    // any call.
+    functions: BTreeMap<Option<u16>, Calls>,
+    // The shadow call stack: the functions called, the total when each was
+    // called and where it is in `stacks`.
+    stack: Vec<(u16, u64, usize)>,
+    // Every call stack seen, as a tree: each one's parent and function, and
+    // how many instructions ran with it on top. The first is the top level.
+    stacks: Vec<(usize, u16, u64)>,
+    children: HashMap<(usize, u16), usize>,
+    // How often each jt and jf was taken and not.
+    branches: BTreeMap<u16, (u64, u64)>,
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        Profile {
+            hits: vec![0; 65536],
+            total: 0,
+            functions: BTreeMap::new(),
+            stack: Vec::new(),
+            stacks: vec![(0, 0, 0)],
+            children: HashMap::new(),
+            branches: BTreeMap::new(),
+        }
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    count as f64 * 100.0 / total.max(1) as f64
+}
+
+impl Profile {
+    pub fn step(&mut self, step: &Step) {
+        self.hits[step.pc as usize] += 1;
+        self.total += 1;
+        let (current, node) = self.stack.last().map_or((None, 0), |&(function, _, node)| (Some(function), node));
+        self.functions.entry(current).or_default().exclusive += 1;
+        self.stacks[node].2 += 1;
+        match step.opcode {
+            17 => {
+                self.functions.entry(Some(step.next)).or_default().calls += 1;
+                let next = self.stacks.len();
+                let child = *self.children.entry((node, step.next)).or_insert(next);
+                if child == next {
+                    self.stacks.push((node, step.next, 0));
+                }
+                self.stack.push((step.next, self.total, child));
+            }
+            // A return with nothing called was pushed by hand; there's
+            // nothing to pop.
+            18 => {
+                if let Some((function, called, _)) = self.stack.pop() {
+                    self.finished(function, called);
+                }
+            }
+            7 | 8 => {
+                let counts = self.branches.entry(step.pc).or_insert((0, 0));
+                if step.next != step.pc.wrapping_add(3) {
+                    counts.0 += 1;
+                } else {
+                    counts.1 += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+    // A recursive call's time is already counted by the outermost one.
+    fn finished(&mut self, function: u16, called: u64) {
+        if self.stack.iter().all(|&(outer, _, _)| outer != function) {
+            self.functions.entry(Some(function)).or_default().inclusive += self.total - called;
+        }
+    }
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+    // The addresses executed most, most first.
+    pub fn hottest(&self) -> Vec<(u16, u64)> {
+        let mut hot: Vec<(u16, u64)> = self.hits.iter().enumerate().filter(|&(_, &hits)| hits > 0).map(|(pc, &hits)| (pc as u16, hits)).collect();
+        hot.sort_by_key(|&(pc, hits)| (std::cmp::Reverse(hits), pc));
+        hot
+    }
+    // Each function's counts, with the calls still running counted up to
+    // now, most inclusive first. The top level includes everything.
+    pub fn functions(&self) -> Vec<(Option<u16>, Calls)> {
+        let mut functions = self.functions.clone();
+        for (depth, &(function, called, _)) in self.stack.iter().enumerate() {
+            if self.stack[..depth].iter().all(|&(outer, _, _)| outer != function) {
+                functions.entry(Some(function)).or_default().inclusive += self.total - called;
+            }
+        }
+        functions.entry(None).or_default().inclusive = self.total;
+        let mut functions: Vec<(Option<u16>, Calls)> = functions.into_iter().collect();
+        functions.sort_by_key(|&(function, calls)| (std::cmp::Reverse(calls.inclusive), function));
+        functions
+    }
+    // How often each conditional jump was taken and not.
+    pub fn branches(&self) -> &BTreeMap<u16, (u64, u64)> {
+        &self.branches
+    }
+    // The `top` most one-sided branches and the `top` most even ones, of
+    // those run often enough to tell.
+    pub fn branch_report<F: Fn(u16) -> u16>(&self, read: F, top: usize) -> Vec<String> {
+        // How far from even a branch is, from 0 to 1.
+        let bias = |taken: u64, not: u64| (taken as f64 - not as f64).abs() / (taken + not) as f64;
+        let mut branches: Vec<(u16, u64, u64, f64)> = self.branches
+            .iter()
+            .filter(|&(_, &(taken, not))| taken + not >= MIN_BRANCHES)
+            .map(|(&pc, &(taken, not))| (pc, taken, not, bias(taken, not)))
+            .collect();
+        let line = |&(pc, taken, not, _): &(u16, u64, u64, f64)| {
+            format!("  {:6.2}% taken {:>10} {:>10}  {:5}  {}", percent(taken, taken + not), taken, not, pc, disasm::instruction(&read, pc).0)
+        };
+        let mut lines = vec![format!("{} branches run at least {} times.", branches.len(), MIN_BRANCHES), "Most one-sided:".to_string()];
+        branches.sort_by(|a, b| b.3.total_cmp(&a.3).then((b.1 + b.2).cmp(&(a.1 + a.2))));
+        lines.extend(branches.iter().take(top).map(line));
+        lines.push("Most even:".to_string());
+        branches.sort_by(|a, b| a.3.total_cmp(&b.3).then((b.1 + b.2).cmp(&(a.1 + a.2))));
+        lines.extend(branches.iter().take(top).map(line));
+        lines
+    }
+    // The call stacks in the folded format flamegraph.pl and inferno read:
+    // a line per stack, its functions from the outside in separated by
+    // semicolons, then how many instructions ran with it on top.
+    pub fn folded(&self) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        for (node, &(_, _, count)) in self.stacks.iter().enumerate().filter(|&(_, &(_, _, count))| count > 0) {
+            let mut frames = Vec::new();
+            let mut at = node;
+            while at != 0 {
+                frames.push(self.stacks[at].1.to_string());
+                at = self.stacks[at].0;
+            }
+            frames.push("top".to_string());
+            frames.reverse();
+            lines.push(format!("{} {}\n", frames.join(";"), count));
+        }
+        lines.sort();
+        lines.concat()
+    }
+    // The `top` hottest addresses and functions, disassembling from `read`.
+    pub fn report<F: Fn(u16) -> u16>(&self, read: F, top: usize) -> Vec<String> {
+        let mut lines = vec![format!("{} instructions profiled.", self.total), "Hottest addresses:".to_string()];
+        for (pc, hits) in self.hottest().into_iter().take(top) {
+            let (text, _) = disasm::instruction(&read, pc);
+            lines.push(format!("  {:6.2}% {:>12}  {:5}  {}", percent(hits, self.total), hits, pc, text));
+        }
+        lines.push(format!("  {:>7} {:>12} {:>7} {:>12} {:>9}  function", "incl", "inclusive", "excl", "exclusive", "calls"));
+        for (function, calls) in self.functions().into_iter().take(top) {
+            let name = function.map_or("(top level)".to_string(), |function| function.to_string());
+            lines.push(format!("  {:6.2}% {:>12} {:6.2}% {:>12} {:>9}  {}", percent(calls.inclusive, self.total), calls.inclusive,
+                               percent(calls.exclusive, self.total), calls.exclusive, calls.calls, name));
+        }
+        lines
+    }
+}
+
+// Feeds a shared profile from the machine.
+pub struct Profiler(pub Rc<RefCell<Profile>>);
+
+impl Tracer for Profiler {
+    fn step(&mut self, step: &Step) {
+        self.0.borrow_mut().step(step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(pc: u16, opcode: u16, next: u16) -> Step {
+        Step { count: 0, pc, opcode, words: [0; 3], before: [0; 8], after: [0; 8], next, stack_depth: 0 }
+    }
+
+    #[test]
+    fn finds_the_hot_spots() {
+        let mut profile = Profile::default();
+        profile.step(&step(0, 17, 10));
+        for _ in 0..3 {
+            profile.step(&step(10, 21, 11));
+            profile.step(&step(11, 21, 12));
+        }
+        profile.step(&step(12, 18, 2));
+        assert_eq!(profile.hottest()[..2], [(10, 3), (11, 3)]);
+        let memory = [17, 10, 0, 0, 0, 0, 0, 0, 0, 0, 21, 21, 18];
+        let report = profile.report(|address| memory.get(address as usize).cloned().unwrap_or(0), 1);
+        assert_eq!(report[..3], ["8 instructions profiled.".to_string(), "Hottest addresses:".to_string(), "   37.50%            3     10  noop".to_string()]);
+        assert_eq!(report[4], "  100.00%            8  12.50%            1         0  (top level)");
+    }
+
+    #[test]
+    fn counts_calls() {
+        let mut profile = Profile::default();
+        // 10 calls 20 twice, and 20 calls itself once the first time.
+        profile.step(&step(0, 17, 10));
+        profile.step(&step(10, 17, 20));
+        profile.step(&step(20, 17, 20));
+        profile.step(&step(20, 21, 21));
+        profile.step(&step(21, 18, 22));
+        profile.step(&step(22, 18, 12));
+        profile.step(&step(12, 17, 20));
+        profile.step(&step(20, 18, 14));
+        let functions = profile.functions();
+        assert_eq!(functions[1], (Some(10), Calls { calls: 1, inclusive: 7, exclusive: 2 }));
+        assert_eq!(functions[2], (Some(20), Calls { calls: 3, inclusive: 5, exclusive: 5 }));
+        assert!(profile.branches().is_empty());
+        assert_eq!(profile.folded(), "top 1\ntop;10 2\ntop;10;20 3\ntop;10;20;20 2\n");
+    }
+
+    #[test]
+    fn counts_branches() {
+        let mut profile = Profile::default();
+        for turn in 0..12 {
+            profile.step(&step(5, 7, if turn % 3 == 0 { 20 } else { 8 }));
+            profile.step(&step(9, 8, 30));
+        }
+        profile.step(&step(40, 8, 43));
+        assert_eq!(profile.branches()[&5], (4, 8));
+        let memory = [0, 0, 0, 0, 0, 7, 32768, 20, 0, 8, 32769, 30];
+        let report = profile.branch_report(|address| memory.get(address as usize).cloned().unwrap_or(0), 1);
+        assert_eq!(report, ["2 branches run at least 10 times.", "Most one-sided:", "  100.00% taken         12          0      9  jf r1 30",
+                            "Most even:", "   33.33% taken          4          8      5  jt r0 20"]);
+    }
+}
@@ -0,0 +1,188 @@
+// How far through the game a session or a save has got. Milestones are
+// spotted in the output as they happen and, since the game keeps no flags
+// for most of them, worked out from what's carried and where for a save (see
+// status.rs). Reading a save misses the ones that leave nothing behind, like
+// the code from the tablet.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::rc::Rc;
+
+use regex::Regex;
+use status::{self, Status};
+use synacor::Synacor;
+use trigger::Trigger;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Milestone {
+    SelfTest,
+    Tablet,
+    Maze,
+    Lantern,
+    Coins,
+    Teleporter,
+    Beach,
+    Vault,
+    Mirror,
+}
+
+pub const MILESTONES: [Milestone; 9] = [
+    Milestone::SelfTest,
+    Milestone::Tablet,
+    Milestone::Maze,
+    Milestone::Lantern,
+    Milestone::Coins,
+    Milestone::Teleporter,
+    Milestone::Beach,
+    Milestone::Vault,
+    Milestone::Mirror,
+];
+
+impl Milestone {
+    // A short name for it, as used in hints.txt.
+    pub fn name(self) -> &'static str {
+        match self {
+            Milestone::SelfTest => "self-test",
+            Milestone::Tablet => "tablet",
+            Milestone::Maze => "maze",
+            Milestone::Lantern => "lantern",
+            Milestone::Coins => "coins",
+            Milestone::Teleporter => "teleporter",
+            Milestone::Beach => "beach",
+            Milestone::Vault => "vault",
+            Milestone::Mirror => "mirror",
+        }
+    }
+    pub fn named(name: &str) -> Option<Milestone> {
+        MILESTONES.iter().cloned().find(|milestone| milestone.name() == name)
+    }
+    // A line of output that shows it was reached.
+    fn sign(self) -> &'static str {
+        match self {
+            Milestone::SelfTest => "self-test complete, all tests pass",
+            Milestone::Tablet => "You find yourself writing",
+            Milestone::Maze => "Chiseled on the wall of one of the passageways",
+            Milestone::Lantern => "You light your lantern.",
+            Milestone::Coins => "As you place the last coin, you hear a click",
+            Milestone::Teleporter => "You activate the teleporter!",
+            Milestone::Beach => "== Beach ==",
+            Milestone::Vault => "You hear a click from the vault door",
+            Milestone::Mirror => "Through the mirror, you see",
+        }
+    }
+}
+
+impl fmt::Display for Milestone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Milestone::SelfTest => "self-test passed",
+            Milestone::Tablet => "tablet used",
+            Milestone::Maze => "through the twisty maze",
+            Milestone::Lantern => "lantern lit",
+            Milestone::Coins => "monument solved",
+            Milestone::Teleporter => "teleporter used",
+            Milestone::Beach => "reached the beach",
+            Milestone::Vault => "vault opened",
+            Milestone::Mirror => "read the mirror",
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Progress {
+    reached: BTreeSet<Milestone>,
+}
+
+impl Progress {
+    // What a save's state shows has been done.
+    pub fn from_status(status: &Status) -> Progress {
+        let carries = |items: &[&str]| status.carried.iter().any(|item| items.contains(&item.as_str()));
+        let room = |titles: &[&str]| titles.iter().any(|title| status.room.starts_with(title));
+        let mut progress = Progress::default();
+        let vault = carries(&["mirror"]) || status.room == "Vault";
+        let beach = vault || carries(&["orb", "journal"]) || room(&["Beach", "Tropical", "Vault"]);
+        let teleporter = beach || status.r7 != 0 || carries(&["business card", "strange book"]) || room(&["Synacor Headquarters"]);
+        let coins = teleporter || status.coins.len() == 5;
+        let lantern = coins || carries(&["lit lantern"]);
+        let maze = lantern || carries(&["can", "lantern"]);
+        let reached = [(Milestone::SelfTest, true), (Milestone::Maze, maze), (Milestone::Lantern, lantern), (Milestone::Coins, coins),
+                       (Milestone::Teleporter, teleporter), (Milestone::Beach, beach), (Milestone::Vault, vault)];
+        progress.reached.extend(reached.iter().filter(|&&(_, reached)| reached).map(|&(milestone, _)| milestone));
+        progress
+    }
+    pub fn read(synacor: &Synacor) -> Result<Progress, String> {
+        let status = Status::read(&status::CHALLENGE, |address| synacor.memory(address), synacor.registers()[7])?;
+        Ok(Progress::from_status(&status))
+    }
+    pub fn merge(&mut self, other: &Progress) {
+        self.reached.extend(other.reached.iter().cloned());
+    }
+    // Takes in a line of output, returning the milestone it shows if it's a
+    // new one.
+    pub fn output_line(&mut self, line: &str) -> Option<Milestone> {
+        let milestone = MILESTONES.iter().cloned().find(|milestone| line.contains(milestone.sign()))?;
+        if self.reached.insert(milestone) {
+            Some(milestone)
+        } else {
+            None
+        }
+    }
+    pub fn reached(&self, milestone: Milestone) -> bool {
+        self.reached.contains(&milestone)
+    }
+    // The milestone after the furthest one reached, which is what the player
+    // is working towards. Ones skipped along the way don't hold them up.
+    pub fn working_on(&self) -> Option<Milestone> {
+        match self.reached.iter().next_back() {
+            Some(&furthest) => MILESTONES.iter().cloned().find(|&milestone| milestone > furthest),
+            None => Some(MILESTONES[0]),
+        }
+    }
+    // A trigger that feeds every line of output to `progress` and announces
+    // each milestone as it's reached.
+    pub fn trigger(progress: Rc<RefCell<Progress>>) -> Trigger {
+        Trigger::new(Regex::new("").unwrap(), move |fired| {
+            let mut progress = progress.borrow_mut();
+            if let Some(milestone) = progress.output_line(fired.line) {
+                notice!("Milestone: {}. {}", milestone, progress);
+            }
+            None
+        })
+    }
+}
+
+// The progress line, e.g. `[####.....] 4/9, next: monument solved`.
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bar: String = MILESTONES.iter().map(|milestone| if self.reached(*milestone) { '#' } else { '.' }).collect();
+        write!(f, "[{}] {}/{}", bar, self.reached.len(), MILESTONES.len())?;
+        match MILESTONES.iter().find(|milestone| !self.reached(**milestone)) {
+            Some(next) => write!(f, ", next: {}", next),
+            None => write!(f, ", all done"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spots_milestones_in_output() {
+        let mut progress = Progress::default();
+        assert_eq!(progress.output_line("self-test complete, all tests pass"), Some(Milestone::SelfTest));
+        assert_eq!(progress.output_line("self-test complete, all tests pass"), None);
+        assert_eq!(progress.output_line("You activate the teleporter!  As you spiral..."), Some(Milestone::Teleporter));
+        assert_eq!(progress.to_string(), "[#....#...] 2/9, next: tablet used");
+    }
+
+    #[test]
+    fn reads_a_save() {
+        let status = Status { room: "Ruins".to_string(), carried: vec!["lit lantern".to_string()], coins: vec![9, 2], r7: 0, orb: None };
+        let progress = Progress::from_status(&status);
+        assert_eq!(progress.to_string(), "[#.##.....] 3/9, next: tablet used");
+        assert!(!progress.reached(Milestone::Coins));
+        assert_eq!(progress.working_on(), Some(Milestone::Coins));
+    }
+}
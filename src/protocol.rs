@@ -0,0 +1,121 @@
+// A framed protocol for driving the VM from another program. Events go out
+// as one JSON object per line:
+//   {"event":"output","text":"..."}
+//   {"event":"input-request"}
+//   {"event":"state","pc":N,"registers":[...],"stack_depth":N,"instructions":N,"hash":"HEX"}
+//   {"event":"halt"}
+//   {"event":"error","message":"..."}
+// and commands come in the same way:
+//   {"command":"input","text":"take tablet\n"}
+//   {"command":"state"}
+//   {"command":"quit"}
+// The VM runs until it wants input, then waits for commands until one gives
+// it some.
+
+use std::io;
+use std::io::prelude::*;
+
+use json::{self, Value};
+use output::Buffer;
+use synacor::{RunOutcome, Synacor};
+
+fn emit<W: Write>(events: &mut W, event: &str, mut fields: Vec<(&str, Value)>) -> io::Result<()> {
+    fields.insert(0, ("event", Value::from(event)));
+    writeln!(events, "{}", Value::object(fields))?;
+    events.flush()
+}
+
+fn state<W: Write>(events: &mut W, synacor: &Synacor) -> io::Result<()> {
+    let registers = synacor.registers().iter().map(|&register| Value::from(register)).collect();
+    emit(
+        events,
+        "state",
+        vec![
+            ("pc", Value::from(synacor.program_counter())),
+            ("registers", Value::Array(registers)),
+            ("stack_depth", Value::from(synacor.stack().len() as u64)),
+            ("instructions", Value::from(synacor.instructions())),
+            // A string, since JSON numbers can't hold 64 bits exactly.
+            ("hash", Value::from(format!("{:016x}", synacor.state_hash()))),
+        ],
+    )
+}
+
+fn error<W: Write>(events: &mut W, message: &str) -> io::Result<()> {
+    emit(events, "error", vec![("message", Value::from(message))])
+}
+
+// Runs the protocol until the VM stops, a quit command arrives or the
+// commands run out. The VM should print to `capture` and yield for input.
+pub fn jsonl<R: BufRead, W: Write>(synacor: &mut Synacor, capture: &Buffer, commands: R, mut events: W)
+    -> io::Result<()> {
+    let mut commands = commands.lines();
+    loop {
+        let outcome = synacor.run();
+        if !capture.is_empty() {
+            emit(&mut events, "output", vec![("text", Value::from(capture.text()))])?;
+            capture.clear();
+        }
+        match outcome {
+            RunOutcome::InputNeeded => emit(&mut events, "input-request", Vec::new())?,
+            RunOutcome::Halted => return emit(&mut events, "halt", Vec::new()),
+            outcome => return error(&mut events, &outcome.to_string()),
+        }
+        loop {
+            let line = match commands.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+            let command = match json::parse(&line) {
+                Ok(command) => command,
+                Err(err) => {
+                    error(&mut events, &err.to_string())?;
+                    continue;
+                }
+            };
+            match (command.get("command").and_then(Value::as_str), command.get("text").and_then(Value::as_str)) {
+                (Some("input"), Some(text)) => {
+                    synacor.push_input(text);
+                    break;
+                }
+                (Some("state"), _) => state(&mut events, synacor)?,
+                (Some("quit"), _) => return Ok(()),
+                _ => error(&mut events, &format!("Unknown command: {}", line))?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::Text;
+    use memory::Image;
+    use synacor::{Config, EofPolicy};
+
+    #[test]
+    fn echoes_through_the_protocol() {
+        // Reads a character, prints it and halts.
+        let program: &[u16] = &[19, '>' as u16, 20, 32768, 19, 32768, 0];
+        let capture = Buffer::default();
+        let config = Config {
+            output: Box::new(capture.clone()),
+            input: Box::new(Text::default()),
+            on_eof: EofPolicy::Yield,
+            ..Config::default()
+        };
+        let mut synacor = Synacor::with_config(config);
+        let bytes = program.iter().flat_map(|word| vec![*word as u8, (*word >> 8) as u8]).collect();
+        synacor.load_image(Image::Bytes(bytes)).ok().unwrap();
+        let commands = "not json\n{\"command\":\"state\"}\n{\"command\":\"input\",\"text\":\"x\"}\n";
+        let mut events = Vec::new();
+        jsonl(&mut synacor, &capture, commands.as_bytes(), &mut events).unwrap();
+        let events = String::from_utf8(events).unwrap();
+        let lines: Vec<&str> = events.lines().collect();
+        assert_eq!(lines[0], r#"{"event":"output","text":">"}"#);
+        assert_eq!(lines[1], r#"{"event":"input-request"}"#);
+        assert!(lines[2].starts_with(r#"{"event":"error""#));
+        assert!(lines[3].starts_with(r#"{"event":"state","pc":2,"registers":[0,0,0,0,0,0,0,0],"stack_depth":0,"instructions":1,"hash":""#));
+        assert_eq!(&lines[4..], [r#"{"event":"output","text":"x"}"#, r#"{"event":"halt"}"#]);
+    }
+}
@@ -0,0 +1,288 @@
+// A small backtracking regular expression matcher, enough for matching game
+// output. Supports literals, `.`, classes like `[a-z]` and `[^0-9]`, the
+// escapes \d \w \s (and \D \W \S), groups, `|`, the quantifiers * + ? and the
+// anchors ^ and $.
+
+use std::fmt;
+
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat { node: Box<Node>, min: usize, max: Option<usize> },
+}
+
+pub struct Regex {
+    source: String,
+    alternatives: Vec<Vec<Node>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RegexErr {
+    pub position: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for RegexErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+struct Parser<'a> {
+    chars: &'a [char],
+    position: usize,
+}
+
+fn escape_class(c: char) -> Option<(Vec<(char, char)>, bool)> {
+    let digits = vec![('0', '9')];
+    let word = vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+    let space = vec![(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')];
+    match c {
+        'd' => Some((digits, false)),
+        'D' => Some((digits, true)),
+        'w' => Some((word, false)),
+        'W' => Some((word, true)),
+        's' => Some((space, false)),
+        'S' => Some((space, true)),
+        _ => None,
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &'static str) -> RegexErr {
+        RegexErr { position: self.position, message }
+    }
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, RegexErr> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.position += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+    fn sequence(&mut self) -> Result<Vec<Node>, RegexErr> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                _ => {
+                    nodes.push(atom);
+                    continue;
+                }
+            };
+            self.position += 1;
+            if let Node::Start | Node::End = atom {
+                return Err(self.error("nothing to repeat"));
+            }
+            nodes.push(Node::Repeat { node: Box::new(atom), min, max });
+        }
+        Ok(nodes)
+    }
+    fn atom(&mut self) -> Result<Node, RegexErr> {
+        match self.next() {
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('(') => {
+                let alternatives = self.alternatives()?;
+                match self.next() {
+                    Some(')') => Ok(Node::Group(alternatives)),
+                    _ => Err(self.error("unclosed group")),
+                }
+            }
+            Some('[') => self.class(),
+            Some('\\') => match self.next() {
+                Some(c) => Ok(match escape_class(c) {
+                    Some((ranges, negated)) => Node::Class { ranges, negated },
+                    None => Node::Char(c),
+                }),
+                None => Err(self.error("trailing backslash")),
+            },
+            Some('*') | Some('+') | Some('?') => Err(self.error("nothing to repeat")),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+    fn class(&mut self) -> Result<Node, RegexErr> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.position += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                Some(']') if !first => return Ok(Node::Class { ranges, negated }),
+                Some('\\') => match self.next() {
+                    Some(c) => match escape_class(c) {
+                        Some((escaped, false)) => {
+                            ranges.extend(escaped);
+                            first = false;
+                            continue;
+                        }
+                        Some((_, true)) => return Err(self.error("negated escape inside a class")),
+                        None => c,
+                    },
+                    None => return Err(self.error("trailing backslash")),
+                },
+                Some(c) => c,
+                None => return Err(self.error("unclosed class")),
+            };
+            first = false;
+            if self.peek() == Some('-') && self.chars.get(self.position + 1).is_some_and(|&end| end != ']') {
+                self.position += 1;
+                let end = self.next().unwrap_or(c);
+                ranges.push((c, end));
+            } else {
+                ranges.push((c, c));
+            }
+        }
+    }
+}
+
+struct Matcher<'a> {
+    text: &'a [char],
+}
+
+impl<'a> Matcher<'a> {
+    // Matches a node that consumes at most one character.
+    fn step(&self, node: &Node, position: usize) -> Option<usize> {
+        let c = self.text.get(position).cloned();
+        match (node, c) {
+            (&Node::Start, _) if position == 0 => Some(position),
+            (&Node::End, None) => Some(position),
+            (&Node::Char(expected), Some(c)) if c == expected => Some(position + 1),
+            (&Node::Any, Some(c)) if c != '\n' => Some(position + 1),
+            (&Node::Class { ref ranges, negated }, Some(c)) => {
+                if ranges.iter().any(|&(low, high)| low <= c && c <= high) != negated {
+                    Some(position + 1)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+    // Matches `nodes[index..]` at `position`, calling `rest` with the end of
+    // each possible match until it accepts one.
+    fn sequence(&self, nodes: &[Node], index: usize, position: usize, rest: &mut dyn FnMut(usize) -> bool) -> bool {
+        match nodes.get(index) {
+            None => rest(position),
+            Some(Node::Group(alternatives)) => alternatives
+                .iter()
+                .any(|alternative| self.sequence(alternative, 0, position, &mut |end| self.sequence(nodes, index + 1, end, rest))),
+            Some(&Node::Repeat { ref node, min, max }) => self.repeat(node, min, max, 0, nodes, index, position, rest),
+            Some(node) => match self.step(node, position) {
+                Some(end) => self.sequence(nodes, index + 1, end, rest),
+                None => false,
+            },
+        }
+    }
+    // Greedily matches `node` again, falling back to the rest of the sequence.
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(&self, node: &Node, min: usize, max: Option<usize>, count: usize, nodes: &[Node], index: usize,
+              position: usize, rest: &mut dyn FnMut(usize) -> bool) -> bool {
+        if max.is_none_or(|max| count < max) {
+            let once = ::std::slice::from_ref(node);
+            let matched = self.sequence(once, 0, position, &mut |end| {
+                // An empty iteration would loop forever.
+                end != position && self.repeat(node, min, max, count + 1, nodes, index, end, rest)
+            });
+            if matched {
+                return true;
+            }
+        }
+        count >= min && self.sequence(nodes, index + 1, position, rest)
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexErr> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut parser = Parser { chars: &chars, position: 0 };
+        let alternatives = parser.alternatives()?;
+        if parser.position < chars.len() {
+            return Err(parser.error("unmatched )"));
+        }
+        Ok(Regex { source: pattern.to_string(), alternatives })
+    }
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+    // Returns the character range of the leftmost match.
+    fn find_chars(&self, text: &[char]) -> Option<(usize, usize)> {
+        let matcher = Matcher { text };
+        for start in 0..=text.len() {
+            let mut found = None;
+            let matched = self.alternatives.iter().any(|alternative| {
+                matcher.sequence(alternative, 0, start, &mut |end| {
+                    found = Some(end);
+                    true
+                })
+            });
+            if matched {
+                return found.map(|end| (start, end));
+            }
+        }
+        None
+    }
+    // The leftmost match in `text`.
+    pub fn find<'t>(&self, text: &'t str) -> Option<&'t str> {
+        let chars: Vec<char> = text.chars().collect();
+        let (start, end) = self.find_chars(&chars)?;
+        let offset = |index: usize| text.char_indices().nth(index).map_or(text.len(), |(offset, _)| offset);
+        Some(&text[offset(start)..offset(end)])
+    }
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'t>(pattern: &str, text: &'t str) -> Option<&'t str> {
+        Regex::new(pattern).unwrap().find(text)
+    }
+
+    #[test]
+    fn matching() {
+        assert_eq!(find("do\\?", "What do you do?"), Some("do?"));
+        assert_eq!(find("^What", "So What"), None);
+        assert_eq!(find("\"[a-zA-Z]+\"", "writing \"efFHYeYFHVGY\" on"), Some("\"efFHYeYFHVGY\""));
+        assert_eq!(find("(north|south)+ ?x", "go southnorth x"), Some("southnorth x"));
+        assert_eq!(find("a.*b", "a1b2b"), Some("a1b2b"));
+        assert_eq!(find("\\d+$", "room 42"), Some("42"));
+        assert_eq!(find("colou?r", "color"), Some("color"));
+        assert_eq!(find("[^ ]+", "  word "), Some("word"));
+        assert_eq!(find("(a*)*b", "aaab"), Some("aaab"));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("a)").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new("[a").is_err());
+    }
+}
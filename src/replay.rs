@@ -0,0 +1,203 @@
+// Deterministic replay files: the state a session started from, every byte
+// of input with the instruction count it was read at, and a hash of the state
+// it ended in. Re-running the input from the starting state has to reproduce
+// the same reads and end in the same state, which makes a replay file a
+// complete bug report.
+//
+// On disk: the magic bytes, the number of inputs as a u64, each input as a
+// u64 instruction count and the byte, the final instruction count and state
+// hash as u64s, then the starting snapshot.
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+use input::Text;
+use output::Null;
+use snapshot::Snapshot;
+use synacor::{Config, EofPolicy, Synacor};
+
+const MAGIC: &[u8; 8] = b"SYNREPL1";
+
+// Input as the program read it, from the state it started in.
+pub struct Recording {
+    pub start: Snapshot,
+    pub input: Vec<(u64, u8)>,
+}
+
+pub struct Replay {
+    pub recording: Recording,
+    pub end: u64,
+    pub hash: u64,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+impl Replay {
+    // Finishes a recording made on `synacor`.
+    pub fn new(synacor: &Synacor, recording: Recording) -> Replay {
+        Replay { recording, end: synacor.instructions(), hash: synacor.state_hash() }
+    }
+    pub fn is_replay(path: &str) -> bool {
+        let mut magic = [0; 8];
+        File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && &magic == MAGIC
+    }
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&(self.recording.input.len() as u64).to_le_bytes())?;
+        for &(at, byte) in &self.recording.input {
+            file.write_all(&at.to_le_bytes())?;
+            file.write_all(&[byte])?;
+        }
+        file.write_all(&self.end.to_le_bytes())?;
+        file.write_all(&self.hash.to_le_bytes())?;
+        self.recording.start.write_to(file)
+    }
+    pub fn load(path: &str) -> io::Result<Replay> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a replay file"));
+        }
+        let count = read_u64(&mut file)?;
+        if count > 1 << 28 {
+            return Err(invalid("the replay file is too large"));
+        }
+        let mut input = Vec::new();
+        for _ in 0..count {
+            let at = read_u64(&mut file)?;
+            let mut byte = [0; 1];
+            file.read_exact(&mut byte)?;
+            input.push((at, byte[0]));
+        }
+        let end = read_u64(&mut file)?;
+        let hash = read_u64(&mut file)?;
+        let start = Snapshot::read_from(file)?;
+        Ok(Replay { recording: Recording { start, input }, end, hash })
+    }
+    // A machine in the starting state, fed the recorded input.
+    pub fn machine(&self) -> Result<Synacor, String> {
+        let text: Vec<u8> = self.recording.input.iter().map(|&(_, byte)| byte).collect();
+        let config = Config {
+            output: Box::new(Null),
+            input: Box::new(Text::new(&String::from_utf8_lossy(&text))),
+            on_eof: EofPolicy::Halt,
+            ..Config::default()
+        };
+        let mut synacor = Synacor::with_config(config);
+        synacor.restore(&self.recording.start)?;
+        Ok(synacor)
+    }
+    // Runs the input again from the starting state and checks that it goes
+    // exactly the same way.
+    pub fn verify(&self) -> Result<(), Mismatch> {
+        let mut synacor = self.machine().map_err(Mismatch::Start)?;
+        synacor.start_recording();
+        synacor.run_for(self.end.saturating_sub(self.recording.start.instructions));
+        let replayed = synacor.take_recording().map_or(Vec::new(), |recording| recording.input);
+        for (index, (&expected, &actual)) in self.recording.input.iter().zip(&replayed).enumerate() {
+            if expected != actual {
+                return Err(Mismatch::Input { index, expected, actual: Some(actual) });
+            }
+        }
+        if let Some(&expected) = self.recording.input.get(replayed.len()) {
+            return Err(Mismatch::Input { index: replayed.len(), expected, actual: None });
+        }
+        if synacor.instructions() != self.end {
+            return Err(Mismatch::End { expected: self.end, actual: synacor.instructions() });
+        }
+        match synacor.state_hash() {
+            actual if actual != self.hash => Err(Mismatch::Hash { expected: self.hash, actual }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    Start(String),
+    Input { index: usize, expected: (u64, u8), actual: Option<(u64, u8)> },
+    End { expected: u64, actual: u64 },
+    Hash { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mismatch::Start(ref err) => write!(f, "The starting state could not be restored: {}", err),
+            Mismatch::Input { index, expected: (at, byte), actual: None } => {
+                write!(f, "Input {} ({:?} at instruction {}) was never read.", index, byte as char, at)
+            }
+            Mismatch::Input { index, expected, actual: Some(actual) } => write!(
+                f,
+                "Input {} was {:?} at instruction {} but is now {:?} at instruction {}.",
+                index, expected.1 as char, expected.0, actual.1 as char, actual.0
+            ),
+            Mismatch::End { expected, actual } => {
+                write!(f, "The run ended after {} instructions instead of {}.", actual, expected)
+            }
+            Mismatch::Hash { expected, actual } => {
+                write!(f, "The final state hash is {:016x} instead of {:016x}.", actual, expected)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::Image;
+    use synacor::RunOutcome;
+
+    // Adds up the input in r1 until it runs out.
+    fn machine(input: &str) -> Synacor {
+        with_config(Config { output: Box::new(Null), input: Box::new(Text::new(input)), ..Config::default() })
+    }
+
+    fn with_config(config: Config) -> Synacor {
+        let words: [u16; 8] = [20, 32768, 9, 32769, 32769, 32768, 6, 0];
+        let mut synacor = Synacor::with_config(config);
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        synacor
+    }
+
+    #[test]
+    fn replays_match() {
+        let mut synacor = machine("abc");
+        synacor.run_for(3);
+        synacor.start_recording();
+        assert!(matches!(synacor.run(), RunOutcome::Halted));
+        let recording = synacor.take_recording().unwrap();
+        assert_eq!(recording.input, vec![(4, b'b'), (7, b'c')]);
+        let mut replay = Replay::new(&synacor, recording);
+        assert_eq!(replay.verify(), Ok(()));
+        replay.hash ^= 1;
+        assert!(matches!(replay.verify(), Err(Mismatch::Hash { .. })));
+        replay.recording.input[1].0 = 8;
+        assert_eq!(replay.verify(), Err(Mismatch::Input { index: 1, expected: (8, b'c'), actual: Some((7, b'c')) }));
+    }
+
+    #[test]
+    fn commands_are_not_recorded() {
+        let input = Box::new(Text::new("a\n/saves\nb"));
+        let mut synacor = with_config(Config { output: Box::new(Null), input, command_prefix: Some(b'/'), ..Config::default() });
+        synacor.start_recording();
+        assert!(matches!(synacor.run(), RunOutcome::Command(_)));
+        assert!(matches!(synacor.run(), RunOutcome::Halted));
+        let recording = synacor.take_recording().unwrap();
+        assert_eq!(recording.input, vec![(1, b'a'), (4, b'\n'), (7, b'b')]);
+        assert_eq!(Replay::new(&synacor, recording).verify(), Ok(()));
+    }
+}
@@ -0,0 +1,105 @@
+// A ring of recent states to step back through. A state is kept every time
+// the game waits for a line of input, so a step is one game turn.
+
+use std::collections::VecDeque;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use snapshot::Snapshot;
+use synacor::Synacor;
+
+pub const KEEP: usize = 50;
+
+// How far back to go: a number of turns, or `30s` for the latest state that
+// is at least 30 seconds old.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Back {
+    Steps(usize),
+    Time(Duration),
+}
+
+impl FromStr for Back {
+    type Err = String;
+    fn from_str(text: &str) -> Result<Back, String> {
+        let parsed = match text.strip_suffix('s') {
+            Some(seconds) => seconds.parse().map(|seconds| Back::Time(Duration::from_secs(seconds))),
+            None => text.parse().map(Back::Steps),
+        };
+        parsed.map_err(|_| format!("{:?} is neither a number of steps nor a number of seconds like 30s", text))
+    }
+}
+
+pub struct Rewind {
+    states: VecDeque<(Instant, Snapshot)>,
+    keep: usize,
+}
+
+impl Rewind {
+    pub fn new(keep: usize) -> Rewind {
+        Rewind { states: VecDeque::new(), keep: keep.max(1) }
+    }
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+    pub fn record(&mut self, synacor: &Synacor) {
+        // Coming back to a state that was just restored adds nothing.
+        if self.states.back().is_some_and(|(_, last)| last.instructions == synacor.instructions()) {
+            return;
+        }
+        if self.states.len() == self.keep {
+            self.states.pop_front();
+        }
+        self.states.push_back((Instant::now(), synacor.snapshot()));
+    }
+    // Restores an earlier state, forgetting everything after it, and returns
+    // how many steps back it went. The newest state is the turn being played,
+    // so one step back is the turn before it.
+    pub fn rewind(&mut self, synacor: &mut Synacor, back: Back) -> Result<usize, String> {
+        let newest = self.states.len().checked_sub(1).ok_or("there is nothing to rewind to")?;
+        let index = match back {
+            Back::Steps(steps) => newest.checked_sub(steps),
+            Back::Time(duration) => self.states.iter().rposition(|&(at, _)| at.elapsed() >= duration),
+        };
+        let index = index.ok_or_else(|| format!("can only go back {} turns", newest))?;
+        synacor.restore(&self.states[index].1)?;
+        self.states.truncate(index + 1);
+        Ok(newest - index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::Text;
+    use memory::Image;
+    use synacor::{Config, RunOutcome};
+
+    #[test]
+    fn parses_amounts() {
+        assert_eq!("3".parse(), Ok(Back::Steps(3)));
+        assert_eq!("30s".parse(), Ok(Back::Time(Duration::from_secs(30))));
+        assert!("soon".parse::<Back>().is_err());
+    }
+
+    #[test]
+    fn steps_back_through_turns() {
+        // Reads characters into r0 forever.
+        let words: [u16; 4] = [20, 32768, 6, 0];
+        let config = Config { input: Box::new(Text::new("a\nb\nc\n")), pause_at_prompt: true, ..Config::default() };
+        let mut synacor = Synacor::with_config(config);
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let mut rewind = Rewind::new(10);
+        while let RunOutcome::Prompt = synacor.run() {
+            rewind.record(&synacor);
+        }
+        assert_eq!(rewind.len(), 4);
+        assert_eq!(rewind.rewind(&mut synacor, Back::Steps(2)), Ok(2));
+        assert_eq!(synacor.registers()[0], b'\n' as u16);
+        assert_eq!(synacor.instructions(), 4);
+        assert!(rewind.rewind(&mut synacor, Back::Steps(2)).is_err());
+        assert_eq!(rewind.rewind(&mut synacor, Back::Time(Duration::from_secs(0))), Ok(0));
+    }
+}
@@ -0,0 +1,190 @@
+// A grid of styled characters for the full-screen frontend to draw into,
+// and the escape sequences that put it on a terminal. Only the rows that
+// changed since the last frame are sent again.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Title,
+    Dim,
+    // Reversed, for the cursor and the current line.
+    Selected,
+    // Something that just changed.
+    Changed,
+    // Breakpoints and faults.
+    Alert,
+}
+
+impl Style {
+    fn escape(self) -> &'static str {
+        match self {
+            Style::Plain => "\x1b[0m",
+            Style::Title => "\x1b[0;1m",
+            Style::Dim => "\x1b[0;2m",
+            Style::Selected => "\x1b[0;7m",
+            Style::Changed => "\x1b[0;1;33m",
+            Style::Alert => "\x1b[0;1;31m",
+        }
+    }
+}
+
+// Part of the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    // Inside the border drawn by Screen::frame.
+    pub fn inner(self) -> Rect {
+        Rect { x: self.x + 1, y: self.y + 1, width: self.width.saturating_sub(2), height: self.height.saturating_sub(2) }
+    }
+    // Cuts off the top `rows` rows, returning them and the rest.
+    pub fn split_top(self, rows: usize) -> (Rect, Rect) {
+        let rows = rows.min(self.height);
+        (Rect { height: rows, ..self }, Rect { y: self.y + rows, height: self.height - rows, ..self })
+    }
+    // Cuts off the left `columns` columns, returning them and the rest.
+    pub fn split_left(self, columns: usize) -> (Rect, Rect) {
+        let columns = columns.min(self.width);
+        (Rect { width: columns, ..self }, Rect { x: self.x + columns, width: self.width - columns, ..self })
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    cells: Vec<(char, Style)>,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Screen {
+        Screen { width, height, cells: vec![(' ', Style::Plain); width * height] }
+    }
+    pub fn area(&self) -> Rect {
+        Rect { x: 0, y: 0, width: self.width, height: self.height }
+    }
+    // Writes `text` from (x, y), cut off at the edge of `within`. Returns
+    // how many columns it took.
+    pub fn put(&mut self, within: Rect, x: usize, y: usize, text: &str, style: Style) -> usize {
+        if y < within.y || y >= within.y + within.height || y >= self.height {
+            return 0;
+        }
+        let end = (within.x + within.width).min(self.width);
+        let mut column = x.max(within.x);
+        for character in text.chars().skip(within.x.saturating_sub(x)) {
+            if column >= end {
+                break;
+            }
+            let printable = if character.is_control() { '?' } else { character };
+            self.cells[y * self.width + column] = (printable, style);
+            column += 1;
+        }
+        column.saturating_sub(x.max(within.x))
+    }
+    // A line of text at the start of row `row` of `within`.
+    pub fn line(&mut self, within: Rect, row: usize, text: &str, style: Style) {
+        if row < within.height {
+            self.put(within, within.x, within.y + row, text, style);
+        }
+    }
+    // A box around `rect` with `title` in the top edge, bright if it has
+    // the focus.
+    pub fn frame(&mut self, rect: Rect, title: &str, focused: bool) {
+        if rect.width < 2 || rect.height < 2 {
+            return;
+        }
+        let style = if focused { Style::Title } else { Style::Dim };
+        let (right, bottom) = (rect.x + rect.width - 1, rect.y + rect.height - 1);
+        let horizontal: String = "─".repeat(rect.width - 2);
+        self.put(rect, rect.x, rect.y, &format!("┌{}┐", horizontal), style);
+        self.put(rect, rect.x, bottom, &format!("└{}┘", horizontal), style);
+        for y in rect.y + 1..bottom {
+            self.put(rect, rect.x, y, "│", style);
+            self.put(rect, right, y, "│", style);
+        }
+        self.put(rect, rect.x + 2, rect.y, &format!(" {} ", title), style);
+    }
+    pub fn cell(&self, x: usize, y: usize) -> (char, Style) {
+        self.cells[y * self.width + x]
+    }
+    // The text of row `y`, for tests.
+    pub fn row(&self, y: usize) -> String {
+        self.cells[y * self.width..(y + 1) * self.width].iter().map(|&(character, _)| character).collect()
+    }
+    // The escape sequences that turn `previous` on the terminal into this,
+    // or draw it from scratch if there's nothing to go on.
+    pub fn render(&self, previous: Option<&Screen>) -> String {
+        let previous = previous.filter(|previous| previous.width == self.width && previous.height == self.height);
+        let mut out = String::new();
+        if previous.is_none() {
+            out += "\x1b[0m\x1b[2J";
+        }
+        for y in 0..self.height {
+            let cells = &self.cells[y * self.width..(y + 1) * self.width];
+            if previous.is_some_and(|previous| &previous.cells[y * self.width..(y + 1) * self.width] == cells) {
+                continue;
+            }
+            out += &format!("\x1b[{};1H", y + 1);
+            let mut style = None;
+            for &(character, cell) in cells {
+                if style != Some(cell) {
+                    out += cell.escape();
+                    style = Some(cell);
+                }
+                out.push(character);
+            }
+        }
+        out += "\x1b[0m";
+        out
+    }
+}
+
+// Breaks `text` into lines of at most `width` characters, at spaces where
+// it can.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let (used, length) = (line.chars().count(), word.chars().count());
+        if used > 0 && used + 1 + length > width {
+            lines.push(std::mem::take(&mut line));
+        } else if used > 0 {
+            line.push(' ');
+        }
+        let mut word: Vec<char> = word.chars().collect();
+        while word.len() > width {
+            lines.push(word.drain(..width).collect());
+        }
+        line.extend(word);
+    }
+    lines.push(line);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_and_clips() {
+        let mut screen = Screen::new(12, 3);
+        let area = screen.area();
+        screen.frame(area, "regs", true);
+        assert_eq!(screen.row(0), "┌─ regs ───┐");
+        screen.line(area.inner(), 0, "r0 12345 r1 0", Style::Changed);
+        assert_eq!(screen.row(1), "│r0 12345 r│");
+        let before = screen.clone();
+        screen.line(area.inner(), 0, "r0 1", Style::Plain);
+        let update = screen.render(Some(&before));
+        assert!(update.starts_with("\x1b[2;1H\x1b[0;1m│\x1b[0mr0 1"));
+        assert!(!update.contains("regs"));
+        assert_eq!(wrap("a long line of text", 6), ["a long", "line", "of", "text"]);
+        assert_eq!(wrap("abcdefgh", 3), ["abc", "def", "gh"]);
+    }
+}
@@ -0,0 +1,156 @@
+// A breadth-first search over game states. From a prompt, each candidate
+// command is tried on a copy of the machine: the exits of the room it's in,
+// taking what lies there and using what it carries. States already reached
+// are recognized by their hash (see hash.rs) and not searched again, which
+// also tells apart rooms that read the same. The search stops at the first
+// command whose output satisfies the goal.
+
+use std::collections::{HashSet, VecDeque};
+
+use map::Map;
+use output::Buffer;
+use snapshot::Snapshot;
+use synacor::{RunOutcome, Synacor};
+
+// How long one command may run before it counts as stuck.
+const COMMAND_LIMIT: u64 = 10_000_000;
+
+// The items listed under `heading` in some output, such as
+// "Things of interest here:" or "Your inventory:".
+pub fn items<'a>(text: &'a str, heading: &str) -> Vec<&'a str> {
+    let mut lines = text.lines().map(str::trim).skip_while(|&line| line != heading).skip(1);
+    let mut items = Vec::new();
+    while let Some(item) = lines.next().and_then(|line| line.strip_prefix("- ")) {
+        items.push(item);
+    }
+    items
+}
+
+pub fn room_items(text: &str) -> Vec<&str> {
+    items(text, "Things of interest here:")
+}
+
+// Types `line` and returns what the game printed, or None if it stopped
+// asking for input.
+fn command(synacor: &mut Synacor, capture: &Buffer, line: &str) -> Option<String> {
+    capture.clear();
+    synacor.push_input(line);
+    synacor.push_input("\n");
+    match synacor.run_for(COMMAND_LIMIT) {
+        RunOutcome::InputNeeded => Some(capture.text()),
+        _ => None,
+    }
+}
+
+// A state to search from, with what it knows about the room it's in.
+struct Node {
+    state: Snapshot,
+    path: Vec<String>,
+    exits: Vec<String>,
+    items: Vec<String>,
+}
+
+impl Node {
+    // Takes in the room shown by `text`, if it shows one.
+    fn seen(&mut self, text: &str) {
+        let mut map = Map::default();
+        text.lines().for_each(|line| map.output_line(line));
+        if let Some(here) = map.here() {
+            self.exits = map.rooms()[here].exits.clone();
+            self.items = room_items(text).into_iter().map(str::to_string).collect();
+        }
+    }
+}
+
+pub struct Search {
+    // The most commands in a solution.
+    pub depth: usize,
+    // The most states to look at before giving up.
+    pub states: usize,
+    // Whether to try taking and using items as well as moving.
+    pub items: bool,
+}
+
+impl Default for Search {
+    fn default() -> Search {
+        Search { depth: 40, states: 100_000, items: true }
+    }
+}
+
+impl Search {
+    fn candidates(&self, synacor: &mut Synacor, capture: &Buffer, node: &Node) -> Vec<String> {
+        let mut candidates = node.exits.clone();
+        if self.items {
+            candidates.extend(node.items.iter().map(|item| format!("take {}", item)));
+            if let Some(text) = command(synacor, capture, "inv") {
+                candidates.extend(items(&text, "Your inventory:").into_iter().map(|item| format!("use {}", item)));
+            }
+        }
+        candidates
+    }
+    // Returns the commands that lead from the machine's current state to
+    // output that satisfies `goal`, or None if there aren't any within the
+    // limits. The machine should print to `capture` and yield for input,
+    // and is left somewhere along the search.
+    pub fn run<G: FnMut(&str) -> bool>(&self, synacor: &mut Synacor, capture: &Buffer, mut goal: G)
+        -> Option<Vec<String>> {
+        let mut start = Node { state: synacor.snapshot(), path: Vec::new(), exits: Vec::new(), items: Vec::new() };
+        let text = command(synacor, capture, "look")?;
+        if goal(&text) {
+            return Some(Vec::new());
+        }
+        start.seen(&text);
+        start.state = synacor.snapshot();
+        let mut seen = HashSet::new();
+        seen.insert(synacor.state_hash());
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some(node) = queue.pop_front() {
+            if node.path.len() == self.depth {
+                continue;
+            }
+            synacor.restore(&node.state).ok()?;
+            for candidate in self.candidates(synacor, capture, &node) {
+                synacor.restore(&node.state).ok()?;
+                let text = match command(synacor, capture, &candidate) {
+                    Some(text) => text,
+                    None => continue,
+                };
+                let mut path = node.path.clone();
+                path.push(candidate);
+                if goal(&text) {
+                    return Some(path);
+                }
+                if seen.len() < self.states && seen.insert(synacor.state_hash()) {
+                    let mut next = Node { state: synacor.snapshot(), path, exits: node.exits.clone(), items: node.items.clone() };
+                    next.seen(&text);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use synacor::Config;
+
+    #[test]
+    fn lists_items() {
+        let text = "== Twisty passages ==\nAll alike.\n\nThings of interest here:\n- can\n- lamp\n\nThere is 1 exit:\n- west\n";
+        assert_eq!(room_items(text), ["can", "lamp"]);
+        assert!(room_items("== Twisty passages ==\n\nThere is 1 exit:\n- west\n").is_empty());
+        assert_eq!(items("Your inventory:\n- tablet\n\nWhat do you do?", "Your inventory:"), ["tablet"]);
+    }
+
+    #[test]
+    fn knows_the_room_it_is_in() {
+        let mut node = Node { state: Synacor::with_config(Config::default()).snapshot(), path: Vec::new(), exits: vec!["north".to_string()], items: Vec::new() };
+        node.seen("Taken.\n");
+        assert_eq!(node.exits, ["north"]);
+        node.seen("== Dark cave ==\nA cave.\n\nThings of interest here:\n- lamp\n\nThere is 1 exit:\n- south\n\nWhat do you do?\n");
+        assert_eq!((node.exits.clone(), node.items.clone()), (vec!["south".to_string()], vec!["lamp".to_string()]));
+    }
+}
@@ -0,0 +1,305 @@
+// Network frontends. Every connection gets its own VM on its own thread,
+// loaded from a shared copy of the ROM. The sessions all count into one set
+// of metrics (see metrics.rs).
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use input::InputSource;
+use memory::Image;
+use metrics::{self, Metrics, Session};
+use output::OutputSink;
+use synacor::{Config, RunOutcome, Synacor};
+use websocket;
+
+const IAC: u8 = 255;
+const SB: u8 = 250;
+const SE: u8 = 240;
+// How many instructions a session runs between updates to the metrics. A
+// session waiting for input may have run up to this many uncounted.
+const CHUNK: u64 = 100_000;
+
+// Writes output to a telnet client, which expects CRLF line endings.
+struct TelnetOutput(BufWriter<TcpStream>);
+
+impl OutputSink for TelnetOutput {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for &byte in bytes {
+            match byte {
+                b'\n' => self.0.write_all(b"\r\n")?,
+                IAC => self.0.write_all(&[IAC, IAC])?,
+                _ => self.0.write_all(&[byte])?,
+            }
+        }
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// Reads a telnet client's lines, dropping option negotiation and turning CR
+// LF or CR NUL into a plain newline.
+struct TelnetInput<R> {
+    reader: R,
+    after_cr: bool,
+}
+
+impl<R: Read> TelnetInput<R> {
+    fn next(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0; 1];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+    // Skips the rest of a command that started with IAC, returning an escaped
+    // 255 if that is what it was.
+    fn command(&mut self) -> io::Result<Option<u8>> {
+        match self.next()? {
+            Some(IAC) => return Ok(Some(IAC)),
+            // WILL, WONT, DO and DONT carry an option byte.
+            Some(251..=254) => {
+                self.next()?;
+            }
+            Some(SB) => {
+                let mut previous = 0;
+                while let Some(byte) = self.next()? {
+                    if previous == IAC && byte == SE {
+                        break;
+                    }
+                    previous = byte;
+                }
+            }
+            _ => (),
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> InputSource for TelnetInput<R> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            let byte = match self.next()? {
+                Some(IAC) => match self.command()? {
+                    Some(byte) => byte,
+                    None => continue,
+                },
+                Some(byte) => byte,
+                None => return Ok(None),
+            };
+            let after_cr = self.after_cr;
+            self.after_cr = byte == b'\r';
+            match byte {
+                b'\r' => return Ok(Some(b'\n')),
+                b'\n' | 0 if after_cr => continue,
+                _ => return Ok(Some(byte)),
+            }
+        }
+    }
+}
+
+// The page served to browsers that ask for anything but a websocket.
+const TERMINAL_PAGE: &str = include_str!("terminal.html");
+
+// Sends everything printed since the last flush as one text message. The VM
+// flushes before it reads input, so each message ends at a prompt.
+struct WebSocketOutput {
+    stream: TcpStream,
+    pending: Vec<u8>,
+}
+
+impl OutputSink for WebSocketOutput {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.pending.extend(bytes);
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            websocket::write_text(&mut self.stream, &self.pending)?;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WebSocketOutput {
+    fn drop(&mut self) {
+        let _ = self.flush();
+        let _ = websocket::write_close(&mut self.stream);
+    }
+}
+
+// Each message from the browser is one line of input.
+struct WebSocketInput {
+    reader: BufReader<TcpStream>,
+    // For answering pings.
+    writer: TcpStream,
+    pending: VecDeque<u8>,
+}
+
+impl InputSource for WebSocketInput {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pending.is_empty() {
+            match websocket::read_message(&mut self.reader, &mut self.writer)? {
+                Some(line) => {
+                    self.pending.extend(line.iter().filter(|&&byte| byte != b'\r'));
+                    if self.pending.back() != Some(&b'\n') {
+                        self.pending.push_back(b'\n');
+                    }
+                }
+                None => return Ok(None),
+            }
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+// Counts the bytes going through a session's input or output.
+struct Counted<T> {
+    inner: T,
+    metrics: Arc<Metrics>,
+}
+
+impl InputSource for Counted<Box<dyn InputSource>> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.inner.read_byte()?;
+        if byte.is_some() {
+            Metrics::add(&self.metrics.input_bytes, 1);
+        }
+        Ok(byte)
+    }
+}
+
+impl OutputSink for Counted<Box<dyn OutputSink>> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        Metrics::add(&self.metrics.output_bytes, bytes.len() as u64);
+        self.inner.write(bytes)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn play(mut config: Config, rom: &[u8], metrics: &Arc<Metrics>) -> io::Result<()> {
+    let _session = Session::start(metrics.clone());
+    config.input = Box::new(Counted { inner: config.input, metrics: metrics.clone() });
+    config.output = Box::new(Counted { inner: config.output, metrics: metrics.clone() });
+    let mut synacor = Synacor::with_config(config);
+    if let Err(err) = synacor.load_image(Image::Bytes(rom.to_vec())) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, err.to_string()));
+    }
+    let outcome = loop {
+        let before = synacor.instructions();
+        let outcome = synacor.run_for(CHUNK);
+        Metrics::add(&metrics.instructions, synacor.instructions() - before);
+        match outcome {
+            RunOutcome::BudgetExceeded => (),
+            outcome => break outcome,
+        }
+    };
+    if let RunOutcome::Faulted(_) = outcome {
+        Metrics::add(&metrics.faults, 1);
+    }
+    notice!("A session ended: {}", outcome);
+    Ok(())
+}
+
+fn telnet_session(stream: TcpStream, rom: &[u8], metrics: &Arc<Metrics>) -> io::Result<()> {
+    let config = Config {
+        output: Box::new(TelnetOutput(BufWriter::new(stream.try_clone()?))),
+        input: Box::new(TelnetInput { reader: BufReader::new(stream), after_cr: false }),
+        ..Config::default()
+    };
+    play(config, rom, metrics)
+}
+
+fn websocket_session(stream: TcpStream, rom: &[u8], metrics: &Arc<Metrics>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = websocket::read_request(&mut reader)?;
+    let mut writer = stream.try_clone()?;
+    if request.path == metrics::PATH {
+        return metrics.respond(&request.path, &mut writer);
+    }
+    let key = match request.key {
+        Some(key) => key,
+        None => {
+            return write!(
+                writer,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                TERMINAL_PAGE.len(),
+                TERMINAL_PAGE
+            );
+        }
+    };
+    websocket::write_handshake(&mut writer, &key)?;
+    let config = Config {
+        output: Box::new(WebSocketOutput { stream, pending: Vec::new() }),
+        input: Box::new(WebSocketInput { reader, writer, pending: VecDeque::new() }),
+        ..Config::default()
+    };
+    play(config, rom, metrics)
+}
+
+type SessionFn = fn(TcpStream, &[u8], &Arc<Metrics>) -> io::Result<()>;
+
+fn serve(address: &str, rom: Vec<u8>, metrics_address: Option<&str>, session: SessionFn) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    notice!("Listening on {}.", listener.local_addr()?);
+    let metrics = Arc::new(Metrics::default());
+    if let Some(metrics_address) = metrics_address {
+        metrics::serve(metrics_address, metrics.clone())?;
+    }
+    let rom = Arc::new(rom);
+    for stream in listener.incoming() {
+        // One client going wrong shouldn't stop the others connecting.
+        let (stream, peer) = match stream.and_then(|stream| stream.peer_addr().map(|peer| (stream, peer))) {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                notice!("Could not accept a connection: {}", err);
+                continue;
+            }
+        };
+        notice!("{} connected.", peer);
+        let (rom, metrics) = (rom.clone(), metrics.clone());
+        thread::spawn(move || {
+            if let Err(err) = session(stream, &rom, &metrics) {
+                notice!("The session with {} failed: {}", peer, err);
+            }
+        });
+    }
+    Ok(())
+}
+
+// Serves the ROM over telnet until the listener fails, and the metrics over
+// HTTP on `metrics` if given.
+pub fn telnet(address: &str, rom: Vec<u8>, metrics: Option<&str>) -> io::Result<()> {
+    serve(address, rom, metrics, telnet_session)
+}
+
+// Serves the ROM to websocket clients. Plain HTTP requests get a terminal
+// page that connects back to the same address, or the metrics at /metrics.
+pub fn websocket(address: &str, rom: Vec<u8>, metrics: Option<&str>) -> io::Result<()> {
+    serve(address, rom, metrics, websocket_session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telnet_input_strips_negotiation() {
+        let bytes: &[u8] = &[IAC, 253, 1, b'h', b'i', b'\r', b'\n', IAC, SB, 24, 1, IAC, SE, b'x', b'\r', 0, IAC, IAC];
+        let mut input = TelnetInput { reader: bytes, after_cr: false };
+        let mut text = Vec::new();
+        while let Some(byte) = input.read_byte().unwrap() {
+            text.push(byte);
+        }
+        assert_eq!(text, b"hi\nx\n\xff");
+    }
+}
@@ -0,0 +1,179 @@
+// Named save slots: each slot is a snapshot in the saves directory with a
+// small metadata file beside it describing when and where it was made.
+//
+// The metadata file holds `key: value` lines for the time it was saved (Unix
+// seconds), the instruction count, the last line printed before the prompt
+// and an optional note.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use snapshot::{self, Snapshot};
+use synacor::Synacor;
+
+pub const DIR: &str = "saves";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub saved: u64,
+    pub instructions: u64,
+    pub last_line: String,
+    pub note: String,
+}
+
+impl Metadata {
+    fn parse(text: &str) -> Metadata {
+        let mut metadata = Metadata::default();
+        for line in text.lines() {
+            let (key, value) = line.split_once(": ").unwrap_or((line, ""));
+            match key {
+                "saved" => metadata.saved = value.parse().unwrap_or(0),
+                "instructions" => metadata.instructions = value.parse().unwrap_or(0),
+                "last_line" => metadata.last_line = value.to_string(),
+                "note" => metadata.note = value.to_string(),
+                _ => (),
+            }
+        }
+        metadata
+    }
+    fn to_text(&self) -> String {
+        format!(
+            "saved: {}\ninstructions: {}\nlast_line: {}\nnote: {}\n",
+            self.saved,
+            self.instructions,
+            self.last_line.replace('\n', " "),
+            self.note.replace('\n', " ")
+        )
+    }
+}
+
+pub struct Slot {
+    pub name: String,
+    pub metadata: Metadata,
+}
+
+// Formats Unix seconds as a UTC date and time.
+pub fn format_time(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+    // Howard Hinnant's days-to-civil conversion.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+pub struct Slots {
+    dir: PathBuf,
+}
+
+impl Slots {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Slots {
+        Slots { dir: dir.as_ref().to_path_buf() }
+    }
+    // Names with a slash or a dot are taken to be paths rather than slots.
+    pub fn path(&self, name: &str) -> PathBuf {
+        if name.contains('/') || name.contains('.') {
+            PathBuf::from(snapshot::path(name))
+        } else {
+            self.dir.join(format!("{}.sav", name))
+        }
+    }
+    fn metadata_path(snapshot: &Path) -> PathBuf {
+        snapshot.with_extension("meta")
+    }
+    // Saves the machine into a slot and returns where it went.
+    pub fn save(&self, synacor: &Synacor, name: &str, note: &str) -> io::Result<PathBuf> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        synacor.snapshot().save(&path.to_string_lossy())?;
+        let recent = synacor.recent_lines();
+        // The last line is the prompt the machine is waiting at.
+        let last_line = match recent.len() {
+            0 => "",
+            1 => &recent[0],
+            len => &recent[len - 2],
+        };
+        let metadata = Metadata {
+            saved: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+            instructions: synacor.instructions(),
+            last_line: last_line.trim().to_string(),
+            note: note.to_string(),
+        };
+        fs::write(Slots::metadata_path(&path), metadata.to_text())?;
+        Ok(path)
+    }
+    pub fn load(&self, name: &str) -> io::Result<(PathBuf, Snapshot)> {
+        let path = self.path(name);
+        let snapshot = Snapshot::load(&path.to_string_lossy())?;
+        Ok((path, snapshot))
+    }
+    // Every slot in the directory, oldest first.
+    pub fn list(&self) -> io::Result<Vec<Slot>> {
+        let mut slots = Vec::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(slots),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "sav") {
+                continue;
+            }
+            let name = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+            let metadata = fs::read_to_string(Slots::metadata_path(&path)).map(|text| Metadata::parse(&text)).unwrap_or_default();
+            slots.push(Slot { name, metadata });
+        }
+        slots.sort_by(|a, b| (a.metadata.saved, &a.name).cmp(&(b.metadata.saved, &b.name)));
+        Ok(slots)
+    }
+    // One line per slot, for `saves list` and `/saves`.
+    pub fn describe(&self) -> io::Result<Vec<String>> {
+        Ok(self
+            .list()?
+            .iter()
+            .map(|slot| {
+                let metadata = &slot.metadata;
+                let mut line = format!(
+                    "{:<16} {}  {:>12} instructions  {}",
+                    slot.name,
+                    format_time(metadata.saved),
+                    metadata.instructions,
+                    metadata.last_line
+                );
+                if !metadata.note.is_empty() {
+                    line += &format!("  ({})", metadata.note);
+                }
+                line
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_round_trip() {
+        let metadata = Metadata { saved: 1, instructions: 2, last_line: "Taken.".to_string(), note: "before the vault".to_string() };
+        assert_eq!(Metadata::parse(&metadata.to_text()), metadata);
+    }
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00");
+        assert_eq!(format_time(951782400 + 3661), "2000-02-29 01:01:01");
+    }
+}
@@ -0,0 +1,392 @@
+// Complete machine state, saved to and loaded from disk. The ROM image is
+// stored with the state, so a snapshot can be restored into any VM.
+//
+// A snapshot file starts with the magic bytes, the format version it was
+// written with and the oldest version that can read it, then the compression
+// method, the body's length and the (compressed) body. The body is a list of
+// tagged sections, each with a u64 length. Files written before the format
+// was versioned start with SYNSNAP1 and still load.
+
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+use compress;
+use hash;
+use memory::{SavedMemory, SavedPage};
+use statejson;
+
+const MAGIC: &[u8; 8] = b"SYNSTATE";
+const LEGACY_MAGIC: &[u8; 8] = b"SYNSNAP1";
+const VERSION: u16 = 2;
+// The oldest reader that understands what this version writes.
+const COMPATIBLE: u16 = 2;
+const NONE: u8 = 0;
+const RLE: u8 = 1;
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub program_counter: u16,
+    pub registers: [u16; 8],
+    pub stack: Vec<u16>,
+    pub memory: SavedMemory,
+    pub instructions: u64,
+    // Input that was queued but not read yet.
+    pub queued_input: Vec<u8>,
+    pub input_eof: bool,
+    pub after_cr: bool,
+    pub output_line: Vec<u8>,
+}
+
+// The file for a snapshot called `name`: the name itself if it already has
+// an extension, otherwise name.sav.
+pub fn path(name: &str) -> String {
+    if name.rsplit('/').next().unwrap_or(name).contains('.') {
+        name.to_string()
+    } else {
+        format!("{}.sav", name)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct Writer<W>(W);
+
+impl<W: Write> Writer<W> {
+    fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.0.write_all(&[value])
+    }
+    fn u16(&mut self, value: u16) -> io::Result<()> {
+        self.0.write_all(&value.to_le_bytes())
+    }
+    fn u64(&mut self, value: u64) -> io::Result<()> {
+        self.0.write_all(&value.to_le_bytes())
+    }
+    fn bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.u64(bytes.len() as u64)?;
+        self.0.write_all(bytes)
+    }
+    fn words(&mut self, words: &[u16]) -> io::Result<()> {
+        self.u64(words.len() as u64)?;
+        words.iter().try_for_each(|&word| self.u16(word))
+    }
+}
+
+struct Reader<R>(R);
+
+impl<R: Read> Reader<R> {
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        self.0.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+    // Lengths are checked against what memory could possibly hold, so a
+    // corrupt file can't ask for an enormous allocation.
+    fn len(&mut self) -> io::Result<usize> {
+        match self.u64()? {
+            len if len <= 1 << 24 => Ok(len as usize),
+            _ => Err(invalid("a length in the snapshot is too large")),
+        }
+    }
+    fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.len()?;
+        let mut bytes = vec![0; len];
+        self.0.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+    fn words(&mut self) -> io::Result<Vec<u16>> {
+        let len = self.len()?;
+        (0..len).map(|_| self.u16()).collect()
+    }
+}
+
+// Sections of a version 2 snapshot. Readers skip sections they don't know,
+// so newer files still load as long as nothing essential changed.
+const MACHINE: u8 = 1;
+const STACK: u8 = 2;
+const INPUT: u8 = 3;
+const OUTPUT: u8 = 4;
+const MEMORY: u8 = 5;
+
+// Flips page `index` of `words` to or from its difference with the image.
+fn xor_image(words: &mut [u16], image: &[u8], index: usize) {
+    let start = index * words.len();
+    for (offset, word) in words.iter_mut().enumerate() {
+        let address = (start + offset) * 2;
+        if let (Some(&low), Some(&high)) = (image.get(address), image.get(address + 1)) {
+            *word ^= (high as u16) << 8 | low as u16;
+        }
+    }
+}
+
+fn section<F>(body: &mut Vec<u8>, tag: u8, fill: F) -> io::Result<()>
+where
+    F: FnOnce(&mut Writer<&mut Vec<u8>>) -> io::Result<()>,
+{
+    let mut payload = Vec::new();
+    fill(&mut Writer(&mut payload))?;
+    body.push(tag);
+    body.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    body.extend_from_slice(&payload);
+    Ok(())
+}
+
+impl Snapshot {
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut body = Vec::new();
+        section(&mut body, MACHINE, |writer| {
+            writer.u16(self.program_counter)?;
+            self.registers.iter().try_for_each(|&register| writer.u16(register))?;
+            writer.u64(self.instructions)
+        })?;
+        section(&mut body, STACK, |writer| writer.words(&self.stack))?;
+        section(&mut body, INPUT, |writer| {
+            writer.bytes(&self.queued_input)?;
+            writer.u8(self.input_eof as u8 | (self.after_cr as u8) << 1)
+        })?;
+        section(&mut body, OUTPUT, |writer| writer.bytes(&self.output_line))?;
+        section(&mut body, MEMORY, |writer| self.write_memory(writer, true))?;
+        let compressed = compress::compress(&body);
+        let mut writer = Writer(writer);
+        writer.0.write_all(MAGIC)?;
+        writer.u16(VERSION)?;
+        writer.u16(COMPATIBLE)?;
+        writer.u8(RLE)?;
+        writer.u64(body.len() as u64)?;
+        writer.bytes(&compressed)?;
+        writer.0.flush()
+    }
+    // Pages are stored as the difference from the image, which is mostly
+    // zeros. Version 1 stored them as they were.
+    fn write_memory<W: Write>(&self, writer: &mut Writer<W>, delta: bool) -> io::Result<()> {
+        writer.bytes(&self.memory.image)?;
+        writer.u64(self.memory.loaded as u64)?;
+        writer.u64(self.memory.pages.len() as u64)?;
+        for page in &self.memory.pages {
+            writer.u64(page.index as u64)?;
+            let mut words = page.words.clone();
+            if delta {
+                xor_image(&mut words, &self.memory.image, page.index);
+            }
+            writer.words(&words)?;
+            writer.u64(page.written.len() as u64)?;
+            page.written.iter().try_for_each(|&bits| writer.u64(bits))?;
+        }
+        Ok(())
+    }
+    fn read_memory<R: Read>(reader: &mut Reader<R>, delta: bool) -> io::Result<SavedMemory> {
+        let image = reader.bytes()?;
+        let loaded = reader.len()?;
+        let mut pages = Vec::new();
+        for _ in 0..reader.len()? {
+            let index = reader.len()?;
+            let mut words = reader.words()?;
+            if delta {
+                xor_image(&mut words, &image, index);
+            }
+            let written_len = reader.len()?;
+            let written = (0..written_len).map(|_| reader.u64()).collect::<io::Result<_>>()?;
+            pages.push(SavedPage { index, words, written });
+        }
+        Ok(SavedMemory { image, loaded, pages })
+    }
+    pub fn read_from<R: Read>(reader: R) -> io::Result<Snapshot> {
+        let mut reader = Reader(reader);
+        match &reader.array::<8>()? {
+            magic if magic == MAGIC => (),
+            magic if magic == LEGACY_MAGIC => return Snapshot::read_legacy(reader),
+            _ => return Err(invalid("not a snapshot file")),
+        }
+        let version = reader.u16()?;
+        if reader.u16()? > VERSION {
+            return Err(invalid(&format!("the snapshot needs a newer emulator (format version {})", version)));
+        }
+        let compression = reader.u8()?;
+        let len = reader.len()?;
+        let stored = reader.bytes()?;
+        let body = match compression {
+            NONE => stored,
+            RLE => compress::decompress(&stored, len).map_err(|err| invalid(&err))?,
+            _ => return Err(invalid(&format!("unknown compression method {}", compression))),
+        };
+        Snapshot::read_sections(&body)
+    }
+    fn read_sections(mut body: &[u8]) -> io::Result<Snapshot> {
+        let mut snapshot = Snapshot {
+            program_counter: 0,
+            registers: [0; 8],
+            stack: Vec::new(),
+            memory: SavedMemory { image: Vec::new(), loaded: 0, pages: Vec::new() },
+            instructions: 0,
+            queued_input: Vec::new(),
+            input_eof: false,
+            after_cr: false,
+            output_line: Vec::new(),
+        };
+        let (mut machine, mut memory) = (false, false);
+        while !body.is_empty() {
+            let mut header = Reader(&body[..body.len().min(9)]);
+            let tag = header.u8()?;
+            let len = header.len()?;
+            let payload = body.get(9..9 + len).ok_or_else(|| invalid("a snapshot section is truncated"))?;
+            body = &body[9 + len..];
+            let mut reader = Reader(payload);
+            match tag {
+                MACHINE => {
+                    snapshot.program_counter = reader.u16()?;
+                    for register in snapshot.registers.iter_mut() {
+                        *register = reader.u16()?;
+                    }
+                    snapshot.instructions = reader.u64()?;
+                    machine = true;
+                }
+                STACK => snapshot.stack = reader.words()?,
+                INPUT => {
+                    snapshot.queued_input = reader.bytes()?;
+                    let flags = reader.u8()?;
+                    snapshot.input_eof = flags & 1 != 0;
+                    snapshot.after_cr = flags & 2 != 0;
+                }
+                OUTPUT => snapshot.output_line = reader.bytes()?,
+                MEMORY => {
+                    snapshot.memory = Snapshot::read_memory(&mut reader, true)?;
+                    memory = true;
+                }
+                _ => (),
+            }
+        }
+        if !machine || !memory {
+            return Err(invalid("the snapshot is missing the machine state"));
+        }
+        Ok(snapshot)
+    }
+    // Version 1 files were uncompressed and had a fixed layout.
+    fn read_legacy<R: Read>(mut reader: Reader<R>) -> io::Result<Snapshot> {
+        let program_counter = reader.u16()?;
+        let mut registers = [0; 8];
+        for register in registers.iter_mut() {
+            *register = reader.u16()?;
+        }
+        let stack = reader.words()?;
+        let instructions = reader.u64()?;
+        let queued_input = reader.bytes()?;
+        let flags = reader.u8()?;
+        let output_line = reader.bytes()?;
+        let memory = Snapshot::read_memory(&mut reader, false)?;
+        Ok(Snapshot {
+            program_counter,
+            registers,
+            stack,
+            memory,
+            instructions,
+            queued_input,
+            input_eof: flags & 1 != 0,
+            after_cr: flags & 2 != 0,
+            output_line,
+        })
+    }
+    // The same hash as Synacor::state_hash.
+    pub fn state_hash(&self) -> u64 {
+        hash::state(self.program_counter, &self.registers, &self.stack, |address| self.memory.word(address))
+    }
+    // Files ending in .json hold the state as JSON (see statejson.rs).
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        if path.ends_with(".json") {
+            return fs::write(path, statejson::to_json(self).pretty() + "\n");
+        }
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+    pub fn load(path: &str) -> io::Result<Snapshot> {
+        if path.ends_with(".json") {
+            return statejson::parse(&fs::read_to_string(path)?).map_err(|err| invalid(&err));
+        }
+        Snapshot::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Snapshot {
+        let mut words = vec![0; 4096];
+        words[7] = 9;
+        Snapshot {
+            program_counter: 5,
+            registers: [1, 2, 3, 4, 5, 6, 7, 8],
+            stack: vec![10, 11],
+            memory: SavedMemory {
+                image: vec![1, 0, 2, 0],
+                loaded: 2,
+                pages: vec![SavedPage { index: 0, words, written: vec![1 << 7; 64] }],
+            },
+            instructions: 99,
+            queued_input: b"look\n".to_vec(),
+            input_eof: false,
+            after_cr: true,
+            output_line: b"> ".to_vec(),
+        }
+    }
+
+    fn written(snapshot: &Snapshot) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip_is_compressed() {
+        let bytes = written(&example());
+        assert!(bytes.len() < 1000);
+        assert!(Snapshot::read_from(&bytes[..]).unwrap() == example());
+    }
+
+    #[test]
+    fn skips_unknown_sections() {
+        let snapshot = example();
+        let mut body = vec![200, 3, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3];
+        let bytes = written(&snapshot);
+        let len = Reader(&bytes[13..]).len().unwrap();
+        body.extend(compress::decompress(&bytes[29..], len).unwrap());
+        let mut file = Vec::new();
+        let mut writer = Writer(&mut file);
+        writer.0.write_all(MAGIC).unwrap();
+        writer.u16(VERSION + 1).unwrap();
+        writer.u16(VERSION).unwrap();
+        writer.u8(NONE).unwrap();
+        writer.u64(body.len() as u64).unwrap();
+        writer.bytes(&body).unwrap();
+        assert!(Snapshot::read_from(&file[..]).unwrap() == snapshot);
+        file[10] = VERSION as u8 + 1;
+        assert!(Snapshot::read_from(&file[..]).is_err());
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let snapshot = example();
+        let mut file = Vec::new();
+        let mut writer = Writer(&mut file);
+        writer.0.write_all(LEGACY_MAGIC).unwrap();
+        writer.u16(snapshot.program_counter).unwrap();
+        snapshot.registers.iter().for_each(|&register| writer.u16(register).unwrap());
+        writer.words(&snapshot.stack).unwrap();
+        writer.u64(snapshot.instructions).unwrap();
+        writer.bytes(&snapshot.queued_input).unwrap();
+        writer.u8(2).unwrap();
+        writer.bytes(&snapshot.output_line).unwrap();
+        snapshot.write_memory(&mut writer, false).unwrap();
+        assert!(Snapshot::read_from(&file[..]).unwrap() == snapshot);
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use input::Text;
+use output::Buffer;
+use search::{self, Search};
+use snapshot::Snapshot;
+use synacor::{Config, EofPolicy, Region, Synacor};
+
+const WORDS: usize = 32768;
+
+// The teleporter's confirmation routine (at 6027 in the challenge binary) is
+// an Ackermann-like function of r0, r1 and r7, all mod 32768:
+//   f(0, b) = b + 1
+//   f(a, 0) = f(a - 1, r7)
+//   f(a, b) = f(a - 1, f(a, b - 1))
+// The game calls it with r0 = 4 and r1 = 1 and wants 6 back. Each row of the
+// function only depends on the row below it, so it is memoized a row at a
+// time instead of recursing billions of times.
+pub fn confirmation(r7: u16) -> u16 {
+    let mut below: Vec<u16> = (0..WORDS).map(|b| ((b + 1) % WORDS) as u16).collect();
+    let mut row = vec![0; WORDS];
+    for _ in 1..4 {
+        row[0] = below[r7 as usize];
+        for b in 1..WORDS {
+            row[b] = below[row[b - 1] as usize];
+        }
+        std::mem::swap(&mut row, &mut below);
+    }
+    let first = below[r7 as usize];
+    below[first as usize]
+}
+
+pub fn teleporter() -> Option<u16> {
+    let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    smallest(workers, |r7| confirmation(r7) == 6)
+}
+
+// The smallest word `holds` is true of, tried on `workers` threads. Each
+// tries its words in order and gives up once past the smallest found yet, so
+// none smaller goes untried.
+fn smallest(workers: usize, holds: fn(u16) -> bool) -> Option<u16> {
+    let best = Arc::new(AtomicUsize::new(WORDS));
+    let handles: Vec<_> = (0..workers)
+        .map(|worker| {
+            let best = best.clone();
+            thread::spawn(move || {
+                let mut word = worker;
+                while word < best.load(Ordering::Relaxed) {
+                    if holds(word as u16) {
+                        best.fetch_min(word, Ordering::Relaxed);
+                        return;
+                    }
+                    word += workers;
+                }
+            })
+        })
+        .collect();
+    handles.into_iter().for_each(|handle| handle.join().unwrap());
+    Some(best.load(Ordering::Relaxed) as u16).filter(|&word| (word as usize) < WORDS)
+}
+
+// The coins in the ruins and their values, from the shapes on them.
+pub const COINS: [(&str, i64); 5] = [
+    ("red coin", 2),
+    ("corroded coin", 3),
+    ("shiny coin", 5),
+    ("concave coin", 7),
+    ("blue coin", 9),
+];
+
+fn permutations(items: &mut Vec<usize>, len: usize, found: &mut dyn FnMut(&[usize]) -> bool) -> bool {
+    if len <= 1 {
+        return found(items);
+    }
+    for i in 0..len {
+        items.swap(i, len - 1);
+        if permutations(items, len - 1, found) {
+            return true;
+        }
+        items.swap(i, len - 1);
+    }
+    false
+}
+
+// The order that satisfies the monument: _ + _ * _^2 + _^3 - _ = 399.
+pub fn coins() -> Option<Vec<&'static str>> {
+    let mut order: Vec<usize> = (0..COINS.len()).collect();
+    let len = order.len();
+    let mut solution = None;
+    permutations(&mut order, len, &mut |order| {
+        let value = |slot: usize| COINS[order[slot]].1;
+        let fits = value(0) + value(1) * value(2).pow(2) + value(3).pow(3) - value(4) == 399;
+        if fits {
+            solution = Some(order.iter().map(|&coin| COINS[coin].0).collect());
+        }
+        fits
+    });
+    solution
+}
+
+#[derive(Clone, Copy)]
+enum Tile {
+    Number(i64),
+    Add,
+    Subtract,
+    Multiply,
+}
+
+// The vault antechamber, north at the top. The orb starts at 22 in the south
+// west corner and the vault door is the 1 in the north east corner.
+const VAULT: [[Tile; 4]; 4] = {
+    use self::Tile::*;
+    [
+        [Multiply, Number(8), Subtract, Number(1)],
+        [Number(4), Multiply, Number(11), Multiply],
+        [Add, Number(4), Subtract, Number(18)],
+        [Number(22), Subtract, Number(9), Multiply],
+    ]
+};
+const ORB: i64 = 22;
+const DOOR: i64 = 30;
+// The orb shatters outside this range.
+const ORB_LIMIT: i64 = 32768;
+
+// The shortest walk from the orb's pedestal to the vault door that arrives
+// with the orb weighing 30. The walk can't pass through the pedestal again or
+// reach the door early, since both reset the orb.
+pub fn vault() -> Option<Vec<&'static str>> {
+    const MOVES: [(&str, i32, i32); 4] = [("north", -1, 0), ("south", 1, 0), ("east", 0, 1), ("west", 0, -1)];
+    let start = (3usize, 0usize, ORB, None::<usize>);
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    seen.insert(start);
+    queue.push_back((start, Vec::new()));
+    while let Some(((row, column, orb, operator), path)) = queue.pop_front() {
+        for &(name, rows, columns) in &MOVES {
+            let (row, column) = (row as i32 + rows, column as i32 + columns);
+            if !(0..4).contains(&row) || !(0..4).contains(&column) || (row, column) == (3, 0) {
+                continue;
+            }
+            let (row, column) = (row as usize, column as usize);
+            let (orb, operator) = match (VAULT[row][column], operator) {
+                (Tile::Number(number), Some(operator)) => {
+                    let orb = match VAULT[operator / 4][operator % 4] {
+                        Tile::Add => orb + number,
+                        Tile::Subtract => orb - number,
+                        _ => orb * number,
+                    };
+                    (orb, None)
+                }
+                (Tile::Number(_), None) => continue,
+                (_, Some(_)) => continue,
+                (_, None) => (orb, Some(row * 4 + column)),
+            };
+            if orb <= 0 || orb >= ORB_LIMIT {
+                continue;
+            }
+            let mut path = path.clone();
+            path.push(name);
+            if (row, column) == (0, 3) {
+                if orb == DOOR {
+                    return Some(path);
+                }
+                continue;
+            }
+            let state = (row, column, orb, operator);
+            if seen.insert(state) {
+                queue.push_back((state, path));
+            }
+        }
+    }
+    None
+}
+
+// Words of the teleporter check, with the address of the confirmation
+// routine left open:
+//   set r0 4; set r1 1; call ROUTINE; eq r1 r0 6
+const CHECK: [Option<u16>; 12] = [
+    Some(1), Some(32768), Some(4),
+    Some(1), Some(32769), Some(1),
+    Some(17), None,
+    Some(4), Some(32769), Some(32768), Some(6),
+];
+
+// Where the challenge's self-test writes instructions and runs them, to check
+// wmem works; the only words it executes after writing them.
+pub const SELF_MODIFIED: Region = Region { start: 937, end: 938 };
+
+// Finds the teleporter check in memory by its signature.
+pub fn find_check<F: Fn(u16) -> u16>(read: F) -> Option<u16> {
+    (0..(WORDS - CHECK.len()) as u16).find(|&address| {
+        CHECK.iter().enumerate().all(|(offset, word)| word.is_none_or(|word| read(address + offset as u16) == word))
+    })
+}
+
+// Memory writes that skip the confirmation routine for the check at
+// `check`: the routine's result is set to 6 up front and the call becomes two
+// noops.
+pub fn bypass(check: u16) -> Vec<(u16, u16)> {
+    vec![(check + 2, 6), (check + 6, 21), (check + 7, 21)]
+}
+
+// The check once bypassed: set r0 6; set r1 1; noop; noop; eq r1 r0 6
+const PATCHED: [u16; 12] = [1, 32768, 6, 1, 32769, 1, 21, 21, 4, 32769, 32768, 6];
+
+fn checksum<I: Iterator<Item = u16>>(words: I) -> u32 {
+    words.fold(0u32, |sum, word| sum.rotate_left(5) ^ word as u32)
+}
+
+// Patches the teleporter check out of the loaded program, then checks the
+// patched words read back as the bypassed check should, and lets them run
+// under --write-exec. Returns where the check was. r7 still has to be set to
+// the solution, but only once the self-test is over, since it fails on a
+// nonzero register.
+pub fn bypass_teleporter(synacor: &mut Synacor) -> Result<u16, String> {
+    let check = find_check(|address| synacor.memory(address)).ok_or("the teleporter check is not in the program")?;
+    for (address, word) in bypass(check) {
+        synacor.poke(address, word);
+    }
+    let patched = checksum((0..PATCHED.len() as u16).map(|offset| synacor.memory(check + offset)));
+    if patched != checksum(PATCHED.iter().cloned()) {
+        return Err(format!("the patch at {} did not take", check));
+    }
+    synacor.allow_exec(Region { start: check, end: check + PATCHED.len() as u16 - 1 });
+    Ok(check)
+}
+
+pub const MAZE_DEPTH: usize = 40;
+
+// The twisty passages all read alike, so this leaves telling them apart to
+// the search's state hashes. Returns the exits to take to reach a room with
+// `item` in it.
+pub fn maze(synacor: &mut Synacor, capture: &Buffer, item: &str, depth: usize) -> Option<Vec<String>> {
+    let search = Search { depth, items: false, ..Search::default() };
+    search.run(synacor, capture, |text| search::room_items(text).contains(&item))
+}
+
+// A machine to work things out on, printing to `capture` and yielding for
+// input, so solvers can fork it and type into it without touching the game.
+pub struct Game {
+    pub synacor: Synacor,
+    pub capture: Buffer,
+}
+
+impl Game {
+    pub fn new(snapshot: &Snapshot) -> Result<Game, String> {
+        let capture = Buffer::default();
+        let config = Config {
+            output: Box::new(capture.clone()),
+            input: Box::new(Text::default()),
+            on_eof: EofPolicy::Yield,
+            ..Config::default()
+        };
+        let mut synacor = Synacor::with_config(config);
+        synacor.restore(snapshot)?;
+        Ok(Game { synacor, capture })
+    }
+    pub fn fork(synacor: &Synacor) -> Result<Game, String> {
+        Game::new(&synacor.snapshot())
+    }
+}
+
+pub struct Solution {
+    // What was found, for the player to read.
+    pub summary: String,
+    // Lines that carry it out, to type into the game. Those starting with the
+    // monitor's prefix are monitor commands.
+    pub commands: Vec<String>,
+}
+
+pub trait Solver {
+    fn name(&self) -> &'static str;
+    // One line on what it solves and what the argument is, if it takes one.
+    fn about(&self) -> &'static str;
+    fn solve(&self, game: &mut Game, argument: Option<&str>) -> Result<Solution, String>;
+}
+
+struct Teleporter;
+
+impl Solver for Teleporter {
+    fn name(&self) -> &'static str {
+        "teleporter"
+    }
+    fn about(&self) -> &'static str {
+        "the r7 that confirms the teleporter, and the patch that skips the check"
+    }
+    fn solve(&self, game: &mut Game, _: Option<&str>) -> Result<Solution, String> {
+        let r7 = teleporter().ok_or("no value of r7 confirms the teleporter")?;
+        let mut commands = vec![format!("/reg 7 {}", r7)];
+        // The game is left solved as well, for `solve teleporter --save`.
+        game.synacor.set_register(7, r7);
+        let summary = match find_check(|address| game.synacor.memory(address)) {
+            Some(_) => {
+                let check = bypass_teleporter(&mut game.synacor)?;
+                commands.extend(bypass(check).into_iter().map(|(address, word)| format!("/poke {} {}", address, word)));
+                format!("The teleporter confirms with r7 = {}. Type these before using it:", r7)
+            }
+            None => format!("The teleporter confirms with r7 = {}, but its check isn't in this program, so patch it by hand.", r7),
+        };
+        Ok(Solution { summary, commands })
+    }
+}
+
+struct Coins;
+
+impl Solver for Coins {
+    fn name(&self) -> &'static str {
+        "coins"
+    }
+    fn about(&self) -> &'static str {
+        "the order to put the coins in the monument"
+    }
+    fn solve(&self, _: &mut Game, _: Option<&str>) -> Result<Solution, String> {
+        let order = coins().ok_or("no order of the coins satisfies the monument")?;
+        let commands = order.iter().map(|coin| format!("use {}", coin)).collect();
+        Ok(Solution { summary: "Use the coins in this order at the monument:".to_string(), commands })
+    }
+}
+
+struct Vault;
+
+impl Solver for Vault {
+    fn name(&self) -> &'static str {
+        "vault"
+    }
+    fn about(&self) -> &'static str {
+        "the walk that brings the orb to the vault door weighing 30"
+    }
+    fn solve(&self, _: &mut Game, _: Option<&str>) -> Result<Solution, String> {
+        let walk = vault().ok_or("no walk brings the orb to the vault at 30")?;
+        let commands = walk.iter().map(|step| format!("go {}", step)).collect();
+        Ok(Solution { summary: "Take the orb and walk:".to_string(), commands })
+    }
+}
+
+struct Maze;
+
+impl Solver for Maze {
+    fn name(&self) -> &'static str {
+        "maze"
+    }
+    fn about(&self) -> &'static str {
+        "the way through the twisty passages to ITEM (the can unless given)"
+    }
+    fn solve(&self, game: &mut Game, item: Option<&str>) -> Result<Solution, String> {
+        let item = item.unwrap_or("can");
+        let path = maze(&mut game.synacor, &game.capture, item, MAZE_DEPTH)
+            .ok_or_else(|| format!("no room within {} moves has the {}", MAZE_DEPTH, item))?;
+        let summary = match path.len() {
+            0 => format!("The {} is right here.", item),
+            moves => format!("The {} is {} moves away:", item, moves),
+        };
+        Ok(Solution { summary, commands: path.iter().map(|exit| format!("go {}", exit)).collect() })
+    }
+}
+
+pub fn solvers() -> Vec<Box<dyn Solver>> {
+    vec![Box::new(Teleporter), Box::new(Coins), Box::new(Vault), Box::new(Maze)]
+}
+
+pub fn solver(name: &str) -> Option<Box<dyn Solver>> {
+    solvers().into_iter().find(|solver| solver.name() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coin_order() {
+        assert_eq!(coins(), Some(vec!["blue coin", "red coin", "shiny coin", "concave coin", "corroded coin"]));
+    }
+
+    #[test]
+    fn vault_walk() {
+        let walk = vault().unwrap();
+        assert_eq!(walk.len(), 12);
+        assert_eq!(walk.join(" "), "north east east north west south east east west north north east");
+    }
+
+    #[test]
+    fn solvers_by_name() {
+        let mut game = Game::fork(&Synacor::with_config(Config::default())).unwrap();
+        let solution = solver("coins").unwrap().solve(&mut game, None).unwrap();
+        assert_eq!(solution.commands[0], "use blue coin");
+        assert!(solver("vault").is_some() && solver("maze").is_some() && solver("lamp").is_none());
+    }
+
+    #[test]
+    fn finds_the_smallest_even_when_a_larger_one_comes_first() {
+        // The worker with 4 finds it while the one with 3 is still on 1.
+        let holds = |word: u16| {
+            if word == 1 {
+                thread::sleep(std::time::Duration::from_millis(50));
+            }
+            word == 3 || word == 4
+        };
+        assert_eq!(smallest(2, holds), Some(3));
+        assert_eq!(smallest(3, |_| false), None);
+    }
+
+    #[test]
+    fn finds_the_check() {
+        let mut memory = [0u16; 100];
+        memory[40..52].copy_from_slice(&[1, 32768, 4, 1, 32769, 1, 17, 6027, 4, 32769, 32768, 6]);
+        let read = |address: u16| memory.get(address as usize).cloned().unwrap_or(0);
+        assert_eq!(find_check(read), Some(40));
+        assert_eq!(bypass(40), vec![(42, 6), (46, 21), (47, 21)]);
+    }
+
+    #[test]
+    fn bypass_patches_the_check() {
+        use memory::Image;
+        use synacor::Config;
+        let words: [u16; 14] = [21, 21, 1, 32768, 4, 1, 32769, 1, 17, 6027, 4, 32769, 32768, 6];
+        let mut synacor = Synacor::with_config(Config::default());
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        assert_eq!(bypass_teleporter(&mut synacor), Ok(2));
+        assert_eq!(synacor.exec_allowed(), [Region { start: 2, end: 13 }]);
+        assert_eq!((2..14).map(|address| synacor.memory(address)).collect::<Vec<_>>(), PATCHED);
+    }
+}
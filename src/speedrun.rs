@@ -0,0 +1,100 @@
+// Shortens the input of a recorded run. The run's commands are played from
+// its starting state to find the state hash they end in, then chunks of
+// commands are dropped, halving the chunk size each pass, as long as what's
+// left still ends in exactly the same state. Anything that only looked
+// around, or walked somewhere and back, goes. Since every command is left in
+// the order it was typed, what remains is always a run that worked.
+
+use input::Text;
+use output::Null;
+use replay::Recording;
+use snapshot::Snapshot;
+use synacor::{Config, EofPolicy, RunOutcome, Synacor};
+
+// How long one command may run before the attempt counts as stuck.
+const COMMAND_LIMIT: u64 = 50_000_000;
+
+// The recorded input as lines, without their line feeds.
+pub fn commands(recording: &Recording) -> Vec<String> {
+    let text: Vec<u8> = recording.input.iter().map(|&(_, byte)| byte).collect();
+    String::from_utf8_lossy(&text).lines().map(str::to_string).collect()
+}
+
+struct Runner {
+    synacor: Synacor,
+    // The state before each command, as far as it's known.
+    before: Vec<Snapshot>,
+}
+
+impl Runner {
+    // Runs up to the next prompt, which is where every command starts.
+    fn settle(&mut self) -> bool {
+        matches!(self.synacor.run_for(COMMAND_LIMIT), RunOutcome::InputNeeded)
+    }
+    fn command(&mut self, line: &str) -> bool {
+        self.synacor.push_input(line);
+        self.synacor.push_input("\n");
+        self.settle()
+    }
+    // The hash of the state `commands` end in from before command `from`,
+    // if they all get read. Fills in `before` along the way when `record`.
+    fn finish(&mut self, from: usize, commands: &[String], record: bool) -> Option<u64> {
+        self.synacor.restore(&self.before[from]).ok()?;
+        for (offset, line) in commands.iter().enumerate() {
+            if record {
+                self.before.truncate(from + offset);
+                self.before.push(self.synacor.snapshot());
+            }
+            if !self.command(line) {
+                return None;
+            }
+        }
+        Some(self.synacor.state_hash())
+    }
+}
+
+// Returns the shortest run of `commands` found that ends where all of them
+// do, starting from `start`. Fails if the full run doesn't finish.
+pub fn shorten(start: &Snapshot, commands: &[String]) -> Result<Vec<String>, String> {
+    let config = Config { output: Box::new(Null), input: Box::new(Text::default()), on_eof: EofPolicy::Yield, ..Config::default() };
+    let mut runner = Runner { synacor: Synacor::with_config(config), before: Vec::new() };
+    runner.synacor.restore(start)?;
+    if !runner.settle() {
+        return Err("the run doesn't start at a prompt".to_string());
+    }
+    runner.before.push(runner.synacor.snapshot());
+    let mut commands = commands.to_vec();
+    let target = runner.finish(0, &commands, true).ok_or("the run stops before all its commands are read")?;
+    let mut chunk = commands.len().div_ceil(2).max(1);
+    loop {
+        let mut at = 0;
+        while at + chunk <= commands.len() {
+            if runner.finish(at, &commands[at + chunk..], false) == Some(target) {
+                commands.drain(at..at + chunk);
+                runner.finish(at, &commands[at..], true);
+            } else {
+                at += 1;
+            }
+        }
+        if chunk == 1 {
+            return Ok(commands);
+        }
+        chunk /= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::Image;
+
+    #[test]
+    fn drops_what_changes_nothing() {
+        // Reads lines forever, counting the ones that start with '+' in r0.
+        let program: &[u16] = &[20, 32769, 4, 32770, 32769, 43, 9, 32768, 32768, 32770, 4, 32770, 32769, 10, 7, 32770, 0, 20, 32769, 6, 10];
+        let mut synacor = Synacor::with_config(Config { input: Box::new(Text::default()), on_eof: EofPolicy::Yield, ..Config::default() });
+        synacor.load_image(Image::Bytes(program.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let lines: Vec<String> = ["x", "+", "y", "z", "+"].iter().map(|line| line.to_string()).collect();
+        assert_eq!(shorten(&synacor.snapshot(), &lines), Ok(vec!["+".to_string(), "+".to_string()]));
+    }
+}
@@ -0,0 +1,135 @@
+// Differences between two snapshots: the program counter, registers, stack
+// and every memory word that changed. Diffing the states before and after
+// picking something up is the quickest way to find where the game keeps it.
+
+use std::fmt;
+
+use snapshot::Snapshot;
+
+// A run of consecutive memory words that changed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Range {
+    pub start: usize,
+    pub before: Vec<u16>,
+    pub after: Vec<u16>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub program_counter: Option<(u16, u16)>,
+    pub instructions: (u64, u64),
+    pub registers: Vec<(usize, u16, u16)>,
+    // The depth both stacks share and what lies above it in each.
+    pub stack: Option<(usize, Vec<u16>, Vec<u16>)>,
+    pub memory: Vec<Range>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.program_counter.is_none() && self.registers.is_empty() && self.stack.is_none() && self.memory.is_empty()
+    }
+}
+
+pub fn diff(a: &Snapshot, b: &Snapshot) -> StateDiff {
+    let program_counter = match (a.program_counter, b.program_counter) {
+        (before, after) if before != after => Some((before, after)),
+        _ => None,
+    };
+    let registers = (0..8)
+        .filter(|&index| a.registers[index] != b.registers[index])
+        .map(|index| (index, a.registers[index], b.registers[index]))
+        .collect();
+    let common = a.stack.iter().zip(&b.stack).take_while(|&(x, y)| x == y).count();
+    let stack = if a.stack == b.stack {
+        None
+    } else {
+        Some((common, a.stack[common..].to_vec(), b.stack[common..].to_vec()))
+    };
+    let mut memory: Vec<Range> = Vec::new();
+    for address in 0..a.memory.extent().max(b.memory.extent()) {
+        let (before, after) = (a.memory.word(address), b.memory.word(address));
+        if before == after {
+            continue;
+        }
+        match memory.last_mut() {
+            Some(range) if range.start + range.before.len() == address => {
+                range.before.push(before);
+                range.after.push(after);
+            }
+            _ => memory.push(Range { start: address, before: vec![before], after: vec![after] }),
+        }
+    }
+    StateDiff { program_counter, instructions: (a.instructions, b.instructions), registers, stack, memory }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "instructions: {} -> {}", self.instructions.0, self.instructions.1)?;
+        if self.is_empty() {
+            return writeln!(f, "The states are identical.");
+        }
+        if let Some((before, after)) = self.program_counter {
+            writeln!(f, "pc: {} -> {}", before, after)?;
+        }
+        for &(index, before, after) in &self.registers {
+            writeln!(f, "r{}: {} -> {}", index, before, after)?;
+        }
+        if let Some((common, ref before, ref after)) = self.stack {
+            writeln!(f, "stack above depth {}: {:?} -> {:?}", common, before, after)?;
+        }
+        for range in &self.memory {
+            match range.before.len() {
+                1 => writeln!(f, "memory {}: {} -> {}", range.start, range.before[0], range.after[0])?,
+                len => writeln!(f, "memory {}..{}: {:?} -> {:?}", range.start, range.start + len - 1, range.before, range.after)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::{SavedMemory, SavedPage};
+
+    fn snapshot(image: &[u16], stack: &[u16]) -> Snapshot {
+        Snapshot {
+            program_counter: 0,
+            registers: [0; 8],
+            stack: stack.to_vec(),
+            memory: SavedMemory {
+                image: image.iter().flat_map(|word| vec![*word as u8, (word >> 8) as u8]).collect(),
+                loaded: image.len(),
+                pages: Vec::new(),
+            },
+            instructions: 0,
+            queued_input: Vec::new(),
+            input_eof: false,
+            after_cr: false,
+            output_line: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn groups_memory_into_ranges() {
+        let a = snapshot(&[1, 2, 3, 4, 5], &[9, 8]);
+        let mut b = snapshot(&[1, 2, 3, 4, 5], &[9, 7, 6]);
+        let mut words = vec![1, 20, 30, 4, 50];
+        words.resize(4096, 0);
+        b.memory.pages.push(SavedPage { index: 0, words, written: vec![0; 64] });
+        b.registers[3] = 12;
+        let diff = diff(&a, &b);
+        assert_eq!(diff.registers, vec![(3, 0, 12)]);
+        assert_eq!(diff.stack, Some((1, vec![8], vec![7, 6])));
+        assert_eq!(diff.memory, vec![
+            Range { start: 1, before: vec![2, 3], after: vec![20, 30] },
+            Range { start: 4, before: vec![5], after: vec![50] },
+        ]);
+    }
+
+    #[test]
+    fn identical_states() {
+        let a = snapshot(&[1, 2], &[]);
+        assert!(diff(&a, &a.clone()).is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+// The machine state as JSON, for inspecting and editing by hand and for
+// scripts in other languages:
+//   {
+//     "format": "synacor-state", "version": 1,
+//     "pc": N, "registers": [8 numbers], "stack": [bottom first],
+//     "instructions": N,
+//     "input": {"queued": "...", "eof": false, "after_cr": false},
+//     "output_line": "...",
+//     "memory": [[address, word], ...]
+//   }
+// Memory only lists the words that aren't zero. An imported state has its
+// memory as the image, so every listed word counts as loaded.
+
+use json::{self, Value};
+use memory::SavedMemory;
+use snapshot::Snapshot;
+
+const FORMAT: &str = "synacor-state";
+const VERSION: u64 = 1;
+
+pub fn to_json(snapshot: &Snapshot) -> Value {
+    let numbers = |words: &[u16]| Value::Array(words.iter().map(|&word| Value::from(word)).collect());
+    let memory = (0..snapshot.memory.extent())
+        .map(|address| (address, snapshot.memory.word(address)))
+        .filter(|&(_, word)| word != 0)
+        .map(|(address, word)| Value::Array(vec![Value::from(address as u64), Value::from(word)]))
+        .collect();
+    Value::object(vec![
+        ("format", Value::from(FORMAT)),
+        ("version", Value::from(VERSION)),
+        ("pc", Value::from(snapshot.program_counter)),
+        ("registers", numbers(&snapshot.registers)),
+        ("stack", numbers(&snapshot.stack)),
+        ("instructions", Value::from(snapshot.instructions)),
+        (
+            "input",
+            Value::object(vec![
+                ("queued", Value::from(String::from_utf8_lossy(&snapshot.queued_input).into_owned())),
+                ("eof", Value::from(snapshot.input_eof)),
+                ("after_cr", Value::from(snapshot.after_cr)),
+            ]),
+        ),
+        ("output_line", Value::from(String::from_utf8_lossy(&snapshot.output_line).into_owned())),
+        ("memory", Value::Array(memory)),
+    ])
+}
+
+fn word(value: &Value, what: &str) -> Result<u16, String> {
+    match value.as_u64() {
+        Some(word) if word <= u16::MAX as u64 => Ok(word as u16),
+        _ => Err(format!("{} is not a 16-bit number", what)),
+    }
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Result<&'a Value, String> {
+    value.get(key).ok_or_else(|| format!("\"{}\" is missing", key))
+}
+
+fn words(value: &Value, key: &str) -> Result<Vec<u16>, String> {
+    let array = field(value, key)?.as_array().ok_or_else(|| format!("\"{}\" is not an array", key))?;
+    array.iter().map(|element| word(element, key)).collect()
+}
+
+pub fn from_json(value: &Value) -> Result<Snapshot, String> {
+    if field(value, "format")?.as_str() != Some(FORMAT) {
+        return Err(format!("not a {} file", FORMAT));
+    }
+    match field(value, "version")?.as_u64() {
+        Some(version) if version <= VERSION => (),
+        _ => return Err("the state needs a newer emulator".to_string()),
+    }
+    let mut registers = [0; 8];
+    match words(value, "registers")? {
+        ref words if words.len() == 8 => registers.copy_from_slice(words),
+        _ => return Err("there have to be 8 registers".to_string()),
+    }
+    let input = field(value, "input")?;
+    let text = |value: &Value, key: &str| -> Result<Vec<u8>, String> {
+        field(value, key)?.as_str().map(|text| text.as_bytes().to_vec()).ok_or_else(|| format!("\"{}\" is not a string", key))
+    };
+    let flag = |key: &str| -> Result<bool, String> {
+        field(input, key)?.as_bool().ok_or_else(|| format!("\"{}\" is not true or false", key))
+    };
+    let mut image = Vec::new();
+    for entry in field(value, "memory")?.as_array().ok_or("\"memory\" is not an array")? {
+        let (address, word) = match entry.as_array() {
+            Some([address, value]) => (word(address, "an address")?, word(value, "a memory word")?),
+            _ => return Err("memory entries are [address, word] pairs".to_string()),
+        };
+        let at = address as usize * 2;
+        if image.len() < at + 2 {
+            image.resize(at + 2, 0);
+        }
+        image[at..at + 2].copy_from_slice(&word.to_le_bytes());
+    }
+    Ok(Snapshot {
+        program_counter: word(field(value, "pc")?, "pc")?,
+        registers,
+        stack: words(value, "stack")?,
+        memory: SavedMemory { loaded: image.len() / 2, image, pages: Vec::new() },
+        instructions: field(value, "instructions")?.as_u64().ok_or("\"instructions\" is not a number")?,
+        queued_input: text(input, "queued")?,
+        input_eof: flag("eof")?,
+        after_cr: flag("after_cr")?,
+        output_line: text(value, "output_line")?,
+    })
+}
+
+pub fn parse(text: &str) -> Result<Snapshot, String> {
+    let value = json::parse(text).map_err(|err| err.to_string())?;
+    from_json(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::SavedPage;
+
+    #[test]
+    fn round_trip_keeps_the_state() {
+        let mut words = vec![0; 4096];
+        words[3] = 77;
+        let snapshot = Snapshot {
+            program_counter: 3,
+            registers: [0, 1, 2, 3, 4, 5, 6, 32767],
+            stack: vec![4, 5],
+            memory: SavedMemory { image: vec![9, 0, 0, 1], loaded: 2, pages: vec![SavedPage { index: 1, words, written: vec![8; 64] }] },
+            instructions: 12,
+            queued_input: b"go\n".to_vec(),
+            input_eof: false,
+            after_cr: true,
+            output_line: b"What do you do?".to_vec(),
+        };
+        let json = to_json(&snapshot);
+        assert_eq!(json.get("memory").unwrap().to_string(), "[[0,9],[1,256],[4099,77]]");
+        let imported = parse(&json.pretty()).unwrap();
+        assert_eq!(imported.state_hash(), snapshot.state_hash());
+        assert_eq!(to_json(&imported), json);
+        assert!(parse(r#"{"format":"synacor-state","version":2}"#).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+// Reads how far the game has got straight out of memory. The addresses were
+// found by diffing saves from either side of each event (see statediff.rs):
+//   2732          the current room; a room starts with a pointer to its name
+//   2668..2732    sixteen items of four words: name, description, location
+//                 and what using it calls. Location 0 means carried and 32767
+//                 means gone or not there yet
+//   2462          how many coins are in the monument
+//   27101         the monument's slots, a count followed by the coins' values
+//   3952          the orb's weight
+// Strings are a length followed by that many characters.
+
+use std::fmt;
+
+pub struct Layout {
+    pub room: u16,
+    pub items: u16,
+    pub item_count: u16,
+    pub coins_placed: u16,
+    pub coin_slots: u16,
+    pub orb: u16,
+}
+
+pub const CHALLENGE: Layout = Layout { room: 2732, items: 2668, item_count: 16, coins_placed: 2462, coin_slots: 27101, orb: 3952 };
+
+const ITEM_SIZE: u16 = 4;
+const CARRIED: u16 = 0;
+// Longer strings than this mean the layout doesn't fit the program.
+const MAX_STRING: u16 = 200;
+
+pub fn string<F: Fn(u16) -> u16>(read: F, address: u16) -> Option<String> {
+    let len = read(address);
+    if len > MAX_STRING {
+        return None;
+    }
+    (1..=len).map(|offset| read(address.wrapping_add(offset))).map(|c| if c < 0x80 { Some(c as u8 as char) } else { None }).collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Status {
+    pub room: String,
+    pub carried: Vec<String>,
+    pub coins: Vec<u16>,
+    pub r7: u16,
+    // The orb's weight while it's carried.
+    pub orb: Option<u16>,
+}
+
+impl Status {
+    pub fn read<F: Fn(u16) -> u16>(layout: &Layout, read: F, r7: u16) -> Result<Status, String> {
+        let mut carried = Vec::new();
+        for index in 0..layout.item_count {
+            let item = layout.items + index * ITEM_SIZE;
+            let name = string(&read, read(item)).ok_or("the items aren't where this game keeps them")?;
+            if read(item + 2) == CARRIED {
+                carried.push(name);
+            }
+        }
+        let room = string(&read, read(read(layout.room))).ok_or("the room isn't where this game keeps it")?;
+        let slots = read(layout.coin_slots);
+        let placed = read(layout.coins_placed).min(slots);
+        let coins = (1..=placed).map(|slot| read(layout.coin_slots + slot)).collect();
+        let orb = if carried.iter().any(|item| item == "orb") { Some(read(layout.orb)) } else { None };
+        Ok(Status { room, carried, coins, r7, orb })
+    }
+    fn lantern(&self) -> &str {
+        let carries = |item: &str| self.carried.iter().any(|carried| carried == item);
+        if carries("lit lantern") {
+            "lit"
+        } else if carries("lantern") {
+            "filled but not lit"
+        } else if carries("empty lantern") {
+            "empty"
+        } else {
+            "not carried"
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Room: {}", self.room)?;
+        if self.carried.is_empty() {
+            writeln!(f, "Carrying: nothing")?;
+        } else {
+            writeln!(f, "Carrying: {}", self.carried.join(", "))?;
+        }
+        writeln!(f, "Lantern: {}", self.lantern())?;
+        write!(f, "Monument: {} of 5 coins placed", self.coins.len())?;
+        self.coins.iter().try_for_each(|coin| write!(f, " {}", coin))?;
+        writeln!(f)?;
+        match self.r7 {
+            0 => writeln!(f, "Teleporter: not calibrated (r7 is 0)")?,
+            r7 => writeln!(f, "Teleporter: calibrated with r7 = {}", r7)?,
+        }
+        match self.orb {
+            Some(weight) => write!(f, "Orb: weighs {}", weight),
+            None => write!(f, "Orb: not carried"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_string(memory: &mut [u16], address: usize, text: &str) {
+        memory[address] = text.len() as u16;
+        for (offset, c) in text.bytes().enumerate() {
+            memory[address + 1 + offset] = c as u16;
+        }
+    }
+
+    #[test]
+    fn decodes_the_layout() {
+        let layout = Layout { room: 10, items: 20, item_count: 2, coins_placed: 11, coin_slots: 30, orb: 12 };
+        let mut memory = [0u16; 100];
+        memory[10] = 40;
+        memory[40] = 41;
+        put_string(&mut memory, 41, "Ruins");
+        memory[20] = 50;
+        put_string(&mut memory, 50, "lit lantern");
+        memory[24] = 70;
+        put_string(&mut memory, 70, "orb");
+        memory[26] = 32767;
+        memory[11] = 2;
+        memory[30..33].copy_from_slice(&[5, 9, 2]);
+        let status = Status::read(&layout, |address| memory[address as usize], 0).unwrap();
+        assert_eq!(status, Status { room: "Ruins".to_string(), carried: vec!["lit lantern".to_string()], coins: vec![9, 2], r7: 0, orb: None });
+        assert!(status.to_string().contains("Lantern: lit\nMonument: 2 of 5 coins placed 9 2\n"));
+        memory[50] = 1000;
+        assert!(Status::read(&layout, |address| memory[address as usize], 0).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+// Statistics about a whole run, for `--stats`: how many instructions ran and
+// of which kind, how deep the stack got, how much memory the program read or
+// wrote (with rmem and wmem) and how fast it went. The time is wall time, so
+// it includes waiting for input, and counting slows the machine down a
+// little.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use disasm;
+use json::Value;
+use trace::{Step, Tracer};
+
+// The name the statistics run under (see Synacor::set_tracer).
+pub const NAME: &str = "stats";
+const OPCODES: usize = 22;
+
+pub struct Summary {
+    started: Instant,
+    total: u64,
+    per_opcode: [u64; OPCODES],
+    peak_stack: usize,
+    touched: Vec<bool>,
+}
+
+impl Default for Summary {
+    fn default() -> Summary {
+        Summary { started: Instant::now(), total: 0, per_opcode: [0; OPCODES], peak_stack: 0, touched: vec![false; 65536] }
+    }
+}
+
+impl Summary {
+    fn words_touched(&self) -> usize {
+        self.touched.iter().filter(|&&touched| touched).count()
+    }
+    fn mips(&self, elapsed: Duration) -> f64 {
+        self.total as f64 / elapsed.as_secs_f64().max(1e-9) / 1e6
+    }
+    // The opcodes that ran and how often, in opcode order.
+    fn opcodes(&self) -> Vec<(&'static str, u64)> {
+        let counts = self.per_opcode.iter().enumerate().filter(|&(_, &count)| count > 0);
+        counts.map(|(opcode, &count)| (disasm::name(opcode as u16).unwrap(), count)).collect()
+    }
+    pub fn text(&self) -> Vec<String> {
+        let elapsed = self.started.elapsed();
+        let mut lines = vec![
+            format!("Instructions: {}", self.total),
+            format!("Peak stack depth: {} words", self.peak_stack),
+            format!("Memory touched: {} words", self.words_touched()),
+            format!("Wall time: {:.3}s ({:.2} MIPS)", elapsed.as_secs_f64(), self.mips(elapsed)),
+            "Per opcode:".to_string(),
+        ];
+        let mut opcodes = self.opcodes();
+        opcodes.sort_by_key(|&(name, count)| (std::cmp::Reverse(count), name));
+        lines.extend(opcodes.into_iter().map(|(name, count)| format!("  {:<5} {:>12}  {:6.2}%", name, count, count as f64 * 100.0 / self.total as f64)));
+        lines
+    }
+    pub fn json(&self) -> Value {
+        let elapsed = self.started.elapsed();
+        let opcodes = self.opcodes().into_iter().map(|(name, count)| (name, Value::from(count))).collect();
+        Value::object(vec![
+            ("instructions", Value::from(self.total)),
+            ("per_opcode", Value::object(opcodes)),
+            ("peak_stack_depth", Value::from(self.peak_stack as u64)),
+            ("memory_words_touched", Value::from(self.words_touched() as u64)),
+            ("wall_time_ms", Value::from(elapsed.as_millis() as u64)),
+            ("mips", Value::Number(self.mips(elapsed))),
+        ])
+    }
+}
+
+impl Summary {
+    pub fn step(&mut self, step: &Step) {
+        self.total += 1;
+        if let Some(count) = self.per_opcode.get_mut(step.opcode as usize) {
+            *count += 1;
+        }
+        self.peak_stack = self.peak_stack.max(step.stack_depth);
+        match step.opcode {
+            15 => self.touched[step.value(step.words[1]) as usize] = true,
+            16 => self.touched[step.value(step.words[0]) as usize] = true,
+            _ => (),
+        }
+    }
+}
+
+// Feeds a shared summary from the machine.
+pub struct Counter(pub Rc<RefCell<Summary>>);
+
+impl Tracer for Counter {
+    fn step(&mut self, step: &Step) {
+        self.0.borrow_mut().step(step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_up_a_run() {
+        let mut summary = Summary::default();
+        let mut step = Step { count: 0, pc: 0, opcode: 16, words: [32768, 7, 0], before: [0; 8], after: [0; 8], next: 3, stack_depth: 4 };
+        step.before[0] = 300;
+        summary.step(&step);
+        step.opcode = 21;
+        step.stack_depth = 2;
+        summary.step(&step);
+        let json = summary.json();
+        assert_eq!(json.get("instructions").and_then(Value::as_u64), Some(2));
+        assert_eq!(json.get("per_opcode").map(Value::to_string), Some(r#"{"wmem":1,"noop":1}"#.to_string()));
+        assert_eq!(json.get("peak_stack_depth").and_then(Value::as_u64), Some(4));
+        assert_eq!(json.get("memory_words_touched").and_then(Value::as_u64), Some(1));
+        assert!(summary.text()[5].starts_with("  noop             1   50.00%"));
+    }
+}
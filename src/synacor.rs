@@ -0,0 +1,1411 @@
+use std::collections::VecDeque;
+use std::io;
+use std::fmt;
+
+use device::{Bus, Device};
+use extension::OpcodeExtension;
+use hash;
+use memory::{Image, LoadError, Memory};
+use input::{InputSource, Stdin};
+use isa::{self, Opcodes};
+use output::{OutputSink, Stdout};
+use snapshot::Snapshot;
+use replay::Recording;
+use transcript::{Direction, Transcript};
+use trace::{self, Step, Tracer};
+use trigger::Trigger;
+use types::{Addr, Operand, Word};
+
+// How many lines of output to remember for save slot descriptions.
+const RECENT_LINES: usize = 2;
+
+pub struct Synacor {
+    registers: [Word; 8],
+    memory: Memory,
+    stack: Vec<Word>,
+    max_stack_depth: Option<usize>,
+    strict: bool,
+    // The interpreter built for the instruction set, strict or not.
+    execute: fn(&mut Synacor) -> Result<(), RunOutcome>,
+    literal_writes: Policy,
+    uninitialized_exec: Policy,
+    write_exec: Policy,
+    exec_allowed: Vec<Region>,
+    pc_overflow: PcOverflow,
+    program_counter: Addr,
+    output: Box<dyn OutputSink>,
+    input: Box<dyn InputSource>,
+    // Bytes pushed by the host, read before anything from `input`.
+    queued_input: VecDeque<u8>,
+    // The last byte of input was a CR.
+    after_cr: bool,
+    // Lines of input starting with this byte are commands for the host.
+    command_prefix: Option<u8>,
+    line_start: bool,
+    // Stop with RunOutcome::Prompt before reading a new line of input.
+    pause_at_prompt: bool,
+    paused: bool,
+    input_eof: bool,
+    on_eof: EofPolicy,
+    non_ascii: NonAscii,
+    transcript: Option<Transcript>,
+    recording: Option<Recording>,
+    triggers: Vec<Trigger>,
+    // Output since the last newline.
+    output_line: Vec<u8>,
+    recent_lines: VecDeque<String>,
+    // Addresses of the last `trace_depth` instructions executed.
+    trace: VecDeque<u16>,
+    trace_depth: usize,
+    // See every instruction executed, each under its own name.
+    tracers: Vec<(&'static str, Box<dyn Tracer>)>,
+    // Asked in turn about opcodes past 21.
+    extensions: Vec<Box<dyn OpcodeExtension>>,
+    bus: Bus,
+    // Instructions fetched so far, used to timestamp transcripts.
+    executed: u64,
+    // The timer and how many instructions are left before it next fires.
+    timer: Option<(Timer, u64)>,
+    read_only: Vec<Region>,
+    #[cfg(feature = "counters")]
+    stats: Stats,
+}
+
+pub enum SynacorErr {
+    BadRegister,
+    StackUnderflow,
+    StackOverflow(usize),
+    BadOptcode,
+    BadAddress(u16),
+    BadNumber(u16),
+    LiteralWrite(u16),
+    DivisionByZero { pc: u16 },
+    BadChar(u16),
+    UninitializedExec(u16),
+    WriteExec(u16),
+    ReadOnlyWrite { pc: u16, address: u16, region: Region },
+    BadHostCall(u16),
+    BadBank(u16),
+    OutputErr(io::Error),
+    InputErr(io::Error),
+}
+
+// Why a run stopped. Only a fault is an error; the rest are normal ways for
+// a program to stop or pause.
+pub enum RunOutcome {
+    Halted,
+    Faulted(SynacorErr),
+    InputNeeded,
+    BudgetExceeded,
+    // The program is about to read a new line of input. Running again reads
+    // it.
+    Prompt,
+    // A line of input started with the command prefix. The program has not
+    // seen it, and running again carries on with the next line.
+    Command(String),
+}
+
+// What opcode 20 got from the input.
+enum Received {
+    Byte(u8),
+    End,
+    Prompt,
+    Command(String),
+}
+
+impl From<SynacorErr> for RunOutcome {
+    fn from(err: SynacorErr) -> RunOutcome {
+        RunOutcome::Faulted(err)
+    }
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunOutcome::Halted => write!(f, "The synacor halted."),
+            RunOutcome::Faulted(ref err) => err.fmt(f),
+            RunOutcome::InputNeeded => write!(f, "The synacor is waiting for input."),
+            RunOutcome::BudgetExceeded => write!(f, "The synacor used up its instruction budget."),
+            RunOutcome::Prompt => write!(f, "The synacor is at a prompt."),
+            RunOutcome::Command(ref line) => write!(f, "The synacor was given the command {:?}.", line),
+        }
+    }
+}
+
+impl fmt::Display for SynacorErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SynacorErr::BadRegister => write!(f, "The synacor accessed a bad register."),
+            SynacorErr::StackUnderflow => write!(f, "The synacor's stack underflowed."),
+            SynacorErr::StackOverflow(limit) => {
+                write!(f, "The synacor's stack overflowed its limit of {} words.", limit)
+            }
+            SynacorErr::BadOptcode => write!(f, "The synacor's optcode is not implemented."),
+            SynacorErr::BadAddress(address) => {
+                write!(f, "The synacor accessed address {} outside its 15-bit address space.", address)
+            }
+            SynacorErr::BadNumber(number) => write!(f, "The synacor used {} as a 15-bit number.", number),
+            SynacorErr::LiteralWrite(literal) => {
+                write!(f, "The synacor tried to write to the literal {}.", literal)
+            }
+            SynacorErr::DivisionByZero { pc } => write!(f, "The synacor divided by zero at {}.", pc),
+            SynacorErr::BadChar(value) => write!(f, "The synacor tried to output {}, which is not ASCII.", value),
+            SynacorErr::UninitializedExec(pc) => {
+                write!(f, "The synacor executed uninitialized memory at {}.", pc)
+            }
+            SynacorErr::WriteExec(pc) => {
+                write!(f, "The synacor executed memory at {} that was written after loading.", pc)
+            }
+            SynacorErr::ReadOnlyWrite { pc, address, region } => {
+                write!(f, "The synacor wrote to {} at {}, inside the read-only region {}.", address, pc, region)
+            }
+            SynacorErr::BadHostCall(number) => write!(f, "The synacor called host function {}, which doesn't exist.", number),
+            SynacorErr::BadBank(bank) => write!(f, "The synacor chose bank {}, which doesn't exist.", bank),
+            SynacorErr::OutputErr(ref err) => write!(f, "{}", err),
+            SynacorErr::InputErr(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// How to react to a suspicious but survivable event, such as an instruction
+// writing to a literal rather than a register.
+#[derive(Clone, Copy, Default)]
+pub enum Policy {
+    #[default]
+    Ignore,
+    Warn,
+    Error,
+}
+
+// What fetching an instruction does once the program counter has left the
+// 15-bit address space, either by running off the end of it or by jumping
+// there. Strict mode always treats it as an error.
+#[derive(Clone, Copy, Default)]
+pub enum PcOverflow {
+    #[default]
+    Wrap,
+    Error,
+}
+
+// How opcode 19 prints values outside of ASCII. Escape also escapes control
+// characters other than newline. Strict mode always treats them as an error.
+#[derive(Clone, Copy, Default)]
+pub enum NonAscii {
+    #[default]
+    Truncate,
+    Escape,
+    Latin1,
+    Error,
+}
+
+// What opcode 20 does once its input has run out. Yield stops the run with
+// RunOutcome::InputNeeded so the host can supply more and carry on.
+#[derive(Clone, Copy, Default)]
+pub enum EofPolicy {
+    #[default]
+    Halt,
+    Yield,
+    Value(u16),
+}
+
+// A timer interrupt for programs written for this VM: every `interval`
+// instructions the program counter is pushed and execution carries on at
+// `handler`, which returns with ret. It doesn't wait for one handler to
+// finish before starting the next, so handlers need to be quicker than the
+// interval. The challenge knows nothing of it, so it's off unless asked for
+// and strict mode ignores it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timer {
+    pub interval: u64,
+    pub handler: u16,
+}
+
+impl Timer {
+    // Parses INTERVAL:HANDLER, with the handler's address in decimal or hex.
+    pub fn parse(text: &str) -> Result<Timer, String> {
+        let (interval, handler) = text.split_once(':').ok_or_else(|| format!("{:?} isn't INTERVAL:HANDLER", text))?;
+        let interval = match interval.parse() {
+            Ok(interval) if interval > 0 => interval,
+            _ => return Err(format!("{:?} isn't a number of instructions", interval)),
+        };
+        Ok(Timer { interval, handler: trace::address(handler)? })
+    }
+}
+
+// An inclusive range of addresses, written START-END or as one address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl Region {
+    pub fn parse(text: &str) -> Result<Region, String> {
+        let (start, end) = text.split_once('-').unwrap_or((text, text));
+        let (start, end) = (trace::address(start.trim())?, trace::address(end.trim())?);
+        if start > end {
+            return Err(format!("{} comes after {}", start, end));
+        }
+        Ok(Region { start, end })
+    }
+    pub fn contains(&self, address: u16) -> bool {
+        self.start <= address && address <= self.end
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+pub struct Config {
+    pub output: Box<dyn OutputSink>,
+    pub input: Box<dyn InputSource>,
+    // Enforce the architecture spec: only 32768 words of memory and only
+    // 15-bit numbers as arithmetic operands.
+    pub strict: bool,
+    pub literal_writes: Policy,
+    // Executing a word that was neither loaded from the image nor written
+    // since.
+    pub uninitialized_exec: Policy,
+    // Executing a word written since the image was loaded, by the program
+    // or the host, outside `exec_allowed`.
+    pub write_exec: Policy,
+    pub exec_allowed: Vec<Region>,
+    pub pc_overflow: PcOverflow,
+    pub on_eof: EofPolicy,
+    pub non_ascii: NonAscii,
+    pub stack_capacity: usize,
+    pub max_stack_depth: Option<usize>,
+    pub transcript: Option<Transcript>,
+    pub triggers: Vec<Trigger>,
+    pub command_prefix: Option<u8>,
+    pub pause_at_prompt: bool,
+    // How many executed instruction addresses to keep for core files.
+    pub trace_depth: usize,
+    pub timer: Option<Timer>,
+    // Where wmem faults rather than writing.
+    pub read_only: Vec<Region>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            output: Box::new(Stdout),
+            input: Box::new(Stdin),
+            strict: false,
+            literal_writes: Policy::default(),
+            uninitialized_exec: Policy::default(),
+            write_exec: Policy::default(),
+            exec_allowed: Vec::new(),
+            pc_overflow: PcOverflow::default(),
+            on_eof: EofPolicy::default(),
+            non_ascii: NonAscii::default(),
+            stack_capacity: 0,
+            max_stack_depth: None,
+            transcript: None,
+            triggers: Vec::new(),
+            command_prefix: None,
+            pause_at_prompt: false,
+            trace_depth: 0,
+            timer: None,
+            read_only: Vec::new(),
+        }
+    }
+}
+
+// Instruction counters for profiling and instruction budgets. They are plain
+// integer increments and only exist when the `counters` feature is enabled.
+#[cfg(feature = "counters")]
+#[derive(Clone)]
+pub struct Stats {
+    pub total: u64,
+    pub per_optcode: [u64; 22],
+}
+
+#[cfg(feature = "counters")]
+impl Stats {
+    fn new() -> Stats {
+        Stats {
+            total: 0,
+            per_optcode: [0; 22],
+        }
+    }
+    fn count(&mut self, optcode: u16) {
+        self.total += 1;
+        if let Some(count) = self.per_optcode.get_mut(optcode as usize) {
+            *count += 1;
+        }
+    }
+    fn uncount(&mut self, optcode: u16) {
+        self.total -= 1;
+        if let Some(count) = self.per_optcode.get_mut(optcode as usize) {
+            *count -= 1;
+        }
+    }
+}
+
+impl Synacor {
+    pub fn with_config(config: Config) -> Synacor {
+        let timer = config.timer.filter(|_| !config.strict).map(|timer| (timer, timer.interval));
+        Synacor {
+            registers: [Word::default(); 8],
+            memory: Memory::new(0x1FFFFF),
+            stack: Vec::with_capacity(config.stack_capacity),
+            max_stack_depth: config.max_stack_depth,
+            strict: config.strict,
+            execute: if config.strict { Synacor::execute::<isa::Spec> } else { Synacor::execute::<isa::Challenge> },
+            literal_writes: config.literal_writes,
+            uninitialized_exec: config.uninitialized_exec,
+            write_exec: config.write_exec,
+            exec_allowed: config.exec_allowed,
+            pc_overflow: config.pc_overflow,
+            program_counter: Addr::default(),
+            output: config.output,
+            input: config.input,
+            queued_input: VecDeque::new(),
+            after_cr: false,
+            command_prefix: config.command_prefix,
+            line_start: true,
+            pause_at_prompt: config.pause_at_prompt,
+            paused: false,
+            input_eof: false,
+            on_eof: config.on_eof,
+            non_ascii: config.non_ascii,
+            transcript: config.transcript,
+            recording: None,
+            triggers: config.triggers,
+            output_line: Vec::new(),
+            recent_lines: VecDeque::new(),
+            trace: VecDeque::new(),
+            trace_depth: config.trace_depth,
+            tracers: Vec::new(),
+            extensions: Vec::new(),
+            bus: Bus::default(),
+            executed: 0,
+            timer,
+            read_only: config.read_only,
+            #[cfg(feature = "counters")]
+            stats: Stats::new(),
+        }
+    }
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter.get()
+    }
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            program_counter: self.program_counter.get(),
+            registers: self.registers(),
+            stack: self.stack(),
+            memory: self.memory.save(),
+            instructions: self.executed,
+            queued_input: self.queued_input.iter().cloned().collect(),
+            input_eof: self.input_eof,
+            after_cr: self.after_cr,
+            output_line: self.output_line.clone(),
+        }
+    }
+    // Puts the machine back into a saved state. The configuration, input and
+    // output are left alone.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        self.memory.restore(&snapshot.memory)?;
+        self.program_counter = Addr::new(snapshot.program_counter);
+        for (register, &saved) in self.registers.iter_mut().zip(&snapshot.registers) {
+            *register = Word::new(saved);
+        }
+        self.stack = snapshot.stack.iter().map(|&word| Word::new(word)).collect();
+        self.executed = snapshot.instructions;
+        self.queued_input = snapshot.queued_input.iter().cloned().collect();
+        self.input_eof = snapshot.input_eof;
+        self.after_cr = snapshot.after_cr;
+        self.line_start = true;
+        self.output_line = snapshot.output_line.clone();
+        // A recording carries on from the restored state.
+        if self.recording.is_some() {
+            self.start_recording();
+        }
+        Ok(())
+    }
+    pub fn stack(&self) -> Vec<u16> {
+        self.stack.iter().map(|word| word.get()).collect()
+    }
+    // See hash.rs; equal to the snapshot's state_hash.
+    pub fn state_hash(&self) -> u64 {
+        let stack = self.stack();
+        hash::state(self.program_counter.get(), &self.registers(), &stack, |address| self.memory.read(address))
+    }
+    pub fn memory(&self, address: u16) -> u16 {
+        self.memory.read(address as usize)
+    }
+    // For patching the program from the host. Panics on a register past 7.
+    pub fn set_register(&mut self, index: usize, value: u16) {
+        self.registers[index] = Word::new(value);
+    }
+    pub fn poke(&mut self, address: u16, word: u16) {
+        self.memory.write(address as usize, word);
+    }
+    // Memory in the challenge's little-endian image format, up to the last
+    // non-zero word. `spec_only` leaves out everything past the spec's 32768
+    // words.
+    pub fn export_memory(&self, spec_only: bool) -> Vec<u8> {
+        let words = if spec_only { 32768 } else { 65536 };
+        let mut image: Vec<u8> = (0..words).flat_map(|address| self.memory.read(address).to_le_bytes()).collect();
+        let len = image.chunks(2).rposition(|word| word != [0, 0]).map_or(0, |last| (last + 1) * 2);
+        image.truncate(len);
+        image
+    }
+    pub fn registers(&self) -> [u16; 8] {
+        let mut registers = [0; 8];
+        for (raw, register) in registers.iter_mut().zip(&self.registers) {
+            *raw = register.get();
+        }
+        registers
+    }
+    #[cfg(feature = "counters")]
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+    // Oldest first.
+    pub fn trace(&self) -> Vec<u16> {
+        self.trace.iter().cloned().collect()
+    }
+    // Starts or stops the tracer called `name`, handing back the one that
+    // was running under that name.
+    pub fn set_tracer(&mut self, name: &'static str, tracer: Option<Box<dyn Tracer>>) -> Option<Box<dyn Tracer>> {
+        let old = self.tracers.iter().position(|&(known, _)| known == name).map(|index| self.tracers.remove(index).1);
+        if let Some(tracer) = tracer {
+            self.tracers.push((name, tracer));
+        }
+        old
+    }
+    pub fn take_tracers(&mut self) -> Vec<(&'static str, Box<dyn Tracer>)> {
+        std::mem::take(&mut self.tracers)
+    }
+    pub fn instructions(&self) -> u64 {
+        self.executed
+    }
+    // Finishes the transcript, if there is one, and hands it back.
+    pub fn take_transcript(&mut self) -> Option<Transcript> {
+        let mut transcript = self.transcript.take()?;
+        if let Err(err) = transcript.finish() {
+            notice!("Could not write the transcript: {}", err);
+        }
+        Some(transcript)
+    }
+    fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        if let Some(ref mut transcript) = self.transcript {
+            if let Err(err) = transcript.record(self.executed, direction, bytes) {
+                notice!("Could not write the transcript: {}", err);
+                self.transcript = None;
+            }
+        }
+    }
+    // Records every byte of input from here on, for a replay file.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording { start: self.snapshot(), input: Vec::new() });
+    }
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+    pub fn add_trigger(&mut self, trigger: Trigger) {
+        self.triggers.push(trigger);
+    }
+    // See extension.rs.
+    pub fn add_extension(&mut self, extension: Box<dyn OpcodeExtension>) {
+        self.extensions.push(extension);
+    }
+    // Makes wmem into `region` fault from now on.
+    pub fn protect(&mut self, region: Region) {
+        self.read_only.push(region);
+    }
+    pub fn protected(&self) -> &[Region] {
+        &self.read_only
+    }
+    pub fn unprotect_all(&mut self) {
+        self.read_only.clear();
+    }
+    // Lets words written into `region` run under the write-exec policy.
+    pub fn allow_exec(&mut self, region: Region) {
+        self.exec_allowed.push(region);
+    }
+    pub fn exec_allowed(&self) -> &[Region] {
+        &self.exec_allowed
+    }
+    // See device.rs.
+    pub fn attach(&mut self, start: usize, len: usize, device: Box<dyn Device>) -> Result<(), String> {
+        self.bus.attach(start, len, device)
+    }
+    // Whether an extension takes `opcode`. Strict mode ignores them.
+    pub fn handles(&self, opcode: u16) -> bool {
+        !self.strict && self.extensions.iter().any(|extension| extension.operands(opcode).is_some())
+    }
+    // The value of an instruction's operand, for extensions.
+    pub fn operand(&self, operand: Operand) -> u16 {
+        self.value(operand).get()
+    }
+    // Writes to an instruction's operand, under the same rules as the
+    // spec's instructions.
+    pub fn write_operand(&mut self, operand: Operand, word: u16) -> Result<(), SynacorErr> {
+        self.write_word_data(operand, Word::new(word))
+    }
+    pub fn push_word(&mut self, word: u16) -> Result<(), SynacorErr> {
+        self.push(Word::new(word))
+    }
+    pub fn pop_word(&mut self) -> Option<u16> {
+        self.stack.pop().map(|word| word.get())
+    }
+    pub fn jump(&mut self, address: u16) {
+        self.program_counter = Addr::new(address);
+    }
+    // The last few lines of output that weren't blank, oldest first.
+    pub fn recent_lines(&self) -> Vec<String> {
+        self.recent_lines.iter().cloned().collect()
+    }
+    // Finishes the current line of output and runs the triggers over it.
+    // Anything they answer is queued as input.
+    fn end_line(&mut self) {
+        let line = String::from_utf8_lossy(&self.output_line).into_owned();
+        self.output_line.clear();
+        if !line.trim().is_empty() {
+            if self.recent_lines.len() == RECENT_LINES {
+                self.recent_lines.pop_front();
+            }
+            self.recent_lines.push_back(line.clone());
+        }
+        for trigger in &mut self.triggers {
+            if let Some(input) = trigger.fire(&line, self.executed) {
+                self.queued_input.extend(input.bytes());
+            }
+        }
+    }
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), SynacorErr> {
+        self.record(Direction::Output, bytes);
+        for &byte in bytes {
+            if byte == b'\n' {
+                self.end_line();
+            } else {
+                self.output_line.push(byte);
+            }
+        }
+        self.output.write(bytes).map_err(SynacorErr::OutputErr)
+    }
+    // Queues text for opcode 20, ahead of the configured input source.
+    pub fn push_input(&mut self, text: &str) {
+        self.queued_input.extend(text.bytes());
+    }
+    fn address<S: Opcodes>(&self, address: Addr) -> Result<usize, SynacorErr> {
+        if S::STRICT && Addr::checked(address.get()).is_none() {
+            Err(SynacorErr::BadAddress(address.get()))
+        } else {
+            Ok(address.index())
+        }
+    }
+    fn read_word_code<S: Opcodes>(&mut self) -> Result<Word, SynacorErr> {
+        let pc = self.program_counter;
+        let address = match if S::STRICT { PcOverflow::Error } else { self.pc_overflow } {
+            _ if Addr::checked(pc.get()).is_some() => pc,
+            PcOverflow::Wrap => pc.wrapped(),
+            PcOverflow::Error => return Err(SynacorErr::BadAddress(pc.get())),
+        };
+        self.program_counter = address.next();
+        Ok(Word::new(self.memory.read(address.index())))
+    }
+    fn read_operand<S: Opcodes>(&mut self) -> Result<Operand, SynacorErr> {
+        let word = self.read_word_code::<S>()?;
+        Operand::decode(word)
+    }
+    fn read_word_data<S: Opcodes>(&mut self) -> Result<Word, SynacorErr> {
+        let operand = self.read_operand::<S>()?;
+        Ok(self.value(operand))
+    }
+    fn read_number<S: Opcodes>(&mut self) -> Result<Word, SynacorErr> {
+        let number = self.read_word_data::<S>()?;
+        if S::STRICT && !number.is_number() {
+            Err(SynacorErr::BadNumber(number.get()))
+        } else {
+            Ok(number)
+        }
+    }
+    fn value(&self, operand: Operand) -> Word {
+        match operand {
+            Operand::Literal(word) => word,
+            // Register indices are always below 8.
+            Operand::Register(register) => unsafe { *self.registers.get_unchecked(register.index()) },
+        }
+    }
+    fn write_word_data(&mut self, operand: Operand, word: Word) -> Result<(), SynacorErr> {
+        match operand {
+            // Register indices are always below 8.
+            Operand::Register(register) => unsafe { *self.registers.get_unchecked_mut(register.index()) = word },
+            Operand::Literal(literal) => match self.literal_writes {
+                Policy::Ignore => (),
+                Policy::Warn => notice!("Ignored a write of {} to the literal {}.", word, literal),
+                Policy::Error => return Err(SynacorErr::LiteralWrite(literal.get())),
+            },
+        }
+        Ok(())
+    }
+    fn push(&mut self, word: Word) -> Result<(), SynacorErr> {
+        if let Some(limit) = self.max_stack_depth {
+            if self.stack.len() >= limit {
+                return Err(SynacorErr::StackOverflow(limit));
+            }
+        }
+        self.stack.push(word);
+        Ok(())
+    }
+    // Reads the rest of a command line straight from the input source.
+    fn read_command(&mut self) -> Result<String, SynacorErr> {
+        let mut line = Vec::new();
+        while let Some(byte) = self.input.read_byte().map_err(SynacorErr::InputErr)? {
+            match byte {
+                b'\n' => break,
+                b'\r' => {
+                    self.after_cr = true;
+                    break;
+                }
+                _ => line.push(byte),
+            }
+        }
+        Ok(String::from_utf8_lossy(&line).trim().to_string())
+    }
+    fn read_byte(&mut self) -> Result<Received, SynacorErr> {
+        self.output.flush().map_err(SynacorErr::OutputErr)?;
+        if !self.output_line.is_empty() {
+            self.end_line();
+        }
+        let byte = loop {
+            let byte = match self.queued_input.pop_front() {
+                Some(byte) => Some(byte),
+                None if self.input_eof => None,
+                None if self.pause_at_prompt && self.line_start && !self.paused => {
+                    self.paused = true;
+                    return Ok(Received::Prompt);
+                }
+                None => {
+                    let byte = self.input.read_byte().map_err(SynacorErr::InputErr)?;
+                    // A yielding VM expects more input to show up later.
+                    self.input_eof = byte.is_none() && !matches!(self.on_eof, EofPolicy::Yield);
+                    if byte.is_some() && byte == self.command_prefix && self.line_start {
+                        self.after_cr = false;
+                        return self.read_command().map(Received::Command);
+                    }
+                    byte
+                }
+            };
+            // CR LF and a lone CR both reach the program as a single LF.
+            let after_cr = self.after_cr;
+            self.after_cr = byte == Some(b'\r');
+            match byte {
+                Some(b'\r') => break Some(b'\n'),
+                Some(b'\n') if after_cr => continue,
+                byte => break byte,
+            }
+        };
+        match byte {
+            Some(byte) => {
+                self.record(Direction::Input, &[byte]);
+                if let Some(ref mut recording) = self.recording {
+                    recording.input.push((self.executed, byte));
+                }
+                self.line_start = byte == b'\n';
+                self.paused = false;
+                Ok(Received::Byte(byte))
+            }
+            None => Ok(Received::End),
+        }
+    }
+    pub fn load_image(&mut self, image: Image) -> Result<usize, LoadError> {
+        let capacity = if self.strict { 32768 } else { 65536 };
+        self.memory.load(image, capacity)
+    }
+    // Runs until the program halts, faults or waits for input.
+    pub fn run(&mut self) -> RunOutcome {
+        loop {
+            if let Err(outcome) = self.run_optcode() {
+                return outcome;
+            }
+        }
+    }
+    // Like run, but gives up after `budget` instructions.
+    pub fn run_for(&mut self, budget: u64) -> RunOutcome {
+        for _ in 0..budget {
+            if let Err(outcome) = self.run_optcode() {
+                return outcome;
+            }
+        }
+        RunOutcome::BudgetExceeded
+    }
+    // Executes a single instruction, returning why execution stopped if it
+    // did.
+    pub fn run_optcode(&mut self) -> Result<(), RunOutcome> {
+        if self.timer.is_some() {
+            self.tick()?;
+        }
+        if self.tracers.is_empty() {
+            return (self.execute)(self);
+        }
+        let pc = self.program_counter.wrapped();
+        let read = |offset: u16| self.memory.read(pc.get().wrapping_add(offset) as usize);
+        let (count, opcode, words, before) = (self.executed, read(0), [read(1), read(2), read(3)], self.registers());
+        (self.execute)(self)?;
+        // An instruction that stopped for input runs again, and is traced
+        // then.
+        let step = Step {
+            count,
+            pc: pc.get(),
+            opcode,
+            words,
+            before,
+            after: self.registers(),
+            next: self.program_counter.get(),
+            stack_depth: self.stack.len(),
+        };
+        for (_, tracer) in &mut self.tracers {
+            tracer.step(&step);
+        }
+        Ok(())
+    }
+    // Counts down to the timer interrupt, and takes it if it's due.
+    fn tick(&mut self) -> Result<(), SynacorErr> {
+        let (timer, left) = match self.timer {
+            Some((timer, ref mut left)) => {
+                *left -= 1;
+                (timer, *left)
+            }
+            None => return Ok(()),
+        };
+        if left == 0 {
+            self.timer = Some((timer, timer.interval));
+            let pc = Word::from(self.program_counter);
+            self.push(pc)?;
+            self.program_counter = Addr::new(timer.handler);
+        }
+        Ok(())
+    }
+    // Hands an opcode past 21 to the first extension that takes it.
+    pub fn run_extension<S: Opcodes>(&mut self, optcode: u16) -> Result<(), SynacorErr> {
+        let count = match self.extensions.iter().find_map(|extension| extension.operands(optcode)) {
+            Some(count) => count,
+            None => return Err(SynacorErr::BadOptcode),
+        };
+        let mut operands = Vec::with_capacity(count);
+        for _ in 0..count {
+            operands.push(self.read_operand::<S>()?);
+        }
+        // Out of the way while they're given the whole VM.
+        let mut extensions = std::mem::take(&mut self.extensions);
+        let extension = extensions.iter_mut().find(|extension| extension.operands(optcode).is_some()).unwrap();
+        let result = extension.execute(optcode, &operands, self);
+        extensions.append(&mut self.extensions);
+        self.extensions = extensions;
+        result
+    }
+    // Backs up to the in at `pc` so that running again runs it again,
+    // without counting or tracing it twice.
+    fn rerun(&mut self, pc: Addr, outcome: RunOutcome) -> RunOutcome {
+        self.program_counter = pc;
+        self.executed -= 1;
+        #[cfg(feature = "counters")]
+        self.stats.uncount(20);
+        if self.trace_depth > 0 {
+            self.trace.pop_back();
+        }
+        if let Some((_, ref mut left)) = self.timer {
+            *left += 1;
+        }
+        outcome
+    }
+    fn execute<S: Opcodes>(&mut self) -> Result<(), RunOutcome> {
+        let pc = self.program_counter.wrapped();
+        match self.uninitialized_exec {
+            Policy::Ignore => (),
+            _ if self.memory.initialized(pc.index()) => (),
+            Policy::Warn => notice!("Executing uninitialized memory at {}.", pc),
+            Policy::Error => return Err(SynacorErr::UninitializedExec(pc.get()).into()),
+        }
+        match self.write_exec {
+            Policy::Ignore => (),
+            _ if !self.memory.written(pc.index()) => (),
+            _ if self.exec_allowed.iter().any(|region| region.contains(pc.get())) => (),
+            Policy::Warn => notice!("Executing memory written after loading at {}.", pc),
+            Policy::Error => return Err(SynacorErr::WriteExec(pc.get()).into()),
+        }
+        if self.trace_depth > 0 {
+            if self.trace.len() == self.trace_depth {
+                self.trace.pop_front();
+            }
+            self.trace.push_back(pc.get());
+        }
+        let optcode = self.read_word_code::<S>()?.get();
+        self.executed += 1;
+        #[cfg(feature = "counters")]
+        self.stats.count(optcode);
+        let result = match optcode {
+            0 => return Err(RunOutcome::Halted),
+            1 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_word_data::<S>()?;
+                self.write_word_data(a, b)
+            }
+            2 => {
+                let a = self.read_word_data::<S>()?;
+                self.push(a)
+            }
+            3 => {
+                let a = self.read_operand::<S>()?;
+                if let Some(word) = self.stack.pop() {
+                    self.write_word_data(a, word)
+                } else {
+                    Err(SynacorErr::StackUnderflow)
+                }
+            }
+            4 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_number::<S>()?;
+                let c = self.read_number::<S>()?;
+                self.write_word_data(a, Word::from(b == c))
+            }
+            5 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_number::<S>()?;
+                let c = self.read_number::<S>()?;
+                self.write_word_data(a, Word::from(b > c))
+            }
+            6 => {
+                let jump = self.read_word_data::<S>()?;
+                self.program_counter = Addr::from(jump);
+                Ok(())
+            }
+            7 => {
+                let test = self.read_word_data::<S>()?;
+                let jump = self.read_word_data::<S>()?;
+                if test != Word::default() {
+                    self.program_counter = Addr::from(jump);
+                }
+                Ok(())
+            }
+            8 => {
+                let test = self.read_word_data::<S>()?;
+                let jump = self.read_word_data::<S>()?;
+                if test == Word::default() {
+                    self.program_counter = Addr::from(jump);
+                }
+                Ok(())
+            }
+            9 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_number::<S>()?;
+                let c = self.read_number::<S>()?;
+                self.write_word_data(a, b + c)
+            }
+            10 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_number::<S>()?;
+                let c = self.read_number::<S>()?;
+                self.write_word_data(a, b * c)
+            }
+            11 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_number::<S>()?;
+                let c = self.read_number::<S>()?;
+                if c == Word::default() {
+                    return Err(SynacorErr::DivisionByZero { pc: pc.get() }.into());
+                }
+                self.write_word_data(a, b % c)
+            }
+            12 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_number::<S>()?;
+                let c = self.read_number::<S>()?;
+                self.write_word_data(a, b & c)
+            }
+            13 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_number::<S>()?;
+                let c = self.read_number::<S>()?;
+                self.write_word_data(a, b | c)
+            }
+            14 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_number::<S>()?;
+                self.write_word_data(a, !b)
+            }
+            15 => {
+                let a = self.read_operand::<S>()?;
+                let b = self.read_word_data::<S>()?;
+                let address = self.address::<S>(Addr::from(b))?;
+                let word = Word::new(self.bus.read(address).unwrap_or_else(|| self.memory.read(address)));
+                self.write_word_data(a, word)
+            }
+            16 => {
+                let a = self.read_word_data::<S>()?;
+                let b = self.read_word_data::<S>()?;
+                let address = self.address::<S>(Addr::from(a))?;
+                if let Some(&region) = self.read_only.iter().find(|region| region.contains(a.get())) {
+                    return Err(SynacorErr::ReadOnlyWrite { pc: pc.get(), address: a.get(), region }.into());
+                }
+                if !self.bus.write(address, b.get()) {
+                    self.memory.write(address, b.get());
+                }
+                Ok(())
+            }
+            17 => {
+                let a = self.read_word_data::<S>()?;
+                let next = Word::from(self.program_counter);
+                self.push(next)?;
+                self.program_counter = Addr::from(a);
+                Ok(())
+            }
+            18 => {
+                if let Some(jump) = self.stack.pop() {
+                    self.program_counter = Addr::from(jump);
+                    Ok(())
+                } else {
+                    Err(SynacorErr::StackUnderflow)
+                }
+            }
+            19 => {
+                let a = self.read_word_data::<S>()?.get();
+                let text = match if S::STRICT { NonAscii::Error } else { self.non_ascii } {
+                    NonAscii::Escape if a >= 128 || (a < 32 && a != 10) => format!("\\x{{{:X}}}", a),
+                    _ if a < 128 => {
+                        return self.emit(&[a as u8]).map_err(RunOutcome::Faulted)
+                    }
+                    NonAscii::Truncate | NonAscii::Escape => (a as u8 as char).to_string(),
+                    NonAscii::Latin1 if a < 256 => (a as u8 as char).to_string(),
+                    NonAscii::Latin1 => char::REPLACEMENT_CHARACTER.to_string(),
+                    NonAscii::Error => return Err(SynacorErr::BadChar(a).into()),
+                };
+                self.emit(text.as_bytes())
+            }
+            20 => {
+                let a = self.read_operand::<S>()?;
+                match (self.read_byte()?, self.on_eof) {
+                    (Received::Byte(byte), _) => self.write_word_data(a, Word::new(byte as u16)),
+                    (Received::Command(line), _) => return Err(self.rerun(pc, RunOutcome::Command(line))),
+                    (Received::Prompt, _) => return Err(self.rerun(pc, RunOutcome::Prompt)),
+                    (Received::End, EofPolicy::Value(value)) => self.write_word_data(a, Word::new(value)),
+                    (Received::End, EofPolicy::Yield) => return Err(self.rerun(pc, RunOutcome::InputNeeded)),
+                    (Received::End, _) => return Err(RunOutcome::Halted),
+                }
+            }
+            21 => Ok(()),
+            _ => S::extended(self, optcode),
+        };
+        result.map_err(RunOutcome::Faulted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::Text;
+    use output::Buffer;
+    use regex::Regex;
+    use slots::Slots;
+
+    const R0: u16 = 32768;
+    const R1: u16 = 32769;
+    const R2: u16 = 32770;
+    const R7: u16 = 32775;
+
+    // Assembles a program word by word and runs it on a fresh VM.
+    struct Program(Vec<u16>);
+
+    impl Program {
+        fn new() -> Program {
+            Program(Vec::new())
+        }
+        fn op(mut self, words: &[u16]) -> Program {
+            self.0.extend_from_slice(words);
+            self
+        }
+        fn vm(&self, config: Config) -> Synacor {
+            let bytes = self.0.iter().flat_map(|word| vec![*word as u8, (*word >> 8) as u8]).collect();
+            let mut vm = Synacor::with_config(config);
+            vm.load_image(Image::Bytes(bytes)).ok().unwrap();
+            vm
+        }
+        fn fault(&self, config: Config) -> SynacorErr {
+            match self.vm(config).run() {
+                RunOutcome::Faulted(err) => err,
+                outcome => panic!("expected a fault: {}", outcome),
+            }
+        }
+        fn run(&self) -> Synacor {
+            let mut vm = self.vm(Config::default());
+            match vm.run() {
+                RunOutcome::Halted => vm,
+                outcome => panic!("expected a halt: {}", outcome),
+            }
+        }
+    }
+
+    #[test]
+    fn halt() {
+        let vm = Program::new().op(&[0]).run();
+        assert_eq!(vm.program_counter(), 1);
+    }
+
+    #[test]
+    fn set() {
+        let vm = Program::new().op(&[1, R0, 42]).op(&[1, R7, R0]).op(&[0]).run();
+        assert_eq!(vm.registers()[0], 42);
+        assert_eq!(vm.registers()[7], 42);
+    }
+
+    #[test]
+    fn push_and_pop() {
+        let vm = Program::new().op(&[2, 1]).op(&[2, 2]).op(&[3, R0]).op(&[3, R1]).op(&[0]).run();
+        assert_eq!(vm.registers()[0], 2);
+        assert_eq!(vm.registers()[1], 1);
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn pop_underflow() {
+        let err = Program::new().op(&[3, R0]).fault(Config::default());
+        assert!(matches!(err, SynacorErr::StackUnderflow));
+    }
+
+    #[test]
+    fn eq_and_gt() {
+        let vm = Program::new()
+            .op(&[4, R0, 7, 7])
+            .op(&[4, R1, 7, 8])
+            .op(&[5, 32770, 8, 7])
+            .op(&[5, 32771, 7, 7])
+            .op(&[0])
+            .run();
+        assert_eq!(&vm.registers()[..4], &[1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn jmp() {
+        let vm = Program::new().op(&[6, 3]).op(&[0]).op(&[1, R0, 1]).op(&[0]).run();
+        assert_eq!(vm.registers()[0], 1);
+    }
+
+    #[test]
+    fn jt_and_jf() {
+        // Each taken jump skips a halt; each untaken one falls through to a set.
+        let vm = Program::new()
+            .op(&[7, 1, 4])
+            .op(&[0])
+            .op(&[8, 0, 8])
+            .op(&[0])
+            .op(&[7, 0, 0])
+            .op(&[8, 1, 0])
+            .op(&[1, R0, 1])
+            .op(&[0])
+            .run();
+        assert_eq!(vm.registers()[0], 1);
+    }
+
+    #[test]
+    fn add_wraps() {
+        let vm = Program::new().op(&[9, R0, 32758, 15]).op(&[0]).run();
+        assert_eq!(vm.registers()[0], 5);
+    }
+
+    #[test]
+    fn mult_wraps() {
+        let vm = Program::new().op(&[10, R0, 16384, 3]).op(&[10, R1, 300, 300]).op(&[0]).run();
+        assert_eq!(vm.registers()[0], 16384);
+        assert_eq!(vm.registers()[1], (90000 % 32768) as u16);
+    }
+
+    #[test]
+    fn mod_() {
+        let vm = Program::new().op(&[11, R0, 32767, 10]).op(&[0]).run();
+        assert_eq!(vm.registers()[0], 7);
+    }
+
+    #[test]
+    fn mod_by_zero() {
+        let err = Program::new().op(&[21]).op(&[11, R0, 1, 0]).fault(Config::default());
+        assert!(matches!(err, SynacorErr::DivisionByZero { pc: 1 }));
+    }
+
+    #[test]
+    fn and_or() {
+        let vm = Program::new().op(&[12, R0, 0b1100, 0b1010]).op(&[13, R1, 0b1100, 0b1010]).op(&[0]).run();
+        assert_eq!(vm.registers()[0], 0b1000);
+        assert_eq!(vm.registers()[1], 0b1110);
+    }
+
+    #[test]
+    fn not_is_15_bit() {
+        let vm = Program::new().op(&[14, R0, 0]).op(&[14, R1, 0x5555]).op(&[0]).run();
+        assert_eq!(vm.registers()[0], 0x7FFF);
+        assert_eq!(vm.registers()[1], 0x2AAA);
+    }
+
+    #[test]
+    fn rmem_and_wmem() {
+        let vm = Program::new().op(&[16, 100, 1234]).op(&[15, R0, 100]).op(&[0]).run();
+        assert_eq!(vm.memory.read(100), 1234);
+        assert_eq!(vm.registers()[0], 1234);
+    }
+
+    #[test]
+    fn call_and_ret() {
+        let vm = Program::new().op(&[17, 3]).op(&[0]).op(&[1, R0, 1]).op(&[18]).run();
+        assert_eq!(vm.registers()[0], 1);
+        assert_eq!(vm.program_counter(), 3);
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn ret_underflow() {
+        let err = Program::new().op(&[18]).fault(Config::default());
+        assert!(matches!(err, SynacorErr::StackUnderflow));
+    }
+
+    #[test]
+    fn out_and_noop() {
+        let vm = Program::new().op(&[21]).op(&[19, 10]).op(&[0]).run();
+        assert_eq!(vm.program_counter(), 4);
+    }
+
+    #[test]
+    fn bad_optcode() {
+        let err = Program::new().op(&[22]).fault(Config::default());
+        assert!(matches!(err, SynacorErr::BadOptcode));
+    }
+
+    #[test]
+    fn bad_register() {
+        let err = Program::new().op(&[1, 32776, 1]).fault(Config::default());
+        assert!(matches!(err, SynacorErr::BadRegister));
+    }
+
+    #[test]
+    fn literal_write_policy() {
+        let program = Program::new().op(&[1, 5, 1]).op(&[0]);
+        program.run();
+        let config = Config { literal_writes: Policy::Error, ..Config::default() };
+        assert!(matches!(program.fault(config), SynacorErr::LiteralWrite(5)));
+    }
+
+    #[test]
+    fn pc_wraps_at_15_bits() {
+        // Runs a noop at 32767, wraps to 0 and then takes the jump to the halt.
+        let program = Program::new().op(&[7, R0, 8]).op(&[1, R0, 1]).op(&[6, 32767]).op(&[0]);
+        let mut vm = program.vm(Config::default());
+        vm.memory.write(32767, 21);
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(vm.program_counter(), 9);
+        let mut vm = program.vm(Config { pc_overflow: PcOverflow::Error, ..Config::default() });
+        vm.memory.write(32767, 21);
+        assert!(matches!(vm.run(), RunOutcome::Faulted(SynacorErr::BadAddress(32768))));
+    }
+
+    #[test]
+    fn uninitialized_exec() {
+        // The noop written at 50 is fine to run; the word after it is not.
+        let program = Program::new().op(&[16, 50, 21]).op(&[6, 50]);
+        assert_eq!(program.run().program_counter(), 52);
+        let config = Config { uninitialized_exec: Policy::Error, ..Config::default() };
+        assert!(matches!(program.fault(config), SynacorErr::UninitializedExec(51)));
+    }
+
+    #[test]
+    fn write_exec() {
+        // Overwrites its own halt with a noop and runs into it.
+        let program = Program::new().op(&[16, 3, 21]).op(&[0]).op(&[0]);
+        assert_eq!(program.run().program_counter(), 5);
+        let config = Config { write_exec: Policy::Error, ..Config::default() };
+        assert!(matches!(program.fault(config), SynacorErr::WriteExec(3)));
+        let config = Config { write_exec: Policy::Error, exec_allowed: vec![Region { start: 3, end: 3 }], ..Config::default() };
+        let mut vm = program.vm(config);
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(vm.program_counter(), 5);
+    }
+
+    #[test]
+    fn yield_for_input() {
+        let config =
+            Config { input: Box::new(Text::default()), on_eof: EofPolicy::Yield, trace_depth: 8, ..Config::default() };
+        let mut vm = Program::new().op(&[21]).op(&[20, R0]).op(&[0]).vm(config);
+        assert!(matches!(vm.run(), RunOutcome::InputNeeded));
+        assert_eq!((vm.program_counter(), vm.instructions()), (1, 1));
+        vm.input = Box::new(Text::new("x"));
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(vm.registers()[0], b'x' as u16);
+        assert_eq!(vm.instructions(), 3);
+        assert_eq!(vm.trace(), [0, 1, 3]);
+        #[cfg(feature = "counters")]
+        assert_eq!((vm.stats().total, vm.stats().per_optcode[20]), (3, 1));
+    }
+
+    #[test]
+    fn triggers_answer_prompts() {
+        let answer = Trigger::new(Regex::new("^>$").unwrap(), |_| Some("y".to_string()));
+        let config = Config { input: Box::new(Text::default()), triggers: vec![answer], ..Config::default() };
+        let mut vm = Program::new().op(&[19, '>' as u16]).op(&[20, R0]).op(&[0]).vm(config);
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(vm.registers()[0], b'y' as u16);
+    }
+
+    #[test]
+    fn crlf_becomes_lf() {
+        let config = Config { input: Box::new(Text::new("a\r\nb\rc")), ..Config::default() };
+        let mut program = Program::new();
+        for _ in 0..5 {
+            program = program.op(&[20, R0]).op(&[19, R0]);
+        }
+        let capture = Buffer::default();
+        let mut vm = program.op(&[0]).vm(Config { output: Box::new(capture.clone()), ..config });
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(capture.text(), "a\nb\nc");
+    }
+
+    #[test]
+    fn command_lines_reach_the_host() {
+        let config = Config { input: Box::new(Text::new("/save x\nab")), command_prefix: Some(b'/'), ..Config::default() };
+        let mut vm = Program::new().op(&[20, R0]).op(&[20, R1]).op(&[0]).vm(config);
+        assert!(matches!(vm.run(), RunOutcome::Command(ref line) if line == "save x"));
+        assert_eq!(vm.program_counter(), 0);
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(vm.registers()[..2], [b'a' as u16, b'b' as u16]);
+    }
+
+    #[test]
+    fn snapshots_restore_exactly() {
+        let mut vm = Program::new().op(&[1, R0, 5]).op(&[2, R0]).op(&[16, 1000, 7]).op(&[9, R0, R0, 1]).op(&[6, 8]).vm(Config::default());
+        let mut path = std::env::temp_dir();
+        path.push(format!("synacor-test-{}.sav", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        vm.run_for(4);
+        vm.snapshot().save(&path).unwrap();
+        let saved = vm.snapshot();
+        vm.run_for(10);
+        assert!(vm.snapshot() != saved);
+        let loaded = Snapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        vm.restore(&loaded).unwrap();
+        assert!(vm.snapshot() == saved);
+        assert_eq!(vm.registers()[0], 6);
+    }
+
+    #[test]
+    fn save_slots_describe_the_prompt() {
+        let mut program = Program::new();
+        for &byte in b"Taken.\nWhat do you do?\n" {
+            program = program.op(&[19, byte as u16]);
+        }
+        let mut vm = program.op(&[0]).vm(Config::default());
+        vm.run_for(23);
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("synacor-slots-{}", std::process::id()));
+        let slots = Slots::new(&dir);
+        slots.save(&vm, "first", "a note").unwrap();
+        let listed = slots.list().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "first");
+        assert_eq!(listed[0].metadata.instructions, 23);
+        assert_eq!(listed[0].metadata.last_line, "Taken.");
+        assert_eq!(listed[0].metadata.note, "a note");
+    }
+
+    #[test]
+    fn pauses_before_each_line_of_input() {
+        let config = Config { input: Box::new(Text::new("a\nb")), pause_at_prompt: true, ..Config::default() };
+        let mut vm = Program::new().op(&[20, R0]).op(&[20, R1]).op(&[20, R2]).op(&[0]).vm(config);
+        assert!(matches!(vm.run(), RunOutcome::Prompt));
+        assert!(matches!(vm.run(), RunOutcome::Prompt));
+        assert_eq!(vm.registers()[..2], [b'a' as u16, b'\n' as u16]);
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(vm.registers()[2], b'b' as u16);
+    }
+
+    #[test]
+    fn exported_memory_reloads() {
+        let mut vm = Program::new().op(&[16, 20000, 9]).op(&[0]).vm(Config::default());
+        vm.run();
+        let image = vm.export_memory(true);
+        assert_eq!(image.len(), 40002);
+        assert_eq!(image[40000..], [9, 0]);
+        let mut reloaded = Synacor::with_config(Config::default());
+        assert!(reloaded.load_image(Image::Bytes(image)).is_ok());
+        assert_eq!((reloaded.memory(20000), reloaded.memory(1)), (9, 20000));
+    }
+
+    #[test]
+    fn state_hash_matches_snapshot() {
+        let mut vm = Program::new().op(&[1, R0, 5]).op(&[2, R0]).op(&[16, 1000, 7]).op(&[0]).vm(Config::default());
+        let before = vm.state_hash();
+        vm.run();
+        assert_ne!(vm.state_hash(), before);
+        assert_eq!(vm.state_hash(), vm.snapshot().state_hash());
+    }
+
+    #[test]
+    fn pushed_input_comes_first() {
+        let config = Config { input: Box::new(Text::new("b")), ..Config::default() };
+        let mut vm = Program::new().op(&[20, R0]).op(&[20, R1]).op(&[0]).vm(config);
+        vm.push_input("a");
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(vm.registers()[..2], [b'a' as u16, b'b' as u16]);
+    }
+
+    #[test]
+    fn run_for_budget() {
+        let mut vm = Program::new().op(&[6, 0]).vm(Config::default());
+        assert!(matches!(vm.run_for(100), RunOutcome::BudgetExceeded));
+    }
+
+    #[test]
+    fn max_stack_depth() {
+        let config = Config { max_stack_depth: Some(1), ..Config::default() };
+        let err = Program::new().op(&[2, 1]).op(&[17, 0]).fault(config);
+        assert!(matches!(err, SynacorErr::StackOverflow(1)));
+    }
+
+    #[test]
+    fn timer_interrupts() {
+        // Counts to 10 in r0 while the handler at 12 counts ticks in r1.
+        let program = Program::new()
+            .op(&[9, R0, R0, 1])
+            .op(&[4, R2, R0, 10])
+            .op(&[8, R2, 0])
+            .op(&[0])
+            .op(&[9, R1, R1, 1])
+            .op(&[18]);
+        let timer = Timer::parse("5:12").unwrap();
+        assert_eq!(timer, Timer { interval: 5, handler: 12 });
+        let mut vm = program.vm(Config { timer: Some(timer), ..Config::default() });
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(&vm.registers()[..2], &[10, 9]);
+        assert!(vm.stack().is_empty());
+
+        let mut vm = program.vm(Config { timer: Some(timer), strict: true, ..Config::default() });
+        assert!(matches!(vm.run(), RunOutcome::Halted));
+        assert_eq!(vm.registers()[1], 0);
+        assert!(Timer::parse("0:12").is_err());
+    }
+
+    #[test]
+    fn read_only_regions() {
+        let region = Region::parse("100-0x70").unwrap();
+        assert_eq!(region, Region { start: 100, end: 112 });
+        assert!(Region::parse("9-8").is_err());
+        let program = Program::new().op(&[16, 99, 1]).op(&[16, 112, 2]).op(&[0]);
+        let config = Config { read_only: vec![region], ..Config::default() };
+        let err = program.fault(config);
+        assert!(matches!(err, SynacorErr::ReadOnlyWrite { pc: 3, address: 112, .. }));
+        assert_eq!(err.to_string(), "The synacor wrote to 112 at 3, inside the read-only region 100-112.");
+    }
+
+    #[test]
+    fn strict_address_space() {
+        let program = Program::new().op(&[15, R1, 7]).op(&[15, R0, R1]).op(&[0]).op(&[40000]);
+        program.run();
+        let config = Config { strict: true, ..Config::default() };
+        assert!(matches!(program.fault(config), SynacorErr::BadAddress(40000)));
+    }
+}
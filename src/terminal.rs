@@ -0,0 +1,258 @@
+// Puts the console into character-at-a-time mode without echo, so the line
+// editor can handle keys itself. The previous mode comes back when the guard
+// is dropped, and a panic hook restores it too in case a panic skips the
+// drop. Ctrl+C is read as a key rather than killing the process, which would
+// leave the terminal raw.
+
+use std::io;
+use std::io::IsTerminal;
+use std::panic;
+use std::sync::{Mutex, Once};
+
+use self::platform::Mode;
+
+static SAVED: Mutex<Option<Mode>> = Mutex::new(None);
+static HOOK: Once = Once::new();
+
+// Whether stdin is a console that can be put into raw mode.
+pub fn supported() -> bool {
+    platform::SUPPORTED && io::stdin().is_terminal()
+}
+
+// The console's columns and rows, if it can tell.
+pub fn size() -> Option<(usize, usize)> {
+    platform::size().filter(|&(columns, rows)| columns > 0 && rows > 0)
+}
+
+fn restore_saved() {
+    if let Ok(mut saved) = SAVED.lock() {
+        if let Some(mode) = saved.take() {
+            let _ = platform::set(&mode);
+        }
+    }
+}
+
+pub struct RawMode(());
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        HOOK.call_once(|| {
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                restore_saved();
+                previous(info);
+            }));
+        });
+        let original = platform::get()?;
+        platform::set(&platform::raw(&original))?;
+        if let Ok(mut saved) = SAVED.lock() {
+            *saved = Some(original);
+        }
+        Ok(RawMode(()))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        restore_saved();
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod platform {
+    use std::io;
+    use std::os::raw::{c_int, c_ulong};
+
+    pub const SUPPORTED: bool = true;
+
+    #[cfg(target_os = "linux")]
+    mod layout {
+        use std::os::raw::c_ulong;
+
+        pub type Flag = u32;
+        pub const NCCS: usize = 32;
+        pub const ISIG: Flag = 0o1;
+        pub const ICANON: Flag = 0o2;
+        pub const ECHO: Flag = 0o10;
+        pub const IXON: Flag = 0o2000;
+        pub const VTIME: usize = 5;
+        pub const VMIN: usize = 6;
+        pub const TIOCGWINSZ: c_ulong = 0x5413;
+
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        pub struct Termios {
+            pub iflag: Flag,
+            pub oflag: Flag,
+            pub cflag: Flag,
+            pub lflag: Flag,
+            pub line: u8,
+            pub cc: [u8; NCCS],
+            pub ispeed: u32,
+            pub ospeed: u32,
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod layout {
+        use std::os::raw::c_ulong;
+
+        pub type Flag = u64;
+        pub const NCCS: usize = 20;
+        pub const ISIG: Flag = 0x80;
+        pub const ICANON: Flag = 0x100;
+        pub const ECHO: Flag = 0x8;
+        pub const IXON: Flag = 0x200;
+        pub const VMIN: usize = 16;
+        pub const VTIME: usize = 17;
+        pub const TIOCGWINSZ: c_ulong = 0x40087468;
+
+        #[repr(C)]
+        #[derive(Clone, Copy)]
+        pub struct Termios {
+            pub iflag: Flag,
+            pub oflag: Flag,
+            pub cflag: Flag,
+            pub lflag: Flag,
+            pub cc: [u8; NCCS],
+            pub ispeed: u64,
+            pub ospeed: u64,
+        }
+    }
+
+    use self::layout::*;
+
+    pub type Mode = Termios;
+
+    const TCSANOW: c_int = 0;
+
+    #[repr(C)]
+    struct WinSize {
+        rows: u16,
+        columns: u16,
+        x_pixels: u16,
+        y_pixels: u16,
+    }
+
+    extern "C" {
+        fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+        fn tcsetattr(fd: c_int, action: c_int, termios: *const Termios) -> c_int;
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+
+    pub fn size() -> Option<(usize, usize)> {
+        let mut size = WinSize { rows: 0, columns: 0, x_pixels: 0, y_pixels: 0 };
+        match unsafe { ioctl(1, TIOCGWINSZ, &mut size as *mut WinSize) } {
+            0 => Some((size.columns as usize, size.rows as usize)),
+            _ => None,
+        }
+    }
+
+    pub fn get() -> io::Result<Mode> {
+        let mut mode: Termios = unsafe { std::mem::zeroed() };
+        if unsafe { tcgetattr(0, &mut mode) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(mode)
+    }
+
+    pub fn set(mode: &Mode) -> io::Result<()> {
+        if unsafe { tcsetattr(0, TCSANOW, mode) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn raw(mode: &Mode) -> Mode {
+        let mut raw = *mode;
+        raw.lflag &= !(ICANON | ECHO | ISIG);
+        // Frees Ctrl+S and Ctrl+Q from flow control.
+        raw.iflag &= !IXON;
+        raw.cc[VMIN] = 1;
+        raw.cc[VTIME] = 0;
+        raw
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io;
+    use std::os::raw::c_void;
+
+    pub const SUPPORTED: bool = true;
+
+    const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_PROCESSED_INPUT: u32 = 0x1;
+    const ENABLE_LINE_INPUT: u32 = 0x2;
+    const ENABLE_ECHO_INPUT: u32 = 0x4;
+    const ENABLE_VIRTUAL_TERMINAL_INPUT: u32 = 0x200;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x4;
+
+    extern "system" {
+        fn GetStdHandle(handle: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    // The input and output console modes.
+    pub type Mode = (u32, u32);
+
+    fn console_mode(handle: u32) -> io::Result<u32> {
+        let mut mode = 0;
+        if unsafe { GetConsoleMode(GetStdHandle(handle), &mut mode) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(mode)
+    }
+
+    fn set_console_mode(handle: u32, mode: u32) -> io::Result<()> {
+        if unsafe { SetConsoleMode(GetStdHandle(handle), mode) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn get() -> io::Result<Mode> {
+        Ok((console_mode(STD_INPUT_HANDLE)?, console_mode(STD_OUTPUT_HANDLE)?))
+    }
+
+    pub fn size() -> Option<(usize, usize)> {
+        None
+    }
+
+    pub fn set(mode: &Mode) -> io::Result<()> {
+        set_console_mode(STD_INPUT_HANDLE, mode.0)?;
+        set_console_mode(STD_OUTPUT_HANDLE, mode.1)
+    }
+
+    // Arrow keys arrive as the same escape sequences as on Unix, and the
+    // editor's escape sequences are understood on output.
+    pub fn raw(mode: &Mode) -> Mode {
+        let input = mode.0 & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT | ENABLE_PROCESSED_INPUT);
+        (input | ENABLE_VIRTUAL_TERMINAL_INPUT, mode.1 | ENABLE_VIRTUAL_TERMINAL_PROCESSING)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use std::io;
+
+    pub const SUPPORTED: bool = false;
+
+    pub type Mode = ();
+
+    pub fn get() -> io::Result<Mode> {
+        Err(io::Error::new(io::ErrorKind::Other, "raw terminal mode is not supported on this platform"))
+    }
+
+    pub fn size() -> Option<(usize, usize)> {
+        None
+    }
+
+    pub fn set(_: &Mode) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn raw(_: &Mode) -> Mode {}
+}
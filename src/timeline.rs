@@ -0,0 +1,120 @@
+// Calls and returns as a timeline in the Trace Event format that Chrome's
+// about:tracing and Perfetto load: a JSON array of events like
+//   {"name": "1723", "ph": "B", "ts": 701453, "pid": 1, "tid": 1}
+// where "B" begins a call to the function at 1723 and "E" ends the latest
+// one. The time is the instruction count, so a "microsecond" is one
+// instruction.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+use json::Value;
+use trace::{Step, Tracer};
+
+// The name the timeline runs under (see Synacor::set_tracer).
+pub const NAME: &str = "timeline";
+
+fn event(name: &str, phase: &str, time: u64) -> Value {
+    Value::object(vec![
+        ("name", Value::from(name)),
+        ("ph", Value::from(phase)),
+        ("ts", Value::from(time)),
+        ("pid", Value::from(1u64)),
+        ("tid", Value::from(1u64)),
+    ])
+}
+
+pub struct Timeline<W: Write> {
+    file: W,
+    // The calls that haven't returned.
+    calls: Vec<u16>,
+    events: u64,
+    last: u64,
+    error: Option<io::Error>,
+}
+
+impl Timeline<BufWriter<File>> {
+    pub fn create(path: &str) -> io::Result<Timeline<BufWriter<File>>> {
+        Ok(Timeline::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> Timeline<W> {
+    pub fn new(mut file: W) -> Timeline<W> {
+        let error = file.write_all(b"[").err();
+        Timeline { file, calls: Vec::new(), events: 0, last: 0, error }
+    }
+    fn write(&mut self, event: Value) {
+        let separator = if self.events == 0 { "\n" } else { ",\n" };
+        self.events += 1;
+        if self.error.is_none() {
+            self.error = write!(self.file, "{}{}", separator, event).err();
+        }
+    }
+    pub fn into_inner(self) -> W {
+        self.file
+    }
+}
+
+impl<W: Write> Tracer for Timeline<W> {
+    fn step(&mut self, step: &Step) {
+        self.last = step.count + 1;
+        match step.opcode {
+            17 => {
+                self.calls.push(step.next);
+                self.write(event(&step.next.to_string(), "B", step.count));
+            }
+            // Returns with no call to match are left out.
+            18 => {
+                if let Some(function) = self.calls.pop() {
+                    self.write(event(&function.to_string(), "E", step.count + 1));
+                }
+            }
+            _ => (),
+        }
+    }
+    // Ends the calls still running, so they show up.
+    fn finish(&mut self) -> io::Result<()> {
+        while let Some(function) = self.calls.pop() {
+            let last = self.last;
+            self.write(event(&function.to_string(), "E", last));
+        }
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.file.write_all(b"\n]\n")?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json;
+
+    fn step(count: u64, opcode: u16, next: u16) -> Step {
+        Step { count, pc: 0, opcode, words: [0; 3], before: [0; 8], after: [0; 8], next, stack_depth: 0 }
+    }
+
+    #[test]
+    fn nests_calls() {
+        let mut timeline = Timeline::new(Vec::new());
+        timeline.step(&step(0, 17, 10));
+        timeline.step(&step(1, 17, 20));
+        timeline.step(&step(2, 18, 3));
+        timeline.step(&step(3, 18, 5));
+        timeline.step(&step(4, 17, 30));
+        timeline.finish().unwrap();
+        let events = json::parse(&String::from_utf8(timeline.into_inner()).unwrap()).unwrap();
+        let events: Vec<(String, String, u64)> = events
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| (event.get("name").unwrap().to_string(), event.get("ph").unwrap().to_string(), event.get("ts").unwrap().as_u64().unwrap()))
+            .collect();
+        let expected = [("10", "B", 0), ("20", "B", 1), ("20", "E", 3), ("10", "E", 4), ("30", "B", 4), ("30", "E", 5)];
+        assert_eq!(events, expected.iter().map(|&(name, phase, time)| (format!("{:?}", name), format!("{:?}", phase), time)).collect::<Vec<_>>());
+    }
+}
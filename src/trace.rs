@@ -0,0 +1,382 @@
+// Instruction traces: every instruction the machine executes, with the
+// values its operands had and the register it wrote, if any. As text each
+// instruction is a line like
+//   1234 5483: add r0 r1 4  r1=5  -> r0=9
+// giving how many instructions came before it, its address and its
+// disassembly. The binary format is the magic bytes followed by one record
+// per instruction:
+//   the gap since the last record's count, as a LEB128 number
+//   its address and opcode as u16s
+//   its operands' values as u16s, as many as the opcode takes
+//   the value written as a u16, for opcodes that can write a register
+// all little-endian.
+//
+// A filter narrows a trace down to some of the code, given as comma-separated
+// terms: an address or range of addresses like `0x1700-0x1800` or `2125`, or
+// a mnemonic like `call`. A `!` in front excludes what a term matches.
+// Something is traced if it matches an included range (or there are none)
+// and an included mnemonic (or there are none), and no excluded term.
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+
+use disasm;
+
+const MAGIC: &[u8; 8] = b"SYNTRACE";
+// The name the trace file runs under (see Synacor::set_tracer).
+pub const NAME: &str = "trace";
+const REGISTERS: u16 = 32768;
+
+// Whether an opcode writes its first operand.
+fn writes(opcode: u16) -> bool {
+    matches!(opcode, 1 | 3..=5 | 9..=15 | 20)
+}
+
+// One executed instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    // How many instructions ran before this one.
+    pub count: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    // The operands as they appear in memory; unused ones are 0.
+    pub words: [u16; 3],
+    pub before: [u16; 8],
+    pub after: [u16; 8],
+    // Where execution went next.
+    pub next: u16,
+    pub stack_depth: usize,
+}
+
+impl Step {
+    pub fn arguments(&self) -> &[u16] {
+        &self.words[..disasm::arguments(self.opcode).unwrap_or(0)]
+    }
+    // What an operand word stood for when the instruction ran.
+    pub fn value(&self, word: u16) -> u16 {
+        match word.checked_sub(REGISTERS) {
+            Some(register) if register < 8 => self.before[register as usize],
+            _ => word,
+        }
+    }
+    // The register the instruction wrote and what it wrote there.
+    pub fn write(&self) -> Option<(usize, u16)> {
+        match self.words[0].checked_sub(REGISTERS) {
+            Some(register) if register < 8 && writes(self.opcode) => Some((register as usize, self.after[register as usize])),
+            _ => None,
+        }
+    }
+    pub fn disassembly(&self) -> String {
+        let read = |address: u16| match address.wrapping_sub(self.pc) {
+            0 => self.opcode,
+            offset @ 1..=3 => self.words[offset as usize - 1],
+            _ => 0,
+        };
+        disasm::instruction(read, self.pc).0
+    }
+    pub fn text(&self) -> String {
+        let mut line = format!("{} {}: {}", self.count, self.pc, self.disassembly());
+        let destination = self.write().map(|(register, _)| register as u16 + REGISTERS);
+        for (index, &word) in self.arguments().iter().enumerate() {
+            if (REGISTERS..REGISTERS + 8).contains(&word) && !(index == 0 && destination == Some(word)) {
+                line += &format!("  r{}={}", word - REGISTERS, self.value(word));
+            }
+        }
+        if let Some((register, value)) = self.write() {
+            line += &format!("  -> r{}={}", register, value);
+        }
+        line
+    }
+}
+
+// Something that watches every instruction the machine executes.
+pub trait Tracer {
+    fn step(&mut self, step: &Step);
+    // Called when tracing stops, to flush anything buffered.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Binary,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    include: Vec<(u16, u16)>,
+    exclude: Vec<(u16, u16)>,
+    opcodes: Vec<u16>,
+    not_opcodes: Vec<u16>,
+}
+
+// An address in decimal or, after `0x`, hex.
+pub fn address(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("{:?} is not an address", text))
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        for term in spec.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            let (excluded, term) = match term.strip_prefix('!') {
+                Some(term) => (true, term),
+                None => (false, term),
+            };
+            if let Some(opcode) = disasm::opcode(term) {
+                if excluded { &mut filter.not_opcodes } else { &mut filter.opcodes }.push(opcode);
+                continue;
+            }
+            let (start, end) = match term.split_once('-') {
+                Some((start, end)) => (address(start)?, address(end)?),
+                None => (address(term)?, address(term)?),
+            };
+            if start > end {
+                return Err(format!("the range {} is backwards", term));
+            }
+            if excluded { &mut filter.exclude } else { &mut filter.include }.push((start, end));
+        }
+        Ok(filter)
+    }
+    pub fn matches(&self, pc: u16, opcode: u16) -> bool {
+        let within = |ranges: &[(u16, u16)]| ranges.iter().any(|&(start, end)| (start..=end).contains(&pc));
+        (self.include.is_empty() || within(&self.include))
+            && (self.opcodes.is_empty() || self.opcodes.contains(&opcode))
+            && !within(&self.exclude)
+            && !self.not_opcodes.contains(&opcode)
+    }
+}
+
+// Passes on only the steps a filter lets through.
+pub struct Filtered {
+    pub filter: Filter,
+    pub tracer: Box<dyn Tracer>,
+}
+
+impl Tracer for Filtered {
+    fn step(&mut self, step: &Step) {
+        if self.filter.matches(step.pc, step.opcode) {
+            self.tracer.step(step);
+        }
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.tracer.finish()
+    }
+}
+
+// The tracer to run: a writer to `path`, behind `filter` if there is one.
+pub fn open(path: &str, format: Format, filter: Option<Filter>) -> io::Result<Box<dyn Tracer>> {
+    let writer = Box::new(Writer::create(path, format)?);
+    Ok(match filter {
+        Some(filter) => Box::new(Filtered { filter, tracer: writer }),
+        None => writer,
+    })
+}
+
+// Writes a trace to a file. The first error stops the writing and is
+// reported by finish.
+pub struct Writer {
+    file: BufWriter<File>,
+    format: Format,
+    last: u64,
+    error: Option<io::Error>,
+}
+
+impl Writer {
+    pub fn create(path: &str, format: Format) -> io::Result<Writer> {
+        let mut file = BufWriter::new(File::create(path)?);
+        if format == Format::Binary {
+            file.write_all(MAGIC)?;
+        }
+        Ok(Writer { file, format, last: 0, error: None })
+    }
+    fn write(&mut self, step: &Step) -> io::Result<()> {
+        match self.format {
+            Format::Text => writeln!(self.file, "{}", step.text()),
+            Format::Binary => {
+                // Counts only go back after a rewind, which starts the gap
+                // over from there.
+                let mut gap = step.count.saturating_sub(self.last);
+                self.last = step.count;
+                loop {
+                    let byte = (gap & 0x7f) as u8;
+                    gap >>= 7;
+                    if gap == 0 {
+                        self.file.write_all(&[byte])?;
+                        break;
+                    }
+                    self.file.write_all(&[byte | 0x80])?;
+                }
+                self.file.write_all(&step.pc.to_le_bytes())?;
+                self.file.write_all(&step.opcode.to_le_bytes())?;
+                for &word in step.arguments() {
+                    self.file.write_all(&step.value(word).to_le_bytes())?;
+                }
+                if writes(step.opcode) {
+                    let value = step.write().map_or(0, |(_, value)| value);
+                    self.file.write_all(&value.to_le_bytes())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Tracer for Writer {
+    fn step(&mut self, step: &Step) {
+        if self.error.is_none() {
+            self.error = self.write(step).err();
+        }
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.file.flush(),
+        }
+    }
+}
+
+// A record from a binary trace. Operands come resolved, so registers can't
+// be told apart from literals any more.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub count: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub values: Vec<u16>,
+    pub written: Option<u16>,
+}
+
+impl Record {
+    // What a binary trace keeps of a step.
+    pub fn of(step: &Step) -> Record {
+        let written = if writes(step.opcode) { Some(step.write().map_or(0, |(_, value)| value)) } else { None };
+        Record { count: step.count, pc: step.pc, opcode: step.opcode, values: step.arguments().iter().map(|&word| step.value(word)).collect(), written }
+    }
+    // Like `1234 5483: add 0 5 4 -> 9`, with the values the operands had.
+    pub fn text(&self) -> String {
+        let mut line = format!("{} {}: {}", self.count, self.pc, disasm::name(self.opcode).unwrap_or("?"));
+        for value in &self.values {
+            line += &format!(" {}", value);
+        }
+        if let Some(value) = self.written {
+            line += &format!(" -> {}", value);
+        }
+        line
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u16<R: Read>(file: &mut R) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    file.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+pub fn is_binary(path: &str) -> bool {
+    let mut magic = [0; 8];
+    File::open(path).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && &magic == MAGIC
+}
+
+// Reads a binary trace back.
+pub fn read(path: &str) -> io::Result<Vec<Record>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    file.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a binary trace"));
+    }
+    let mut records = Vec::new();
+    let mut count = 0u64;
+    loop {
+        let mut gap = 0u64;
+        let mut shift = 0;
+        loop {
+            let mut byte = [0];
+            if file.read(&mut byte)? == 0 {
+                return if shift == 0 { Ok(records) } else { Err(invalid("the trace ends in the middle of a record")) };
+            }
+            if shift > 63 {
+                return Err(invalid("a count in the trace is too long"));
+            }
+            gap |= u64::from(byte[0] & 0x7f) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        count += gap;
+        let pc = read_u16(&mut file)?;
+        let opcode = read_u16(&mut file)?;
+        let arguments = disasm::arguments(opcode).ok_or_else(|| invalid("the trace has an instruction that isn't one"))?;
+        let values = (0..arguments).map(|_| read_u16(&mut file)).collect::<io::Result<Vec<_>>>()?;
+        // Whether a register was written depends on the operand, which the
+        // trace doesn't keep, so the opcodes that can write one always carry
+        // a value; writes to a literal come out as 0.
+        let written = if writes(opcode) { Some(read_u16(&mut file)?) } else { None };
+        records.push(Record { count, pc, opcode, values, written });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memory::Image;
+    use output::Null;
+    use synacor::{Config, Synacor};
+
+    #[test]
+    fn describes_a_step() {
+        let mut step = Step { count: 7, pc: 100, opcode: 9, words: [32768, 32769, 4], before: [0; 8], after: [0; 8], next: 104, stack_depth: 0 };
+        step.before[1] = 5;
+        step.after = step.before;
+        step.after[0] = 9;
+        assert_eq!(step.write(), Some((0, 9)));
+        assert_eq!(step.text(), "7 100: add r0 r1 4  r1=5  -> r0=9");
+    }
+
+    #[test]
+    fn filters_by_range_and_opcode() {
+        let filter = Filter::parse("0x1700-0x1800, call,ret, !0x1750").unwrap();
+        assert!(filter.matches(0x1700, 17));
+        assert!(!filter.matches(0x1700, 9));
+        assert!(!filter.matches(0x1750, 18));
+        assert!(!filter.matches(0x1801, 18));
+        assert!(Filter::parse("!out").unwrap().matches(5, 9));
+        assert_eq!(Filter::parse("9-3"), Err("the range 9-3 is backwards".to_string()));
+        assert_eq!(Filter::parse("jump"), Err("\"jump\" is not an address".to_string()));
+    }
+
+    #[test]
+    fn binary_traces_round_trip() {
+        // set r0 3; add r1 r0 1; out 'A'; halt
+        let words: [u16; 10] = [1, 32768, 3, 9, 32769, 32768, 1, 19, 65, 0];
+        let config = Config { output: Box::new(Null), ..Config::default() };
+        let mut synacor = Synacor::with_config(config);
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let mut path = std::env::temp_dir();
+        path.push(format!("synacor-test-{}.trace", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        synacor.set_tracer(NAME, Some(Box::new(Writer::create(&path, Format::Binary).unwrap())));
+        synacor.run();
+        synacor.set_tracer(NAME, None).unwrap().finish().unwrap();
+        let records = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(records, [
+            Record { count: 0, pc: 0, opcode: 1, values: vec![0, 3], written: Some(3) },
+            Record { count: 1, pc: 3, opcode: 9, values: vec![0, 3, 1], written: Some(4) },
+            Record { count: 2, pc: 7, opcode: 19, values: vec![65], written: None },
+        ]);
+    }
+}
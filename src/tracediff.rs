@@ -0,0 +1,166 @@
+// Compares two runs instruction by instruction and finds the first place
+// they part ways, with a few instructions either side. A run is a text
+// trace, a binary trace or a replay file, which is run again to trace it.
+// Text traces name the registers operands came from where the others only
+// keep their values, so a text trace can only be compared with another.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::rc::Rc;
+
+use replay::Replay;
+use synacor::Synacor;
+use trace::{self, Record, Step, Tracer};
+
+// How many instructions a replay runs for at a time.
+const CHUNK: u64 = 4096;
+// How many instructions either side of a divergence to show.
+pub const CONTEXT: usize = 3;
+
+// The instructions of one run, as lines of text.
+pub enum Run {
+    Text(io::Lines<BufReader<File>>),
+    Records(std::vec::IntoIter<Record>),
+    Replay { synacor: Box<Synacor>, end: u64, steps: Rc<RefCell<VecDeque<Record>>> },
+}
+
+// Queues up what a replay executes.
+struct Collector(Rc<RefCell<VecDeque<Record>>>);
+
+impl Tracer for Collector {
+    fn step(&mut self, step: &Step) {
+        self.0.borrow_mut().push_back(Record::of(step));
+    }
+}
+
+impl Run {
+    pub fn open(path: &str) -> io::Result<Run> {
+        if Replay::is_replay(path) {
+            let replay = Replay::load(path)?;
+            let mut synacor = replay.machine().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            let steps = Rc::new(RefCell::new(VecDeque::new()));
+            synacor.set_tracer(trace::NAME, Some(Box::new(Collector(steps.clone()))));
+            return Ok(Run::Replay { synacor: Box::new(synacor), end: replay.end, steps });
+        }
+        if trace::is_binary(path) {
+            return Ok(Run::Records(trace::read(path)?.into_iter()));
+        }
+        Ok(Run::Text(BufReader::new(File::open(path)?).lines()))
+    }
+    fn is_text(&self) -> bool {
+        matches!(*self, Run::Text(_))
+    }
+}
+
+impl Iterator for Run {
+    type Item = io::Result<String>;
+    fn next(&mut self) -> Option<io::Result<String>> {
+        match *self {
+            Run::Text(ref mut lines) => lines.next(),
+            Run::Records(ref mut records) => records.next().map(|record| Ok(record.text())),
+            Run::Replay { ref mut synacor, end, ref steps } => {
+                while steps.borrow().is_empty() && synacor.instructions() < end {
+                    let before = synacor.instructions();
+                    synacor.run_for(CHUNK.min(end - before));
+                    if synacor.instructions() == before {
+                        break;
+                    }
+                }
+                let step = steps.borrow_mut().pop_front();
+                step.map(|record| Ok(record.text()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Divergence {
+    // How many instructions matched.
+    pub matched: u64,
+    // The last of those.
+    pub before: Vec<String>,
+    // Where each run goes from there; empty if it ended.
+    pub a: Vec<String>,
+    pub b: Vec<String>,
+}
+
+// The first divergence between two runs, or None if they're the same, with
+// `context` instructions either side.
+pub fn diff<A, B>(a: A, b: B, context: usize) -> io::Result<Option<Divergence>>
+where
+    A: Iterator<Item = io::Result<String>>,
+    B: Iterator<Item = io::Result<String>>,
+{
+    let (mut a, mut b) = (a.fuse(), b.fuse());
+    let mut before = VecDeque::new();
+    let mut matched = 0;
+    loop {
+        let (line_a, line_b) = (a.next().transpose()?, b.next().transpose()?);
+        if line_a.is_none() && line_b.is_none() {
+            return Ok(None);
+        }
+        if line_a != line_b {
+            let rest = |first: Option<String>, rest: &mut dyn Iterator<Item = io::Result<String>>| -> io::Result<Vec<String>> {
+                first.into_iter().map(Ok).chain(rest.take(context)).collect()
+            };
+            let (a, b) = (rest(line_a, &mut a)?, rest(line_b, &mut b)?);
+            return Ok(Some(Divergence { matched, before: before.into_iter().collect(), a, b }));
+        }
+        matched += 1;
+        before.extend(line_a);
+        if before.len() > context {
+            before.pop_front();
+        }
+    }
+}
+
+// Diffs the runs in two files.
+pub fn diff_files(a: &str, b: &str, context: usize) -> Result<Option<Divergence>, String> {
+    let run_a = Run::open(a).map_err(|err| format!("Could not read {}: {}", a, err))?;
+    let run_b = Run::open(b).map_err(|err| format!("Could not read {}: {}", b, err))?;
+    if run_a.is_text() != run_b.is_text() {
+        return Err("A text trace can only be compared with another text trace.".to_string());
+    }
+    diff(run_a, run_b, context).map_err(|err| format!("Could not read the runs: {}", err))
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "The runs agree for {} instructions, then differ:", self.matched)?;
+        for line in &self.before {
+            writeln!(f, "  {}", line)?;
+        }
+        for (sign, lines) in [('-', &self.a), ('+', &self.b)] {
+            if lines.is_empty() {
+                writeln!(f, "{} (ends)", sign)?;
+            }
+            for line in lines {
+                writeln!(f, "{} {}", sign, line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(lines: &[&str]) -> impl Iterator<Item = io::Result<String>> {
+        lines.iter().map(|line| Ok(line.to_string())).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn finds_the_first_divergence() {
+        assert_eq!(diff(run(&["1", "2"]), run(&["1", "2"]), 1).unwrap(), None);
+        let divergence = diff(run(&["1", "2", "3", "4", "5", "6"]), run(&["1", "2", "3", "x", "5"]), 1).unwrap().unwrap();
+        assert_eq!(divergence, Divergence { matched: 3, before: vec!["3".to_string()], a: vec!["4".to_string(), "5".to_string()], b: vec!["x".to_string(), "5".to_string()] });
+        let divergence = diff(run(&["1"]), run(&["1", "2"]), 2).unwrap().unwrap();
+        assert_eq!(divergence.to_string(), "The runs agree for 1 instructions, then differ:\n  1\n- (ends)\n+ 2\n");
+    }
+}
@@ -0,0 +1,173 @@
+// Transcripts of a session: every chunk of output and every line of input,
+// stamped with the number of instructions executed when it began. Since the
+// VM is deterministic, replaying the inputs of a transcript should reproduce
+// its outputs exactly.
+//
+// On disk each entry is one line: the instruction count, `out` or `in`, and
+// the text with backslashes and newlines escaped.
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Output,
+    Input,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub at: u64,
+    pub direction: Direction,
+    pub text: Vec<u8>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Output => "out",
+            Direction::Input => "in",
+        };
+        write!(f, "{} {} {}", self.at, direction, escape(&self.text))
+    }
+}
+
+fn escape(text: &[u8]) -> String {
+    String::from_utf8_lossy(text).replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') => bytes.push(b'\n'),
+                Some(other) => bytes.extend(other.to_string().bytes()),
+                None => bytes.push(b'\\'),
+            },
+            (c, false) => bytes.extend(c.to_string().bytes()),
+        }
+    }
+    bytes
+}
+
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut parts = line.splitn(3, ' ');
+    let at = parts.next()?.parse().ok()?;
+    let direction = match parts.next()? {
+        "out" => Direction::Output,
+        "in" => Direction::Input,
+        _ => return None,
+    };
+    Some(Entry { at, direction, text: unescape(parts.next().unwrap_or("")) })
+}
+
+#[derive(Default)]
+pub struct Transcript {
+    entries: Vec<Entry>,
+    // The entry still being added to.
+    current: Option<Entry>,
+    file: Option<BufWriter<File>>,
+}
+
+impl Transcript {
+    // Records a transcript that is also written to `path` as it grows.
+    pub fn create(path: &str) -> io::Result<Transcript> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Transcript { file: Some(file), ..Transcript::default() })
+    }
+    pub fn open(path: &str) -> io::Result<Transcript> {
+        let text = ::std::fs::read_to_string(path)?;
+        let mut entries = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            match parse_entry(line) {
+                Some(entry) => entries.push(entry),
+                None => {
+                    let message = format!("line {} is not a transcript entry", number + 1);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                }
+            }
+        }
+        Ok(Transcript { entries, ..Transcript::default() })
+    }
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+    // All of the input, in order.
+    pub fn input(&self) -> Vec<u8> {
+        self.entries.iter().filter(|entry| entry.direction == Direction::Input).flat_map(|entry| entry.text.clone()).collect()
+    }
+    pub fn record(&mut self, at: u64, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+        match self.current {
+            Some(ref mut entry) if entry.direction == direction => entry.text.extend(bytes),
+            _ => {
+                self.finish()?;
+                self.current = Some(Entry { at, direction, text: bytes.to_vec() });
+            }
+        }
+        // A line of input is complete as soon as its newline is read.
+        if direction == Direction::Input && bytes.ends_with(b"\n") {
+            self.finish()?;
+        }
+        Ok(())
+    }
+    // Closes the entry in progress, writing it out if there is a file.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(entry) = self.current.take() {
+            if let Some(ref mut file) = self.file {
+                writeln!(file, "{}", entry)?;
+                file.flush()?;
+            }
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+}
+
+// Where a replay first stopped matching the recording.
+pub struct Divergence {
+    pub index: usize,
+    pub recorded: Option<Entry>,
+    pub replayed: Option<Entry>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |entry: &Option<Entry>| entry.as_ref().map_or("(nothing)".to_string(), |entry| entry.to_string());
+        writeln!(f, "The replay diverged at entry {}:", self.index + 1)?;
+        writeln!(f, "  recorded: {}", show(&self.recorded))?;
+        write!(f, "  replayed: {}", show(&self.replayed))
+    }
+}
+
+pub fn compare(recorded: &Transcript, replayed: &Transcript) -> Option<Divergence> {
+    let (recorded, replayed) = (recorded.entries(), replayed.entries());
+    (0..recorded.len().max(replayed.len()))
+        .find(|&index| recorded.get(index) != replayed.get(index))
+        .map(|index| Divergence { index, recorded: recorded.get(index).cloned(), replayed: replayed.get(index).cloned() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_merge_and_round_trip() {
+        let mut transcript = Transcript::default();
+        transcript.record(1, Direction::Output, b"What do ").unwrap();
+        transcript.record(2, Direction::Output, b"you do?\n").unwrap();
+        transcript.record(9, Direction::Input, b"l").unwrap();
+        transcript.record(10, Direction::Input, b"ook\n").unwrap();
+        transcript.record(11, Direction::Input, b"back\\slash\n").unwrap();
+        transcript.finish().unwrap();
+        let lines: Vec<String> = transcript.entries().iter().map(|entry| entry.to_string()).collect();
+        assert_eq!(lines, ["1 out What do you do?\\n", "9 in look\\n", "11 in back\\\\slash\\n"]);
+        let parsed: Vec<Entry> = lines.iter().filter_map(|line| parse_entry(line)).collect();
+        assert_eq!(parsed, transcript.entries());
+        assert_eq!(transcript.input(), b"look\nback\\slash\n");
+    }
+}
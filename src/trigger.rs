@@ -0,0 +1,88 @@
+// Hooks that run when a line of output matches a pattern. A trigger's action
+// gets the line and the matching text, and can answer with input for the VM
+// to read next. The prompt before an input is checked as well, even though it
+// has no newline yet.
+
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+
+use regex::Regex;
+
+pub struct Fired<'a> {
+    pub line: &'a str,
+    pub matched: &'a str,
+    // Instructions executed when the line was finished.
+    pub at: u64,
+}
+
+type Action = Box<dyn FnMut(&Fired) -> Option<String>>;
+
+pub struct Trigger {
+    pattern: Regex,
+    action: Action,
+}
+
+impl Trigger {
+    pub fn new<F: FnMut(&Fired) -> Option<String> + 'static>(pattern: Regex, action: F) -> Trigger {
+        Trigger { pattern, action: Box::new(action) }
+    }
+    pub fn fire(&mut self, line: &str, at: u64) -> Option<String> {
+        let matched = self.pattern.find(line)?;
+        (self.action)(&Fired { line, matched, at })
+    }
+    // Reads triggers from lines like `PATTERN => ACTION`, where the action is
+    // one of
+    //   print         echo the matching line to stderr
+    //   log FILE      append the matching text to FILE
+    //   send TEXT     answer with TEXT and a newline
+    // Blank lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> Result<Vec<Trigger>, String> {
+        let mut triggers = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fail = |message: String| format!("Line {} of the triggers: {}", number + 1, message);
+            let (pattern, action) = line.split_once(" => ").ok_or_else(|| fail("expected PATTERN => ACTION".to_string()))?;
+            let pattern = Regex::new(pattern).map_err(|err| fail(err.to_string()))?;
+            let (command, argument) = action.split_once(' ').unwrap_or((action, ""));
+            let trigger = match command {
+                "print" => Trigger::new(pattern, |fired| {
+                    notice!("{}", fired.line);
+                    None
+                }),
+                "log" if !argument.is_empty() => {
+                    let path = argument.to_string();
+                    Trigger::new(pattern, move |fired| {
+                        let file = OpenOptions::new().create(true).append(true).open(&path);
+                        if let Err(err) = file.and_then(|mut file| writeln!(file, "{}", fired.matched)) {
+                            notice!("Could not log to {}: {}", path, err);
+                        }
+                        None
+                    })
+                }
+                "send" => {
+                    let input = argument.to_string() + "\n";
+                    Trigger::new(pattern, move |_| Some(input.clone()))
+                }
+                _ => return Err(fail(format!("unknown action {:?}", action))),
+            };
+            triggers.push(trigger);
+        }
+        Ok(triggers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_actions() {
+        let mut triggers = Trigger::parse("# answer the prompt\nWhat do you do\\? => send look\n").unwrap();
+        assert_eq!(triggers[0].fire("What do you do?", 0), Some("look\n".to_string()));
+        assert_eq!(triggers[0].fire("Taken.", 0), None);
+        assert!(Trigger::parse("What => dance").is_err());
+        assert!(Trigger::parse("(What => print").is_err());
+    }
+}
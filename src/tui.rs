@@ -0,0 +1,1300 @@
+// `synacor tui`: the game and the machine on one screen. The game's output
+// and the line being typed take up the left; the registers, the stack and
+// the code around the program counter are on the right, all kept up to date
+// while the program runs. Under the game are watched expressions, lit up
+// when they change, and memory in hex and as text, with the words written
+// lately picked out, or a map of the rooms seen so far (see map.rs). Stops at breakpoints, faults and saves go in a log of
+// their own under the code, timed in instructions. Keys:
+//   typing, Backspace, Enter  edit and send a line of input
+//   Tab                       switch between the game, memory, code,
+//                             breakpoints, watches and the log
+//   arrows, PgUp/PgDn, Home/End  move around memory or code; moving in the
+//                             code stops it following the program counter
+//   g                         in memory or code, go to an address (Enter to go)
+//   m                         in memory, switch to the map and back; arrows
+//                             move the map about
+//   b                         in code, set or clear a breakpoint
+//   f                         in code, follow the program counter again
+//   a, Enter, Space, d        in breakpoints, add, change, turn on or off and
+//                             delete breakpoints and watchpoints (see
+//                             debugger.rs); a form takes the kind, address
+//                             and condition, Enter saves it and Ctrl+G
+//                             gives up
+//   a, d                      in watches, add an expression (see
+//                             debugger.rs) or delete one
+//   arrows, PgUp/PgDn, End    in the log, scroll back and forth
+//   Ctrl+S, Ctrl+L            save to or load from the quick save slot
+//   Ctrl+P                    pause or carry on
+//   Ctrl+N                    run one instruction while paused
+//   Ctrl+Q or Ctrl+C          quit
+// `synacor inspect` shows a core file here read-only: the machine can be
+// looked over but not run, typed into, saved or loaded.
+// The screen is drawn with plain escape sequences (see screen.rs).
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::io::prelude::*;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use coredump::Core;
+use debugger::{Debugger, Kind, Point, Stop};
+use disasm;
+use map::Map;
+use monitor;
+use output::Buffer;
+use screen::{self, Rect, Screen, Style};
+use slots::{self, Slots};
+use synacor::{RunOutcome, Synacor};
+use terminal::{self, RawMode};
+use trace::{self, Step, Tracer};
+
+// The name the frontend watches the machine under (see Synacor::set_tracer).
+pub const NAME: &str = "tui";
+
+// How many instructions run between looks at the keyboard, and how often
+// the screen is redrawn while running.
+const CHUNK: u64 = 20_000;
+const FRAME: Duration = Duration::from_millis(40);
+// How many lines of the game's output are kept.
+const SCROLLBACK: usize = 2000;
+// The size to draw at if the terminal won't say.
+const DEFAULT_SIZE: (usize, usize) = (80, 24);
+const WORDS: usize = 32768;
+// Words written within this many instructions are picked out.
+const RECENT: u64 = 100_000;
+// The size of a room on the map, and of the gap between rooms.
+const ROOM: (usize, usize) = (14, 3);
+const GAP: (usize, usize) = (2, 1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Tab,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    // Ctrl and a letter, given in lower case.
+    Ctrl(char),
+    Ignored,
+}
+
+fn next_byte<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0; 1];
+    match reader.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+pub fn read_key<R: Read>(reader: &mut R) -> io::Result<Option<Key>> {
+    let byte = match next_byte(reader)? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        8 | 0x7f => Key::Backspace,
+        1..=26 => Key::Ctrl((b'a' + byte - 1) as char),
+        0x1b => {
+            if next_byte(reader)? != Some(b'[') {
+                return Ok(Some(Key::Ignored));
+            }
+            match next_byte(reader)? {
+                Some(b'A') => Key::Up,
+                Some(b'B') => Key::Down,
+                Some(b'C') => Key::Right,
+                Some(b'D') => Key::Left,
+                Some(b'H') => Key::Home,
+                Some(b'F') => Key::End,
+                Some(digit @ b'1'..=b'8') => match (digit, next_byte(reader)?) {
+                    (b'1', Some(b'~')) | (b'7', Some(b'~')) => Key::Home,
+                    (b'4', Some(b'~')) | (b'8', Some(b'~')) => Key::End,
+                    (b'5', Some(b'~')) => Key::PageUp,
+                    (b'6', Some(b'~')) => Key::PageDown,
+                    _ => Key::Ignored,
+                },
+                _ => Key::Ignored,
+            }
+        }
+        0x20..=0x7e => Key::Char(byte as char),
+        _ => Key::Ignored,
+    };
+    Ok(Some(key))
+}
+
+// Reads keys on a thread of their own, so the machine can run meanwhile.
+fn keys() -> Receiver<Key> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        while let Ok(Some(key)) = read_key(&mut stdin) {
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// The terminal's alternate screen with the cursor hidden, until dropped.
+struct Fullscreen;
+
+impl Fullscreen {
+    fn enter() -> io::Result<Fullscreen> {
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l")?;
+        stdout.flush()?;
+        Ok(Fullscreen)
+    }
+}
+
+impl Drop for Fullscreen {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+    }
+}
+
+// Remembers when each word of memory was last written, counting from 1 so
+// that 0 is never.
+struct Writes(Rc<RefCell<Vec<u64>>>);
+
+impl Tracer for Writes {
+    fn step(&mut self, step: &Step) {
+        if step.opcode == 16 {
+            if let Some(written) = self.0.borrow_mut().get_mut(step.value(step.words[0]) as usize) {
+                *written = step.count + 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Focus {
+    Game,
+    Memory,
+    Code,
+    Points,
+    Watches,
+    Events,
+}
+
+// Edits the address being typed after `g`, returning it once Enter is
+// pressed. Anything but a character or Backspace gives up.
+fn goto_key(goto: &mut Option<String>, key: Key) -> Option<u16> {
+    let mut typed = goto.take()?;
+    match key {
+        Key::Char(character) => typed.push(character),
+        Key::Backspace => {
+            typed.pop();
+        }
+        Key::Enter => return trace::address(typed.trim()).ok().map(|address| address.min(WORDS as u16 - 1)),
+        _ => return None,
+    }
+    *goto = Some(typed);
+    None
+}
+
+// Where the memory pane is. The pane's shape is only known once it's
+// drawn, so drawing keeps it up to date for the keys to go by.
+#[derive(Default)]
+struct MemoryView {
+    cursor: u16,
+    top: Cell<u16>,
+    columns: Cell<usize>,
+    rows: Cell<usize>,
+    // The address being typed after `g`.
+    goto: Option<String>,
+}
+
+impl MemoryView {
+    fn key(&mut self, key: Key) {
+        if self.goto.is_some() {
+            if let Some(address) = goto_key(&mut self.goto, key) {
+                self.cursor = address;
+            }
+            return;
+        }
+        let (columns, page) = (self.columns.get().max(1) as i64, (self.columns.get() * self.rows.get()).max(1) as i64);
+        let moved = match key {
+            Key::Left => -1,
+            Key::Right => 1,
+            Key::Up => -columns,
+            Key::Down => columns,
+            Key::PageUp => -page,
+            Key::PageDown => page,
+            Key::Home => -(WORDS as i64),
+            Key::End => WORDS as i64,
+            Key::Char('g') => {
+                self.goto = Some(String::new());
+                0
+            }
+            _ => 0,
+        };
+        self.cursor = (self.cursor as i64 + moved).clamp(0, WORDS as i64 - 1) as u16;
+    }
+}
+
+// The code pane follows the program counter until moved about in, when it
+// stays put around its own cursor.
+#[derive(Default)]
+struct CodeView {
+    cursor: Option<u16>,
+    rows: Cell<usize>,
+    goto: Option<String>,
+}
+
+impl CodeView {
+    fn key(&mut self, key: Key, synacor: &Synacor, debugger: &mut Debugger) {
+        let read = |address: u16| synacor.memory(address);
+        let cursor = self.cursor.unwrap_or_else(|| synacor.program_counter());
+        if self.goto.is_some() {
+            if let Some(address) = goto_key(&mut self.goto, key) {
+                self.cursor = Some(address);
+            }
+            return;
+        }
+        let page = self.rows.get().max(1);
+        let down = |count: usize| (0..count).fold(cursor, |at, _| (at + disasm::instruction(read, at).1).min(WORDS as u16 - 1));
+        let up = |count: usize| disasm::preceding(read, cursor, count).first().cloned().unwrap_or(cursor);
+        self.cursor = Some(match key {
+            Key::Up => up(1),
+            Key::Down => down(1),
+            Key::PageUp => up(page),
+            Key::PageDown => down(page),
+            Key::Home => 0,
+            Key::End => WORDS as u16 - 1,
+            Key::Char('g') => {
+                self.goto = Some(String::new());
+                cursor
+            }
+            Key::Char('b') => {
+                debugger.toggle_break(cursor);
+                cursor
+            }
+            Key::Char('f') => {
+                self.cursor = None;
+                return;
+            }
+            _ => return,
+        });
+    }
+}
+
+// The form for adding a point or changing one.
+struct Form {
+    // The point being changed, or None for a new one.
+    editing: Option<usize>,
+    kind: Kind,
+    address: String,
+    condition: String,
+    // The kind, the address or the condition.
+    field: usize,
+    error: Option<String>,
+}
+
+impl Form {
+    fn text(&mut self) -> Option<&mut String> {
+        match self.field {
+            1 => Some(&mut self.address),
+            2 => Some(&mut self.condition),
+            _ => None,
+        }
+    }
+    // Handles a key, returning true once the form is done with.
+    fn key(&mut self, key: Key, debugger: &mut Debugger) -> bool {
+        match key {
+            Key::Ctrl('g') => return true,
+            Key::Enter => return self.save(debugger),
+            Key::Tab | Key::Down => self.field = (self.field + 1) % 3,
+            Key::Up => self.field = (self.field + 2) % 3,
+            Key::Left | Key::Right | Key::Char(' ') if self.field == 0 => {
+                let at = Kind::ALL.iter().position(|&kind| kind == self.kind).unwrap_or(0);
+                let by = if key == Key::Left { Kind::ALL.len() - 1 } else { 1 };
+                self.kind = Kind::ALL[(at + by) % Kind::ALL.len()];
+            }
+            Key::Backspace => {
+                self.text().map(String::pop);
+            }
+            Key::Char(character) => {
+                if let Some(text) = self.text() {
+                    text.push(character);
+                }
+            }
+            _ => (),
+        }
+        false
+    }
+    fn save(&mut self, debugger: &mut Debugger) -> bool {
+        let point = trace::address(self.address.trim()).and_then(|address| Point::new(self.kind, address, &self.condition));
+        match (point, self.editing) {
+            (Ok(point), Some(index)) => {
+                let old = &mut debugger.points[index];
+                *old = Point { enabled: old.enabled, hits: old.hits, ..point };
+            }
+            (Ok(point), None) => debugger.points.push(point),
+            (Err(err), _) => {
+                self.error = Some(err);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// The list of breakpoints and watchpoints.
+#[derive(Default)]
+struct PointsView {
+    selected: usize,
+    form: Option<Form>,
+}
+
+impl PointsView {
+    // New points start at `address`.
+    fn key(&mut self, key: Key, debugger: &mut Debugger, address: u16) {
+        if let Some(mut form) = self.form.take() {
+            if !form.key(key, debugger) {
+                self.form = Some(form);
+            }
+            return;
+        }
+        let points = &mut debugger.points;
+        self.selected = self.selected.min(points.len().saturating_sub(1));
+        match key {
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => self.selected = (self.selected + 1).min(points.len().saturating_sub(1)),
+            Key::Char('a') => {
+                let address = address.to_string();
+                self.form = Some(Form { editing: None, kind: Kind::Break, address, condition: String::new(), field: 1, error: None });
+            }
+            Key::Enter | Key::Char('e') if self.selected < points.len() => {
+                let point = &points[self.selected];
+                let condition = point.condition.as_ref().map_or(String::new(), |(text, _)| text.clone());
+                let address = point.address.to_string();
+                self.form = Some(Form { editing: Some(self.selected), kind: point.kind, address, condition, field: 1, error: None });
+            }
+            Key::Char(' ') if self.selected < points.len() => points[self.selected].enabled = !points[self.selected].enabled,
+            Key::Char('d') | Key::Backspace if self.selected < points.len() => {
+                points.remove(self.selected);
+                self.selected = self.selected.min(points.len().saturating_sub(1));
+            }
+            _ => (),
+        }
+    }
+}
+
+#[derive(Default)]
+struct WatchView {
+    selected: usize,
+    // The expression being typed after `a`, and what was wrong with it.
+    adding: Option<String>,
+    error: Option<String>,
+}
+
+impl WatchView {
+    fn key(&mut self, key: Key, debugger: &mut Debugger, synacor: &Synacor) {
+        if let Some(mut adding) = self.adding.take() {
+            match key {
+                Key::Ctrl('g') => return,
+                Key::Enter => match debugger.watch(&adding, synacor) {
+                    Ok(()) => {
+                        self.selected = debugger.watches.len() - 1;
+                        return;
+                    }
+                    Err(err) => self.error = Some(err),
+                },
+                Key::Backspace => {
+                    adding.pop();
+                }
+                Key::Char(character) => adding.push(character),
+                _ => (),
+            }
+            self.adding = Some(adding);
+            return;
+        }
+        let last = debugger.watches.len().saturating_sub(1);
+        self.selected = self.selected.min(last);
+        match key {
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => self.selected = (self.selected + 1).min(last),
+            Key::Char('a') => {
+                self.adding = Some(String::new());
+                self.error = None;
+            }
+            Key::Char('d') | Key::Backspace if self.selected < debugger.watches.len() => {
+                debugger.watches.remove(self.selected);
+                self.selected = self.selected.min(debugger.watches.len().saturating_sub(1));
+            }
+            _ => (),
+        }
+    }
+}
+
+// The map, when it's shown in place of memory, kept centred on where the
+// player is but for being moved about.
+#[derive(Default)]
+struct MapView {
+    shown: bool,
+    moved: (i32, i32),
+}
+
+impl MapView {
+    fn key(&mut self, key: Key) {
+        let (x, y) = self.moved;
+        self.moved = match key {
+            Key::Left => (x - 1, y),
+            Key::Right => (x + 1, y),
+            Key::Up => (x, y - 1),
+            Key::Down => (x, y + 1),
+            Key::Home => (0, 0),
+            _ => self.moved,
+        };
+    }
+}
+
+struct Event {
+    // The instruction count when it happened.
+    at: u64,
+    text: String,
+    alert: bool,
+}
+
+#[derive(Default)]
+struct EventView {
+    // How many rows back from the latest event the view is.
+    back: usize,
+    // How many events had been seen when the log last had the focus.
+    seen: usize,
+    rows: Cell<usize>,
+}
+
+impl EventView {
+    fn key(&mut self, key: Key, events: usize) {
+        let (page, most) = (self.rows.get().max(1), events.saturating_sub(self.rows.get()));
+        self.back = match key {
+            Key::Up => self.back + 1,
+            Key::Down => self.back.saturating_sub(1),
+            Key::PageUp => self.back + page,
+            Key::PageDown => self.back.saturating_sub(page),
+            Key::Home => most,
+            Key::End => 0,
+            _ => self.back,
+        }
+        .min(most);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum State {
+    Running,
+    Paused,
+    // Paused at a breakpoint or watchpoint, as this says.
+    Break(String),
+    // The program wants a line of input.
+    Waiting,
+    // Halted or faulted, as the outcome says.
+    Stopped(String),
+}
+
+impl State {
+    fn name(&self) -> &str {
+        match *self {
+            State::Running => "running",
+            State::Paused => "paused",
+            State::Waiting => "waiting for input",
+            State::Break(ref point) | State::Stopped(ref point) => point,
+        }
+    }
+}
+
+pub struct Tui {
+    synacor: Synacor,
+    output: Buffer,
+    // The game's output: whole lines, then the one being printed.
+    lines: Vec<String>,
+    partial: String,
+    typed: String,
+    state: State,
+    // The registers at the last frame, to show what changed.
+    registers: [u16; 8],
+    writes: Rc<RefCell<Vec<u64>>>,
+    focus: Focus,
+    memory: MemoryView,
+    code: CodeView,
+    debugger: Debugger,
+    points: PointsView,
+    watches: WatchView,
+    events: Vec<Event>,
+    log: EventView,
+    slots: Slots,
+    map: Rc<RefCell<Map>>,
+    map_view: MapView,
+    shown: Option<Screen>,
+    // Looking at a core file (see coredump.rs): nothing runs, is typed,
+    // saved or loaded.
+    read_only: bool,
+}
+
+impl Tui {
+    // Plays on `synacor`, which has to print to `output` and yield when it
+    // runs out of input.
+    pub fn new(mut synacor: Synacor, output: Buffer) -> Tui {
+        let registers = synacor.registers();
+        let writes = Rc::new(RefCell::new(vec![0; WORDS]));
+        synacor.set_tracer(NAME, Some(Box::new(Writes(writes.clone()))));
+        Tui {
+            synacor,
+            output,
+            lines: Vec::new(),
+            partial: String::new(),
+            typed: String::new(),
+            state: State::Running,
+            registers,
+            writes,
+            focus: Focus::Game,
+            memory: MemoryView::default(),
+            code: CodeView::default(),
+            debugger: Debugger::default(),
+            points: PointsView::default(),
+            watches: WatchView::default(),
+            events: Vec::new(),
+            log: EventView::default(),
+            slots: Slots::new(slots::DIR),
+            map: Rc::new(RefCell::new(Map::default())),
+            map_view: MapView::default(),
+            shown: None,
+            read_only: false,
+        }
+    }
+    // Shows a core file's machine as it was when it faulted, read-only, with
+    // the instructions before the fault and the fault itself in the log.
+    pub fn with_core(mut self, core: &Core) -> Tui {
+        let read = |address: u16| core.snapshot.memory.word(address as usize);
+        for &address in &core.trace {
+            let text = format!("ran {:5}  {}", address, disasm::instruction(read, address).0);
+            self.log(text, false);
+        }
+        self.log(core.fault.clone(), true);
+        self.state = State::Stopped(core.fault.clone());
+        self.read_only = true;
+        self
+    }
+    // Saves and loads go to `slots` rather than the usual directory.
+    pub fn with_slots(mut self, slots: Slots) -> Tui {
+        self.slots = slots;
+        self
+    }
+    // Maps onto `map`, which may have rooms in it already.
+    pub fn with_map(mut self, map: Rc<RefCell<Map>>) -> Tui {
+        self.map = map;
+        self
+    }
+    fn log(&mut self, text: String, alert: bool) {
+        self.events.push(Event { at: self.synacor.instructions(), text, alert });
+        if self.events.len() > SCROLLBACK {
+            self.events.drain(..self.events.len() - SCROLLBACK);
+        }
+    }
+    fn stopped(&mut self, outcome: RunOutcome) {
+        self.state = match outcome {
+            RunOutcome::InputNeeded => State::Waiting,
+            RunOutcome::Halted | RunOutcome::Faulted(_) => State::Stopped(outcome.to_string()),
+            _ => return,
+        };
+        if let State::Stopped(ref text) = self.state {
+            let (text, alert) = (text.clone(), matches!(outcome, RunOutcome::Faulted(_)));
+            self.log(text, alert);
+        }
+    }
+    fn save(&mut self) {
+        match self.slots.save(&self.synacor, monitor::QUICK, "") {
+            Ok(path) => self.log(format!("saved to {}", path.display()), false),
+            Err(err) => self.log(format!("could not save to {}: {}", self.slots.path(monitor::QUICK).display(), err), true),
+        }
+    }
+    fn load(&mut self) {
+        match self.slots.load(monitor::QUICK).map(|(path, snapshot)| (self.synacor.restore(&snapshot), path)) {
+            Ok((Ok(()), path)) => {
+                self.log(format!("loaded {}", path.display()), false);
+                self.map.borrow_mut().lost();
+                self.state = State::Running;
+                self.debugger.refresh(&self.synacor);
+            }
+            Ok((Err(err), path)) => self.log(format!("could not restore {}: {}", path.display(), err), true),
+            Err(err) => self.log(format!("could not load {}: {}", self.slots.path(monitor::QUICK).display(), err), true),
+        }
+    }
+    fn collect_output(&mut self) {
+        let text = self.output.text();
+        self.output.clear();
+        for character in text.chars() {
+            match character {
+                '\n' => {
+                    self.map.borrow_mut().output_line(&self.partial);
+                    self.lines.push(std::mem::take(&mut self.partial));
+                }
+                _ => self.partial.push(character),
+            }
+        }
+        if self.lines.len() > SCROLLBACK {
+            self.lines.drain(..self.lines.len() - SCROLLBACK);
+        }
+    }
+    fn run_chunk(&mut self) {
+        match self.debugger.run_for(&mut self.synacor, CHUNK) {
+            Stop::Hit(index) => {
+                let text = format!("stopped at {}", self.debugger.points[index].describe());
+                self.log(text.clone(), true);
+                self.state = State::Break(text);
+            }
+            Stop::Outcome(RunOutcome::BudgetExceeded) => (),
+            Stop::Outcome(outcome) => self.stopped(outcome),
+        }
+        self.debugger.refresh(&self.synacor);
+        self.collect_output();
+    }
+    // Handles a key, returning false to quit.
+    pub fn key(&mut self, key: Key) -> bool {
+        if self.focus == Focus::Events {
+            self.log.seen = self.events.len();
+        }
+        match key {
+            Key::Ctrl('q') | Key::Ctrl('c') => return false,
+            Key::Ctrl('n') | Key::Ctrl('s') | Key::Ctrl('l') if self.read_only => (),
+            _ if self.read_only && self.focus == Focus::Game => (),
+            Key::Ctrl('p') => match self.state {
+                State::Running => self.state = State::Paused,
+                State::Paused | State::Break(_) => self.state = State::Running,
+                _ => (),
+            },
+            Key::Ctrl('n') if matches!(self.state, State::Paused | State::Break(_)) => {
+                self.state = State::Paused;
+                if let Err(outcome) = self.debugger.step(&mut self.synacor) {
+                    self.stopped(outcome);
+                }
+                self.debugger.refresh(&self.synacor);
+                self.collect_output();
+            }
+            Key::Ctrl('s') => self.save(),
+            Key::Ctrl('l') => self.load(),
+            Key::Tab if self.points.form.is_none() && self.watches.adding.is_none() => {
+                self.focus = match self.focus {
+                    Focus::Game => Focus::Memory,
+                    Focus::Memory => Focus::Code,
+                    Focus::Code => Focus::Points,
+                    Focus::Points => Focus::Watches,
+                    Focus::Watches => Focus::Events,
+                    Focus::Events => Focus::Game,
+                }
+            }
+            Key::Char('m') if self.focus == Focus::Memory && self.memory.goto.is_none() => self.map_view.shown = !self.map_view.shown,
+            _ if self.focus == Focus::Memory && self.map_view.shown => self.map_view.key(key),
+            _ if self.focus == Focus::Memory => self.memory.key(key),
+            _ if self.focus == Focus::Code => self.code.key(key, &self.synacor, &mut self.debugger),
+            _ if self.focus == Focus::Points => {
+                let address = self.code.cursor.unwrap_or_else(|| self.synacor.program_counter());
+                self.points.key(key, &mut self.debugger, address);
+            }
+            _ if self.focus == Focus::Watches => self.watches.key(key, &mut self.debugger, &self.synacor),
+            _ if self.focus == Focus::Events => self.log.key(key, self.events.len()),
+            Key::Enter => {
+                let line = std::mem::take(&mut self.typed);
+                self.map.borrow_mut().command(&line);
+                self.synacor.push_input(&format!("{}\n", line));
+                self.partial += &line;
+                self.lines.push(std::mem::take(&mut self.partial));
+                if self.state == State::Waiting {
+                    self.state = State::Running;
+                }
+            }
+            Key::Backspace => {
+                self.typed.pop();
+            }
+            Key::Char(character) => self.typed.push(character),
+            _ => (),
+        }
+        true
+    }
+    fn draw_game(&self, screen: &mut Screen, rect: Rect) {
+        screen.frame(rect, "game", self.focus == Focus::Game);
+        let inner = rect.inner();
+        let current = format!("{}{}", self.partial, self.typed);
+        let mut shown = screen::wrap(&current, inner.width);
+        for line in self.lines.iter().rev() {
+            if shown.len() >= inner.height {
+                break;
+            }
+            let mut wrapped = screen::wrap(line, inner.width);
+            wrapped.append(&mut shown);
+            shown = wrapped;
+        }
+        let skip = shown.len().saturating_sub(inner.height);
+        for (row, line) in shown[skip..].iter().enumerate() {
+            screen.line(inner, row, line, Style::Plain);
+        }
+        // The cursor, after what's been typed.
+        let last = shown.len() - skip - 1;
+        let column = shown[shown.len() - 1].chars().count();
+        let (x, y) = if column < inner.width { (inner.x + column, inner.y + last) } else { (inner.x, inner.y + last + 1) };
+        screen.put(inner, x, y, " ", Style::Selected);
+    }
+    fn draw_memory(&self, screen: &mut Screen, rect: Rect) {
+        let view = &self.memory;
+        let title = match view.goto {
+            Some(ref goto) => format!("memory: go to {}_", goto),
+            None => format!("memory: {} = {}", view.cursor, self.synacor.memory(view.cursor)),
+        };
+        screen.frame(rect, &title, self.focus == Focus::Memory);
+        let inner = rect.inner();
+        // An address, then each word in hex and as a character.
+        let columns = [16, 8, 4, 2, 1].iter().cloned().find(|&columns| 7 + columns * 6 <= inner.width).unwrap_or(1);
+        let rows = inner.height.max(1);
+        view.columns.set(columns);
+        view.rows.set(rows);
+        // Scrolls just far enough to keep the cursor in sight.
+        let (cursor_row, mut top_row) = (view.cursor as usize / columns, view.top.get() as usize / columns);
+        if cursor_row < top_row {
+            top_row = cursor_row;
+        } else if cursor_row >= top_row + rows {
+            top_row = cursor_row + 1 - rows;
+        }
+        view.top.set((top_row * columns) as u16);
+        let (now, writes) = (self.synacor.instructions(), self.writes.borrow());
+        for row in 0..inner.height {
+            let start = (top_row + row) * columns;
+            if start >= WORDS {
+                break;
+            }
+            let y = inner.y + row;
+            screen.put(inner, inner.x, y, &format!("{:5}", start), Style::Dim);
+            for (column, address) in (start..(start + columns).min(WORDS)).enumerate() {
+                let word = self.synacor.memory(address as u16);
+                let style = if address == view.cursor as usize {
+                    Style::Selected
+                } else if writes[address] > 0 && now - writes[address] < RECENT {
+                    Style::Changed
+                } else {
+                    Style::Plain
+                };
+                let character = match word {
+                    32..=126 => word as u8 as char,
+                    _ => '.',
+                };
+                screen.put(inner, inner.x + 6 + column * 5, y, &format!("{:04x}", word), style);
+                screen.put(inner, inner.x + 7 + columns * 5 + column, y, &character.to_string(), style);
+            }
+        }
+    }
+    // Each room as a box with its title, the one the player is in picked out,
+    // and lines between rooms side by side that have a passage between them.
+    fn draw_map(&self, screen: &mut Screen, rect: Rect) {
+        let map = self.map.borrow();
+        let here = map.here();
+        let title = match here {
+            Some(here) => format!("map: {} of {}", map.rooms()[here].title, map.rooms().len()),
+            None => format!("map: {} rooms", map.rooms().len()),
+        };
+        screen.frame(rect, &title, self.focus == Focus::Memory);
+        let inner = rect.inner();
+        let here = match here {
+            Some(here) => here,
+            None => return screen.line(inner, 0, "Where you are isn't known yet; try looking around.", Style::Dim),
+        };
+        let grid = map.grid(here);
+        let (width, height) = ((ROOM.0 + GAP.0) as i32, (ROOM.1 + GAP.1) as i32);
+        let (moved_x, moved_y) = self.map_view.moved;
+        let left = inner.x as i32 + (inner.width as i32 - ROOM.0 as i32) / 2 - moved_x * width;
+        let top = inner.y as i32 + (inner.height as i32 - ROOM.1 as i32) / 2 - moved_y * height;
+        // Text at a spot that may be off the screen.
+        let mut put = |x: i32, y: i32, text: &str, style: Style| {
+            if y >= 0 {
+                let text: String = text.chars().skip((-x).max(0) as usize).collect();
+                screen.put(inner, x.max(0) as usize, y as usize, &text, style);
+            }
+        };
+        for (from, _, to) in map.passages() {
+            if let (Some(&(x, y)), Some(&(to_x, to_y))) = (grid.get(&from), grid.get(&to)) {
+                let (x, y) = (left + x.min(to_x) * width, top + y.min(to_y) * height);
+                match (to_x - grid[&from].0, to_y - grid[&from].1) {
+                    (1, 0) | (-1, 0) => put(x + ROOM.0 as i32, y + 1, &"─".repeat(GAP.0), Style::Dim),
+                    (0, 1) | (0, -1) => (0..GAP.1 as i32).for_each(|row| put(x + ROOM.0 as i32 / 2, y + ROOM.1 as i32 + row, "│", Style::Dim)),
+                    _ => (),
+                }
+            }
+        }
+        for (&room, &(x, y)) in &grid {
+            let (x, y) = (left + x * width, top + y * height);
+            let style = if room == here { Style::Changed } else { Style::Plain };
+            let name: String = map.rooms()[room].title.chars().take(ROOM.0 - 2).collect();
+            put(x, y, &format!("┌{}┐", "─".repeat(ROOM.0 - 2)), style);
+            put(x, y + 1, &format!("│{:^1$}│", name, ROOM.0 - 2), style);
+            put(x, y + 2, &format!("└{}┘", "─".repeat(ROOM.0 - 2)), style);
+        }
+    }
+    fn draw_registers(&self, screen: &mut Screen, rect: Rect) {
+        screen.frame(rect, "machine", false);
+        let inner = rect.inner();
+        let registers = self.synacor.registers();
+        for row in 0..4 {
+            for &index in &[row, row + 4] {
+                let style = if registers[index] != self.registers[index] { Style::Changed } else { Style::Plain };
+                let x = inner.x + if index < 4 { 0 } else { 10 };
+                screen.put(inner, x, inner.y + row, &format!("r{} {:5}", index, registers[index]), style);
+            }
+        }
+        let counters = format!("pc {:5}  {} run", self.synacor.program_counter(), self.synacor.instructions());
+        screen.line(inner, 4, &counters, Style::Plain);
+    }
+    fn draw_stack(&self, screen: &mut Screen, rect: Rect) {
+        let stack = self.synacor.stack();
+        screen.frame(rect, &format!("stack: {}", stack.len()), false);
+        let inner = rect.inner();
+        for (row, word) in stack.iter().rev().take(inner.height).enumerate() {
+            screen.line(inner, row, &format!("{:5}  {:5}", stack.len() - 1 - row, word), Style::Plain);
+        }
+    }
+    // The code around the program counter, or the cursor once moved, with
+    // `>` at the program counter, `*` at breakpoints (`-` if they're off)
+    // and labels above the instructions jumped or called to.
+    fn draw_code(&self, screen: &mut Screen, rect: Rect) {
+        let title = match (&self.code.goto, self.code.cursor) {
+            (Some(goto), _) => format!("code: go to {}_", goto),
+            (None, Some(_)) => "code (held)".to_string(),
+            (None, None) => "code".to_string(),
+        };
+        screen.frame(rect, &title, self.focus == Focus::Code);
+        let inner = rect.inner();
+        self.code.rows.set(inner.height);
+        let read = |address: u16| self.synacor.memory(address);
+        let labels = disasm::labels(read, WORDS);
+        let pc = self.synacor.program_counter();
+        let centre = self.code.cursor.unwrap_or(pc);
+        let mut lines = Vec::new();
+        let mut address = disasm::preceding(read, centre, inner.height).first().cloned().unwrap_or(centre);
+        let mut at_centre = None;
+        while at_centre.is_none_or(|at| lines.len() < at + inner.height) && (address as usize) < WORDS {
+            if let Some(label) = labels.get(&address) {
+                lines.push((format!("{}:", label), Style::Dim));
+            }
+            if address == centre {
+                at_centre = Some(lines.len());
+            }
+            let (text, length) = disasm::instruction(read, address);
+            let breakpoint = self.debugger.break_at(address);
+            let mark = match breakpoint {
+                Some(true) => '*',
+                Some(false) => '-',
+                None => ' ',
+            };
+            let mark = format!("{}{}", mark, if address == pc { '>' } else { ' ' });
+            let style = match breakpoint {
+                _ if address == centre => Style::Selected,
+                Some(true) => Style::Alert,
+                _ => Style::Plain,
+            };
+            lines.push((format!("{}{:5}  {}", mark, address, text), style));
+            address += length;
+        }
+        let skip = at_centre.unwrap_or(0).saturating_sub(inner.height / 2).min(lines.len().saturating_sub(inner.height));
+        for (row, (line, style)) in lines.iter().skip(skip).take(inner.height).enumerate() {
+            screen.line(inner, row, line, *style);
+        }
+    }
+    fn draw_points(&self, screen: &mut Screen, rect: Rect) {
+        let inner = rect.inner();
+        if let Some(ref form) = self.points.form {
+            let title = match (&form.error, form.editing) {
+                (Some(error), _) => error.clone(),
+                (None, Some(index)) => format!("change point {}", index + 1),
+                (None, None) => "new point".to_string(),
+            };
+            screen.frame(rect, &title, true);
+            let fields = [("kind", format!("< {} >", form.kind.name())), ("address", form.address.clone()), ("if", form.condition.clone())];
+            for (row, (name, value)) in fields.iter().enumerate() {
+                let (style, cursor) = match row {
+                    _ if row != form.field => (Style::Plain, ""),
+                    0 => (Style::Selected, ""),
+                    _ => (Style::Selected, "_"),
+                };
+                screen.line(inner, row, &format!("{:>7} {}{}", name, value, cursor), style);
+            }
+            return;
+        }
+        let (points, focused) = (&self.debugger.points, self.focus == Focus::Points);
+        screen.frame(rect, &format!("break and watch: {}", points.len()), focused);
+        if points.is_empty() {
+            screen.line(inner, 0, "none yet; a adds one", Style::Dim);
+        }
+        let selected = self.points.selected.min(points.len().saturating_sub(1));
+        let skip = (selected + 1).saturating_sub(inner.height);
+        for (row, (index, point)) in points.iter().enumerate().skip(skip).take(inner.height).enumerate() {
+            let text = format!("{} {:2} {}  {} hits", if point.enabled { "[x]" } else { "[ ]" }, index + 1, point.describe(), point.hits);
+            let style = match () {
+                _ if focused && index == selected => Style::Selected,
+                _ if !point.enabled => Style::Dim,
+                _ => Style::Plain,
+            };
+            screen.line(inner, row, &text, style);
+        }
+    }
+    fn draw_watches(&self, screen: &mut Screen, rect: Rect) {
+        let view = &self.watches;
+        let title = match (&view.adding, &view.error) {
+            (Some(_), Some(error)) => error.clone(),
+            (Some(_), None) => "watch: new".to_string(),
+            (None, _) => format!("watch: {}", self.debugger.watches.len()),
+        };
+        let focused = self.focus == Focus::Watches;
+        screen.frame(rect, &title, focused);
+        let inner = rect.inner();
+        let watches = &self.debugger.watches;
+        // Room at the bottom for the one being typed.
+        let (rows, selected) = (inner.height.saturating_sub(view.adding.is_some() as usize), view.selected.min(watches.len().saturating_sub(1)));
+        let skip = if view.adding.is_some() { watches.len().saturating_sub(rows) } else { (selected + 1).saturating_sub(rows) };
+        for (row, (index, watch)) in watches.iter().enumerate().skip(skip).take(rows).enumerate() {
+            let value = watch.value.map_or("?".to_string(), |value| value.to_string());
+            let style = match () {
+                _ if focused && view.adding.is_none() && index == selected => Style::Selected,
+                _ if watch.changed => Style::Changed,
+                _ => Style::Plain,
+            };
+            screen.line(inner, row, &format!("{} = {}", watch.text, value), style);
+        }
+        match view.adding {
+            Some(ref adding) => screen.line(inner, watches.len().min(rows), &format!("{}_", adding), Style::Selected),
+            None if watches.is_empty() => screen.line(inner, 0, "none yet; a adds one", Style::Dim),
+            None => (),
+        }
+    }
+    fn draw_events(&self, screen: &mut Screen, rect: Rect) {
+        let focused = self.focus == Focus::Events;
+        let unseen = if focused { 0 } else { self.events.len().saturating_sub(self.log.seen) };
+        let title = match unseen {
+            0 => format!("events: {}", self.events.len()),
+            _ => format!("events: {}, {} new", self.events.len(), unseen),
+        };
+        screen.frame(rect, &title, focused || unseen > 0);
+        let inner = rect.inner();
+        self.log.rows.set(inner.height);
+        let end = self.events.len().saturating_sub(self.log.back);
+        let start = end.saturating_sub(inner.height);
+        for (row, event) in self.events[start..end].iter().enumerate() {
+            let style = if event.alert { Style::Alert } else { Style::Plain };
+            screen.line(inner, row, &format!("{:>9}  {}", event.at, event.text), style);
+        }
+    }
+    // Lays the panes out on a screen `width` by `height`.
+    pub fn frame(&self, width: usize, height: usize) -> Screen {
+        let mut screen = Screen::new(width, height);
+        let (body, status) = screen.area().split_top(height.saturating_sub(1));
+        let (left, side) = body.split_left(width * 3 / 5);
+        let memory_rows = if left.height >= 12 { left.height * 2 / 5 } else { 0 };
+        let (game, memory) = left.split_top(left.height - memory_rows);
+        let (game, watches) = game.split_top(game.height.saturating_sub(if game.height >= 10 { 5 } else { 0 }));
+        let (registers, rest) = side.split_top(7);
+        let (stack, rest) = rest.split_top((rest.height / 4).max(3));
+        let (points, rest) = rest.split_top((rest.height / 4).max(5));
+        let (code, events) = rest.split_top(rest.height.saturating_sub((rest.height / 3).max(4)));
+        self.draw_game(&mut screen, game);
+        self.draw_watches(&mut screen, watches);
+        if self.map_view.shown {
+            self.draw_map(&mut screen, memory);
+        } else {
+            self.draw_memory(&mut screen, memory);
+        }
+        self.draw_registers(&mut screen, registers);
+        self.draw_stack(&mut screen, stack);
+        self.draw_code(&mut screen, code);
+        self.draw_points(&mut screen, points);
+        self.draw_events(&mut screen, events);
+        let style = if let State::Stopped(_) | State::Break(_) = self.state { Style::Alert } else { Style::Title };
+        let used = screen.put(status, 0, status.y, &format!(" {} ", self.state.name()), style);
+        let help = match self.focus {
+            Focus::Game => " Enter send  Tab memory  ^S/^L save/load  ^P pause/run  ^N step  ^Q quit",
+            Focus::Memory if self.map_view.shown => " arrows move  Home centre  m memory  Tab code  ^P pause/run  ^N step  ^Q quit",
+            Focus::Memory => " arrows/PgUp/PgDn move  g go to  m map  Tab code  ^P pause/run  ^N step  ^Q quit",
+            Focus::Code => " arrows/PgUp/PgDn move  g go to  b breakpoint  f follow  Tab breakpoints  ^P pause/run  ^N step  ^Q quit",
+            Focus::Points if self.points.form.is_some() => " Up/Down field  Left/Right kind  Enter save  ^G cancel",
+            Focus::Points => " a add  Enter change  Space on/off  d delete  Tab watches  ^P pause/run  ^N step  ^Q quit",
+            Focus::Watches if self.watches.adding.is_some() => " Enter add  ^G cancel",
+            Focus::Watches => " a add  d delete  Tab events  ^P pause/run  ^N step  ^Q quit",
+            Focus::Events => " arrows/PgUp/PgDn scroll  End latest  Tab game  ^P pause/run  ^N step  ^Q quit",
+        };
+        screen.put(status, used, status.y, help, Style::Dim);
+        screen
+    }
+    fn draw<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let (width, height) = terminal::size().unwrap_or(DEFAULT_SIZE);
+        let screen = self.frame(width, height);
+        out.write_all(screen.render(self.shown.as_ref()).as_bytes())?;
+        out.flush()?;
+        self.shown = Some(screen);
+        self.registers = self.synacor.registers();
+        Ok(())
+    }
+    // Takes over the terminal until the player quits.
+    pub fn run(&mut self) -> io::Result<()> {
+        let _raw = RawMode::enable()?;
+        let _fullscreen = Fullscreen::enter()?;
+        let keys = keys();
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let mut drawn: Option<Instant> = None;
+        loop {
+            loop {
+                match keys.try_recv() {
+                    Ok(key) if !self.key(key) => return Ok(()),
+                    Ok(_) => (),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+            if self.state == State::Running {
+                self.run_chunk();
+            }
+            if self.state != State::Running || drawn.is_none_or(|drawn| drawn.elapsed() >= FRAME) {
+                self.draw(&mut stdout)?;
+                drawn = Some(Instant::now());
+            }
+            if self.state != State::Running {
+                match keys.recv() {
+                    Ok(key) if !self.key(key) => return Ok(()),
+                    Ok(_) => (),
+                    Err(_) => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::Text;
+    use memory::Image;
+    use synacor::{Config, EofPolicy};
+
+    #[test]
+    fn reads_keys() {
+        let mut keys: &[u8] = b"a\x10\x1b[A\x1b[5~\x7f\r";
+        let mut read = Vec::new();
+        while let Some(key) = read_key(&mut keys).unwrap() {
+            read.push(key);
+        }
+        assert_eq!(read, [Key::Char('a'), Key::Ctrl('p'), Key::Up, Key::PageUp, Key::Backspace, Key::Enter]);
+    }
+
+    #[test]
+    fn plays_and_shows_the_machine() {
+        // out 'h'; out '\n'; in r0; out r0; halt
+        let words: [u16; 9] = [19, 104, 19, 10, 20, 32768, 19, 32768, 0];
+        let output = Buffer::default();
+        let config = Config { output: Box::new(output.clone()), input: Box::new(Text::default()), on_eof: EofPolicy::Yield, ..Config::default() };
+        let mut synacor = Synacor::with_config(config);
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let mut tui = Tui::new(synacor, output);
+        tui.run_chunk();
+        assert_eq!(tui.state, State::Waiting);
+        tui.key(Key::Char('x'));
+        let screen = tui.frame(60, 24);
+        assert_eq!(screen.row(1), "│h                                 ││r0     0  r4     0    │");
+        assert!(screen.row(2).starts_with("│x "));
+        assert!(screen.row(23).starts_with(" waiting for input  Enter send"));
+        assert!((0..24).any(|y| screen.row(y).contains(" >    4  in r0")));
+        tui.key(Key::Enter);
+        tui.run_chunk();
+        assert_eq!((tui.lines.clone(), tui.partial.clone()), (vec!["h".to_string(), "x".to_string()], "x".to_string()));
+        assert!(tui.frame(60, 24).row(1).contains("r0   120"));
+    }
+
+    #[test]
+    fn shows_a_core_read_only() {
+        // noop; out 40000, which is past the registers
+        let words: [u16; 3] = [21, 19, 40000];
+        let config = Config { output: Box::new(Buffer::default()), trace_depth: 4, ..Config::default() };
+        let mut synacor = Synacor::with_config(config);
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let fault = match synacor.run() {
+            RunOutcome::Faulted(err) => err.to_string(),
+            _ => panic!("the program should fault"),
+        };
+        let core = Core::new(&synacor, &fault);
+        let output = Buffer::default();
+        let mut inspected = Synacor::with_config(Config { output: Box::new(output.clone()), ..Config::default() });
+        inspected.restore(&core.snapshot).unwrap();
+        let mut tui = Tui::new(inspected, output).with_core(&core);
+        assert_eq!(tui.events.iter().map(|event| event.text.as_str()).collect::<Vec<_>>(), ["ran     0  noop", "ran     1  out <40000>", fault.as_str()]);
+        let pc = tui.synacor.program_counter();
+        for key in [Key::Char('x'), Key::Enter, Key::Ctrl('p'), Key::Ctrl('n'), Key::Ctrl('l')] {
+            tui.key(key);
+        }
+        assert_eq!((tui.typed.as_str(), tui.synacor.program_counter()), ("", pc));
+        assert_eq!(tui.state, State::Stopped(fault));
+    }
+
+    #[test]
+    fn shows_memory_and_what_was_written() {
+        // wmem 100 'A'; halt
+        let words: [u16; 4] = [16, 100, 65, 0];
+        let output = Buffer::default();
+        let mut synacor = Synacor::with_config(Config { output: Box::new(output.clone()), ..Config::default() });
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let mut tui = Tui::new(synacor, output);
+        tui.run_chunk();
+        tui.key(Key::Tab);
+        for key in [Key::Char('g'), Key::Char('1'), Key::Char('0'), Key::Char('0'), Key::Enter] {
+            tui.key(key);
+        }
+        tui.frame(80, 30);
+        tui.key(Key::Up);
+        let screen = tui.frame(80, 30);
+        assert!((0..30).any(|y| screen.row(y).contains("memory: 96 = 0")));
+        // Four words to a row in a pane 48 wide, scrolled just far enough to
+        // show the cursor.
+        let row = (0..30).find(|&y| screen.row(y).starts_with("│  100 ")).unwrap();
+        assert!(screen.row(row - 1).starts_with("│   96 0000 0000 0000 0000  ....   "));
+        assert!(screen.row(row).starts_with("│  100 0041 0000 0000 0000  A...   "));
+        assert_eq!(screen.cell(7, row - 1).1, Style::Selected);
+        assert_eq!(screen.cell(7, row).1, Style::Changed);
+        assert_eq!(screen.cell(28, row).1, Style::Changed);
+        assert_eq!(screen.cell(12, row).1, Style::Plain);
+    }
+
+    #[test]
+    fn stops_at_breakpoints_in_the_code() {
+        // jmp 2; noop; jmp 2
+        let words: [u16; 5] = [6, 2, 21, 6, 2];
+        let output = Buffer::default();
+        let mut synacor = Synacor::with_config(Config { output: Box::new(output.clone()), ..Config::default() });
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let mut tui = Tui::new(synacor, output);
+        for key in [Key::Tab, Key::Tab, Key::Down, Key::Down, Key::Char('b')] {
+            tui.key(key);
+        }
+        tui.run_chunk();
+        assert_eq!((tui.state.clone(), tui.synacor.instructions()), (State::Break("stopped at break 3".to_string()), 2));
+        let screen = tui.frame(60, 40);
+        let row = (0..40).find(|&y| screen.row(y).contains("*>    3  jmp 2")).unwrap();
+        assert!(screen.row(row - 2).contains("L_2:"));
+        assert_eq!(screen.cell(40, row).1, Style::Selected);
+        tui.key(Key::Ctrl('p'));
+        tui.run_chunk();
+        assert_eq!((tui.state.clone(), tui.synacor.instructions()), (State::Break("stopped at break 3".to_string()), 4));
+    }
+
+    #[test]
+    fn edits_points_in_a_form() {
+        // wmem 100 'A'; halt
+        let words: [u16; 4] = [16, 100, 65, 0];
+        let output = Buffer::default();
+        let mut synacor = Synacor::with_config(Config { output: Box::new(output.clone()), ..Config::default() });
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let mut tui = Tui::new(synacor, output);
+        let keys = |tui: &mut Tui, keys: &[Key], text: &str| {
+            for &key in keys.iter().chain(text.chars().map(Key::Char).collect::<Vec<_>>().iter()) {
+                tui.key(key);
+            }
+        };
+        keys(&mut tui, &[Key::Tab, Key::Tab, Key::Tab, Key::Char('a'), Key::Backspace], "100");
+        keys(&mut tui, &[Key::Up, Key::Right, Key::Right, Key::Down, Key::Down], "[100] ==");
+        assert!((0..24).any(|y| tui.frame(60, 24).row(y).contains("if [100] ==_")));
+        tui.key(Key::Enter);
+        assert!((0..24).any(|y| tui.frame(60, 24).row(y).contains("─ the expression ends")));
+        keys(&mut tui, &[], " 0");
+        tui.key(Key::Enter);
+        tui.run_chunk();
+        assert_eq!(tui.state, State::Break("stopped at write 100 if [100] == 0".to_string()));
+        assert!((0..24).any(|y| tui.frame(100, 24).row(y).contains("[x]  1 write 100 if [100] == 0  1 hits")));
+        keys(&mut tui, &[], " ");
+        assert!(!tui.debugger.points[0].enabled);
+        keys(&mut tui, &[], "d");
+        assert!(tui.debugger.points.is_empty());
+    }
+
+    #[test]
+    fn lights_up_watches_that_change() {
+        // add r0 r0 1; jmp 0
+        let words: [u16; 6] = [9, 32768, 32768, 1, 6, 0];
+        let output = Buffer::default();
+        let mut synacor = Synacor::with_config(Config { output: Box::new(output.clone()), ..Config::default() });
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let mut tui = Tui::new(synacor, output);
+        for key in [Key::Tab, Key::Tab, Key::Tab, Key::Tab, Key::Char('a'), Key::Char('r'), Key::Char('0'), Key::Enter, Key::Tab, Key::Ctrl('p'), Key::Ctrl('n')] {
+            tui.key(key);
+        }
+        let shown = |tui: &Tui| {
+            let screen = tui.frame(60, 24);
+            let row = (0..24).find(|&y| screen.row(y).starts_with("│r0 = ")).unwrap();
+            (screen.row(row)[3..9].to_string(), screen.cell(1, row).1)
+        };
+        assert_eq!(shown(&tui), ("r0 = 1".to_string(), Style::Changed));
+        tui.key(Key::Ctrl('n'));
+        assert_eq!(shown(&tui), ("r0 = 1".to_string(), Style::Plain));
+    }
+
+    #[test]
+    fn logs_events_apart_from_the_game() {
+        // noop; halt
+        let words: [u16; 2] = [21, 0];
+        let output = Buffer::default();
+        let mut synacor = Synacor::with_config(Config { output: Box::new(output.clone()), ..Config::default() });
+        synacor.load_image(Image::Bytes(words.iter().flat_map(|word| word.to_le_bytes()).collect())).ok().unwrap();
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("synacor-tui-{}", std::process::id()));
+        let mut tui = Tui::new(synacor, output).with_slots(Slots::new(&dir));
+        tui.debugger.toggle_break(1);
+        tui.run_chunk();
+        tui.key(Key::Ctrl('s'));
+        tui.key(Key::Ctrl('p'));
+        tui.run_chunk();
+        tui.key(Key::Ctrl('l'));
+        assert_eq!((tui.state.clone(), tui.synacor.program_counter()), (State::Running, 1));
+        let screen = tui.frame(100, 40);
+        let title = (0..40).find(|&y| screen.row(y).contains("─ events: 4, 4 new ─")).unwrap();
+        let rows: Vec<String> = (title + 1..title + 5).map(|y| screen.row(y).chars().skip(61).collect::<String>().trim_end_matches([' ', '│']).to_string()).collect();
+        assert_eq!(rows[0], "        1  stopped at break 1");
+        assert!(rows[1].starts_with("        1  saved to "));
+        assert_eq!(rows[2], "        2  The synacor halted.");
+        assert!(rows[3].starts_with("        1  loaded "));
+        assert_eq!(screen.cell(61, title + 1).1, Style::Alert);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn draws_the_map() {
+        let output = Buffer::default();
+        let synacor = Synacor::with_config(Config { output: Box::new(output.clone()), ..Config::default() });
+        let map = Rc::new(RefCell::new(Map::default()));
+        let rooms = "== Foothills ==\nHills.\n\nThere is 1 exit:\n- south\n\nWhat do you do?\n\
+                     == Path ==\nA path.\n\nThere is 1 exit:\n- north\n\nWhat do you do?\n";
+        for (index, line) in rooms.lines().enumerate() {
+            if index == 7 {
+                map.borrow_mut().command("south");
+            }
+            map.borrow_mut().output_line(line);
+        }
+        let mut tui = Tui::new(synacor, output).with_map(map);
+        tui.key(Key::Tab);
+        tui.key(Key::Char('m'));
+        let screen = tui.frame(80, 40);
+        let row = (0..40).find(|&y| screen.row(y).contains("│    Path    │")).unwrap();
+        let x = screen.row(row).chars().position(|c| c == 'P').unwrap();
+        assert!(screen.row(row - 4).contains("│ Foothills  │"));
+        assert_eq!(screen.cell(x + 2, row - 2), ('│', Style::Dim));
+        assert_eq!(screen.cell(x, row), ('P', Style::Changed));
+        assert!((0..40).any(|y| screen.row(y).contains("─ map: Path of 2 ─")));
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+use std::ops::{Add, BitAnd, BitOr, Mul, Not, Rem};
+
+use synacor::SynacorErr;
+
+const MODULUS: u16 = 32768;
+
+// A 16-bit word as stored in memory, a register or the stack. Words below
+// 32768 are numbers; the arithmetic operators work modulo 32768 like the
+// architecture's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Word(u16);
+
+impl Word {
+    pub fn new(raw: u16) -> Word {
+        Word(raw)
+    }
+    // Only accepts 15-bit numbers.
+    pub fn number(number: u16) -> Option<Word> {
+        if number < MODULUS {
+            Some(Word(number))
+        } else {
+            None
+        }
+    }
+    // Reduces a 16-bit result modulo 32768. Since 65536 is a multiple of
+    // 32768 this is also correct for results that wrapped around u16.
+    fn wrap(raw: u16) -> Word {
+        Word(raw % MODULUS)
+    }
+    pub fn get(self) -> u16 {
+        self.0
+    }
+    pub fn is_number(self) -> bool {
+        self.0 < MODULUS
+    }
+}
+
+impl From<bool> for Word {
+    fn from(value: bool) -> Word {
+        Word(value as u16)
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Add for Word {
+    type Output = Word;
+    fn add(self, other: Word) -> Word {
+        Word::wrap(self.0.wrapping_add(other.0))
+    }
+}
+
+impl Mul for Word {
+    type Output = Word;
+    fn mul(self, other: Word) -> Word {
+        Word::wrap(self.0.wrapping_mul(other.0))
+    }
+}
+
+impl Rem for Word {
+    type Output = Word;
+    fn rem(self, other: Word) -> Word {
+        Word(self.0 % other.0)
+    }
+}
+
+impl BitAnd for Word {
+    type Output = Word;
+    fn bitand(self, other: Word) -> Word {
+        Word(self.0 & other.0)
+    }
+}
+
+impl BitOr for Word {
+    type Output = Word;
+    fn bitor(self, other: Word) -> Word {
+        Word(self.0 | other.0)
+    }
+}
+
+// The 15-bit complement.
+impl Not for Word {
+    type Output = Word;
+    fn not(self) -> Word {
+        Word(self.0 ^ (MODULUS - 1))
+    }
+}
+
+// A memory address. Any 16-bit value can address the emulator's memory, but
+// only the first 32768 words exist in the spec.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Addr(u16);
+
+impl Addr {
+    pub fn new(address: u16) -> Addr {
+        Addr(address)
+    }
+    // Only accepts addresses inside the spec's 15-bit address space.
+    pub fn checked(address: u16) -> Option<Addr> {
+        if address < MODULUS {
+            Some(Addr(address))
+        } else {
+            None
+        }
+    }
+    pub fn get(self) -> u16 {
+        self.0
+    }
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+    // Reduces the address into the 15-bit address space.
+    pub fn wrapped(self) -> Addr {
+        Addr(self.0 % MODULUS)
+    }
+    pub fn next(self) -> Addr {
+        Addr(self.0.wrapping_add(1))
+    }
+}
+
+impl From<Word> for Addr {
+    fn from(word: Word) -> Addr {
+        Addr(word.0)
+    }
+}
+
+impl From<Addr> for Word {
+    fn from(address: Addr) -> Word {
+        Word(address.0)
+    }
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// One of the eight registers. The index can only come from Register::extract,
+// so it is always below 8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Register(u8);
+
+impl Register {
+    // Words 32768 to 32775 name registers 0 to 7.
+    pub fn extract(word: Word) -> Option<Register> {
+        match word.0 {
+            32768..=32775 => Some(Register((word.0 - MODULUS) as u8)),
+            _ => None,
+        }
+    }
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+// A decoded instruction argument. Register indices are checked once when the
+// word is decoded, so everything downstream can index the register file
+// without checking again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Literal(Word),
+    Register(Register),
+}
+
+impl Operand {
+    pub fn decode(word: Word) -> Result<Operand, SynacorErr> {
+        if word.is_number() {
+            Ok(Operand::Literal(word))
+        } else {
+            Register::extract(word).map(Operand::Register).ok_or(SynacorErr::BadRegister)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_wraps_at_15_bits() {
+        assert_eq!(Word(32758) + Word(15), Word(5));
+        assert_eq!(Word(300) * Word(300), Word((90000 % 32768) as u16));
+        assert_eq!(!Word(0), Word(32767));
+    }
+
+    #[test]
+    fn checked_constructors() {
+        assert_eq!(Word::number(32767), Some(Word(32767)));
+        assert_eq!(Word::number(32768), None);
+        assert_eq!(Addr::checked(32768), None);
+        assert_eq!(Addr::new(65535).next(), Addr(0));
+    }
+
+    #[test]
+    fn register_extraction() {
+        assert_eq!(Register::extract(Word(32768)), Some(Register(0)));
+        assert_eq!(Register::extract(Word(32775)), Some(Register(7)));
+        assert_eq!(Register::extract(Word(32776)), None);
+        assert_eq!(Register::extract(Word(7)), None);
+        assert!(Operand::decode(Word(40000)).is_err());
+    }
+}
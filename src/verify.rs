@@ -0,0 +1,34 @@
+use output::Buffer;
+use synacor::Synacor;
+
+const COMPLETE: &str = "self-test complete";
+// The challenge binary finishes its self-test in well under a million
+// instructions, so anything that runs this long has gone wrong.
+const STEP_LIMIT: u64 = 10_000_000;
+
+// Runs the binary until it reports that its self-test is complete. Returns
+// whether it got there, describing what went wrong on stderr if it didn't.
+pub fn self_test(synacor: &mut Synacor, capture: &Buffer) -> bool {
+    let mut printed = 0;
+    for _ in 0..STEP_LIMIT {
+        let result = synacor.run_optcode();
+        if capture.len() != printed {
+            printed = capture.len();
+            let text = capture.text();
+            if text.ends_with('\n') && text.contains(COMPLETE) {
+                return true;
+            }
+        }
+        if let Err(err) = result {
+            report(capture, &err.to_string());
+            return false;
+        }
+    }
+    report(capture, "The self-test did not finish.");
+    false
+}
+
+fn report(capture: &Buffer, reason: &str) {
+    eprint!("{}", capture.text());
+    notice!("{}", reason);
+}
@@ -0,0 +1,43 @@
+// Scripts that play the game from the start, one per segment. `--play-to`
+// types them in, saves the machine when the last one is done and leaves the
+// player there. The teleporter only goes on to the beach once r7 is set, so
+// that segment needs the bypass from solve.rs.
+
+#[derive(Debug)]
+pub struct Segment {
+    // Where the segment ends, which is also the name of its save slot.
+    pub milestone: &'static str,
+    pub script: &'static str,
+    pub teleporter: bool,
+}
+
+pub const SEGMENTS: [Segment; 4] = [
+    Segment { milestone: "ruins", script: include_str!("walkthrough/ruins.txt"), teleporter: false },
+    Segment { milestone: "headquarters", script: include_str!("walkthrough/headquarters.txt"), teleporter: false },
+    Segment { milestone: "beach", script: include_str!("walkthrough/beach.txt"), teleporter: true },
+    Segment { milestone: "vault", script: include_str!("walkthrough/vault.txt"), teleporter: false },
+];
+
+// The segments to play to reach `milestone`.
+pub fn to(milestone: &str) -> Result<&'static [Segment], String> {
+    match SEGMENTS.iter().position(|segment| segment.milestone == milestone) {
+        Some(index) => Ok(&SEGMENTS[..=index]),
+        None => Err(format!("there is no milestone {:?}; try one of {}", milestone, milestones().join(", "))),
+    }
+}
+
+pub fn milestones() -> Vec<&'static str> {
+    SEGMENTS.iter().map(|segment| segment.milestone).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_to_a_milestone() {
+        assert_eq!(to("beach").unwrap().len(), 3);
+        assert!(to("nowhere").unwrap_err().contains("ruins, headquarters, beach, vault"));
+        assert!(SEGMENTS.iter().all(|segment| segment.script.ends_with('\n')));
+    }
+}
@@ -0,0 +1,233 @@
+// Just enough of RFC 6455 to talk to a browser: the opening handshake, text
+// frames out, and text frames (plus pings and close) in.
+
+use std::io;
+use std::io::prelude::*;
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const CONTINUATION: u8 = 0;
+const TEXT: u8 = 1;
+const BINARY: u8 = 2;
+const CLOSE: u8 = 8;
+const PING: u8 = 9;
+const PONG: u8 = 10;
+
+// The longest message taken from a peer, across all of its frames. Players
+// type lines, so anything near this is an attack.
+pub const MAX_MESSAGE: u64 = 64 * 1024;
+
+fn sha1(message: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend(&((message.len() as u64) * 8).to_be_bytes());
+    for block in padded.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip(&[a, b, c, d, e]) {
+            *value = value.wrapping_add(*add);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(&state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+// The parts of an HTTP request the server cares about.
+pub struct Request {
+    pub path: String,
+    pub key: Option<String>,
+}
+
+// Reads the request line and headers, leaving the stream at the body.
+pub fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let path = line.split_whitespace().nth(1).unwrap_or("/").to_string();
+    let mut key = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    Ok(Request { path, key })
+}
+
+pub fn write_handshake<W: Write>(writer: &mut W, key: &str) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    )?;
+    writer.flush()
+}
+
+fn write_frame<W: Write>(writer: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut header = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => header.push(len as u8),
+        len if len < 65536 => {
+            header.push(126);
+            header.extend(&(len as u16).to_be_bytes());
+        }
+        len => {
+            header.push(127);
+            header.extend(&(len as u64).to_be_bytes());
+        }
+    }
+    writer.write_all(&header)?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+pub fn write_text<W: Write>(writer: &mut W, text: &[u8]) -> io::Result<()> {
+    write_frame(writer, TEXT, text)
+}
+
+pub fn write_close<W: Write>(writer: &mut W) -> io::Result<()> {
+    write_frame(writer, CLOSE, &[])
+}
+
+// Reads frames until a whole text or binary message has arrived, answering
+// pings on `writer` along the way. Returns None once the peer closes, and
+// an error for a message longer than MAX_MESSAGE or a frame cut short.
+pub fn read_message<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<Option<Vec<u8>>> {
+    let mut message = Vec::new();
+    loop {
+        let mut header = [0; 2];
+        if let Err(err) = reader.read_exact(&mut header) {
+            return match err.kind() {
+                io::ErrorKind::UnexpectedEof => Ok(None),
+                _ => Err(err),
+            };
+        }
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0F;
+        let len = match header[1] & 0x7F {
+            126 => {
+                let mut len = [0; 2];
+                reader.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as u64
+            }
+            127 => {
+                let mut len = [0; 8];
+                reader.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => len as u64,
+        };
+        let mut mask = [0; 4];
+        if header[1] & 0x80 != 0 {
+            reader.read_exact(&mut mask)?;
+        }
+        if len > MAX_MESSAGE - message.len() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "websocket message is too long"));
+        }
+        let mut payload = vec![0; len as usize];
+        reader.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        match opcode {
+            CLOSE => return Ok(None),
+            PING => write_frame(writer, PONG, &payload)?,
+            PONG => (),
+            TEXT | BINARY | CONTINUATION => {
+                message.extend(payload);
+                if fin {
+                    return Ok(Some(message));
+                }
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown websocket opcode")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_key() {
+        // The example from RFC 6455.
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64(b"ab"), "YWI=");
+    }
+
+    #[test]
+    fn masked_frames() {
+        let mask = [1, 2, 3, 4];
+        let mut frame = vec![0x80 | PING, 0x80, 1, 2, 3, 4];
+        frame.extend(&[0x80 | TEXT, 0x80 | 4, 1, 2, 3, 4]);
+        frame.extend(b"look".iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        let mut pongs = Vec::new();
+        assert_eq!(read_message(&mut &frame[..], &mut pongs).unwrap(), Some(b"look".to_vec()));
+        assert_eq!(pongs, [0x80 | PONG, 0]);
+        assert_eq!(read_message(&mut &[0x88, 0][..], &mut pongs).unwrap(), None);
+
+        let mut huge = vec![TEXT, 127];
+        huge.extend(&u64::MAX.to_be_bytes());
+        assert_eq!(read_message(&mut &huge[..], &mut pongs).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let mut pieces = Vec::new();
+        for _ in 0..2 {
+            pieces.extend(&[CONTINUATION, 127]);
+            pieces.extend(&(MAX_MESSAGE / 2 + 1).to_be_bytes());
+            pieces.resize(pieces.len() + (MAX_MESSAGE / 2 + 1) as usize, b'a');
+        }
+        assert_eq!(read_message(&mut &pieces[..], &mut pongs).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let cut = [0x80 | TEXT, 4, b'l', b'o'];
+        assert_eq!(read_message(&mut &cut[..], &mut pongs).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
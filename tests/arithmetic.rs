@@ -0,0 +1,107 @@
+// Property tests for the arithmetic and comparison opcodes. Each case runs
+// one instruction on random 15-bit operands, passed either as literals or
+// through registers, and compares the result against a model.
+
+extern crate synacor;
+
+use synacor::input::Text;
+use synacor::output::Null;
+use synacor::{Config, Image, RunOutcome, Synacor};
+
+const CASES: usize = 2000;
+
+// xorshift64*, seeded so failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    // Biased towards the edges of the 15-bit domain, where bugs live.
+    fn number(&mut self) -> u16 {
+        match self.next() % 8 {
+            0 => 0,
+            1 => 1,
+            2 => 32767,
+            3 => 32767 - (self.next() % 16) as u16,
+            _ => (self.next() % 32768) as u16,
+        }
+    }
+}
+
+fn run(b: u16, c: u16, instruction: &[u16]) -> u16 {
+    let mut program = vec![1, 32769, b, 1, 32770, c];
+    program.extend_from_slice(instruction);
+    program.push(0);
+    let bytes = program.iter().flat_map(|word| vec![*word as u8, (*word >> 8) as u8]).collect();
+    let config = Config { output: Box::new(Null), input: Box::new(Text::default()), ..Config::default() };
+    let mut synacor = Synacor::with_config(config);
+    synacor.load_image(Image::Bytes(bytes)).ok().unwrap();
+    match synacor.run() {
+        RunOutcome::Halted => synacor.registers()[0],
+        outcome => panic!("{:?} stopped: {}", instruction, outcome),
+    }
+}
+
+fn check(optcode: u16, seed: u64, model: fn(u16, u16) -> u16) {
+    let mut rng = Rng(seed);
+    for _ in 0..CASES {
+        let b = rng.number();
+        let c = match rng.number() {
+            0 if optcode == 11 => 1,
+            c => c,
+        };
+        // Use registers r1 and r2 (holding b and c) or the literals directly.
+        let b_operand = if rng.next() & 1 == 0 { b } else { 32769 };
+        let c_operand = if rng.next() & 1 == 0 { c } else { 32770 };
+        let instruction = if optcode == 14 {
+            vec![optcode, 32768, b_operand]
+        } else {
+            vec![optcode, 32768, b_operand, c_operand]
+        };
+        assert_eq!(run(b, c, &instruction), model(b, c), "{:?} with b = {}, c = {}", instruction, b, c);
+    }
+}
+
+#[test]
+fn eq() {
+    check(4, 1, |b, c| (b == c) as u16);
+}
+
+#[test]
+fn gt() {
+    check(5, 2, |b, c| (b > c) as u16);
+}
+
+#[test]
+fn add() {
+    check(9, 3, |b, c| ((b as u32 + c as u32) % 32768) as u16);
+}
+
+#[test]
+fn mult() {
+    check(10, 4, |b, c| ((b as u32 * c as u32) % 32768) as u16);
+}
+
+#[test]
+fn mod_() {
+    check(11, 5, |b, c| b % c);
+}
+
+#[test]
+fn and() {
+    check(12, 6, |b, c| b & c);
+}
+
+#[test]
+fn or() {
+    check(13, 7, |b, c| b | c);
+}
+
+#[test]
+fn not() {
+    check(14, 8, |b, _| !b & 0x7FFF);
+}